@@ -0,0 +1,827 @@
+//! Generates `src/registers.rs` from the STM32H743 SVD file. The peripherals to include, the
+//! register block structs to write and the reset values to correct are listed in
+//! `peripherals.txt`. Run from the repository root with
+//!
+//! `cargo run --manifest-path codegen/Cargo.toml --target <host triple>`
+//!
+//! The SVD file, config and output paths default to `codegen/STM32H743.svd`,
+//! `codegen/peripherals.txt` and `src/registers.rs`, and can be given as the first, second and
+//! third argument
+use std::{collections::BTreeMap, env, fmt::Write, fs, process};
+
+use roxmltree::{Document, Node};
+
+const DEFAULT_SVD: &str = "codegen/STM32H743.svd";
+const DEFAULT_CONFIG: &str = "codegen/peripherals.txt";
+const DEFAULT_OUTPUT: &str = "src/registers.rs";
+
+const HEADER: &str = r#"// +--------------------------------------------------------------------------------+
+// | MIT License                                                                    |
+// |                                                                                |
+// | Copyright (c) 2024 Josef Utbult                                                |
+// |                                                                                |
+// | Permission is hereby granted, free of charge, to any person obtaining a copy   |
+// | of this software and associated documentation files (the "Software"), to deal  |
+// | in the Software without restriction, including without limitation the rights   |
+// | to use, copy, modify, merge, publish, distribute, sublicense, and/or sell      |
+// | copies of the Software, and to permit persons to whom the Software is          |
+// | furnished to do so, subject to the following conditions:                       |
+// |                                                                                |
+// | The above copyright notice and this permission notice shall be included in all |
+// | copies or substantial portions of the Software.                                |
+// |                                                                                |
+// | THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR     |
+// | IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,       |
+// | FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE    |
+// | AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER         |
+// | LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,  |
+// | OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE  |
+// | SOFTWARE.                                                                      |
+// +--------------------------------------------------------------------------------+
+
+//! # STM32H743 Register Map
+//!
+//! This file is generated from the STM32H743 SVD file by the generator in `codegen`, which
+//! is based on [svdToRustRegisterMap](https://github.com/JosefUtbult/svdToRustRegisterMap).
+//! Don't edit it by hand. List new peripherals in `codegen/peripherals.txt` and rerun the
+//! generator instead.
+//! These registry maps can be used for integrating with bare metal hardware in an
+//! minimal-abstraction fashion. **This should not be used if you don't know what
+//! you're doing, as this approach disregards all compile-time and run-time
+//! securities, in favour of a more manual approach**. If you're not sure about
+//! this kind of stuff, you should probably be using a full-fetched HAL, such as
+//! the [stm32f4xx-hal](https://github.com/stm32-rs/stm32f4xx-hal) or
+//! [esp-hal](https://github.com/esp-rs/esp-hal). Another good alternative to this
+//! method for STM32 processors is
+//! [stm32ral](https://github.com/adamgreig/stm32ral), which supplies a Register
+//! Access Layer in the form of structs.
+//!
+//! **Example:**
+//!
+//! You can read from a register using `read_volatile`, and mask the value with the included fields
+//! ```
+//! use core::ptr::read_volatile;
+//! unsafe { read_volatile(adc2::DIFSEL) & (0b1 << adc2::difsel::DIFSEL) }
+//! ```
+//!
+//! You can write to a register using `write_volatile` and use the included fields to shift into
+//! the correct position
+//! ```
+//! use core::ptr::write_volatile;
+//! unsafe { write_volatile(adc2::DIFSEL, 0b1 << adc2::difsel::DIFSEL); }
+//! ```
+//!
+//! You can combine the two to add to a populated register
+//! ```
+//! use core::ptr::{read_volatile, write_volatile};
+//! unsafe {
+//!     write_volatile(
+//!         adc2::DIFSEL,
+//!         read_volatile(adc2::DIFSEL) | (0b1 << adc2::difsel::DIFSEL)
+//!     );
+//! }
+//! ```
+"#;
+
+const BLOCKS_DOC: &str = r#"/// Register blocks of peripherals with several identical instances. A block is a `#[repr(C)]`
+/// struct with one field per register, and each instance is a pointer to a block at the base
+/// address of the peripheral, so the registers of an instance can be selected at runtime:
+/// ```no_run
+/// use stm32h743_tools::registers::blocks::GPIOA;
+/// let moder: *mut u32 = unsafe { &raw mut (*GPIOA).moder };
+/// ```
+/// The field offsets are checked against the register offsets at compile time
+"#;
+
+struct Config {
+    peripherals: Vec<String>,
+    blocks: Vec<Block>,
+    reset_values: Vec<ResetValue>,
+}
+
+struct Block {
+    name: String,
+    instances: Vec<String>,
+}
+
+/// A reset value replacing the one in the SVD file for a register of a single peripheral
+struct ResetValue {
+    peripheral: String,
+    register: String,
+    value: u32,
+}
+
+#[derive(Clone)]
+struct EnumeratedValue {
+    name: String,
+    description: Option<String>,
+    value: u32,
+}
+
+#[derive(Clone)]
+struct Field {
+    name: String,
+    description: Option<String>,
+    offset: u32,
+    width: u32,
+    values: Vec<EnumeratedValue>,
+}
+
+#[derive(Clone)]
+struct Register {
+    name: String,
+    description: Option<String>,
+    access: Option<String>,
+    /// Value after reset, from the register or the defaults of its peripheral or the device
+    reset_value: Option<u32>,
+    offset: u32,
+    fields: Vec<Field>,
+}
+
+struct Interrupt {
+    name: String,
+    description: Option<String>,
+    value: u32,
+}
+
+struct Peripheral {
+    name: String,
+    description: Option<String>,
+    group: String,
+    address: u32,
+    registers: Vec<Register>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let svd_path = args.first().map_or(DEFAULT_SVD, String::as_str);
+    let config_path = args.get(1).map_or(DEFAULT_CONFIG, String::as_str);
+    let output_path = args.get(2).map_or(DEFAULT_OUTPUT, String::as_str);
+
+    let config = parse_config(&read(config_path));
+    let svd = read(svd_path);
+    let document = Document::parse(&svd).unwrap_or_else(|error| fail(&error.to_string()));
+
+    let (mut peripherals, interrupts) = parse_device(document.root_element());
+    apply_reset_values(&mut peripherals, &config.reset_values);
+
+    let selected: Vec<&Peripheral> = config
+        .peripherals
+        .iter()
+        .map(|name| {
+            find_peripheral(&peripherals, name)
+                .unwrap_or_else(|| fail(&format!("{name} isn't in the SVD file")))
+        })
+        .collect();
+
+    let output = generate(&selected, &peripherals, &interrupts, &config.blocks);
+    fs::write(output_path, output)
+        .unwrap_or_else(|error| fail(&format!("can't write {output_path}: {error}")));
+
+    eprintln!(
+        "Wrote {} peripherals and {} interrupts to {output_path}",
+        selected.len(),
+        interrupts.len()
+    );
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
+
+fn read(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|error| fail(&format!("can't read {path}: {error}")))
+}
+
+fn parse_config(text: &str) -> Config {
+    let mut config = Config {
+        peripherals: Vec::new(),
+        blocks: Vec::new(),
+        reset_values: Vec::new(),
+    };
+    let mut section = "";
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = match line {
+                "[peripherals]" => "peripherals",
+                "[blocks]" => "blocks",
+                "[reset values]" => "reset values",
+                _ => fail(&format!("unknown config section {line}")),
+            };
+            continue;
+        }
+
+        match section {
+            "peripherals" => config.peripherals.push(line.to_string()),
+            "blocks" => {
+                let mut words = line.split_whitespace().map(str::to_string);
+                let name = words.next().unwrap_or_default();
+                let instances: Vec<String> = words.collect();
+                if instances.is_empty() {
+                    fail(&format!("block {name} has no instances"));
+                }
+                config.blocks.push(Block { name, instances });
+            }
+            "reset values" => {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                let [peripheral, register, value] = words[..] else {
+                    fail(&format!(
+                        "expected a peripheral, register and value: {line}"
+                    ));
+                };
+                let value = parse_number(value)
+                    .unwrap_or_else(|| fail(&format!("invalid reset value {value}")));
+                config.reset_values.push(ResetValue {
+                    peripheral: peripheral.to_string(),
+                    register: register.to_string(),
+                    value,
+                });
+            }
+            _ => fail(&format!("config line outside of a section: {line}")),
+        }
+    }
+
+    config
+}
+
+/// Replace reset values that are wrong in the SVD file. Derived peripherals copy the registers
+/// of their base, so an override only changes the peripheral it names
+fn apply_reset_values(peripherals: &mut [Peripheral], reset_values: &[ResetValue]) {
+    for reset_value in reset_values {
+        let peripheral = peripherals
+            .iter_mut()
+            .find(|peripheral| {
+                peripheral
+                    .name
+                    .eq_ignore_ascii_case(&reset_value.peripheral)
+            })
+            .unwrap_or_else(|| fail(&format!("{} isn't in the SVD file", reset_value.peripheral)));
+        let register = peripheral
+            .registers
+            .iter_mut()
+            .find(|register| register.name.eq_ignore_ascii_case(&reset_value.register))
+            .unwrap_or_else(|| {
+                fail(&format!(
+                    "{} has no register {}",
+                    peripheral.name, reset_value.register
+                ))
+            });
+        register.reset_value = Some(reset_value.value);
+    }
+}
+
+fn find_peripheral<'a>(peripherals: &'a [Peripheral], name: &str) -> Option<&'a Peripheral> {
+    peripherals
+        .iter()
+        .find(|peripheral| peripheral.name.eq_ignore_ascii_case(name))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn number(node: Node, name: &str) -> Option<u32> {
+    text(node, name).map(|value| {
+        parse_number(value).unwrap_or_else(|| fail(&format!("invalid number {value} in <{name}>")))
+    })
+}
+
+/// Parse a scaled non-negative integer as written in SVD files, being decimal, hexadecimal with
+/// `0x`, or binary with `0b` or `#`
+fn parse_number(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = value.strip_prefix("0b").or_else(|| value.strip_prefix('#')) {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+/// Turn an SVD description into a single line starting with a capital letter
+fn format_description(description: Option<&str>) -> Option<String> {
+    let description = description?
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    let mut characters = description.chars();
+    let first = characters.next()?;
+    Some(first.to_uppercase().chain(characters).collect())
+}
+
+/// Turn an SVD name into an identifier, keeping its case
+fn identifier(name: &str) -> String {
+    let identifier: String = name
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if identifier.starts_with(|character: char| character.is_ascii_digit()) {
+        format!("_{identifier}")
+    } else {
+        identifier
+    }
+}
+
+fn parse_device(device: Node) -> (Vec<Peripheral>, Vec<Interrupt>) {
+    let peripherals_node =
+        child(device, "peripherals").unwrap_or_else(|| fail("the SVD file has no <peripherals>"));
+
+    let mut peripherals: Vec<Peripheral> = Vec::new();
+    let mut interrupts: BTreeMap<u32, Interrupt> = BTreeMap::new();
+    let device_reset_value = number(device, "resetValue");
+
+    for node in children(peripherals_node, "peripheral") {
+        let name = text(node, "name").unwrap_or_else(|| fail("peripheral without a <name>"));
+        let address = number(node, "baseAddress")
+            .unwrap_or_else(|| fail(&format!("{name} has no <baseAddress>")));
+
+        // A derived peripheral shares the registers and group of its base, but only gets a
+        // description if it has its own
+        let base = node.attribute("derivedFrom").map(|base| {
+            find_peripheral(&peripherals, base)
+                .unwrap_or_else(|| fail(&format!("{name} is derived from unknown {base}")))
+        });
+
+        let registers = match child(node, "registers") {
+            Some(registers) => {
+                parse_registers(registers, number(node, "resetValue").or(device_reset_value))
+            }
+            None => base.map_or_else(Vec::new, |base| base.registers.clone()),
+        };
+
+        let group = text(node, "groupName")
+            .map(str::to_string)
+            .or_else(|| base.map(|base| base.group.clone()))
+            .unwrap_or_else(|| name.to_string());
+
+        // The first peripheral listing an interrupt names it
+        for interrupt in children(node, "interrupt") {
+            let value = number(interrupt, "value")
+                .unwrap_or_else(|| fail(&format!("interrupt of {name} has no <value>")));
+            interrupts.entry(value).or_insert_with(|| Interrupt {
+                name: text(interrupt, "name").unwrap_or(name).to_string(),
+                description: format_description(text(interrupt, "description")),
+                value,
+            });
+        }
+
+        peripherals.push(Peripheral {
+            name: name.to_string(),
+            description: format_description(text(node, "description")),
+            group,
+            address,
+            registers,
+        });
+    }
+
+    (peripherals, interrupts.into_values().collect())
+}
+
+/// The names and offsets of a register with `dim` set, or just the register otherwise
+fn expand_dim(node: Node, name: &str, offset: u32) -> Vec<(String, u32)> {
+    let Some(count) = number(node, "dim") else {
+        return vec![(name.to_string(), offset)];
+    };
+    let increment = number(node, "dimIncrement").unwrap_or(4);
+
+    let indices: Vec<String> = match text(node, "dimIndex") {
+        Some(list) if list.contains('-') => {
+            let (first, last) = list.split_once('-').unwrap();
+            match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+                (Ok(first), Ok(last)) => (first..=last).map(|index| index.to_string()).collect(),
+                _ => fail(&format!("unsupported <dimIndex> {list} on {name}")),
+            }
+        }
+        Some(list) => list
+            .split(',')
+            .map(|index| index.trim().to_string())
+            .collect(),
+        None => (0..count).map(|index| index.to_string()).collect(),
+    };
+
+    indices
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let name = name.replace("[%s]", value).replace("%s", value);
+            (name, offset + index as u32 * increment)
+        })
+        .collect()
+}
+
+fn parse_registers(node: Node, default_reset_value: Option<u32>) -> Vec<Register> {
+    let mut registers = Vec::new();
+
+    for cluster in children(node, "cluster") {
+        eprintln!(
+            "warning: skipping cluster {}, clusters aren't supported",
+            text(cluster, "name").unwrap_or("?")
+        );
+    }
+
+    for register in children(node, "register") {
+        let name = text(register, "name").unwrap_or_else(|| fail("register without a <name>"));
+        let offset = number(register, "addressOffset")
+            .unwrap_or_else(|| fail(&format!("register {name} has no <addressOffset>")));
+
+        let fields: Vec<Field> = child(register, "fields")
+            .map(|fields| children(fields, "field").map(parse_field).collect())
+            .unwrap_or_default();
+
+        for (name, offset) in expand_dim(register, name, offset) {
+            registers.push(Register {
+                name,
+                description: format_description(text(register, "description")),
+                access: text(register, "access").map(str::to_string),
+                reset_value: number(register, "resetValue").or(default_reset_value),
+                offset,
+                fields: fields.clone(),
+            });
+        }
+    }
+
+    registers
+}
+
+fn parse_field(node: Node) -> Field {
+    let name = text(node, "name").unwrap_or_else(|| fail("field without a <name>"));
+
+    // Bit positions are given as an offset and width, as lsb and msb, or as a bit range
+    let (offset, width) = if let Some(offset) = number(node, "bitOffset") {
+        (offset, number(node, "bitWidth").unwrap_or(1))
+    } else if let (Some(lsb), Some(msb)) = (number(node, "lsb"), number(node, "msb")) {
+        (lsb, msb - lsb + 1)
+    } else if let Some(range) = text(node, "bitRange") {
+        let range = range.trim_start_matches('[').trim_end_matches(']');
+        let (msb, lsb) = range
+            .split_once(':')
+            .and_then(|(msb, lsb)| Some((parse_number(msb)?, parse_number(lsb)?)))
+            .unwrap_or_else(|| fail(&format!("invalid <bitRange> on field {name}")));
+        (lsb, msb - lsb + 1)
+    } else {
+        fail(&format!("field {name} has no bit position"))
+    };
+
+    let mut values: Vec<EnumeratedValue> = Vec::new();
+    for set in children(node, "enumeratedValues") {
+        for value in children(set, "enumeratedValue") {
+            // Default values without an explicit value cover every remaining value
+            let (Some(value_name), Some(number)) = (text(value, "name"), number(value, "value"))
+            else {
+                continue;
+            };
+
+            if values.iter().any(|value| value.name == value_name) {
+                continue;
+            }
+
+            values.push(EnumeratedValue {
+                name: value_name.to_string(),
+                description: format_description(text(value, "description")),
+                value: number,
+            });
+        }
+    }
+
+    Field {
+        name: name.to_string(),
+        description: format_description(text(node, "description")),
+        offset,
+        width,
+        values,
+    }
+}
+
+fn group_module(group: &str) -> String {
+    format!("{}_registers", identifier(group).to_lowercase())
+}
+
+fn generate(
+    selected: &[&Peripheral],
+    peripherals: &[Peripheral],
+    interrupts: &[Interrupt],
+    blocks: &[Block],
+) -> String {
+    let mut output = String::from(HEADER);
+
+    write_addresses(&mut output, selected);
+    write_groups(&mut output, selected);
+    if !blocks.is_empty() {
+        write_blocks(&mut output, peripherals, blocks);
+    }
+    write_interrupts(&mut output, interrupts);
+
+    for peripheral in selected {
+        write_peripheral(&mut output, peripheral);
+    }
+
+    output
+}
+
+fn write_addresses(output: &mut String, selected: &[&Peripheral]) {
+    let mut sorted = selected.to_vec();
+    sorted.sort_by_key(|peripheral| peripheral.address);
+
+    output.push_str("\n#[rustfmt::skip]\nmod peripherals {\n");
+    for peripheral in sorted {
+        let name = format!("{}_ADDR:", identifier(&peripheral.name).to_uppercase());
+        writeln!(
+            output,
+            "    pub const {name:<41}u32 = 0x{:08X};",
+            peripheral.address
+        )
+        .unwrap();
+    }
+    output.push_str("}\n");
+}
+
+/// Write the register offsets of every group. Peripherals of the same group share the offset
+/// module, and a register name keeps the offset of the first peripheral defining it
+fn write_groups(output: &mut String, selected: &[&Peripheral]) {
+    let mut groups: Vec<(String, Vec<(String, u32)>)> = Vec::new();
+
+    for peripheral in selected {
+        let module = group_module(&peripheral.group);
+        let index = match groups.iter().position(|(name, _)| *name == module) {
+            Some(index) => index,
+            None => {
+                groups.push((module, Vec::new()));
+                groups.len() - 1
+            }
+        };
+
+        let (module, offsets) = &mut groups[index];
+        for register in &peripheral.registers {
+            let name = identifier(&register.name).to_uppercase();
+            match offsets.iter().find(|(existing, _)| *existing == name) {
+                Some((_, offset)) if *offset != register.offset => eprintln!(
+                    "warning: {name} of {} is at {:#X}, but the {} offset is {offset:#X}",
+                    peripheral.name, register.offset, module
+                ),
+                Some(_) => {}
+                None => offsets.push((name, register.offset)),
+            }
+        }
+    }
+
+    for (module, offsets) in groups {
+        write!(output, "\n#[rustfmt::skip]\nmod {module} {{\n").unwrap();
+        for (name, offset) in offsets {
+            let name = format!("{name}_ADDR:");
+            writeln!(output, "    pub const {name:<41}u32 = 0x{offset:05X};").unwrap();
+        }
+        output.push_str("}\n");
+    }
+}
+
+fn write_blocks(output: &mut String, peripherals: &[Peripheral], blocks: &[Block]) {
+    let mut contents = String::new();
+    let mut imports = vec![String::from("peripherals")];
+
+    for block in blocks {
+        let instances: Vec<&Peripheral> = block
+            .instances
+            .iter()
+            .map(|name| {
+                find_peripheral(peripherals, name).unwrap_or_else(|| {
+                    fail(&format!("block instance {name} isn't in the SVD file"))
+                })
+            })
+            .collect();
+
+        // Registers at the same offset, such as the input and output capture modes of a timer,
+        // are the same register, so only the first one gets a field
+        let mut registers: Vec<&Register> = instances[0].registers.iter().collect();
+        registers.sort_by_key(|register| register.offset);
+        registers.dedup_by_key(|register| register.offset);
+
+        for instance in &instances[1..] {
+            for register in &registers {
+                let matches = instance
+                    .registers
+                    .iter()
+                    .any(|other| other.offset == register.offset);
+                if !matches {
+                    fail(&format!(
+                        "{} has no register at {:#X}, so it can't share {}",
+                        instance.name, register.offset, block.name
+                    ));
+                }
+            }
+        }
+
+        let module = group_module(&instances[0].group);
+        if !imports.contains(&module) {
+            imports.push(module.clone());
+        }
+
+        writeln!(
+            contents,
+            "\n    #[repr(C)]\n    pub struct {} {{",
+            block.name
+        )
+        .unwrap();
+        let mut position = 0;
+        for register in &registers {
+            if register.offset > position {
+                writeln!(
+                    contents,
+                    "        _reserved_{position:x}: [u32; {}],",
+                    (register.offset - position) / 4
+                )
+                .unwrap();
+            }
+            writeln!(
+                contents,
+                "        pub {}: u32,",
+                identifier(&register.name).to_lowercase()
+            )
+            .unwrap();
+            position = register.offset + 4;
+        }
+        contents.push_str("    }\n\n    const _: () = {\n");
+        for register in &registers {
+            writeln!(
+                contents,
+                "        assert!(offset_of!({}, {}) as u32 == {module}::{}_ADDR);",
+                block.name,
+                identifier(&register.name).to_lowercase(),
+                identifier(&register.name).to_uppercase()
+            )
+            .unwrap();
+        }
+        contents.push_str("    };\n\n");
+        for instance in &instances {
+            let name = identifier(&instance.name).to_uppercase();
+            writeln!(
+                contents,
+                "    pub const {name}: *mut {block} = peripherals::{name}_ADDR as *mut {block};",
+                block = block.name
+            )
+            .unwrap();
+        }
+    }
+
+    imports.sort();
+    write!(
+        output,
+        "\n{BLOCKS_DOC}pub mod blocks {{\n    use core::mem::offset_of;\n\n    use super::{{{}}};\n{contents}}}\n",
+        imports.join(", ")
+    )
+    .unwrap();
+}
+
+fn write_interrupts(output: &mut String, interrupts: &[Interrupt]) {
+    output.push_str("\n#[rustfmt::skip]\n#[allow(unused)]\npub mod irq {\n");
+
+    let count = interrupts.last().map_or(0, |interrupt| interrupt.value + 1);
+    for value in 0..count {
+        output.push('\n');
+        match interrupts.iter().find(|interrupt| interrupt.value == value) {
+            Some(interrupt) => {
+                if let Some(description) = &interrupt.description {
+                    writeln!(output, "    /// {description}").unwrap();
+                }
+                writeln!(
+                    output,
+                    "    pub const {}_IRQ: u32 = {value};",
+                    identifier(&interrupt.name).to_uppercase()
+                )
+                .unwrap();
+            }
+            None => {
+                writeln!(output, "    // Unknown interrupt").unwrap();
+                writeln!(output, "    //pub const UNKNOWN_IRQ: u32 = {value};").unwrap();
+            }
+        }
+    }
+
+    output.push_str("}\n");
+}
+
+fn write_peripheral(output: &mut String, peripheral: &Peripheral) {
+    let name = identifier(&peripheral.name);
+    let group = group_module(&peripheral.group);
+
+    output.push('\n');
+    if let Some(description) = &peripheral.description {
+        writeln!(output, "/// {description}").unwrap();
+    }
+    writeln!(
+        output,
+        "#[rustfmt::skip]\n#[allow(unused)]\npub mod {} {{\n    use super::{{peripherals, {group}}};",
+        name.to_lowercase()
+    )
+    .unwrap();
+
+    for register in &peripheral.registers {
+        let register_name = identifier(&register.name);
+
+        output.push('\n');
+        if let Some(description) = &register.description {
+            writeln!(output, "    /// {description}").unwrap();
+        }
+        if let Some(access) = &register.access {
+            writeln!(output, "    /// Access: {access}").unwrap();
+        }
+        writeln!(
+            output,
+            "    pub const {upper}: *mut u32 = (peripherals::{}_ADDR + {group}::{upper}_ADDR) as *mut u32;",
+            name.to_uppercase(),
+            upper = register_name.to_uppercase()
+        )
+        .unwrap();
+        if let Some(reset_value) = register.reset_value {
+            writeln!(
+                output,
+                "    pub const {}_RESET: u32 = 0x{reset_value:08X};",
+                register_name.to_uppercase()
+            )
+            .unwrap();
+        }
+        writeln!(output, "    pub mod {} {{", register_name.to_lowercase()).unwrap();
+
+        for (index, field) in register.fields.iter().enumerate() {
+            if index > 0 {
+                output.push('\n');
+            }
+            write_field(output, field);
+        }
+
+        output.push_str("    }\n");
+    }
+
+    output.push_str("}\n");
+}
+
+fn write_field(output: &mut String, field: &Field) {
+    let name = identifier(&field.name);
+
+    if let Some(description) = &field.description {
+        writeln!(output, "        /// {description}").unwrap();
+    }
+    let upper = name.to_uppercase();
+    writeln!(output, "        pub const {upper}: u8 = {};", field.offset).unwrap();
+    writeln!(
+        output,
+        "        pub const {upper}_WIDTH: u8 = {};",
+        field.width
+    )
+    .unwrap();
+
+    if field.values.is_empty() {
+        return;
+    }
+
+    // The enumerated values are unshifted, to be written at the field offset
+    writeln!(output, "        pub mod {} {{", name.to_lowercase()).unwrap();
+    for (index, value) in field.values.iter().enumerate() {
+        if index > 0 {
+            output.push('\n');
+        }
+        if let Some(description) = &value.description {
+            writeln!(output, "            /// {description}").unwrap();
+        }
+        writeln!(
+            output,
+            "            pub const {}: u32 = {:#b};",
+            identifier(&value.name).to_uppercase(),
+            value.value
+        )
+        .unwrap();
+    }
+    output.push_str("        }\n");
+}
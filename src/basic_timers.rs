@@ -0,0 +1,229 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    register_tools::{
+        ClearByWrite0, clear_bit, get_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    timers::TimerError,
+};
+
+/// Basic timers TIM6 and TIM7. They have a 16 bit up-counter without channels, and are meant as
+/// time bases and as triggers for the DAC, which they drive through their update event
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BasicTimer {
+    Tim6,
+    Tim7,
+}
+
+/// Start a basic timer with an update event at `frequency`, which fires the update interrupt and
+/// is output as trigger for the DAC. Note that the TIM6 interrupt is shared with the DAC underrun
+/// interrupt
+pub fn setup_basic_timer(
+    timer: &BasicTimer,
+    clock_frequency: u32,
+    frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::tim6;
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    if frequency == 0 || frequency > clock_frequency {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    // Use the smallest prescaler that fits the period in the 16 bit counter
+    let ticks = clock_frequency / frequency;
+    let prescaler = (ticks - 1) / 0x1_0000;
+    if prescaler > 0xFFFF {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let auto_reload = ticks / (prescaler + 1) - 1;
+
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    enable_basic_timer_clock(timer);
+
+    unsafe {
+        clear_bit(cr1_control_register, tim6::cr1::CEN);
+
+        write_register(get_psc_prescaler_register(timer), prescaler);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+
+        // Output the update event as trigger output, used by the DAC
+        write_bits(
+            get_cr2_control_register(timer),
+            tim6::cr2::MMS,
+            0b010,
+            0b111,
+        );
+
+        // Load the pre-scaler and clear the update flag this sets
+        set_bit(get_egr_event_generator_register(timer), tim6::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim6::sr::UIF);
+
+        // Enable the update interrupt for the timer
+        set_bit(get_dier_interrupt_register(timer), tim6::dier::UIE);
+        enable_interrupt(get_nvic_interrupt_id(timer));
+
+        // Enable the timer
+        set_bit(cr1_control_register, tim6::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Stop a basic timer and disable its interrupt
+pub fn cleanup_basic_timer(timer: &BasicTimer) {
+    use registers::tim6;
+
+    unsafe {
+        clear_bit(get_cr1_control_register(timer), tim6::cr1::CEN);
+        clear_bit(get_dier_interrupt_register(timer), tim6::dier::UIE);
+        write_bits(
+            get_cr2_control_register(timer),
+            tim6::cr2::MMS,
+            0b000,
+            0b111,
+        );
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim6::sr::UIF);
+    }
+
+    disable_interrupt(get_nvic_interrupt_id(timer));
+}
+
+/// Current value of the counter, in ticks of the prescaled clock
+pub fn get_basic_timer_count(timer: &BasicTimer) -> u16 {
+    unsafe { read_register(get_cnt_counter_register(timer)) as u16 }
+}
+
+pub fn is_basic_timer_update_pending(timer: &BasicTimer) -> bool {
+    use registers::tim6;
+    unsafe { get_bit(get_sr_status_register(timer), tim6::sr::UIF) == 1 }
+}
+
+pub fn clear_basic_timer_interrupt_flag(timer: &BasicTimer) {
+    use registers::tim6;
+    unsafe {
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim6::sr::UIF);
+    }
+}
+
+fn enable_basic_timer_clock(timer: &BasicTimer) {
+    use registers::rcc::{APB1LENR, apb1lenr};
+
+    let clock_enable_field = match timer {
+        BasicTimer::Tim6 => apb1lenr::TIM6EN,
+        BasicTimer::Tim7 => apb1lenr::TIM7EN,
+    };
+
+    unsafe {
+        set_bit(APB1LENR, clock_enable_field);
+    }
+}
+
+fn get_cr1_control_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::CR1,
+        BasicTimer::Tim7 => tim7::CR1,
+    }
+}
+
+fn get_cr2_control_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::CR2,
+        BasicTimer::Tim7 => tim7::CR2,
+    }
+}
+
+fn get_dier_interrupt_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::DIER,
+        BasicTimer::Tim7 => tim7::DIER,
+    }
+}
+
+fn get_sr_status_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::SR,
+        BasicTimer::Tim7 => tim7::SR,
+    }
+}
+
+fn get_egr_event_generator_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::EGR,
+        BasicTimer::Tim7 => tim7::EGR,
+    }
+}
+
+fn get_cnt_counter_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::CNT,
+        BasicTimer::Tim7 => tim7::CNT,
+    }
+}
+
+fn get_psc_prescaler_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::PSC,
+        BasicTimer::Tim7 => tim7::PSC,
+    }
+}
+
+fn get_arr_auto_reload_register(timer: &BasicTimer) -> *mut u32 {
+    use registers::{tim6, tim7};
+
+    match timer {
+        BasicTimer::Tim6 => tim6::ARR,
+        BasicTimer::Tim7 => tim7::ARR,
+    }
+}
+
+fn get_nvic_interrupt_id(timer: &BasicTimer) -> Irq {
+    match timer {
+        BasicTimer::Tim6 => Irq::Tim6Dac,
+        BasicTimer::Tim7 => Irq::Tim7,
+    }
+}
+
+pub fn setup_timer6(clock_frequency: u32, frequency: u32) -> Result<(), TimerError> {
+    setup_basic_timer(&BasicTimer::Tim6, clock_frequency, frequency)
+}
+
+pub fn setup_timer7(clock_frequency: u32, frequency: u32) -> Result<(), TimerError> {
+    setup_basic_timer(&BasicTimer::Tim7, clock_frequency, frequency)
+}
+
+pub fn cleanup_timer6() {
+    cleanup_basic_timer(&BasicTimer::Tim6);
+}
+
+pub fn cleanup_timer7() {
+    cleanup_basic_timer(&BasicTimer::Tim7);
+}
+
+pub fn clear_timer6_interrupt_flag() {
+    clear_basic_timer_interrupt_flag(&BasicTimer::Tim6);
+}
+
+pub fn clear_timer7_interrupt_flag() {
+    clear_basic_timer_interrupt_flag(&BasicTimer::Tim7);
+}
@@ -0,0 +1,439 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{
+    gpio::Gpio,
+    interrupts::enable_interrupt,
+    pinmap::PortPin,
+    register_tools::{
+        ClearByWrite0, clear_bit, get_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    timers::{
+        CHANNELS, Timer, TimerChannel, TimerError, enable_timer_clock,
+        get_arr_auto_reload_register, get_ccer_capture_compare_enable_register,
+        get_ccer_enable_field, get_ccmr_capture_compare_mode_register,
+        get_ccr_capture_compare_register, get_cr1_control_register, get_dier_interrupt_register,
+        get_egr_event_generator_register, get_nvic_capture_compare_interrupt_id,
+        get_nvic_interrupt_id, get_psc_prescaler_register, get_smcr_slave_mode_control_register,
+        get_sr_status_register, get_timer_channel_gpio, get_timer_max_count,
+    },
+};
+
+const TIMERS: usize = 6;
+const CAPTURE_CHANNELS: usize = TIMERS * 4;
+
+/// Clock frequency of each timer used for input capture, set by `setup_input_capture`
+static CAPTURE_CLOCKS: [AtomicU32; TIMERS] = [const { AtomicU32::new(0) }; TIMERS];
+
+/// Number of counter overflows of each timer, extending the counter to 32 bits for the 16 bit
+/// timers
+static CAPTURE_OVERFLOWS: [AtomicU32; TIMERS] = [const { AtomicU32::new(0) }; TIMERS];
+
+/// Extended time of the last rising edge of each channel
+static CAPTURE_RISING_EDGES: [AtomicU32; CAPTURE_CHANNELS] =
+    [const { AtomicU32::new(0) }; CAPTURE_CHANNELS];
+
+/// Ticks between the last two rising edges of each channel. Zero means that no period has been
+/// measured yet
+static CAPTURE_PERIODS: [AtomicU32; CAPTURE_CHANNELS] =
+    [const { AtomicU32::new(0) }; CAPTURE_CHANNELS];
+
+/// Ticks between the last rising edge and the falling edge after it. Zero means that no pulse
+/// has been measured yet
+static CAPTURE_PULSE_WIDTHS: [AtomicU32; CAPTURE_CHANNELS] =
+    [const { AtomicU32::new(0) }; CAPTURE_CHANNELS];
+
+fn get_capture_index(timer: &Timer, channel: TimerChannel) -> usize {
+    *timer as usize * 4 + channel as usize
+}
+
+/// Measure the frequency and high pulse width of a signal on a timer channel. The counter runs
+/// freely at `clock_frequency`, and the channel captures it alternately on rising and falling
+/// edges. `gpio` is the pin the channel is routed to, configured as alternate function of the
+/// timer. Call `handle_input_capture_interrupt` from the interrupt handler of the timer, and
+/// from the capture/compare handler as well for TIM1 and TIM8
+pub fn setup_input_capture(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    let (ccmr_capture_compare_mode_register, offset) =
+        get_ccmr_capture_compare_mode_register(timer, channel);
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let ccer_enable_field = get_ccer_enable_field(channel);
+    let dier_interrupt_register = get_dier_interrupt_register(timer);
+    let index = get_capture_index(timer, channel);
+
+    CAPTURE_CLOCKS[*timer as usize].store(clock_frequency, Ordering::Relaxed);
+    CAPTURE_PERIODS[index].store(0, Ordering::Relaxed);
+    CAPTURE_PULSE_WIDTHS[index].store(0, Ordering::Relaxed);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        // Count at the full timer clock over the whole counter range
+        write_register(get_psc_prescaler_register(timer), 0);
+        write_register(
+            get_arr_auto_reload_register(timer),
+            get_timer_max_count(timer),
+        );
+
+        // Map the channel input to its own pin, without filter or prescaler
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field);
+        write_bits(ccmr_capture_compare_mode_register, offset, 0b01, 0xFF);
+
+        // Capture on the rising edge first
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 1);
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 3);
+        set_bit(ccer_capture_compare_enable_register, ccer_enable_field);
+
+        // Load the pre-scaler, without leaving the update flag set
+        if get_bit(get_cr1_control_register(timer), tim2::cr1::CEN) == 0 {
+            set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+            ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+        }
+
+        // Enable the capture interrupt, and the update interrupt to count overflows
+        set_bit(dier_interrupt_register, tim2::dier::CC1IE + channel as u8);
+        set_bit(dier_interrupt_register, tim2::dier::UIE);
+    }
+
+    enable_interrupt(get_nvic_interrupt_id(timer));
+    enable_interrupt(get_nvic_capture_compare_interrupt_id(timer));
+
+    unsafe {
+        set_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Measure a signal like `setup_input_capture`, with the channel routed to `pin`. Returns
+/// `TimerError::InvalidPin` if the channel isn't available on the pin. The configured pin is
+/// returned so that it can be passed to `cleanup_input_capture`
+pub fn setup_input_capture_on_pin(
+    timer: &Timer,
+    channel: TimerChannel,
+    pin: PortPin,
+    clock_frequency: u32,
+) -> Result<Gpio, TimerError> {
+    let gpio = get_timer_channel_gpio(timer, channel, pin)?;
+    setup_input_capture(timer, channel, &gpio, clock_frequency)?;
+
+    Ok(gpio)
+}
+
+/// Stop capturing on a channel and release its pin. The timer is stopped when no channel is left
+/// enabled
+pub fn cleanup_input_capture(timer: &Timer, channel: TimerChannel, gpio: &Gpio) {
+    use registers::tim2;
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+
+    unsafe {
+        clear_bit(
+            get_dier_interrupt_register(timer),
+            tim2::dier::CC1IE + channel as u8,
+        );
+        clear_bit(
+            ccer_capture_compare_enable_register,
+            get_ccer_enable_field(channel),
+        );
+
+        if CHANNELS.iter().all(|channel| {
+            get_bit(
+                ccer_capture_compare_enable_register,
+                get_ccer_enable_field(*channel),
+            ) == 0
+        }) {
+            clear_bit(get_dier_interrupt_register(timer), tim2::dier::UIE);
+            clear_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+        }
+    }
+
+    gpio.cleanup();
+}
+
+/// Count overflows and record captured edges. This is the body of the timer interrupt handler
+/// when input capture is used
+pub fn handle_input_capture_interrupt(timer: &Timer) {
+    use registers::tim2;
+
+    let sr_status_register = get_sr_status_register(timer);
+    let dier_interrupt_register = get_dier_interrupt_register(timer);
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let overflows = &CAPTURE_OVERFLOWS[*timer as usize];
+    let range = get_timer_max_count(timer).wrapping_add(1);
+
+    // An overflow that happened after a capture in the same interrupt is counted after the
+    // capture has been handled, so the capture isn't placed a whole counter period late
+    let overflowed = unsafe { get_bit(sr_status_register, tim2::sr::UIF) } == 1;
+    if overflowed {
+        unsafe {
+            ClearByWrite0::new(sr_status_register).clear(tim2::sr::UIF);
+        }
+    }
+    let mut overflow_counted = false;
+
+    for channel in CHANNELS {
+        // Skip channels that aren't capturing, such as PWM outputs of the same timer
+        let capturing =
+            unsafe { get_bit(dier_interrupt_register, tim2::dier::CC1IE + channel as u8) } == 1;
+        let captured = unsafe { get_bit(sr_status_register, tim2::sr::CC1IF + channel as u8) } == 1;
+        if !capturing || !captured {
+            continue;
+        }
+
+        // Reading the capture register clears the capture flag
+        let captured = unsafe { read_register(get_ccr_capture_compare_register(timer, channel)) };
+
+        // A small captured value with a pending overflow means the capture came after it
+        if overflowed && !overflow_counted && captured < range.wrapping_sub(1) / 2 {
+            overflows.fetch_add(1, Ordering::Relaxed);
+            overflow_counted = true;
+        }
+
+        let time = overflows
+            .load(Ordering::Relaxed)
+            .wrapping_mul(range)
+            .wrapping_add(captured);
+
+        let index = get_capture_index(timer, channel);
+        let polarity_field = get_ccer_enable_field(channel) + 1;
+        let falling = unsafe { get_bit(ccer_capture_compare_enable_register, polarity_field) } == 1;
+
+        if falling {
+            let rising = CAPTURE_RISING_EDGES[index].load(Ordering::Relaxed);
+            CAPTURE_PULSE_WIDTHS[index].store(time.wrapping_sub(rising), Ordering::Relaxed);
+        } else {
+            let previous = CAPTURE_RISING_EDGES[index].swap(time, Ordering::Relaxed);
+            CAPTURE_PERIODS[index].store(time.wrapping_sub(previous), Ordering::Relaxed);
+        }
+
+        // Capture the opposite edge next
+        unsafe {
+            write_bits(
+                ccer_capture_compare_enable_register,
+                polarity_field,
+                !falling as u32,
+                0b1,
+            );
+        }
+    }
+
+    if overflowed && !overflow_counted {
+        overflows.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Frequency of the signal on a channel, from the last two rising edges. Returns `None` until two
+/// rising edges have been captured. The last measurement is kept if the signal stops, see
+/// `clear_input_capture`
+pub fn measure_frequency(timer: &Timer, channel: TimerChannel) -> Option<u32> {
+    let period = CAPTURE_PERIODS[get_capture_index(timer, channel)].load(Ordering::Relaxed);
+    let clock = CAPTURE_CLOCKS[*timer as usize].load(Ordering::Relaxed);
+
+    match period {
+        0 => None,
+        period => Some(clock / period),
+    }
+}
+
+/// Length of the last high pulse on a channel in nanoseconds. Returns `None` until a whole pulse
+/// has been captured
+pub fn measure_pulse_width_ns(timer: &Timer, channel: TimerChannel) -> Option<u64> {
+    let width = CAPTURE_PULSE_WIDTHS[get_capture_index(timer, channel)].load(Ordering::Relaxed);
+    let clock = CAPTURE_CLOCKS[*timer as usize].load(Ordering::Relaxed);
+
+    match (width, clock) {
+        (0, _) | (_, 0) => None,
+        (width, clock) => Some(width as u64 * 1_000_000_000 / clock as u64),
+    }
+}
+
+/// Forget the measurements of a channel, e.g. to detect that a tachometer signal has stopped
+pub fn clear_input_capture(timer: &Timer, channel: TimerChannel) {
+    let index = get_capture_index(timer, channel);
+    CAPTURE_PERIODS[index].store(0, Ordering::Relaxed);
+    CAPTURE_PULSE_WIDTHS[index].store(0, Ordering::Relaxed);
+}
+
+/// A period and high time measured in PWM input mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PwmInputMeasurement {
+    period_ticks: u32,
+    high_ticks: u32,
+    tick_frequency: u32,
+}
+
+impl PwmInputMeasurement {
+    pub fn period_ns(&self) -> u64 {
+        self.period_ticks as u64 * 1_000_000_000 / self.tick_frequency as u64
+    }
+
+    pub fn high_time_ns(&self) -> u64 {
+        self.high_ticks as u64 * 1_000_000_000 / self.tick_frequency as u64
+    }
+
+    pub fn frequency(&self) -> u32 {
+        self.tick_frequency / self.period_ticks
+    }
+
+    /// Share of the period the signal is high, from 0 to 100
+    pub fn duty_percent(&self) -> u8 {
+        (self.high_ticks.min(self.period_ticks) as u64 * 100 / self.period_ticks as u64) as u8
+    }
+}
+
+/// Measure the period and duty cycle of a PWM signal on channel 1 of a timer, without
+/// interrupts. Channel 1 captures the period on rising edges, which also reset the counter, and
+/// channel 2 captures the high time on falling edges of the same input. `gpio` is the pin of
+/// channel 1, and `min_frequency` the lowest frequency that has to be measured, which sets the
+/// counter resolution. The timer can't be used for anything else
+pub fn setup_pwm_input(
+    timer: &Timer,
+    gpio: &Gpio,
+    min_frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    if min_frequency == 0 || min_frequency > clock_frequency {
+        return Err(TimerError::InvalidFrequency(min_frequency));
+    }
+
+    // Use the smallest prescaler that fits the longest period in the counter
+    let max_ticks = (clock_frequency / min_frequency) as u64;
+    let prescaler = max_ticks / (get_timer_max_count(timer) as u64 + 1);
+    if prescaler > 0xFFFF {
+        return Err(TimerError::InvalidFrequency(min_frequency));
+    }
+
+    let (ccmr_capture_compare_mode_register, _) =
+        get_ccmr_capture_compare_mode_register(timer, TimerChannel::Ch1);
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let smcr_slave_mode_control_register = get_smcr_slave_mode_control_register(timer);
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    CAPTURE_CLOCKS[*timer as usize]
+        .store(clock_frequency / (prescaler as u32 + 1), Ordering::Relaxed);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+        write_register(ccer_capture_compare_enable_register, 0);
+
+        write_register(get_psc_prescaler_register(timer), prescaler as u32);
+        write_register(
+            get_arr_auto_reload_register(timer),
+            get_timer_max_count(timer),
+        );
+
+        // Map channel 1 to TI1 and channel 2 to TI1 as well, without filters or prescalers
+        write_bits(ccmr_capture_compare_mode_register, 0, 0b01, 0xFF);
+        write_bits(ccmr_capture_compare_mode_register, 8, 0b10, 0xFF);
+
+        // Capture rising edges on channel 1 and falling edges on channel 2
+        write_register(
+            ccer_capture_compare_enable_register,
+            1 << tim2::ccer::CC1E | 1 << tim2::ccer::CC2E | 1 << tim2::ccer::CC2P,
+        );
+
+        // Reset the counter on the filtered TI1 rising edge
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::TS,
+            0b101,
+            0b111,
+        );
+        clear_bit(smcr_slave_mode_control_register, tim2::smcr::TS_4_3);
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::SMS,
+            0b100,
+            0b111,
+        );
+        clear_bit(smcr_slave_mode_control_register, tim2::smcr::SMS_3);
+
+        // Only counter overflows set the update flag, so that it marks a lost signal
+        set_bit(cr1_control_register, tim2::cr1::URS);
+
+        // Load the pre-scaler and clear the flags. The capture registers are read-only in input
+        // mode, but they're only read once the first capture has set CC1IF
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        write_register(get_sr_status_register(timer), 0);
+
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Stop PWM input mode and release the pin
+pub fn cleanup_pwm_input(timer: &Timer, gpio: &Gpio) {
+    use registers::tim2;
+
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    unsafe {
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+        clear_bit(cr1_control_register, tim2::cr1::URS);
+        write_register(get_ccer_capture_compare_enable_register(timer), 0);
+        write_bits(
+            get_smcr_slave_mode_control_register(timer),
+            tim2::smcr::SMS,
+            0b000,
+            0b111,
+        );
+    }
+
+    gpio.cleanup();
+}
+
+/// Read the last measured period and high time. Returns `None` before the first whole period
+/// and when no rising edge has been seen for longer than the counter range, such as when the
+/// signal is stuck high or low
+pub fn measure_pwm_input(timer: &Timer) -> Option<PwmInputMeasurement> {
+    use registers::tim2;
+
+    let sr_status_register = get_sr_status_register(timer);
+
+    unsafe {
+        let new_period = get_bit(sr_status_register, tim2::sr::CC1IF) == 1;
+        let period_ticks =
+            read_register(get_ccr_capture_compare_register(timer, TimerChannel::Ch1));
+        let high_ticks = read_register(get_ccr_capture_compare_register(timer, TimerChannel::Ch2));
+
+        if new_period {
+            // A rising edge has been seen since the last overflow
+            ClearByWrite0::new(sr_status_register).clear(tim2::sr::UIF);
+        } else if get_bit(sr_status_register, tim2::sr::UIF) == 1 {
+            return None;
+        }
+
+        if period_ticks == 0 {
+            return None;
+        }
+
+        Some(PwmInputMeasurement {
+            period_ticks,
+            high_ticks,
+            tick_frequency: CAPTURE_CLOCKS[*timer as usize].load(Ordering::Relaxed),
+        })
+    }
+}
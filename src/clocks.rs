@@ -0,0 +1,1392 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    exti::{ExtiEdge, ExtiLine, clear_exti_line_pending, setup_exti_line},
+    interrupts::{Irq, enable_interrupt},
+    rcc::{self, CSI_FREQUENCY, Pll, PllFrequencies},
+    register_tools::{
+        clear_bit, get_bit, modify, read_bits, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    rtc::RtcClock,
+};
+
+/// Registered clock security system callback, stored as a function pointer. A zero means that no
+/// callback is registered
+static CSS_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of polls of a ready flag before an oscillator or PLL is considered to have failed
+const READY_TIMEOUT: u32 = 1_000_000;
+
+/// Voltage scale of the core domain, from the lowest to the highest voltage. A higher voltage
+/// allows faster clocks at a higher power consumption. Scale 0 is scale 1 with the overdrive of
+/// the SYSCFG enabled
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum VoltageScale {
+    Scale3,
+    Scale2,
+    Scale1,
+    Scale0,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PllSource {
+    Hsi,
+    Csi,
+    Hse,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SysclkSource {
+    Hsi,
+    Csi,
+    Hse,
+    /// The P output of PLL1
+    Pll1,
+}
+
+/// Dividers of a PLL. The reference clock is the PLL source divided by `m`, which has to be
+/// between 1 and 16 MHz, and the VCO runs at the reference clock times `n`. Each output divides
+/// the VCO by its divider and is disabled when the divider isn't set
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PllConfig {
+    /// 1 to 63
+    pub m: u8,
+    /// 4 to 512
+    pub n: u16,
+    /// 1 to 128. Only even dividers are allowed on PLL1
+    pub p: Option<u8>,
+    /// 1 to 128
+    pub q: Option<u8>,
+    /// 1 to 128
+    pub r: Option<u8>,
+}
+
+impl PllConfig {
+    pub const fn new(m: u8, n: u16) -> Self {
+        Self {
+            m,
+            n,
+            p: None,
+            q: None,
+            r: None,
+        }
+    }
+
+    pub const fn p(mut self, divider: u8) -> Self {
+        self.p = Some(divider);
+        self
+    }
+
+    pub const fn q(mut self, divider: u8) -> Self {
+        self.q = Some(divider);
+        self
+    }
+
+    pub const fn r(mut self, divider: u8) -> Self {
+        self.r = Some(divider);
+        self
+    }
+}
+
+/// Configuration of the clock tree, applied with `setup_clocks`. Defaults to running from the
+/// HSI without PLLs or prescalers, which is the state after reset
+///
+/// ```ignore
+/// // 480 MHz from an 8 MHz crystal, with the AHB at 240 MHz and the APB buses at 120 MHz
+/// let config = ClockConfig::new()
+///     .hse(8_000_000)
+///     .pll_source(PllSource::Hse)
+///     .pll1(PllConfig::new(1, 120).p(2).q(8))
+///     .sysclk(SysclkSource::Pll1)
+///     .hpre(2)
+///     .d1ppre(2)
+///     .d2ppre1(2)
+///     .d2ppre2(2)
+///     .d3ppre(2);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClockConfig {
+    /// Frequency of the HSE crystal or external clock. The HSE is left off if not set
+    pub hse: Option<u32>,
+    /// The HSE is driven by an external clock instead of a crystal
+    pub hse_bypass: bool,
+    pub pll_source: PllSource,
+    pub pll1: Option<PllConfig>,
+    pub pll2: Option<PllConfig>,
+    pub pll3: Option<PllConfig>,
+    pub sysclk: SysclkSource,
+    /// Divider from SYSCLK to the CPU clock. 1, 2, 4, 8, 16, 64, 128, 256 or 512
+    pub d1cpre: u16,
+    /// Divider from the CPU clock to the AHB buses. 1, 2, 4, 8, 16, 64, 128, 256 or 512
+    pub hpre: u16,
+    /// Divider from the AHB to APB3. 1, 2, 4, 8 or 16
+    pub d1ppre: u8,
+    /// Divider from the AHB to APB1. 1, 2, 4, 8 or 16
+    pub d2ppre1: u8,
+    /// Divider from the AHB to APB2. 1, 2, 4, 8 or 16
+    pub d2ppre2: u8,
+    /// Divider from the AHB to APB4. 1, 2, 4, 8 or 16
+    pub d3ppre: u8,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockConfig {
+    pub const fn new() -> Self {
+        Self {
+            hse: None,
+            hse_bypass: false,
+            pll_source: PllSource::Hsi,
+            pll1: None,
+            pll2: None,
+            pll3: None,
+            sysclk: SysclkSource::Hsi,
+            d1cpre: 1,
+            hpre: 1,
+            d1ppre: 1,
+            d2ppre1: 1,
+            d2ppre2: 1,
+            d3ppre: 1,
+        }
+    }
+
+    /// Run the HSE from a crystal of `frequency`
+    pub const fn hse(mut self, frequency: u32) -> Self {
+        self.hse = Some(frequency);
+        self.hse_bypass = false;
+        self
+    }
+
+    /// Run the HSE from an external clock of `frequency`
+    pub const fn hse_bypass(mut self, frequency: u32) -> Self {
+        self.hse = Some(frequency);
+        self.hse_bypass = true;
+        self
+    }
+
+    /// Source of all three PLLs, which share it
+    pub const fn pll_source(mut self, source: PllSource) -> Self {
+        self.pll_source = source;
+        self
+    }
+
+    pub const fn pll1(mut self, pll: PllConfig) -> Self {
+        self.pll1 = Some(pll);
+        self
+    }
+
+    pub const fn pll2(mut self, pll: PllConfig) -> Self {
+        self.pll2 = Some(pll);
+        self
+    }
+
+    pub const fn pll3(mut self, pll: PllConfig) -> Self {
+        self.pll3 = Some(pll);
+        self
+    }
+
+    pub const fn sysclk(mut self, source: SysclkSource) -> Self {
+        self.sysclk = source;
+        self
+    }
+
+    pub const fn d1cpre(mut self, divider: u16) -> Self {
+        self.d1cpre = divider;
+        self
+    }
+
+    pub const fn hpre(mut self, divider: u16) -> Self {
+        self.hpre = divider;
+        self
+    }
+
+    pub const fn d1ppre(mut self, divider: u8) -> Self {
+        self.d1ppre = divider;
+        self
+    }
+
+    pub const fn d2ppre1(mut self, divider: u8) -> Self {
+        self.d2ppre1 = divider;
+        self
+    }
+
+    pub const fn d2ppre2(mut self, divider: u8) -> Self {
+        self.d2ppre2 = divider;
+        self
+    }
+
+    pub const fn d3ppre(mut self, divider: u8) -> Self {
+        self.d3ppre = divider;
+        self
+    }
+}
+
+/// Kernel clock selections of the RCC. Each selection is shared by a group of peripherals, such
+/// as `Usart234578` for USART2/3, UART4/5 and UART7/8
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelClock {
+    /// The per_ck clock, which can in turn drive other kernel clocks
+    Per,
+    Fmc,
+    Qspi,
+    Sdmmc,
+    Spi123,
+    Spi45,
+    Fdcan,
+    Usart16,
+    Usart234578,
+    I2c123,
+    Lpuart1,
+    I2c4,
+    Spi6,
+    Adc,
+    /// The USB OTG kernel clock, which is disabled when no source is selected
+    Usb,
+}
+
+/// Sources of the kernel clocks. `Pclk` is the APB clock of the peripheral group, and `Hclk` the
+/// AHB clock. Each kernel clock supports a subset of the sources, see `set_kernel_clock_source`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelClockSource {
+    Pclk,
+    Hclk,
+    Pll1Q,
+    Pll2P,
+    Pll2Q,
+    Pll2R,
+    Pll3P,
+    Pll3Q,
+    Pll3R,
+    Hsi,
+    Csi,
+    Hse,
+    Lse,
+    Hsi48,
+    Per,
+    /// The external I2S_CKIN pin
+    I2sCkin,
+}
+
+/// Drive strength of the LSE oscillator. A higher drive starts crystals with a higher load
+/// capacitance more reliably, at a higher power consumption
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LseDrive {
+    Low = 0b00,
+    MediumLow = 0b10,
+    MediumHigh = 0b01,
+    High = 0b11,
+}
+
+/// Oscillator failures detected by the clock security system
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CssEvent {
+    HseFailure,
+    LseFailure,
+}
+
+/// Frequencies of the clock tree, to be passed on to the drivers that need a clock frequency
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Clocks {
+    pub sysclk: u32,
+    /// Clock of the CPU and SysTick
+    pub cpu: u32,
+    /// Clock of the AHB buses
+    pub hclk: u32,
+    /// APB1, which clocks USART2/3, UART4/5/7/8 and TIM2 to TIM7
+    pub pclk1: u32,
+    /// APB2, which clocks USART1/6 and TIM1/8
+    pub pclk2: u32,
+    /// APB3, which clocks the LTDC and WWDG1
+    pub pclk3: u32,
+    /// APB4, which clocks LPUART1 and the SYSCFG
+    pub pclk4: u32,
+    /// Kernel clock of the timers on APB1
+    pub timer1: u32,
+    /// Kernel clock of the timers on APB2
+    pub timer2: u32,
+    pub pll1: Option<PllFrequencies>,
+    pub pll2: Option<PllFrequencies>,
+    pub pll3: Option<PllFrequencies>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockError {
+    /// The HSE is used but no HSE frequency is configured
+    HseNotConfigured,
+    /// The HSE didn't become ready, such as when no crystal is fitted
+    HseNotReady,
+    /// A divider of the PLL is out of range, or its reference or VCO frequency is
+    InvalidPllConfig(Pll),
+    /// PLL1 is selected as SYSCLK but isn't configured with a P output
+    PllNotConfigured,
+    /// The PLL didn't lock
+    PllNotReady(Pll),
+    /// A prescaler isn't one of the supported dividers
+    InvalidPrescaler,
+    /// The configuration results in a clock above its maximum frequency in voltage scale 0
+    FrequencyTooHigh,
+    /// The kernel clock can't be driven by the source
+    UnsupportedKernelClockSource(KernelClock, KernelClockSource),
+    /// The backup domain already runs the RTC from another clock, which can only be changed by
+    /// resetting the backup domain
+    RtcClockInUse,
+    /// The LSE isn't running
+    LseNotReady,
+}
+
+/// Configure the oscillators, PLLs and bus prescalers and switch SYSCLK to the configured source.
+/// The configuration is checked before anything is changed. The system runs from the HSI while
+/// the PLLs are reconfigured, so this can be called again to change an earlier configuration
+///
+/// The lowest voltage scale that supports the new clocks is selected, and the flash wait states
+/// are set for the new AHB frequency. The voltage and wait states are raised before the clocks
+/// are changed and lowered after, so the core and flash keep up with the clocks throughout
+pub fn setup_clocks(config: &ClockConfig) -> Result<Clocks, ClockError> {
+    use registers::{
+        flash::{ACR, acr},
+        rcc::{
+            CFGR, CR, D1CFGR, D2CFGR, D3CFGR, PLLCKSELR, cfgr, cr, d1cfgr, d2cfgr, d3cfgr,
+            pllckselr,
+        },
+    };
+
+    let (scale, hclk) = check_clock_config(config)?;
+    let current_scale = get_voltage_scale();
+
+    let d1cpre = get_ahb_prescaler(config.d1cpre)?;
+    let hpre = get_ahb_prescaler(config.hpre)?;
+    let d1ppre = get_apb_prescaler(config.d1ppre)?;
+    let d2ppre1 = get_apb_prescaler(config.d2ppre1)?;
+    let d2ppre2 = get_apb_prescaler(config.d2ppre2)?;
+    let d3ppre = get_apb_prescaler(config.d3ppre)?;
+
+    if scale > current_scale {
+        set_voltage_scale(scale);
+    }
+
+    unsafe {
+        // Use the most wait states while the clocks are changed, which is enough for any clock
+        write_bits(ACR, acr::LATENCY, 0b111, 0b1111);
+        while read_bits(ACR, acr::LATENCY, 0b1111) != 0b111 {}
+
+        // Run from the HSI while the other clocks are changed
+        set_bit(CR, cr::HSION);
+        while get_bit(CR, cr::HSIRDY) == 0 {}
+
+        write_bits(CFGR, cfgr::SW, 0b000, 0b111);
+        while read_bits(CFGR, cfgr::SWS, 0b111) != 0b000 {}
+
+        if let Some(frequency) = config.hse {
+            rcc::set_hse_frequency(frequency);
+
+            // The bypass can only be changed while the HSE is off
+            clear_bit(CR, cr::HSEON);
+            while get_bit(CR, cr::HSERDY) == 1 {}
+
+            write_bits(CR, cr::HSEBYP, config.hse_bypass as u32, 0b1);
+            set_bit(CR, cr::HSEON);
+            if !wait_for_ready(CR, cr::HSERDY) {
+                clear_bit(CR, cr::HSEON);
+                return Err(ClockError::HseNotReady);
+            }
+        }
+
+        if config.pll_source == PllSource::Csi || config.sysclk == SysclkSource::Csi {
+            set_bit(CR, cr::CSION);
+            while get_bit(CR, cr::CSIRDY) == 0 {}
+        }
+
+        // The PLL source and dividers can only be changed while all PLLs are off
+        for pll in [Pll::Pll1, Pll::Pll2, Pll::Pll3] {
+            let (on, ready) = get_pll_cr_fields(pll);
+            clear_bit(CR, on);
+            while get_bit(CR, ready) == 1 {}
+        }
+
+        let source = match config.pll_source {
+            PllSource::Hsi => 0b00,
+            PllSource::Csi => 0b01,
+            PllSource::Hse => 0b10,
+        };
+        write_bits(PLLCKSELR, pllckselr::PLLSRC, source, 0b11);
+
+        for (pll, pll_config) in [
+            (Pll::Pll1, config.pll1),
+            (Pll::Pll2, config.pll2),
+            (Pll::Pll3, config.pll3),
+        ] {
+            if let Some(pll_config) = pll_config {
+                setup_pll(pll, &pll_config, get_pll_source_frequency(config)?)?;
+            }
+        }
+
+        // See section 8.7.7 RCC domain 1 clock configuration register (RCC_D1CFGR)
+        modify(D1CFGR, |value| {
+            (value & !(0b1111 << d1cfgr::D1CPRE | 0b111 << d1cfgr::D1PPRE | 0b1111 << d1cfgr::HPRE))
+                | d1cpre << d1cfgr::D1CPRE
+                | d1ppre << d1cfgr::D1PPRE
+                | hpre << d1cfgr::HPRE
+        });
+        modify(D2CFGR, |value| {
+            (value & !(0b111 << d2cfgr::D2PPRE1 | 0b111 << d2cfgr::D2PPRE2))
+                | d2ppre1 << d2cfgr::D2PPRE1
+                | d2ppre2 << d2cfgr::D2PPRE2
+        });
+        write_bits(D3CFGR, d3cfgr::D3PPRE, d3ppre, 0b111);
+
+        let sysclk = match config.sysclk {
+            SysclkSource::Hsi => 0b000,
+            SysclkSource::Csi => 0b001,
+            SysclkSource::Hse => 0b010,
+            SysclkSource::Pll1 => 0b011,
+        };
+        write_bits(CFGR, cfgr::SW, sysclk, 0b111);
+        while read_bits(CFGR, cfgr::SWS, 0b111) != sysclk {}
+
+        // See section 4.3.8 FLASH read operations and table 17 FLASH recommended number of wait
+        // states and programming delay
+        let (latency, programming_delay) = get_flash_latency(hclk, scale);
+        modify(ACR, |value| {
+            (value & !(0b1111 << acr::LATENCY | 0b11 << acr::WRHIGHFREQ))
+                | latency << acr::LATENCY
+                | programming_delay << acr::WRHIGHFREQ
+        });
+        while read_bits(ACR, acr::LATENCY, 0b1111) != latency {}
+    }
+
+    if scale < current_scale {
+        set_voltage_scale(scale);
+    }
+
+    get_clocks().ok_or(ClockError::HseNotConfigured)
+}
+
+/// The current frequencies of the clock tree, read back from the RCC. Returns `None` if a clock
+/// is derived from the HSE and its frequency isn't known
+pub fn get_clocks() -> Option<Clocks> {
+    Some(Clocks {
+        sysclk: rcc::get_sysclk_frequency()?,
+        cpu: rcc::get_cpu_frequency()?,
+        hclk: rcc::get_hclk_frequency()?,
+        pclk1: rcc::get_pclk1_frequency()?,
+        pclk2: rcc::get_pclk2_frequency()?,
+        pclk3: rcc::get_pclk3_frequency()?,
+        pclk4: rcc::get_pclk4_frequency()?,
+        timer1: rcc::get_apb1_timer_frequency()?,
+        timer2: rcc::get_apb2_timer_frequency()?,
+        pll1: rcc::get_pll_frequencies(Pll::Pll1),
+        pll2: rcc::get_pll_frequencies(Pll::Pll2),
+        pll3: rcc::get_pll_frequencies(Pll::Pll3),
+    })
+}
+
+/// Change the voltage scale of the core domain and wait for the voltage to settle. The clocks
+/// have to be within the limits of the new scale, which `setup_clocks` takes care of. See
+/// section 6.6.2 Voltage scaling
+pub fn set_voltage_scale(scale: VoltageScale) {
+    use registers::{
+        pwr::{D3CR, d3cr},
+        rcc::{APB4ENR, apb4enr},
+        syscfg::{PWRCR, pwrcr},
+    };
+
+    let vos = match scale {
+        VoltageScale::Scale0 | VoltageScale::Scale1 => 0b11,
+        VoltageScale::Scale2 => 0b10,
+        VoltageScale::Scale3 => 0b01,
+    };
+
+    unsafe {
+        // Enable the syscfg clock, which holds the overdrive
+        set_bit(APB4ENR, apb4enr::SYSCFGEN);
+
+        // The overdrive has to be disabled before leaving scale 0
+        if scale != VoltageScale::Scale0 && get_bit(PWRCR, pwrcr::ODEN) == 1 {
+            clear_bit(PWRCR, pwrcr::ODEN);
+            while get_bit(D3CR, d3cr::VOSRDY) == 0 {}
+        }
+
+        write_bits(D3CR, d3cr::VOS, vos, 0b11);
+        while get_bit(D3CR, d3cr::VOSRDY) == 0 {}
+
+        // Scale 0 is reached from scale 1 by enabling the overdrive
+        if scale == VoltageScale::Scale0 {
+            set_bit(PWRCR, pwrcr::ODEN);
+            while get_bit(D3CR, d3cr::VOSRDY) == 0 {}
+        }
+    }
+}
+
+pub fn get_voltage_scale() -> VoltageScale {
+    use registers::{
+        pwr::{D3CR, d3cr},
+        syscfg::{PWRCR, pwrcr},
+    };
+
+    unsafe {
+        match read_bits(D3CR, d3cr::VOS, 0b11) {
+            0b11 if get_bit(PWRCR, pwrcr::ODEN) == 1 => VoltageScale::Scale0,
+            0b11 => VoltageScale::Scale1,
+            0b10 => VoltageScale::Scale2,
+            _ => VoltageScale::Scale3,
+        }
+    }
+}
+
+/// Highest SYSCLK, AHB and APB frequencies of a voltage scale
+const fn get_max_frequencies(scale: VoltageScale) -> (u32, u32, u32) {
+    match scale {
+        VoltageScale::Scale0 => (480_000_000, 240_000_000, 120_000_000),
+        VoltageScale::Scale1 => (400_000_000, 200_000_000, 100_000_000),
+        VoltageScale::Scale2 => (300_000_000, 150_000_000, 75_000_000),
+        VoltageScale::Scale3 => (200_000_000, 100_000_000, 50_000_000),
+    }
+}
+
+/// Flash wait states and programming delay for an AHB frequency, from table 17 FLASH recommended
+/// number of wait states and programming delay
+fn get_flash_latency(hclk: u32, scale: VoltageScale) -> (u32, u32) {
+    const MHZ: u32 = 1_000_000;
+
+    // Highest AHB frequency of each number of wait states, with its programming delay
+    let table: &[(u32, u32, u32)] = match scale {
+        VoltageScale::Scale0 | VoltageScale::Scale1 => &[
+            (70 * MHZ, 0, 0b00),
+            (140 * MHZ, 1, 0b01),
+            (185 * MHZ, 2, 0b01),
+            (210 * MHZ, 2, 0b10),
+            (225 * MHZ, 3, 0b10),
+            (240 * MHZ, 4, 0b11),
+        ],
+        VoltageScale::Scale2 => &[
+            (55 * MHZ, 0, 0b00),
+            (110 * MHZ, 1, 0b01),
+            (165 * MHZ, 2, 0b01),
+            (225 * MHZ, 3, 0b10),
+        ],
+        VoltageScale::Scale3 => &[
+            (45 * MHZ, 0, 0b00),
+            (90 * MHZ, 1, 0b01),
+            (135 * MHZ, 2, 0b01),
+            (180 * MHZ, 3, 0b10),
+            (225 * MHZ, 4, 0b10),
+        ],
+    };
+
+    table
+        .iter()
+        .find(|(max, _, _)| hclk <= *max)
+        .map(|&(_, latency, programming_delay)| (latency, programming_delay))
+        .unwrap_or((0b111, 0b11))
+}
+
+/// Check the dividers of the configuration and the frequencies they result in. Returns the
+/// lowest voltage scale that supports the clocks, together with the AHB frequency
+fn check_clock_config(config: &ClockConfig) -> Result<(VoltageScale, u32), ClockError> {
+    let pll1 = match config.pll1 {
+        Some(pll) => Some(get_pll_output(
+            Pll::Pll1,
+            &pll,
+            get_pll_source_frequency(config)?,
+        )?),
+        None => None,
+    };
+    if let Some(pll) = config.pll2 {
+        get_pll_output(Pll::Pll2, &pll, get_pll_source_frequency(config)?)?;
+    }
+    if let Some(pll) = config.pll3 {
+        get_pll_output(Pll::Pll3, &pll, get_pll_source_frequency(config)?)?;
+    }
+
+    let sysclk = match config.sysclk {
+        SysclkSource::Hsi => rcc::get_hsi_frequency(),
+        SysclkSource::Csi => CSI_FREQUENCY,
+        SysclkSource::Hse => config.hse.ok_or(ClockError::HseNotConfigured)?,
+        SysclkSource::Pll1 => pll1
+            .and_then(|pll| pll.p)
+            .ok_or(ClockError::PllNotConfigured)?,
+    };
+
+    get_ahb_prescaler(config.d1cpre)?;
+    get_ahb_prescaler(config.hpre)?;
+    let cpu = sysclk / config.d1cpre as u32;
+    let hclk = cpu / config.hpre as u32;
+
+    let pclk_dividers = [config.d1ppre, config.d2ppre1, config.d2ppre2, config.d3ppre];
+    for divider in pclk_dividers {
+        get_apb_prescaler(divider)?;
+    }
+
+    let max_pclk = pclk_dividers
+        .iter()
+        .map(|&divider| hclk / divider as u32)
+        .max()
+        .unwrap_or(hclk);
+
+    let scale = [
+        VoltageScale::Scale3,
+        VoltageScale::Scale2,
+        VoltageScale::Scale1,
+        VoltageScale::Scale0,
+    ]
+    .into_iter()
+    .find(|&scale| {
+        let (max_sysclk, max_hclk, max_pclk_frequency) = get_max_frequencies(scale);
+        sysclk <= max_sysclk && hclk <= max_hclk && max_pclk <= max_pclk_frequency
+    })
+    .ok_or(ClockError::FrequencyTooHigh)?;
+
+    Ok((scale, hclk))
+}
+
+fn get_pll_source_frequency(config: &ClockConfig) -> Result<u32, ClockError> {
+    match config.pll_source {
+        PllSource::Hsi => Ok(rcc::get_hsi_frequency()),
+        PllSource::Csi => Ok(CSI_FREQUENCY),
+        PllSource::Hse => config.hse.ok_or(ClockError::HseNotConfigured),
+    }
+}
+
+/// Output frequencies of a PLL configuration. The reference clock has to be between 1 and 16
+/// MHz, and the VCO between 150 and 420 MHz for a reference below 2 MHz or between 192 and 960
+/// MHz otherwise. See section 8.5.5 PLL description
+fn get_pll_output(pll: Pll, config: &PllConfig, source: u32) -> Result<PllFrequencies, ClockError> {
+    let invalid = ClockError::InvalidPllConfig(pll);
+
+    if !(1..=63).contains(&config.m) || !(4..=512).contains(&config.n) {
+        return Err(invalid);
+    }
+
+    let reference = source / config.m as u32;
+    let vco = reference as u64 * config.n as u64;
+    let vco_range = if reference < 2_000_000 {
+        150_000_000..=420_000_000
+    } else {
+        192_000_000..=960_000_000
+    };
+    if !(1_000_000..=16_000_000).contains(&reference) || !vco_range.contains(&vco) {
+        return Err(invalid);
+    }
+
+    // PLL1 P only divides by even values
+    if pll == Pll::Pll1 && config.p.is_some_and(|divider| divider % 2 != 0) {
+        return Err(invalid);
+    }
+
+    let output = |divider: Option<u8>| match divider {
+        Some(divider @ 1..=128) => Ok(Some((vco / divider as u64) as u32)),
+        Some(_) => Err(invalid),
+        None => Ok(None),
+    };
+
+    Ok(PllFrequencies {
+        p: output(config.p)?,
+        q: output(config.q)?,
+        r: output(config.r)?,
+    })
+}
+
+/// Write the dividers of a PLL, which has to be off, then enable it and wait for it to lock
+unsafe fn setup_pll(pll: Pll, config: &PllConfig, source: u32) -> Result<(), ClockError> {
+    use registers::rcc::{
+        CR, PLL1DIVR, PLL2DIVR, PLL3DIVR, PLLCFGR, PLLCKSELR, pll1divr, pllcfgr, pllckselr,
+    };
+
+    let (index, divr) = match pll {
+        Pll::Pll1 => (0, PLL1DIVR),
+        Pll::Pll2 => (1, PLL2DIVR),
+        Pll::Pll3 => (2, PLL3DIVR),
+    };
+
+    let reference = source / config.m as u32;
+    let range = match reference {
+        0..2_000_000 => 0b00,
+        2_000_000..4_000_000 => 0b01,
+        4_000_000..8_000_000 => 0b10,
+        _ => 0b11,
+    };
+    // The medium VCO range is needed for a reference clock below 2 MHz
+    let medium_vco = (reference < 2_000_000) as u32;
+
+    unsafe {
+        write_bits(
+            PLLCKSELR,
+            pllckselr::DIVM1 + index * 8,
+            config.m as u32,
+            0b11_1111,
+        );
+
+        // See section 8.7.11 RCC PLLs configuration register (RCC_PLLCFGR)
+        let fracen = pllcfgr::PLL1FRACEN + index * 4;
+        let vcosel = pllcfgr::PLL1VCOSEL + index * 4;
+        let rge = pllcfgr::PLL1RGE + index * 4;
+        let enable = pllcfgr::DIVP1EN + index * 3;
+        modify(PLLCFGR, |value| {
+            (value & !(1 << fracen | 1 << vcosel | 0b11 << rge | 0b111 << enable))
+                | medium_vco << vcosel
+                | range << rge
+                | (config.p.is_some() as u32) << enable
+                | (config.q.is_some() as u32) << (enable + 1)
+                | (config.r.is_some() as u32) << (enable + 2)
+        });
+
+        // The dividers are written as the division factor minus one
+        let divider = |divider: Option<u8>| divider.unwrap_or(1) as u32 - 1;
+        modify(divr, |value| {
+            (value
+                & !(0x1FF << pll1divr::DIVN1
+                    | 0x7F << pll1divr::DIVP1
+                    | 0x7F << pll1divr::DIVQ1
+                    | 0x7F << pll1divr::DIVR1))
+                | (config.n as u32 - 1) << pll1divr::DIVN1
+                | divider(config.p) << pll1divr::DIVP1
+                | divider(config.q) << pll1divr::DIVQ1
+                | divider(config.r) << pll1divr::DIVR1
+        });
+
+        let (on, ready) = get_pll_cr_fields(pll);
+        set_bit(CR, on);
+        if !wait_for_ready(CR, ready) {
+            clear_bit(CR, on);
+            return Err(ClockError::PllNotReady(pll));
+        }
+    }
+
+    Ok(())
+}
+
+/// The enable and ready fields of a PLL in the RCC_CR register
+fn get_pll_cr_fields(pll: Pll) -> (u8, u8) {
+    use registers::rcc::cr;
+
+    match pll {
+        Pll::Pll1 => (cr::PLL1ON, cr::PLL1RDY),
+        Pll::Pll2 => (cr::PLL2ON, cr::PLL2RDY),
+        Pll::Pll3 => (cr::PLL3ON, cr::PLL3RDY),
+    }
+}
+
+/// Poll a ready flag until it is set. Returns false if it isn't set within `READY_TIMEOUT` polls
+unsafe fn wait_for_ready(register: *const u32, field: u8) -> bool {
+    (0..READY_TIMEOUT).any(|_| unsafe { get_bit(register, field) } == 1)
+}
+
+/// Value of the D1CPRE and HPRE prescalers for a divider, see `rcc::get_ahb_divider`
+fn get_ahb_prescaler(divider: u16) -> Result<u32, ClockError> {
+    match divider {
+        1 => Ok(0b0000),
+        2 => Ok(0b1000),
+        4 => Ok(0b1001),
+        8 => Ok(0b1010),
+        16 => Ok(0b1011),
+        64 => Ok(0b1100),
+        128 => Ok(0b1101),
+        256 => Ok(0b1110),
+        512 => Ok(0b1111),
+        _ => Err(ClockError::InvalidPrescaler),
+    }
+}
+
+/// Value of the APB prescalers for a divider, see `rcc::get_apb_divider`
+fn get_apb_prescaler(divider: u8) -> Result<u32, ClockError> {
+    match divider {
+        1 => Ok(0b000),
+        2 => Ok(0b100),
+        4 => Ok(0b101),
+        8 => Ok(0b110),
+        16 => Ok(0b111),
+        _ => Err(ClockError::InvalidPrescaler),
+    }
+}
+
+/// Select the source of a kernel clock, which changes it for every peripheral in its group.
+/// Should be done before the peripherals are setup
+pub fn set_kernel_clock_source(
+    clock: KernelClock,
+    source: KernelClockSource,
+) -> Result<(), ClockError> {
+    use registers::rcc::{CR, cr};
+
+    let (register, field, mask, sources) = get_kernel_clock_selection(clock);
+    let (value, _) = sources
+        .iter()
+        .find(|(_, candidate)| *candidate == source)
+        .ok_or(ClockError::UnsupportedKernelClockSource(clock, source))?;
+
+    unsafe {
+        // Keep the HSI or CSI kernel clock running, also in Stop mode
+        match source {
+            KernelClockSource::Hsi => set_bit(CR, cr::HSIKERON),
+            KernelClockSource::Csi => set_bit(CR, cr::CSIKERON),
+            _ => {}
+        }
+
+        write_bits(register, field, *value, mask);
+    }
+
+    Ok(())
+}
+
+/// The selected source of a kernel clock, or `None` if the selection is reserved
+pub fn get_kernel_clock_source(clock: KernelClock) -> Option<KernelClockSource> {
+    let (register, field, mask, sources) = get_kernel_clock_selection(clock);
+    let value = unsafe { read_bits(register, field, mask) };
+
+    sources
+        .iter()
+        .find(|(candidate, _)| *candidate == value)
+        .map(|&(_, source)| source)
+}
+
+/// Frequency of a kernel clock, read from its selected source. Returns `None` if the source is
+/// off, is the external I2S_CKIN pin, or is derived from the HSE and `rcc::set_hse_frequency`
+/// hasn't been called
+pub fn get_kernel_clock_frequency(clock: KernelClock) -> Option<u32> {
+    use registers::rcc::{CR, cr};
+
+    let pll = |pll: Pll| rcc::get_pll_frequencies(pll);
+    let ready = |flag: u8| unsafe { get_bit(CR, flag) == 1 };
+
+    match get_kernel_clock_source(clock)? {
+        KernelClockSource::Pclk => match clock {
+            KernelClock::Spi45 | KernelClock::Usart16 => rcc::get_pclk2_frequency(),
+            KernelClock::Lpuart1 | KernelClock::I2c4 | KernelClock::Spi6 => {
+                rcc::get_pclk4_frequency()
+            }
+            _ => rcc::get_pclk1_frequency(),
+        },
+        KernelClockSource::Hclk => rcc::get_hclk_frequency(),
+        KernelClockSource::Pll1Q => pll(Pll::Pll1)?.q,
+        KernelClockSource::Pll2P => pll(Pll::Pll2)?.p,
+        KernelClockSource::Pll2Q => pll(Pll::Pll2)?.q,
+        KernelClockSource::Pll2R => pll(Pll::Pll2)?.r,
+        KernelClockSource::Pll3P => pll(Pll::Pll3)?.p,
+        KernelClockSource::Pll3Q => pll(Pll::Pll3)?.q,
+        KernelClockSource::Pll3R => pll(Pll::Pll3)?.r,
+        KernelClockSource::Hsi => ready(cr::HSIRDY).then(rcc::get_hsi_frequency),
+        KernelClockSource::Csi => ready(cr::CSIRDY).then_some(CSI_FREQUENCY),
+        KernelClockSource::Hse => rcc::get_hse_frequency().filter(|_| ready(cr::HSERDY)),
+        KernelClockSource::Lse => is_lse_ready().then_some(rcc::LSE_FREQUENCY),
+        KernelClockSource::Hsi48 => ready(cr::RC48RDY).then_some(rcc::HSI48_FREQUENCY),
+        KernelClockSource::Per => get_kernel_clock_frequency(KernelClock::Per),
+        KernelClockSource::I2sCkin => None,
+    }
+}
+
+/// Selection register, field and mask of a kernel clock, with the value of each supported
+/// source. See sections 8.7.19 to 8.7.22 of the RCC kernel clock configuration registers
+fn get_kernel_clock_selection(
+    clock: KernelClock,
+) -> (*mut u32, u8, u32, &'static [(u32, KernelClockSource)]) {
+    use KernelClockSource::*;
+    use registers::rcc::{
+        D1CCIPR, D2CCIP1R, D2CCIP2R, D3CCIPR, d1ccipr, d2ccip1r, d2ccip2r, d3ccipr,
+    };
+
+    const USART: &[(u32, KernelClockSource)] = &[
+        (0b000, Pclk),
+        (0b001, Pll2Q),
+        (0b010, Pll3Q),
+        (0b011, Hsi),
+        (0b100, Csi),
+        (0b101, Lse),
+    ];
+    const SPI: &[(u32, KernelClockSource)] = &[
+        (0b000, Pclk),
+        (0b001, Pll2Q),
+        (0b010, Pll3Q),
+        (0b011, Hsi),
+        (0b100, Csi),
+        (0b101, Hse),
+    ];
+    const I2C: &[(u32, KernelClockSource)] =
+        &[(0b00, Pclk), (0b01, Pll3R), (0b10, Hsi), (0b11, Csi)];
+    const MEMORY: &[(u32, KernelClockSource)] =
+        &[(0b00, Hclk), (0b01, Pll1Q), (0b10, Pll2R), (0b11, Per)];
+
+    match clock {
+        KernelClock::Per => (
+            D1CCIPR,
+            d1ccipr::CKPERSRC,
+            0b11,
+            &[(0b00, Hsi), (0b01, Csi), (0b10, Hse)],
+        ),
+        KernelClock::Fmc => (D1CCIPR, d1ccipr::FMCSRC, 0b11, MEMORY),
+        KernelClock::Qspi => (D1CCIPR, d1ccipr::QSPISRC, 0b11, MEMORY),
+        KernelClock::Sdmmc => (D1CCIPR, d1ccipr::SDMMCSRC, 0b1, &[(0, Pll1Q), (1, Pll2R)]),
+        KernelClock::Spi123 => (
+            D2CCIP1R,
+            d2ccip1r::SPI123SRC,
+            0b111,
+            &[
+                (0b000, Pll1Q),
+                (0b001, Pll2P),
+                (0b010, Pll3P),
+                (0b011, I2sCkin),
+                (0b100, Per),
+            ],
+        ),
+        KernelClock::Spi45 => (D2CCIP1R, d2ccip1r::SPI45SRC, 0b111, SPI),
+        KernelClock::Fdcan => (
+            D2CCIP1R,
+            d2ccip1r::FDCANSRC,
+            0b11,
+            &[(0b00, Hse), (0b01, Pll1Q), (0b10, Pll2Q)],
+        ),
+        KernelClock::Usart16 => (D2CCIP2R, d2ccip2r::USART16SRC, 0b111, USART),
+        KernelClock::Usart234578 => (D2CCIP2R, d2ccip2r::USART234578SRC, 0b111, USART),
+        KernelClock::I2c123 => (D2CCIP2R, d2ccip2r::I2C123SRC, 0b11, I2C),
+        KernelClock::Lpuart1 => (D3CCIPR, d3ccipr::LPUART1SRC, 0b111, USART),
+        KernelClock::I2c4 => (D3CCIPR, d3ccipr::I2C4SRC, 0b11, I2C),
+        KernelClock::Spi6 => (D3CCIPR, d3ccipr::SPI6SRC, 0b111, SPI),
+        KernelClock::Adc => (
+            D3CCIPR,
+            d3ccipr::ADCSRC,
+            0b11,
+            &[(0b00, Pll2P), (0b01, Pll3R), (0b10, Per)],
+        ),
+        KernelClock::Usb => (
+            D2CCIP2R,
+            d2ccip2r::USBSRC,
+            0b11,
+            &[(0b01, Pll1Q), (0b10, Pll3Q), (0b11, Hsi48)],
+        ),
+    }
+}
+
+/// Allow writes to the backup domain, which holds RCC_BDCR and the RTC registers
+pub fn enable_backup_domain_access() {
+    use registers::pwr::{CR1, cr1};
+
+    unsafe {
+        set_bit(CR1, cr1::DBP);
+        while get_bit(CR1, cr1::DBP) == 0 {}
+    }
+}
+
+/// Protect the backup domain against stray writes again
+pub fn disable_backup_domain_access() {
+    use registers::pwr::{CR1, cr1};
+
+    unsafe {
+        clear_bit(CR1, cr1::DBP);
+    }
+}
+
+/// Reset the backup domain, which stops the LSE and the RTC, clears the RTC clock selection and
+/// erases the RTC backup registers
+pub fn reset_backup_domain() {
+    use registers::rcc::{BDCR, bdcr};
+
+    enable_backup_domain_access();
+
+    unsafe {
+        set_bit(BDCR, bdcr::VSWRST);
+        clear_bit(BDCR, bdcr::VSWRST);
+    }
+}
+
+/// Start the LSE and wait for it to stabilize, which can take up to a couple of seconds. With
+/// `bypass` the LSE is driven by an external clock on OSC32_IN instead of a crystal. The LSE is in
+/// the backup domain and keeps running through resets, so if it's running already it's left
+/// untouched, as the drive and bypass can only be changed while it's off
+pub fn enable_lse(drive: LseDrive, bypass: bool) {
+    use registers::rcc::{BDCR, bdcr};
+
+    enable_backup_domain_access();
+
+    unsafe {
+        if get_bit(BDCR, bdcr::LSEON) == 1 {
+            while get_bit(BDCR, bdcr::LSERDY) == 0 {}
+            return;
+        }
+
+        modify(BDCR, |value| {
+            (value & !(1 << bdcr::LSEBYP | 0b11 << bdcr::LSEDRV))
+                | (bypass as u32) << bdcr::LSEBYP
+                | (drive as u32) << bdcr::LSEDRV
+        });
+
+        set_bit(BDCR, bdcr::LSEON);
+        while get_bit(BDCR, bdcr::LSERDY) == 0 {}
+    }
+}
+
+/// Stop the LSE. This also stops the RTC if it runs from the LSE
+pub fn disable_lse() {
+    use registers::rcc::{BDCR, bdcr};
+
+    enable_backup_domain_access();
+
+    unsafe {
+        clear_bit(BDCR, bdcr::LSEON);
+    }
+}
+
+pub fn is_lse_ready() -> bool {
+    use registers::rcc::{BDCR, bdcr};
+    unsafe { get_bit(BDCR, bdcr::LSERDY) == 1 }
+}
+
+/// Start the LSI and wait for it to stabilize. The LSI clocks the independent watchdog, and can
+/// clock the RTC and low-power timers
+pub fn enable_lsi() {
+    use registers::rcc::{CSR, csr};
+
+    unsafe {
+        set_bit(CSR, csr::LSION);
+        while get_bit(CSR, csr::LSIRDY) == 0 {}
+    }
+}
+
+/// Stop the LSI. It keeps running while the independent watchdog is enabled
+pub fn disable_lsi() {
+    use registers::rcc::{CSR, csr};
+
+    unsafe {
+        clear_bit(CSR, csr::LSION);
+    }
+}
+
+pub fn is_lsi_ready() -> bool {
+    use registers::rcc::{CSR, csr};
+    unsafe { get_bit(CSR, csr::LSIRDY) == 1 }
+}
+
+/// Start the HSI48 and wait for it to stabilize. The HSI48 can clock the USB OTG and RNG, and is
+/// trimmed by the clock recovery system, see `crs::setup_crs`
+pub fn enable_hsi48() {
+    use registers::rcc::{CR, cr};
+
+    unsafe {
+        set_bit(CR, cr::RC48ON);
+        while get_bit(CR, cr::RC48RDY) == 0 {}
+    }
+}
+
+pub fn disable_hsi48() {
+    use registers::rcc::{CR, cr};
+
+    unsafe {
+        clear_bit(CR, cr::RC48ON);
+    }
+}
+
+/// Select the clock of the RTC and enable the RTC clock. The selection can only be written once
+/// after a backup domain reset, so selecting another clock than the current one returns
+/// `ClockError::RtcClockInUse` until `reset_backup_domain` is called. The clock has to be
+/// running already, see `enable_lse` and `enable_lsi`
+pub fn set_rtc_clock(clock: RtcClock) -> Result<(), ClockError> {
+    use registers::rcc::{BDCR, bdcr};
+
+    enable_backup_domain_access();
+
+    unsafe {
+        match read_bits(BDCR, bdcr::RTCSRC, 0b11) {
+            0b00 => write_bits(BDCR, bdcr::RTCSRC, clock as u32, 0b11),
+            source if source != clock as u32 => return Err(ClockError::RtcClockInUse),
+            _ => {}
+        }
+
+        set_bit(BDCR, bdcr::RTCEN);
+    }
+
+    Ok(())
+}
+
+/// The clock selected for the RTC, or `None` if no clock is selected or the RTC runs from the
+/// HSE
+pub fn get_rtc_clock() -> Option<RtcClock> {
+    use registers::rcc::{BDCR, bdcr};
+
+    match unsafe { read_bits(BDCR, bdcr::RTCSRC, 0b11) } {
+        0b01 => Some(RtcClock::Lse),
+        0b10 => Some(RtcClock::Lsi),
+        _ => None,
+    }
+}
+
+/// Enable the clock security system of the HSE, which has to be running. When the HSE fails it
+/// is switched off, SYSCLK falls back to the HSI if it ran from the HSE or PLL1, and an NMI is
+/// raised, which should be handled with `handle_css_interrupt`. The flash wait states are left as
+/// they were, so the system keeps running from the HSI. Can only be disabled by a reset
+pub fn enable_hse_css() {
+    use registers::rcc::{CR, cr};
+
+    unsafe {
+        set_bit(CR, cr::HSECSSON);
+    }
+}
+
+/// Enable the clock security system of the LSE. When the LSE fails it stops clocking the RTC and
+/// the RTC tamper, timestamp and LSE CSS interrupt is raised through EXTI line 18, which should
+/// be handled with `handle_css_interrupt`. Can only be disabled by a backup domain reset
+pub fn enable_lse_css() -> Result<(), ClockError> {
+    use registers::rcc::{BDCR, CIER, bdcr, cier};
+
+    if !is_lse_ready() {
+        return Err(ClockError::LseNotReady);
+    }
+
+    enable_backup_domain_access();
+
+    unsafe {
+        set_bit(BDCR, bdcr::LSECSSON);
+        set_bit(CIER, cier::LSECSSIE);
+    }
+
+    let _ = setup_exti_line(ExtiLine::RtcTamperTimestampCssLse, ExtiEdge::Rising);
+    enable_interrupt(Irq::RtcTampStampCssLse);
+
+    Ok(())
+}
+
+/// The clock security system has detected a failure of the LSE, which stays set until the backup
+/// domain is reset
+pub fn is_lse_failure_detected() -> bool {
+    use registers::rcc::{BDCR, bdcr};
+    unsafe { get_bit(BDCR, bdcr::LSECSSD) == 1 }
+}
+
+/// Register a callback that is run from `handle_css_interrupt` when an oscillator fails. For the
+/// HSE it runs from the NMI, so it should only do what's needed to switch to a safe state, such
+/// as calling `setup_clocks` with a configuration that doesn't use the HSE
+pub fn register_css_callback(callback: fn(CssEvent)) {
+    CSS_CALLBACK.store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_css_callback() {
+    CSS_CALLBACK.store(0, Ordering::Release);
+}
+
+/// Clear the clock security system flags and run the registered callback for each failure. This
+/// is the body of the NMI handler for the HSE, and should be called from the RTC tamper,
+/// timestamp and LSE CSS interrupt handler for the LSE
+pub fn handle_css_interrupt() {
+    use registers::rcc::{CICR, CIFR, cicr, cifr};
+
+    let flags = unsafe { read_register(CIFR) };
+
+    for (flag, clear, event) in [
+        (cifr::HSECSSF, cicr::HSECSSC, CssEvent::HseFailure),
+        (cifr::LSECSSF, cicr::LSECSSC, CssEvent::LseFailure),
+    ] {
+        if flags >> flag & 1 == 0 {
+            continue;
+        }
+
+        // The flags are cleared by writing a one, so no read-modify-write is done
+        unsafe {
+            write_register(CICR, 1 << clear);
+        }
+
+        if event == CssEvent::LseFailure {
+            clear_exti_line_pending(ExtiLine::RtcTamperTimestampCssLse);
+        }
+
+        let callback = CSS_CALLBACK.load(Ordering::Acquire);
+        if callback != 0 {
+            // Safety: only valid `fn(CssEvent)` pointers are stored in the callback
+            let callback: fn(CssEvent) =
+                unsafe { core::mem::transmute::<usize, fn(CssEvent)>(callback) };
+            callback(event);
+        }
+    }
+}
+
+/// NMI handler, exported with the exception name used by `cortex-m-rt` when the `css-handler`
+/// feature is enabled. Leave it disabled if the application defines the handler itself
+#[cfg(feature = "css-handler")]
+#[allow(non_snake_case)]
+mod handlers {
+    use super::handle_css_interrupt;
+
+    #[unsafe(no_mangle)]
+    extern "C" fn NonMaskableInt() {
+        handle_css_interrupt();
+    }
+}
+
+#[cfg(all(test, feature = "mock-registers"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_configurations() {
+        // PLL1 P only divides by even values
+        let config = ClockConfig::new()
+            .pll1(PllConfig::new(4, 50).p(3))
+            .sysclk(SysclkSource::Pll1);
+        assert_eq!(
+            setup_clocks(&config),
+            Err(ClockError::InvalidPllConfig(Pll::Pll1))
+        );
+
+        // 64 MHz / 4 * 60 / 2 gives 480 MHz, but the AHB can't run above 240 MHz
+        let config = ClockConfig::new()
+            .pll1(PllConfig::new(4, 60).p(2))
+            .sysclk(SysclkSource::Pll1);
+        assert_eq!(setup_clocks(&config), Err(ClockError::FrequencyTooHigh));
+
+        let config = ClockConfig::new().hpre(3);
+        assert_eq!(setup_clocks(&config), Err(ClockError::InvalidPrescaler));
+    }
+
+    #[test]
+    fn get_flash_latency_follows_the_wait_state_tables() {
+        let cases = [
+            (0, VoltageScale::Scale1, (0, 0b00)),
+            (70_000_000, VoltageScale::Scale1, (0, 0b00)),
+            (70_000_001, VoltageScale::Scale1, (1, 0b01)),
+            (200_000_000, VoltageScale::Scale1, (2, 0b10)),
+            (240_000_000, VoltageScale::Scale0, (4, 0b11)),
+            (55_000_000, VoltageScale::Scale2, (0, 0b00)),
+            (150_000_000, VoltageScale::Scale2, (2, 0b01)),
+            (225_000_000, VoltageScale::Scale2, (3, 0b10)),
+            (45_000_000, VoltageScale::Scale3, (0, 0b00)),
+            (200_000_000, VoltageScale::Scale3, (4, 0b10)),
+            // Above the tables the slowest setting is used
+            (241_000_000, VoltageScale::Scale0, (7, 0b11)),
+            (226_000_000, VoltageScale::Scale3, (7, 0b11)),
+        ];
+
+        for (hclk, scale, expected) in cases {
+            assert_eq!(
+                get_flash_latency(hclk, scale),
+                expected,
+                "{hclk} Hz in {scale:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn get_pll_output_checks_the_dividers_and_ranges() {
+        let frequencies = |p, q, r| Ok(PllFrequencies { p, q, r });
+        let invalid = |pll| Err(ClockError::InvalidPllConfig(pll));
+
+        let cases = [
+            // 64 MHz / 4 * 50 gives a VCO of 800 MHz
+            (
+                Pll::Pll1,
+                PllConfig::new(4, 50).p(2).q(4),
+                64_000_000,
+                frequencies(Some(400_000_000), Some(200_000_000), None),
+            ),
+            (
+                Pll::Pll2,
+                PllConfig::new(4, 50).p(3).r(128),
+                64_000_000,
+                frequencies(Some(266_666_666), None, Some(6_250_000)),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(4, 50).p(3),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll2,
+                PllConfig::new(4, 50).q(0),
+                64_000_000,
+                invalid(Pll::Pll2),
+            ),
+            (
+                Pll::Pll2,
+                PllConfig::new(4, 50).q(129),
+                64_000_000,
+                invalid(Pll::Pll2),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(0, 50),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(64, 50),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(4, 3),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(32, 513),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            // The reference clock has to be between 1 and 16 MHz
+            (
+                Pll::Pll1,
+                PllConfig::new(1, 10),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(26, 300),
+                25_000_000,
+                invalid(Pll::Pll1),
+            ),
+            // Below a 2 MHz reference the medium VCO range of 150 to 420 MHz is used
+            (
+                Pll::Pll1,
+                PllConfig::new(25, 150).q(1),
+                25_000_000,
+                frequencies(None, Some(150_000_000), None),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(25, 149),
+                25_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(25, 421),
+                25_000_000,
+                invalid(Pll::Pll1),
+            ),
+            // From a 2 MHz reference the wide VCO range of 192 to 960 MHz is used
+            (
+                Pll::Pll1,
+                PllConfig::new(32, 96).q(1),
+                64_000_000,
+                frequencies(None, Some(192_000_000), None),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(32, 95),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(32, 480).p(2),
+                64_000_000,
+                frequencies(Some(480_000_000), None, None),
+            ),
+            (
+                Pll::Pll1,
+                PllConfig::new(32, 481),
+                64_000_000,
+                invalid(Pll::Pll1),
+            ),
+        ];
+
+        for (pll, config, source, expected) in cases {
+            assert_eq!(get_pll_output(pll, &config, source), expected, "{config:?}");
+        }
+    }
+}
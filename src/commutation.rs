@@ -0,0 +1,146 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    register_tools::{clear_bit, set_bit, write_bits},
+    registers,
+    timer_sync::{SlaveMode, TimerTrigger, set_timer_slave_mode},
+    timers::{
+        Timer, TimerChannel, TimerError, get_bdtr_break_dead_time_register,
+        get_ccer_capture_compare_enable_register, get_ccer_enable_field,
+        get_ccmr_capture_compare_mode_register, get_cr2_control_register,
+        get_egr_event_generator_register,
+    },
+};
+
+/// How a phase of a three-phase bridge is driven during a commutation step. Each phase is a
+/// channel output for the high side switch and its complementary output for the low side switch
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PhaseDrive {
+    /// Both switches are off
+    Floating,
+    /// The high side switch follows the PWM duty cycle, with the low side switch complementary
+    Pwm,
+    /// The low side switch is kept on
+    Low,
+}
+
+/// What applies the preloaded pattern
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CommutationTrigger {
+    /// Only `trigger_commutation` applies the pattern
+    Software,
+    /// A rising edge of the trigger input also applies the pattern, e.g. from a timer in hall
+    /// sensor mode through an internal trigger
+    Trigger(TimerTrigger),
+}
+
+/// The standard six-step sequence for phases U, V and W on channel 1, 2 and 3
+pub const SIX_STEP_SEQUENCE: [[PhaseDrive; 3]; 6] = [
+    [PhaseDrive::Pwm, PhaseDrive::Low, PhaseDrive::Floating],
+    [PhaseDrive::Pwm, PhaseDrive::Floating, PhaseDrive::Low],
+    [PhaseDrive::Floating, PhaseDrive::Pwm, PhaseDrive::Low],
+    [PhaseDrive::Low, PhaseDrive::Pwm, PhaseDrive::Floating],
+    [PhaseDrive::Low, PhaseDrive::Floating, PhaseDrive::Pwm],
+    [PhaseDrive::Floating, PhaseDrive::Low, PhaseDrive::Pwm],
+];
+
+const PHASE_CHANNELS: [TimerChannel; 3] = [TimerChannel::Ch1, TimerChannel::Ch2, TimerChannel::Ch3];
+
+/// Preload the output enables and modes of channel 1 to 3 of TIM1 or TIM8, so that a new pattern
+/// is applied atomically at the commutation event. Channel 1 to 3 should already be setup with
+/// `pwm::setup_complementary_pwm`. Returns `TimerError::Unsupported` on the general-purpose
+/// timers
+pub fn setup_commutation(timer: &Timer, trigger: CommutationTrigger) -> Result<(), TimerError> {
+    use registers::tim1;
+
+    get_bdtr_break_dead_time_register(timer).ok_or(TimerError::Unsupported)?;
+
+    let cr2_control_register = get_cr2_control_register(timer);
+
+    unsafe {
+        set_bit(cr2_control_register, tim1::cr2::CCPC);
+
+        match trigger {
+            CommutationTrigger::Software => clear_bit(cr2_control_register, tim1::cr2::CCUS),
+            CommutationTrigger::Trigger(timer_trigger) => {
+                // Only the trigger selection is used, the slave mode stays disabled
+                set_timer_slave_mode(timer, SlaveMode::Disabled, timer_trigger);
+                set_bit(cr2_control_register, tim1::cr2::CCUS);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Stop preloading the commutation pattern, so that output changes apply immediately again
+pub fn cleanup_commutation(timer: &Timer) {
+    use registers::tim1;
+
+    if get_bdtr_break_dead_time_register(timer).is_none() {
+        return;
+    }
+
+    let cr2_control_register = get_cr2_control_register(timer);
+
+    unsafe {
+        clear_bit(cr2_control_register, tim1::cr2::CCPC);
+        clear_bit(cr2_control_register, tim1::cr2::CCUS);
+    }
+}
+
+/// Preload the drive of phase U, V and W on channel 1, 2 and 3. The pattern takes effect at the
+/// next commutation event
+pub fn set_commutation_pattern(timer: &Timer, pattern: [PhaseDrive; 3]) {
+    use registers::tim2;
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+
+    for (channel, drive) in PHASE_CHANNELS.into_iter().zip(pattern) {
+        let (ccmr_capture_compare_mode_register, offset) =
+            get_ccmr_capture_compare_mode_register(timer, channel);
+        let ccer_enable_field = get_ccer_enable_field(channel);
+
+        // PWM mode 1 for the duty cycle, or forced inactive to keep the low side on
+        let (output_mode, enabled) = match drive {
+            PhaseDrive::Floating => (0b110, false),
+            PhaseDrive::Pwm => (0b110, true),
+            PhaseDrive::Low => (0b100, true),
+        };
+
+        unsafe {
+            write_bits(
+                ccmr_capture_compare_mode_register,
+                tim2::ccmr1_output::OC1M + offset,
+                output_mode,
+                0b111,
+            );
+
+            // Both the output and the complementary output
+            write_bits(
+                ccer_capture_compare_enable_register,
+                ccer_enable_field,
+                enabled as u32,
+                0b1,
+            );
+            write_bits(
+                ccer_capture_compare_enable_register,
+                ccer_enable_field + 2,
+                enabled as u32,
+                0b1,
+            );
+        }
+    }
+}
+
+/// Apply the preloaded pattern now
+pub fn trigger_commutation(timer: &Timer) {
+    use registers::tim1;
+    unsafe {
+        set_bit(get_egr_event_generator_register(timer), tim1::egr::COMG);
+    }
+}
+
+/// Preload step `step` of `SIX_STEP_SEQUENCE`, wrapping around after the sixth step
+pub fn set_six_step(timer: &Timer, step: usize) {
+    set_commutation_pattern(timer, SIX_STEP_SEQUENCE[step % 6]);
+}
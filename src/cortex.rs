@@ -0,0 +1,89 @@
+/// See the [ARMv7-M Architecture Reference Manual](https://developer.arm.com/documentation/ddi0403/latest/)
+/// for the barrier instructions and cache maintenance operations
+#[cfg(target_arch = "arm")]
+use core::arch::asm;
+
+use crate::register_tools::write_register;
+
+// Cortex-M7 cache maintenance registers, see the ARMv7-M Architecture Reference Manual
+const DCIMVAC: *mut u32 = 0xE000_EF5C as *mut u32;
+const DCCMVAC: *mut u32 = 0xE000_EF68 as *mut u32;
+const DCCIMVAC: *mut u32 = 0xE000_EF70 as *mut u32;
+
+/// Size of a data cache line on the Cortex-M7
+pub const DCACHE_LINE_SIZE: usize = 32;
+
+/// Data synchronization barrier. Waits until all earlier memory accesses have completed, such as
+/// a peripheral enable write that has to take effect before the next access
+#[inline(always)]
+pub fn dsb() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("dsb sy", options(nostack, preserves_flags));
+    }
+}
+
+/// Data memory barrier. Orders the memory accesses before it against the ones after it, without
+/// waiting for them to complete
+#[inline(always)]
+pub fn dmb() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("dmb sy", options(nostack, preserves_flags));
+    }
+}
+
+/// Instruction synchronization barrier. Flushes the pipeline, so that following instructions see
+/// the effect of earlier system register changes
+#[inline(always)]
+pub fn isb() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("isb sy", options(nostack, preserves_flags));
+    }
+}
+
+/// Run a cache maintenance operation on every cache line overlapping `length` bytes at
+/// `address`, followed by the barriers that make the result visible
+fn for_each_dcache_line(register: *mut u32, address: usize, length: usize) {
+    if length == 0 {
+        return;
+    }
+
+    let first = address & !(DCACHE_LINE_SIZE - 1);
+    let end = address + length;
+
+    dsb();
+
+    for line in (first..end).step_by(DCACHE_LINE_SIZE) {
+        unsafe {
+            write_register(register, line as u32);
+        }
+    }
+
+    dsb();
+    isb();
+}
+
+/// Write the cached content of a buffer back to memory, so that a DMA transfer reading from the
+/// buffer sees the latest data. Does nothing if the data cache is disabled
+pub fn clean_dcache_by_addr(address: usize, length: usize) {
+    for_each_dcache_line(DCCMVAC, address, length);
+}
+
+/// Discard the cached content of a buffer, so that the CPU reads what a DMA transfer wrote to
+/// memory. Does nothing if the data cache is disabled
+///
+/// # Safety
+/// Whole cache lines are discarded, so any data sharing the first or last line with the buffer
+/// that hasn't been written back is lost. Align DMA buffers to `DCACHE_LINE_SIZE` and round
+/// their size up to it
+pub unsafe fn invalidate_dcache_by_addr(address: usize, length: usize) {
+    for_each_dcache_line(DCIMVAC, address, length);
+}
+
+/// Write the cached content of a buffer back to memory and discard it from the cache, for
+/// buffers that are both read and written by DMA
+pub fn clean_invalidate_dcache_by_addr(address: usize, length: usize) {
+    for_each_dcache_line(DCCIMVAC, address, length);
+}
@@ -0,0 +1,184 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{
+    clocks::enable_hsi48,
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    rcc::{HSI48_FREQUENCY, Peripheral, reset_peripheral},
+    register_tools::{clear_bit, modify, read_bits, read_register, set_bit, write_register},
+    registers,
+};
+
+/// Frequency of the USB start of frame packets in full speed mode
+pub const USB_SOF_FREQUENCY: u32 = 1_000;
+
+/// Sync errors, sync misses, trim overflows and sync warnings counted by `handle_crs_interrupt`
+static CRS_ERROR_COUNTS: [AtomicU32; 4] = [const { AtomicU32::new(0) }; 4];
+
+/// Signal the HSI48 is trimmed against, selected with SYNCSRC in CRS_CFGR
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CrsSyncSource {
+    /// The CRS_SYNC pin, which has to be setup as its alternate function
+    Pin = 0b00,
+    /// The LSE, which has to be running, see `clocks::enable_lse`
+    Lse = 0b01,
+    /// The start of frame packets received by the USB OTG peripheral
+    UsbSof = 0b10,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum CrsError {
+    /// The HSI48 periods between two sync events don't fit in the 16 bit counter
+    InvalidSyncFrequency(u32),
+}
+
+/// Trimming state of the HSI48
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CrsStatus {
+    /// Trim of the HSI48, where 32 is the middle of the range
+    pub trim: u8,
+    /// HSI48 periods counted past the expected number at the last sync event
+    pub frequency_error: u16,
+    /// The HSI48 ran faster than the target at the last sync event
+    pub running_fast: bool,
+    /// The frequency error at the last sync event was within the tolerance
+    pub synchronized: bool,
+}
+
+/// Errors counted by `handle_crs_interrupt` since the CRS was setup
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CrsErrorCounts {
+    /// The frequency error was too large for the trimming to correct
+    pub sync_errors: u32,
+    /// A sync event didn't arrive in time, such as when the USB cable is unplugged
+    pub sync_misses: u32,
+    /// The trim reached the end of its range
+    pub trim_overflows: u32,
+    /// The frequency error was large enough to need a trim step
+    pub sync_warnings: u32,
+}
+
+/// Start the HSI48 and trim it automatically against a sync signal of `sync_frequency`. With the
+/// USB start of frame packets as the sync signal, the HSI48 is accurate enough to clock the USB
+/// OTG in device mode without a crystal, selected with
+/// `clocks::set_kernel_clock_source(KernelClock::Usb, KernelClockSource::Hsi48)`.
+///
+/// The error interrupt is enabled, and should be handled with `handle_crs_interrupt`
+pub fn setup_crs(source: CrsSyncSource, sync_frequency: u32) -> Result<(), CrsError> {
+    use registers::{
+        crs::{CFGR, CR, ICR, cfgr, cr, icr},
+        rcc::{APB1HENR, apb1henr},
+    };
+
+    if sync_frequency == 0 {
+        return Err(CrsError::InvalidSyncFrequency(sync_frequency));
+    }
+
+    // The counter is reloaded with the number of HSI48 periods per sync event, see the frequency
+    // error measurement section of the CRS chapter
+    let reload = (HSI48_FREQUENCY + sync_frequency / 2) / sync_frequency;
+    if !(2..=0x1_0000).contains(&reload) {
+        return Err(CrsError::InvalidSyncFrequency(sync_frequency));
+    }
+
+    // Allow an error of half of a trim step of about 0.14 %
+    let limit = (reload * 14).div_ceil(20_000).clamp(1, 0xFF);
+
+    enable_hsi48();
+
+    unsafe {
+        set_bit(APB1HENR, apb1henr::CRSEN);
+    }
+
+    // Start from the reset values, as the configuration can only be written while disabled
+    reset_peripheral(Peripheral::Crs);
+
+    for count in &CRS_ERROR_COUNTS {
+        count.store(0, Ordering::Relaxed);
+    }
+
+    unsafe {
+        write_register(
+            CFGR,
+            (reload - 1) << cfgr::RELOAD | limit << cfgr::FELIM | (source as u32) << cfgr::SYNCSRC,
+        );
+
+        write_register(
+            ICR,
+            1 << icr::SYNCOKC | 1 << icr::SYNCWARNC | 1 << icr::ERRC | 1 << icr::ESYNCC,
+        );
+
+        modify(CR, |value| {
+            value | 1 << cr::ERRIE | 1 << cr::SYNCWARNIE | 1 << cr::AUTOTRIMEN | 1 << cr::CEN
+        });
+    }
+
+    enable_interrupt(Irq::Crs);
+
+    Ok(())
+}
+
+/// Trim the HSI48 against the USB start of frame packets, see `setup_crs`
+pub fn setup_crs_usb() -> Result<(), CrsError> {
+    setup_crs(CrsSyncSource::UsbSof, USB_SOF_FREQUENCY)
+}
+
+/// Stop the trimming. The HSI48 keeps running with its current trim
+pub fn cleanup_crs() {
+    use registers::crs::{CR, cr};
+
+    disable_interrupt(Irq::Crs);
+
+    unsafe {
+        clear_bit(CR, cr::CEN);
+        clear_bit(CR, cr::ERRIE);
+        clear_bit(CR, cr::SYNCWARNIE);
+    }
+}
+
+pub fn get_crs_status() -> CrsStatus {
+    use registers::crs::{CR, ISR, cr, isr};
+
+    unsafe {
+        let status = read_register(ISR);
+
+        CrsStatus {
+            trim: read_bits(CR, cr::TRIM, 0x3F) as u8,
+            frequency_error: (status >> isr::FECAP) as u16,
+            // The counter counts up when the HSI48 is faster than the target
+            running_fast: status >> isr::FEDIR & 1 == 0,
+            synchronized: status >> isr::SYNCWARNF & 1 == 0 && status >> isr::ERRF & 1 == 0,
+        }
+    }
+}
+
+pub fn get_crs_error_counts() -> CrsErrorCounts {
+    let count = |index: usize| CRS_ERROR_COUNTS[index].load(Ordering::Relaxed);
+
+    CrsErrorCounts {
+        sync_errors: count(0),
+        sync_misses: count(1),
+        trim_overflows: count(2),
+        sync_warnings: count(3),
+    }
+}
+
+/// Count and clear the error and sync warning flags. This is the body of the CRS interrupt
+/// handler and can be called from a user defined handler as well
+pub fn handle_crs_interrupt() {
+    use registers::crs::{ICR, ISR, icr, isr};
+
+    let status = unsafe { read_register(ISR) };
+
+    let flags = [isr::SYNCERR, isr::SYNCMISS, isr::TRIMOVF, isr::SYNCWARNF];
+    for (count, flag) in CRS_ERROR_COUNTS.iter().zip(flags) {
+        if status >> flag & 1 == 1 {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // The error flags are cleared together through ERRC
+    unsafe {
+        write_register(ICR, 1 << icr::ERRC | 1 << icr::SYNCWARNC);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::dwt::{delay_cycles, enable_cycle_counter};
+
+/// Blocking delays measured with the DWT cycle counter of the core, so no timer is used. The
+/// delays are at least as long as requested, but can be longer if interrupts run in between
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Delay {
+    core_frequency: u32,
+}
+
+impl Delay {
+    /// Create a delay provider for a core running at `core_frequency`, and start the cycle
+    /// counter
+    pub fn new(core_frequency: u32) -> Self {
+        enable_cycle_counter();
+        Self { core_frequency }
+    }
+
+    pub fn core_frequency(&self) -> u32 {
+        self.core_frequency
+    }
+
+    pub fn delay_ns(&mut self, ns: u32) {
+        // Round up, so that the delay is never shorter than requested
+        self.delay_cycles((ns as u64 * self.core_frequency as u64).div_ceil(1_000_000_000));
+    }
+
+    pub fn delay_us(&mut self, us: u32) {
+        self.delay_cycles((us as u64 * self.core_frequency as u64).div_ceil(1_000_000));
+    }
+
+    pub fn delay_ms(&mut self, ms: u32) {
+        self.delay_cycles((ms as u64 * self.core_frequency as u64).div_ceil(1_000));
+    }
+
+    fn delay_cycles(&self, mut cycles: u64) {
+        // The cycle counter wraps after 2^32 cycles, so long delays are split up
+        while cycles > 0 {
+            let chunk = cycles.min(u32::MAX as u64 / 2);
+            delay_cycles(chunk as u32);
+            cycles -= chunk;
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl embedded_hal::delay::DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        Delay::delay_ns(self, ns);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        Delay::delay_us(self, us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        Delay::delay_ms(self, ms);
+    }
+}
@@ -0,0 +1,219 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use super::{
+    register_tools::{
+        Field, clear_bit, get_bit, read_register, set_bit, write_field, write_register,
+    },
+    registers,
+};
+
+/// A stream of DMA1 or DMA2. Each stream is connected to the DMAMUX1 channel with the same
+/// index, where DMA2 streams use channel 8 to 15. Note that neither controller can access the
+/// DTCM, so transfer buffers must be placed in the AXI SRAM or SRAM1 to SRAM3
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DmaStream {
+    Dma1Stream0,
+    Dma1Stream1,
+    Dma1Stream2,
+    Dma1Stream3,
+    Dma1Stream4,
+    Dma1Stream5,
+    Dma1Stream6,
+    Dma1Stream7,
+    Dma2Stream0,
+    Dma2Stream1,
+    Dma2Stream2,
+    Dma2Stream3,
+    Dma2Stream4,
+    Dma2Stream5,
+    Dma2Stream6,
+    Dma2Stream7,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DmaDirection {
+    PeripheralToMemory = 0b00,
+    MemoryToPeripheral = 0b01,
+}
+
+/// Size of a single transfer, used for both the peripheral and memory side
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DmaSize {
+    Byte = 0b00,
+    HalfWord = 0b01,
+    Word = 0b10,
+}
+
+/// Configuration of a transfer between a peripheral register and a memory buffer. The memory
+/// address is incremented after each transfer while the peripheral address is kept fixed. With
+/// the data cache enabled, clean the buffer with `cortex::clean_dcache_by_addr` before a memory
+/// to peripheral transfer, and invalidate it after a peripheral to memory transfer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DmaTransfer {
+    /// DMAMUX1 request line of the peripheral, see the DMAMUX1 request mapping in section 17.3.2
+    pub request: u8,
+    pub peripheral_address: u32,
+    pub memory_address: u32,
+    /// Number of transfers, not bytes
+    pub length: u16,
+    pub direction: DmaDirection,
+    pub size: DmaSize,
+    /// Restart from the beginning of the buffer when the transfer completes
+    pub circular: bool,
+}
+
+/// Index of the stream within its controller
+fn get_stream_index(stream: DmaStream) -> usize {
+    stream as usize % 8
+}
+
+fn get_dma_clock_field(stream: DmaStream) -> u8 {
+    use registers::rcc::ahb1enr;
+
+    if (stream as usize) < 8 {
+        ahb1enr::DMA1EN
+    } else {
+        ahb1enr::DMA2EN
+    }
+}
+
+fn get_scr_stream_configuration_register(stream: DmaStream) -> *mut u32 {
+    use registers::{dma1, dma2};
+
+    let base = if (stream as usize) < 8 {
+        dma1::S0CR
+    } else {
+        dma2::S0CR
+    };
+
+    // The stream registers are laid out in blocks of 0x18 bytes, see section 15.5 DMA
+    // registers
+    base.wrapping_add(6 * get_stream_index(stream))
+}
+
+fn get_sndtr_number_of_data_register(stream: DmaStream) -> *mut u32 {
+    get_scr_stream_configuration_register(stream).wrapping_add(1)
+}
+
+fn get_spar_peripheral_address_register(stream: DmaStream) -> *mut u32 {
+    get_scr_stream_configuration_register(stream).wrapping_add(2)
+}
+
+fn get_sm0ar_memory_address_register(stream: DmaStream) -> *mut u32 {
+    get_scr_stream_configuration_register(stream).wrapping_add(3)
+}
+
+/// Flag clear register of the stream, and the offset of the stream flags in it. Streams 0 to 3
+/// use the low register and 4 to 7 the high register
+fn get_ifcr_interrupt_flag_clear_register(stream: DmaStream) -> (*mut u32, u8) {
+    use registers::{dma1, dma2};
+
+    let index = get_stream_index(stream);
+    let register = match ((stream as usize) < 8, index < 4) {
+        (true, true) => dma1::LIFCR,
+        (true, false) => dma1::HIFCR,
+        (false, true) => dma2::LIFCR,
+        (false, false) => dma2::HIFCR,
+    };
+    let offset = [0, 6, 16, 22][index % 4];
+
+    (register, offset)
+}
+
+fn get_ccr_dmamux_channel_register(stream: DmaStream) -> *mut u32 {
+    use registers::dmamux1;
+
+    // The channel registers are four bytes apart
+    dmamux1::C0CR.wrapping_add(stream as usize)
+}
+
+/// Enable the clock of the DMA controller a stream belongs to
+pub fn enable_dma_clock(stream: DmaStream) {
+    use registers::rcc::AHB1ENR;
+
+    unsafe {
+        set_bit(AHB1ENR, get_dma_clock_field(stream));
+    }
+}
+
+/// Configure and start a transfer on a stream. The stream is disabled before it's reconfigured
+pub fn setup_dma_stream(stream: DmaStream, transfer: &DmaTransfer) {
+    use registers::{dma1::s0cr, dmamux1::c0cr};
+
+    let scr_stream_configuration_register = get_scr_stream_configuration_register(stream);
+
+    enable_dma_clock(stream);
+    disable_dma_stream(stream);
+    clear_dma_stream_flags(stream);
+
+    unsafe {
+        // Route the peripheral request to the stream
+        write_field(
+            get_ccr_dmamux_channel_register(stream),
+            Field::<{ c0cr::DMAREQ_ID }, { c0cr::DMAREQ_ID_WIDTH }>,
+            transfer.request as u32,
+        );
+
+        write_register(
+            get_spar_peripheral_address_register(stream),
+            transfer.peripheral_address,
+        );
+        write_register(
+            get_sm0ar_memory_address_register(stream),
+            transfer.memory_address,
+        );
+        write_register(
+            get_sndtr_number_of_data_register(stream),
+            transfer.length as u32,
+        );
+
+        // Start from a cleared configuration, so that settings from an earlier transfer doesn't
+        // carry over
+        let mut configuration = ((transfer.direction as u32) << s0cr::DIR)
+            | ((transfer.size as u32) << s0cr::PSIZE)
+            | ((transfer.size as u32) << s0cr::MSIZE)
+            | (1 << s0cr::MINC);
+
+        if transfer.circular {
+            configuration |= 1 << s0cr::CIRC;
+        }
+
+        write_register(scr_stream_configuration_register, configuration);
+
+        // Start the transfer
+        set_bit(scr_stream_configuration_register, s0cr::EN);
+    }
+}
+
+/// Stop a stream and wait until the ongoing transfer has finished
+pub fn disable_dma_stream(stream: DmaStream) {
+    use registers::dma1::s0cr;
+
+    let scr_stream_configuration_register = get_scr_stream_configuration_register(stream);
+
+    unsafe {
+        clear_bit(scr_stream_configuration_register, s0cr::EN);
+        while get_bit(scr_stream_configuration_register, s0cr::EN) == 1 {}
+    }
+}
+
+pub fn is_dma_stream_enabled(stream: DmaStream) -> bool {
+    use registers::dma1::s0cr;
+    unsafe { get_bit(get_scr_stream_configuration_register(stream), s0cr::EN) == 1 }
+}
+
+/// Clear the transfer complete, half transfer, transfer error, direct mode error and FIFO error
+/// flags of a stream
+pub fn clear_dma_stream_flags(stream: DmaStream) {
+    let (ifcr_interrupt_flag_clear_register, offset) =
+        get_ifcr_interrupt_flag_clear_register(stream);
+
+    // The flags are cleared by writing a one, so no read-modify-write is done
+    unsafe {
+        write_register(ifcr_interrupt_flag_clear_register, 0b11_1101 << offset);
+    }
+}
+
+/// Number of transfers left before the stream wraps or completes
+pub fn get_dma_remaining_transfers(stream: DmaStream) -> u16 {
+    unsafe { read_register(get_sndtr_number_of_data_register(stream)) as u16 }
+}
@@ -0,0 +1,54 @@
+/// See the [ARMv7-M Architecture Reference Manual](https://developer.arm.com/documentation/ddi0403/latest/)
+/// for the Data Watchpoint and Trace unit
+use crate::register_tools::{read_register, set_bit, write_register};
+
+// Cortex-M7 debug registers, see the ARMv7-M Architecture Reference Manual
+const DEMCR: *mut u32 = 0xE000_EDFC as *mut u32;
+const DEMCR_TRCENA: u8 = 24;
+const DWT_CTRL: *mut u32 = 0xE000_1000 as *mut u32;
+const DWT_CTRL_CYCCNTENA: u8 = 0;
+const DWT_CYCCNT: *mut u32 = 0xE000_1004 as *mut u32;
+const DWT_LAR: *mut u32 = 0xE000_1FB0 as *mut u32;
+const DWT_LAR_UNLOCK: u32 = 0xC5AC_CE55;
+
+/// Enable the trace unit and start the cycle counter. Calling it again leaves the counter
+/// running
+pub fn enable_cycle_counter() {
+    unsafe {
+        set_bit(DEMCR, DEMCR_TRCENA);
+        // The DWT of the Cortex-M7 is locked after reset, and only a debugger unlocks it
+        write_register(DWT_LAR, DWT_LAR_UNLOCK);
+        set_bit(DWT_CTRL, DWT_CTRL_CYCCNTENA);
+    }
+}
+
+/// Current value of the cycle counter, which wraps after 2^32 core clock cycles
+pub fn cycles() -> u32 {
+    unsafe { read_register(DWT_CYCCNT) }
+}
+
+/// Set the cycle counter to zero
+pub fn reset_cycles() {
+    unsafe {
+        write_register(DWT_CYCCNT, 0);
+    }
+}
+
+/// Busy-wait for at least `cycles` core clock cycles. The cycle counter has to be enabled
+pub fn delay_cycles(cycles: u32) {
+    if cycles == 0 {
+        return;
+    }
+
+    let start = self::cycles();
+    while self::cycles().wrapping_sub(start) < cycles {}
+}
+
+/// Run `f` and return the number of core clock cycles it took, including the few cycles used to
+/// read the counter. The cycle counter has to be enabled, and the result wraps if `f` runs for
+/// longer than 2^32 cycles
+pub fn measure<F: FnOnce()>(f: F) -> u32 {
+    let start = cycles();
+    f();
+    cycles().wrapping_sub(start)
+}
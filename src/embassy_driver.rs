@@ -0,0 +1,83 @@
+use core::{cell::UnsafeCell, task::Waker};
+
+use embassy_time_driver::Driver;
+use embassy_time_queue_utils::Queue;
+
+use crate::{
+    interrupts,
+    rtic_monotonic::{TICK_FREQUENCY, Tim2Monotonic},
+    timers::TimerError,
+};
+
+// The tick rate selected for embassy-time has to match the counter
+const _: () = assert!(embassy_time_driver::TICK_HZ == TICK_FREQUENCY as u64);
+
+/// The timer queue, only accessed through `with_queue`
+struct QueueCell(UnsafeCell<Queue>);
+
+// Safety: the queue is only accessed with interrupts disabled
+unsafe impl Sync for QueueCell {}
+
+static QUEUE: QueueCell = QueueCell(UnsafeCell::new(Queue::new()));
+
+/// Time driver for `embassy-time` on TIM2, using the same counter and interrupts as
+/// `Tim2Monotonic`. The next expiration of the timer queue is scheduled on compare channel 1
+struct Tim2Driver;
+
+embassy_time_driver::time_driver_impl!(static DRIVER: Tim2Driver = Tim2Driver);
+
+impl Driver for Tim2Driver {
+    fn now(&self) -> u64 {
+        Tim2Monotonic::now()
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        with_queue(|queue| {
+            if queue.schedule_wake(at, waker) {
+                set_next_alarm(queue);
+            }
+        });
+    }
+}
+
+/// Start TIM2 as the time base of `embassy-time`. The timer clock has to be a multiple of 1 MHz,
+/// and the TIM2 interrupt handler has to call `handle_embassy_interrupt`
+pub fn setup_embassy_time_driver(clock_frequency: u32) -> Result<(), TimerError> {
+    Tim2Monotonic::start(clock_frequency)
+}
+
+/// Count the counter overflows and wake the tasks that are due. Call this from the TIM2
+/// interrupt handler
+pub fn handle_embassy_interrupt() {
+    Tim2Monotonic::on_interrupt();
+
+    // Clear the compare flag before the queue is checked, so an alarm set below isn't lost. The
+    // queue is checked on every interrupt, which also picks up alarms that were too far away
+    // for the compare register when they were scheduled
+    Tim2Monotonic::clear_compare_flag();
+
+    with_queue(set_next_alarm);
+}
+
+/// Wake the expired timers and schedule the compare for the next one. Retry if the next
+/// expiration passes while it's being scheduled
+fn set_next_alarm(queue: &mut Queue) {
+    loop {
+        let next = queue.next_expiration(Tim2Monotonic::now());
+        if next == u64::MAX {
+            return;
+        }
+
+        Tim2Monotonic::set_compare(next);
+
+        if Tim2Monotonic::now() < next {
+            return;
+        }
+    }
+}
+
+/// Run `f` with exclusive access to the timer queue by masking interrupts
+fn with_queue<R>(f: impl FnOnce(&mut Queue) -> R) -> R {
+    // Safety: interrupts are masked, so nothing else can access the queue
+    interrupts::free(|| f(unsafe { &mut *QUEUE.0.get() }))
+}
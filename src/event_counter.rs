@@ -0,0 +1,171 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    gpio::Gpio,
+    register_tools::{
+        ClearByWrite0, clear_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    timer_sync::{SlaveMode, TimerTrigger, set_timer_slave_mode},
+    timers::{
+        Timer, TimerChannel, TimerError, enable_timer_clock, get_arr_auto_reload_register,
+        get_ccer_capture_compare_enable_register, get_ccer_enable_field,
+        get_ccmr_capture_compare_mode_register, get_cnt_counter_register, get_cr1_control_register,
+        get_egr_event_generator_register, get_psc_prescaler_register,
+        get_smcr_slave_mode_control_register, get_sr_status_register, get_timer_max_count,
+    },
+};
+
+/// Division of the external trigger input before it's counted. The divided signal has to stay
+/// below a quarter of the timer clock
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EtrPrescaler {
+    Div1 = 0b00,
+    Div2 = 0b01,
+    Div4 = 0b10,
+    Div8 = 0b11,
+}
+
+/// Pin the counted pulses come in on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExternalClockInput {
+    /// The channel 1 input, in external clock mode 1
+    Channel1,
+    /// The channel 2 input, in external clock mode 1
+    Channel2,
+    /// The external trigger input ETR, in external clock mode 2
+    Etr(EtrPrescaler),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExternalClockEdge {
+    Rising,
+    Falling,
+}
+
+/// Count pulses on a pin instead of the timer clock, e.g. from a flow meter. `gpio` is the pin of
+/// the input, configured as alternate function of the timer. `filter` is the 4 bit input filter
+/// value, where 0 disables the filter and higher values require the input to be stable for more
+/// and slower samples. The count wraps at the maximum count of the timer
+pub fn setup_external_clock(
+    timer: &Timer,
+    gpio: &Gpio,
+    input: ExternalClockInput,
+    edge: ExternalClockEdge,
+    filter: u8,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if filter > 0b1111 {
+        return Err(TimerError::InvalidFilter(filter));
+    }
+
+    let smcr_slave_mode_control_register = get_smcr_slave_mode_control_register(timer);
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+
+        // Count every input edge through the whole counter range
+        write_register(get_psc_prescaler_register(timer), 0);
+        write_register(
+            get_arr_auto_reload_register(timer),
+            get_timer_max_count(timer),
+        );
+    }
+
+    match input {
+        ExternalClockInput::Channel1 | ExternalClockInput::Channel2 => {
+            let (channel, trigger) = if input == ExternalClockInput::Channel1 {
+                (TimerChannel::Ch1, TimerTrigger::Ti1Fp1)
+            } else {
+                (TimerChannel::Ch2, TimerTrigger::Ti2Fp2)
+            };
+
+            let (ccmr_capture_compare_mode_register, offset) =
+                get_ccmr_capture_compare_mode_register(timer, channel);
+            let ccer_capture_compare_enable_register =
+                get_ccer_capture_compare_enable_register(timer);
+            let ccer_enable_field = get_ccer_enable_field(channel);
+
+            unsafe {
+                // Map the channel to its own input, with the filter in the upper four bits
+                write_bits(
+                    ccmr_capture_compare_mode_register,
+                    offset,
+                    (filter as u32) << 4 | 0b01,
+                    0xFF,
+                );
+
+                // Select the edge with the polarity bits, without enabling the capture
+                write_bits(
+                    ccer_capture_compare_enable_register,
+                    ccer_enable_field + 1,
+                    (edge == ExternalClockEdge::Falling) as u32,
+                    0b1,
+                );
+                clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 3);
+            }
+
+            set_timer_slave_mode(timer, SlaveMode::ExternalClock, trigger);
+        }
+        ExternalClockInput::Etr(prescaler) => unsafe {
+            write_bits(
+                smcr_slave_mode_control_register,
+                tim2::smcr::ETF,
+                filter as u32,
+                0b1111,
+            );
+            write_bits(
+                smcr_slave_mode_control_register,
+                tim2::smcr::ETPS,
+                prescaler as u32,
+                0b11,
+            );
+            write_bits(
+                smcr_slave_mode_control_register,
+                tim2::smcr::ETP,
+                (edge == ExternalClockEdge::Falling) as u32,
+                0b1,
+            );
+
+            // External clock mode 2 is independent of the slave mode
+            set_bit(smcr_slave_mode_control_register, tim2::smcr::ECE);
+        },
+    }
+
+    unsafe {
+        // Load the pre-scaler without leaving the update flag set, and start counting from zero
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Stop counting external pulses and release the pin
+pub fn cleanup_external_clock(timer: &Timer, gpio: &Gpio) {
+    use registers::tim2;
+
+    unsafe {
+        clear_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+        write_register(get_smcr_slave_mode_control_register(timer), 0);
+    }
+
+    gpio.cleanup();
+}
+
+/// Number of pulses counted since the timer was setup or the count was reset
+pub fn get_event_count(timer: &Timer) -> u32 {
+    unsafe { read_register(get_cnt_counter_register(timer)) }
+}
+
+pub fn reset_event_count(timer: &Timer) {
+    unsafe {
+        write_register(get_cnt_counter_register(timer), 0);
+    }
+}
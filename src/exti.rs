@@ -0,0 +1,422 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    gpio::{Gpio, GpioPin, GpioRegister},
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    register_tools::{ClearByWrite1, clear_bit, get_bit, set_bit, write_bits, write_register},
+    registers,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExtiEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// EXTI lines connected to peripherals instead of gpios. The configurable lines have an edge
+/// selection and a pending bit in the EXTI, while the direct lines follow the wakeup signal of
+/// the peripheral, which is cleared in the peripheral itself. See the EXTI event input mapping
+/// table in the EXTI chapter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExtiLine {
+    PvdAvd = 16,
+    RtcAlarm = 17,
+    RtcTamperTimestampCssLse = 18,
+    RtcWakeup = 19,
+    Comp1 = 20,
+    Comp2 = 21,
+    I2c1Wakeup = 22,
+    I2c2Wakeup = 23,
+    I2c3Wakeup = 24,
+    I2c4Wakeup = 25,
+    Usart1Wakeup = 26,
+    Usart2Wakeup = 27,
+    Usart3Wakeup = 28,
+    Usart6Wakeup = 29,
+    Uart4Wakeup = 30,
+    Uart5Wakeup = 31,
+    Uart7Wakeup = 32,
+    Uart8Wakeup = 33,
+    Lpuart1RxWakeup = 34,
+    Lpuart1TxWakeup = 35,
+    Lptim1Wakeup = 47,
+    Lptim2Wakeup = 48,
+    Lptim2Output = 49,
+    Lptim3Wakeup = 50,
+    Lptim3Output = 51,
+    Lptim4Wakeup = 52,
+    Lptim5Wakeup = 53,
+    WakeupPin1 = 55,
+    WakeupPin2 = 56,
+    WakeupPin3 = 57,
+    WakeupPin4 = 58,
+    WakeupPin5 = 59,
+    WakeupPin6 = 60,
+}
+
+impl ExtiLine {
+    /// Whether the line has an edge selection and a pending bit in the EXTI
+    pub const fn is_configurable(self) -> bool {
+        matches!(
+            self,
+            ExtiLine::PvdAvd
+                | ExtiLine::RtcAlarm
+                | ExtiLine::RtcTamperTimestampCssLse
+                | ExtiLine::RtcWakeup
+                | ExtiLine::Comp1
+                | ExtiLine::Comp2
+                | ExtiLine::Lptim2Output
+                | ExtiLine::Lptim3Output
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExtiError {
+    /// Direct lines only trigger on the rising edge of the wakeup signal
+    EdgeNotConfigurable(ExtiLine),
+}
+
+/// Registers of the EXTI bank a line is in. Each bank of 32 lines has its own set
+struct ExtiBank {
+    rtsr_rising_trigger_register: *mut u32,
+    ftsr_falling_trigger_register: *mut u32,
+    cpuimr_interrupt_mask_register: *mut u32,
+    cpuemr_event_mask_register: *mut u32,
+    cpupr_pending_register: *mut u32,
+}
+
+/// Registered callbacks for EXTI line 0 to 15, stored as function pointers. A zero means that no
+/// callback is registered for the line
+static EXTI_CALLBACKS: [AtomicUsize; 16] = [const { AtomicUsize::new(0) }; 16];
+
+fn get_exticr_register(pin: GpioPin) -> *mut u32 {
+    use registers::syscfg;
+
+    match pin as u8 / 4 {
+        0 => syscfg::EXTICR1,
+        1 => syscfg::EXTICR2,
+        2 => syscfg::EXTICR3,
+        _ => syscfg::EXTICR4,
+    }
+}
+
+fn get_exticr_port_value(register: GpioRegister) -> u32 {
+    // See section 12.3.2 SYSCFG external interrupt configuration register 1 (SYSCFG_EXTICR1)
+    match register {
+        GpioRegister::GpioA => 0b0000,
+        GpioRegister::GpioB => 0b0001,
+        GpioRegister::GpioC => 0b0010,
+        GpioRegister::GpioD => 0b0011,
+        GpioRegister::GpioE => 0b0100,
+        GpioRegister::GpioH => 0b0111,
+        GpioRegister::GpioI => 0b1000,
+        GpioRegister::GpioJ => 0b1001,
+        GpioRegister::GpioK => 0b1010,
+    }
+}
+
+fn get_nvic_interrupt_id(pin: GpioPin) -> Irq {
+    match pin {
+        GpioPin::P0 => Irq::Exti0,
+        GpioPin::P1 => Irq::Exti1,
+        GpioPin::P2 => Irq::Exti2,
+        GpioPin::P3 => Irq::Exti3,
+        GpioPin::P4 => Irq::Exti4,
+        GpioPin::P5 | GpioPin::P6 | GpioPin::P7 | GpioPin::P8 | GpioPin::P9 => Irq::Exti5To9,
+        _ => Irq::Exti10To15,
+    }
+}
+
+/// The register bank of a line and the bit of the line in it
+fn get_exti_bank(line: ExtiLine) -> (ExtiBank, u8) {
+    use registers::exti::{
+        CPUEMR1, CPUEMR2, CPUIMR1, CPUIMR2, CPUPR1, CPUPR2, FTSR1, FTSR2, RTSR1, RTSR2,
+    };
+
+    let line = line as u8;
+    let bank = if line < 32 {
+        ExtiBank {
+            rtsr_rising_trigger_register: RTSR1,
+            ftsr_falling_trigger_register: FTSR1,
+            cpuimr_interrupt_mask_register: CPUIMR1,
+            cpuemr_event_mask_register: CPUEMR1,
+            cpupr_pending_register: CPUPR1,
+        }
+    } else {
+        ExtiBank {
+            rtsr_rising_trigger_register: RTSR2,
+            ftsr_falling_trigger_register: FTSR2,
+            cpuimr_interrupt_mask_register: CPUIMR2,
+            cpuemr_event_mask_register: CPUEMR2,
+            cpupr_pending_register: CPUPR2,
+        }
+    };
+
+    (bank, line % 32)
+}
+
+/// Route a gpio to its EXTI line and enable the interrupt on the selected edge. The gpio itself
+/// should already be setup as an input. Note that only one port can be connected to each line,
+/// so `PA3` and `PB3` share EXTI line 3
+pub fn setup_exti(gpio: &Gpio, edge: ExtiEdge) {
+    use registers::{
+        exti::{CPUIMR1, FTSR1, RTSR1},
+        rcc::{APB4ENR, apb4enr},
+    };
+
+    let line = gpio.pin as u8;
+
+    unsafe {
+        // Enable the syscfg clock, needed to write the EXTI routing
+        set_bit(APB4ENR, apb4enr::SYSCFGEN);
+
+        // Select the gpio port as source for the EXTI line
+        write_bits(
+            get_exticr_register(gpio.pin),
+            (line % 4) * 4,
+            get_exticr_port_value(gpio.register),
+            0b1111,
+        );
+
+        match edge {
+            ExtiEdge::Rising => {
+                set_bit(RTSR1, line);
+                clear_bit(FTSR1, line);
+            }
+            ExtiEdge::Falling => {
+                clear_bit(RTSR1, line);
+                set_bit(FTSR1, line);
+            }
+            ExtiEdge::Both => {
+                set_bit(RTSR1, line);
+                set_bit(FTSR1, line);
+            }
+        }
+
+        // Unmask the line for the CPU
+        set_bit(CPUIMR1, line);
+    }
+
+    enable_interrupt(get_nvic_interrupt_id(gpio.pin));
+}
+
+/// Mask the EXTI line of a gpio and remove any registered callback. The shared NVIC interrupt
+/// for lines 5-9 and 10-15 is only disabled when no other line in the group is unmasked
+pub fn cleanup_exti(gpio: &Gpio) {
+    use registers::exti::{CPUIMR1, FTSR1, RTSR1};
+
+    let line = gpio.pin as u8;
+
+    unsafe {
+        clear_bit(CPUIMR1, line);
+        clear_bit(RTSR1, line);
+        clear_bit(FTSR1, line);
+    }
+
+    unregister_exti_callback(gpio.pin);
+
+    let group = match line {
+        0..=4 => line..=line,
+        5..=9 => 5..=9,
+        _ => 10..=15,
+    };
+
+    if group
+        .into_iter()
+        .all(|line| unsafe { get_bit(CPUIMR1, line) } == 0)
+    {
+        disable_interrupt(get_nvic_interrupt_id(gpio.pin));
+    }
+}
+
+/// Select the edges of a configurable line. Direct lines only accept `ExtiEdge::Rising`
+fn set_exti_line_edge(line: ExtiLine, edge: ExtiEdge) -> Result<(), ExtiError> {
+    if !line.is_configurable() {
+        return match edge {
+            ExtiEdge::Rising => Ok(()),
+            _ => Err(ExtiError::EdgeNotConfigurable(line)),
+        };
+    }
+
+    let (bank, field) = get_exti_bank(line);
+    let (rising, falling) = match edge {
+        ExtiEdge::Rising => (true, false),
+        ExtiEdge::Falling => (false, true),
+        ExtiEdge::Both => (true, true),
+    };
+
+    unsafe {
+        write_bits(bank.rtsr_rising_trigger_register, field, rising as u32, 0b1);
+        write_bits(
+            bank.ftsr_falling_trigger_register,
+            field,
+            falling as u32,
+            0b1,
+        );
+    }
+
+    Ok(())
+}
+
+/// Unmask the interrupt of a peripheral EXTI line for the CPU. The EXTI only forwards the
+/// request, so the interrupt itself is enabled in the NVIC by the driver of the peripheral.
+/// Unmasked lines wake the core from Stop mode
+pub fn setup_exti_line(line: ExtiLine, edge: ExtiEdge) -> Result<(), ExtiError> {
+    set_exti_line_edge(line, edge)?;
+
+    let (bank, field) = get_exti_bank(line);
+    unsafe {
+        set_bit(bank.cpuimr_interrupt_mask_register, field);
+    }
+
+    Ok(())
+}
+
+/// Unmask the event of a peripheral EXTI line for the CPU, which wakes the core from
+/// `wait_for_event` without running an interrupt handler
+pub fn setup_exti_line_event(line: ExtiLine, edge: ExtiEdge) -> Result<(), ExtiError> {
+    set_exti_line_edge(line, edge)?;
+
+    let (bank, field) = get_exti_bank(line);
+    unsafe {
+        set_bit(bank.cpuemr_event_mask_register, field);
+    }
+
+    Ok(())
+}
+
+/// Mask both the interrupt and event of a peripheral EXTI line and clear its edge selection
+pub fn cleanup_exti_line(line: ExtiLine) {
+    let (bank, field) = get_exti_bank(line);
+
+    unsafe {
+        clear_bit(bank.cpuimr_interrupt_mask_register, field);
+        clear_bit(bank.cpuemr_event_mask_register, field);
+
+        if line.is_configurable() {
+            clear_bit(bank.rtsr_rising_trigger_register, field);
+            clear_bit(bank.ftsr_falling_trigger_register, field);
+        }
+    }
+}
+
+/// Whether a configurable line is pending. Direct lines are always reported as not pending, as
+/// their state is kept in the peripheral
+pub fn is_exti_line_pending(line: ExtiLine) -> bool {
+    if !line.is_configurable() {
+        return false;
+    }
+
+    let (bank, field) = get_exti_bank(line);
+    unsafe { get_bit(bank.cpupr_pending_register, field) == 1 }
+}
+
+pub fn clear_exti_line_pending(line: ExtiLine) {
+    if !line.is_configurable() {
+        return;
+    }
+
+    let (bank, field) = get_exti_bank(line);
+
+    unsafe {
+        ClearByWrite1::new(bank.cpupr_pending_register).clear(field);
+    }
+}
+
+/// Register a callback that is run from the EXTI interrupt handler when the line triggers
+pub fn register_exti_callback(pin: GpioPin, callback: fn()) {
+    EXTI_CALLBACKS[pin as usize].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_exti_callback(pin: GpioPin) {
+    EXTI_CALLBACKS[pin as usize].store(0, Ordering::Release);
+}
+
+/// Setup the EXTI line for a gpio and register a callback for it in one call
+pub fn setup_exti_callback(gpio: &Gpio, edge: ExtiEdge, callback: fn()) {
+    register_exti_callback(gpio.pin, callback);
+    setup_exti(gpio, edge);
+}
+
+pub fn is_exti_pending(pin: GpioPin) -> bool {
+    use registers::exti::CPUPR1;
+    unsafe { get_bit(CPUPR1, pin as u8) == 1 }
+}
+
+pub fn clear_exti_pending(pin: GpioPin) {
+    use registers::exti::CPUPR1;
+
+    unsafe {
+        ClearByWrite1::new(CPUPR1).clear(pin as u8);
+    }
+}
+
+/// Clear and dispatch all pending lines in the range `first..=last`. This is the body of the
+/// EXTI interrupt handlers and can be called from user defined handlers as well
+pub fn handle_exti_lines(first: u8, last: u8) {
+    use registers::exti::CPUPR1;
+
+    for line in first..=last {
+        if unsafe { get_bit(CPUPR1, line) } == 0 {
+            continue;
+        }
+
+        unsafe {
+            write_register(CPUPR1, 1 << line);
+        }
+
+        let callback = EXTI_CALLBACKS[line as usize].load(Ordering::Acquire);
+        if callback != 0 {
+            // Safety: only valid `fn()` pointers are stored in the callback table
+            let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+            callback();
+        }
+    }
+}
+
+/// EXTI interrupt handlers, exported with the vector names used by `cortex-m-rt` when the
+/// `exti-handlers` feature is enabled. Leave it disabled if the application defines these
+/// handlers itself
+#[cfg(feature = "exti-handlers")]
+#[allow(non_snake_case)]
+mod handlers {
+    use super::handle_exti_lines;
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI0() {
+        handle_exti_lines(0, 0);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI1() {
+        handle_exti_lines(1, 1);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI2() {
+        handle_exti_lines(2, 2);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI3() {
+        handle_exti_lines(3, 3);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI4() {
+        handle_exti_lines(4, 4);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI9_5() {
+        handle_exti_lines(5, 9);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn EXTI15_10() {
+        handle_exti_lines(10, 15);
+    }
+}
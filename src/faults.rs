@@ -0,0 +1,243 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupts::wait_for_interrupt,
+    register_tools::{read_register, set_bit},
+    registers,
+    usart::{USART, UsartWriter},
+};
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FaultKind {
+    HardFault,
+    MemManage,
+    BusFault,
+    UsageFault,
+}
+
+/// The registers stacked by the core when the fault was taken, together with the fault status
+/// and address registers of the SCB
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FaultReport {
+    pub kind: FaultKind,
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    /// Address of the instruction that faulted, or the next one for imprecise faults
+    pub pc: u32,
+    pub xpsr: u32,
+    /// Configurable fault status register, holding the MemManage, BusFault and UsageFault status
+    pub cfsr: u32,
+    /// HardFault status register
+    pub hfsr: u32,
+    /// MemManage fault address, only valid if MMARVALID is set in the CFSR
+    pub mmfar: u32,
+    /// BusFault address, only valid if BFARVALID is set in the CFSR
+    pub bfar: u32,
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at pc={:#010x}\r\n", self.kind, self.pc)?;
+        write!(
+            f,
+            "r0={:#010x} r1={:#010x} r2={:#010x} r3={:#010x}\r\n",
+            self.r0, self.r1, self.r2, self.r3
+        )?;
+        write!(
+            f,
+            "r12={:#010x} lr={:#010x} xpsr={:#010x}\r\n",
+            self.r12, self.lr, self.xpsr
+        )?;
+        write!(
+            f,
+            "cfsr={:#010x} hfsr={:#010x} mmfar={:#010x} bfar={:#010x}\r\n",
+            self.cfsr, self.hfsr, self.mmfar, self.bfar
+        )
+    }
+}
+
+const FAULT_REPORT_WORDS: usize = 13;
+
+/// The last fault, stored as the fault kind plus one followed by the registers in the order of
+/// `FaultReport`. A zero kind means that no fault has been recorded
+static FAULT_REPORT: [AtomicU32; FAULT_REPORT_WORDS] =
+    [const { AtomicU32::new(0) }; FAULT_REPORT_WORDS];
+
+/// Registered fault callback, stored as a function pointer. A zero means that no callback is
+/// registered
+static FAULT_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Usart the fault report is printed to, stored as its index plus one. A zero means that the
+/// report isn't printed
+static FAULT_USART: AtomicUsize = AtomicUsize::new(0);
+
+/// The usarts in declaration order, to turn a stored index back into a usart
+const USARTS: [USART; 9] = [
+    USART::USART1,
+    USART::USART2,
+    USART::USART3,
+    USART::UART4,
+    USART::UART5,
+    USART::USART6,
+    USART::UART7,
+    USART::UART8,
+    USART::LPUART1,
+];
+
+/// Enable the MemManage, BusFault and UsageFault exceptions. Without this, these faults
+/// escalate to a HardFault
+pub fn enable_fault_handlers() {
+    use registers::scb::{SHCSR, shcsr};
+
+    unsafe {
+        set_bit(SHCSR, shcsr::MEMFAULTENA);
+        set_bit(SHCSR, shcsr::BUSFAULTENA);
+        set_bit(SHCSR, shcsr::USGFAULTENA);
+    }
+}
+
+/// The last recorded fault, which can be read from the fault callback or with a debugger
+pub fn get_fault_report() -> Option<FaultReport> {
+    let word = |index: usize| FAULT_REPORT[index].load(Ordering::Relaxed);
+
+    let kind = match word(0) {
+        1 => FaultKind::HardFault,
+        2 => FaultKind::MemManage,
+        3 => FaultKind::BusFault,
+        4 => FaultKind::UsageFault,
+        _ => return None,
+    };
+
+    Some(FaultReport {
+        kind,
+        r0: word(1),
+        r1: word(2),
+        r2: word(3),
+        r3: word(4),
+        r12: word(5),
+        lr: word(6),
+        pc: word(7),
+        xpsr: word(8),
+        cfsr: word(9),
+        hfsr: word(10),
+        mmfar: word(11),
+        bfar: word(12),
+    })
+}
+
+pub fn clear_fault_report() {
+    FAULT_REPORT[0].store(0, Ordering::Relaxed);
+}
+
+/// Register a callback that is run from the fault handlers after the report has been recorded.
+/// The core is in a fault state, so the callback should only do what's needed to save or report
+/// the fault, such as writing it to a backup register
+pub fn register_fault_callback(callback: fn(&FaultReport)) {
+    FAULT_CALLBACK.store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_fault_callback() {
+    FAULT_CALLBACK.store(0, Ordering::Release);
+}
+
+/// Print the fault report to a usart from the fault handlers. The usart has to be setup already
+pub fn set_fault_usart(usart: USART) {
+    FAULT_USART.store(usart as usize + 1, Ordering::Relaxed);
+}
+
+pub fn clear_fault_usart() {
+    FAULT_USART.store(0, Ordering::Relaxed);
+}
+
+/// Record a fault from the exception frame the core stacked, report it and halt. This is the
+/// body of the fault handlers and can be called from user defined handlers as well, with
+/// `frame` pointing to the stacked r0
+///
+/// # Safety
+/// `frame` must point to the eight word exception frame of the fault
+pub unsafe extern "C" fn handle_fault(frame: *const u32, kind: FaultKind) -> ! {
+    use registers::scb::{BFAR, CFSR_UFSR_BFSR_MMFSR, HFSR, MMFAR};
+
+    for index in 0..8 {
+        let value = unsafe { frame.add(index).read_volatile() };
+        FAULT_REPORT[index + 1].store(value, Ordering::Relaxed);
+    }
+
+    unsafe {
+        FAULT_REPORT[9].store(read_register(CFSR_UFSR_BFSR_MMFSR), Ordering::Relaxed);
+        FAULT_REPORT[10].store(read_register(HFSR), Ordering::Relaxed);
+        FAULT_REPORT[11].store(read_register(MMFAR), Ordering::Relaxed);
+        FAULT_REPORT[12].store(read_register(BFAR), Ordering::Relaxed);
+    }
+
+    FAULT_REPORT[0].store(kind as u32 + 1, Ordering::Relaxed);
+
+    if let Some(report) = get_fault_report() {
+        let callback = FAULT_CALLBACK.load(Ordering::Acquire);
+        if callback != 0 {
+            // Safety: only valid `fn(&FaultReport)` pointers are stored in the callback
+            let callback: fn(&FaultReport) =
+                unsafe { core::mem::transmute::<usize, fn(&FaultReport)>(callback) };
+            callback(&report);
+        }
+
+        let usart = FAULT_USART.load(Ordering::Relaxed);
+        if usart != 0 {
+            let _ = fmt::Write::write_fmt(
+                &mut UsartWriter::new(USARTS[usart - 1]),
+                format_args!("{}", report),
+            );
+        }
+    }
+
+    // Halt here, where a debugger can pick up the report
+    loop {
+        wait_for_interrupt();
+    }
+}
+
+/// Fault handlers, exported with the exception names used by `cortex-m-rt` when the
+/// `fault-handlers` feature is enabled. Each handler finds the exception frame on the stack that
+/// was active when the fault was taken and passes it to `handle_fault`. Leave the feature
+/// disabled if the application defines these handlers itself
+#[cfg(feature = "fault-handlers")]
+#[allow(non_snake_case)]
+mod handlers {
+    use core::arch::naked_asm;
+
+    use super::{FaultKind, handle_fault};
+
+    /// Bit 2 of the EXC_RETURN value in lr selects between the main and process stack
+    macro_rules! fault_handler {
+        ($name:ident, $kind:expr) => {
+            #[unsafe(naked)]
+            #[unsafe(no_mangle)]
+            extern "C" fn $name() {
+                naked_asm!(
+                    "tst lr, #4",
+                    "ite eq",
+                    "mrseq r0, MSP",
+                    "mrsne r0, PSP",
+                    "movs r1, #{kind}",
+                    "b {handler}",
+                    kind = const $kind as u8,
+                    handler = sym handle_fault,
+                );
+            }
+        };
+    }
+
+    fault_handler!(HardFault, FaultKind::HardFault);
+    fault_handler!(MemoryManagement, FaultKind::MemManage);
+    fault_handler!(BusFault, FaultKind::BusFault);
+    fault_handler!(UsageFault, FaultKind::UsageFault);
+}
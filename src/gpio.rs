@@ -1,9 +1,24 @@
 /// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
 use super::{
-    register_tools::{clear_bit, get_bit, set_bit, toggle_bit, write_bits},
-    registers,
+    dwt::{delay_cycles, enable_cycle_counter},
+    register_tools::{
+        FieldArray, clear_bit, dump, get_bit, read_register, set_bit, toggle_bit,
+        write_array_field, write_register,
+    },
+    registers::{self, blocks::GpioBlock},
 };
 
+/// The two bit per pin fields of the MODER, OSPEEDR and PUPDR registers
+const PIN_FIELDS_2: FieldArray<2, 16> = FieldArray;
+
+/// The four bit per pin fields of the AFRL and AFRH registers, each covering eight pins
+const AFR_FIELDS: FieldArray<4, 8> = FieldArray;
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum GpioRegister {
     GpioA,
@@ -58,6 +73,9 @@ pub enum GpioOutputMode {
     OpenDrain = 0b1,
 }
 
+/// Output slew rate of a pin. When driving pins at `HighSpeed` or `VeryHighSpeed`, for example
+/// for SDMMC or FMC, the I/O compensation cell should be enabled with
+/// `syscfg::enable_io_compensation`
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum GpioSpeed {
     LowSpeed,
@@ -97,6 +115,12 @@ pub struct Gpio {
     pub alternate: GpioAlternate,
 }
 
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Gpio {
     pub const fn new() -> Self {
         Self {
@@ -110,58 +134,104 @@ impl Gpio {
         }
     }
 
-    pub fn setup(&self) {
-        use registers::{
-            gpioa, gpiob, gpioc, gpiod, gpioe, gpioh, gpioi, gpioj, gpiok,
-            rcc::{AHB4ENR, ahb4enr},
-        };
+    /// Start a builder chain for a pin. The pin defaults to a floating, push-pull, low speed
+    /// input until configured otherwise, e.g.
+    /// `Gpio::on(GpioRegister::GpioB, GpioPin::P7).alternate(GpioAlternate::AF4).open_drain().setup()`
+    pub const fn on(register: GpioRegister, pin: GpioPin) -> Self {
+        let mut gpio = Self::new();
+        gpio.register = register;
+        gpio.pin = pin;
+        gpio
+    }
 
-        // Enable the gpio clock in ahb1
-        let ahb1_en_field = match self.register {
-            GpioRegister::GpioA => ahb4enr::GPIOAEN,
-            GpioRegister::GpioB => ahb4enr::GPIOBEN,
-            GpioRegister::GpioC => ahb4enr::GPIOCEN,
-            GpioRegister::GpioD => ahb4enr::GPIODEN,
-            GpioRegister::GpioE => ahb4enr::GPIOEEN,
-            GpioRegister::GpioH => ahb4enr::GPIOHEN,
-            GpioRegister::GpioI => ahb4enr::GPIOIEN,
-            GpioRegister::GpioJ => ahb4enr::GPIOJEN,
-            GpioRegister::GpioK => ahb4enr::GPIOKEN,
-        };
+    pub const fn input(mut self) -> Self {
+        self.mode = GpioMode::Input;
+        self
+    }
+
+    pub const fn output(mut self) -> Self {
+        self.mode = GpioMode::Output;
+        self
+    }
+
+    pub const fn alternate(mut self, alternate: GpioAlternate) -> Self {
+        self.mode = GpioMode::Alternate;
+        self.alternate = alternate;
+        self
+    }
+
+    pub const fn analog(mut self) -> Self {
+        self.mode = GpioMode::Analog;
+        self
+    }
+
+    pub const fn push_pull(mut self) -> Self {
+        self.output_mode = GpioOutputMode::PushPull;
+        self
+    }
+
+    pub const fn open_drain(mut self) -> Self {
+        self.output_mode = GpioOutputMode::OpenDrain;
+        self
+    }
+
+    pub const fn no_pull(mut self) -> Self {
+        self.pull = GpioPull::NoPull;
+        self
+    }
+
+    pub const fn pull_up(mut self) -> Self {
+        self.pull = GpioPull::PullUp;
+        self
+    }
+
+    pub const fn pull_down(mut self) -> Self {
+        self.pull = GpioPull::PullDown;
+        self
+    }
+
+    pub const fn speed(mut self, speed: GpioSpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub const fn low_speed(self) -> Self {
+        self.speed(GpioSpeed::LowSpeed)
+    }
+
+    pub const fn medium_speed(self) -> Self {
+        self.speed(GpioSpeed::MediumSpeed)
+    }
+
+    pub const fn high_speed(self) -> Self {
+        self.speed(GpioSpeed::HighSpeed)
+    }
+
+    pub const fn very_high_speed(self) -> Self {
+        self.speed(GpioSpeed::VeryHighSpeed)
+    }
+
+    pub fn setup(&self) {
+        use registers::rcc::AHB4ENR;
 
         // See section 6.3.9 RCC AHB1 peripheral clock enable register (RCC_ahb4enr)
         unsafe {
-            set_bit(AHB4ENR, ahb1_en_field);
+            set_bit(AHB4ENR, get_ahb4enr_clock_field(self.register));
         }
 
-        let moder_register = match self.register {
-            GpioRegister::GpioA => gpioa::MODER,
-            GpioRegister::GpioB => gpiob::MODER,
-            GpioRegister::GpioC => gpioc::MODER,
-            GpioRegister::GpioD => gpiod::MODER,
-            GpioRegister::GpioE => gpioe::MODER,
-            GpioRegister::GpioH => gpioh::MODER,
-            GpioRegister::GpioI => gpioi::MODER,
-            GpioRegister::GpioJ => gpioj::MODER,
-            GpioRegister::GpioK => gpiok::MODER,
-        };
+        let moder_register = get_moder_register(self.register);
 
         unsafe {
             // Clear and write the general pin mode to the MODER register
-            write_bits(moder_register, self.pin as u8 * 2, self.mode as u32, 0b11);
+            write_array_field(
+                moder_register,
+                PIN_FIELDS_2,
+                self.pin as u8,
+                self.mode as u32,
+            );
         }
 
-        let otyper_register = match self.register {
-            GpioRegister::GpioA => gpioa::OTYPER,
-            GpioRegister::GpioB => gpiob::OTYPER,
-            GpioRegister::GpioC => gpioc::OTYPER,
-            GpioRegister::GpioD => gpiod::OTYPER,
-            GpioRegister::GpioE => gpioe::OTYPER,
-            GpioRegister::GpioH => gpioh::OTYPER,
-            GpioRegister::GpioI => gpioi::OTYPER,
-            GpioRegister::GpioJ => gpioj::OTYPER,
-            GpioRegister::GpioK => gpiok::OTYPER,
-        };
+        let otyper_register = get_otyper_register(self.register);
 
         if self.output_mode == GpioOutputMode::PushPull {
             unsafe {
@@ -175,55 +245,79 @@ impl Gpio {
             }
         }
 
-        let pupdr_register = match self.register {
-            GpioRegister::GpioA => gpioa::PUPDR,
-            GpioRegister::GpioB => gpiob::PUPDR,
-            GpioRegister::GpioC => gpioc::PUPDR,
-            GpioRegister::GpioD => gpiod::PUPDR,
-            GpioRegister::GpioE => gpioe::PUPDR,
-            GpioRegister::GpioH => gpioh::PUPDR,
-            GpioRegister::GpioI => gpioi::PUPDR,
-            GpioRegister::GpioJ => gpioj::PUPDR,
-            GpioRegister::GpioK => gpiok::PUPDR,
-        };
+        let ospeedr_register = get_ospeedr_register(self.register);
+
+        unsafe {
+            // Set the output speed in the OSPEEDR register
+            write_array_field(
+                ospeedr_register,
+                PIN_FIELDS_2,
+                self.pin as u8,
+                self.speed as u32,
+            );
+        }
+
+        let pupdr_register = get_pupdr_register(self.register);
 
         unsafe {
             // Set the PUPDR register to enable/disable pull up/down
-            write_bits(pupdr_register, self.pin as u8, self.pull as u32, 0b11);
+            write_array_field(
+                pupdr_register,
+                PIN_FIELDS_2,
+                self.pin as u8,
+                self.pull as u32,
+            );
         }
 
         if self.mode == GpioMode::Alternate {
-            let afr_register = if self.pin < GpioPin::P8 {
-                match self.register {
-                    GpioRegister::GpioA => gpioa::AFRL,
-                    GpioRegister::GpioB => gpiob::AFRL,
-                    GpioRegister::GpioC => gpioc::AFRL,
-                    GpioRegister::GpioD => gpiod::AFRL,
-                    GpioRegister::GpioE => gpioe::AFRL,
-                    GpioRegister::GpioH => gpioh::AFRL,
-                    GpioRegister::GpioI => gpioi::AFRL,
-                    GpioRegister::GpioJ => gpioj::AFRL,
-                    GpioRegister::GpioK => gpiok::AFRL,
-                }
-            } else {
-                match self.register {
-                    GpioRegister::GpioA => gpioa::AFRH,
-                    GpioRegister::GpioB => gpiob::AFRH,
-                    GpioRegister::GpioC => gpioc::AFRH,
-                    GpioRegister::GpioD => gpiod::AFRH,
-                    GpioRegister::GpioE => gpioe::AFRH,
-                    GpioRegister::GpioH => gpioh::AFRH,
-                    GpioRegister::GpioI => gpioi::AFRH,
-                    GpioRegister::GpioJ => gpioj::AFRH,
-                    GpioRegister::GpioK => gpiok::AFRH,
-                }
-            };
+            let afr_register = get_afr_register(self.register, self.pin);
 
             // Set the alternate function for the pin in either the AFR high or low register
-            let afr_field = (self.pin as u8 % 8) * 4;
-
             unsafe {
-                write_bits(afr_register, afr_field, self.alternate as u32, 0b1111);
+                write_array_field(
+                    afr_register,
+                    AFR_FIELDS,
+                    self.pin as u8 % 8,
+                    self.alternate as u32,
+                );
+            }
+        }
+    }
+
+    /// Return the pin to its reset state, being analog mode with no pull, push-pull and AF0. The
+    /// port clock is gated off if no other pin of the port is in use, meaning that all pins of
+    /// the port are in analog mode
+    pub fn cleanup(&self) {
+        use registers::rcc::AHB4ENR;
+
+        let moder_register = get_moder_register(self.register);
+        let pin = self.pin as u8;
+
+        unsafe {
+            write_array_field(moder_register, PIN_FIELDS_2, pin, GpioMode::Analog as u32);
+            clear_bit(get_otyper_register(self.register), pin);
+            write_array_field(
+                get_ospeedr_register(self.register),
+                PIN_FIELDS_2,
+                pin,
+                GpioSpeed::LowSpeed as u32,
+            );
+            write_array_field(
+                get_pupdr_register(self.register),
+                PIN_FIELDS_2,
+                pin,
+                GpioPull::NoPull as u32,
+            );
+            write_array_field(
+                get_afr_register(self.register, self.pin),
+                AFR_FIELDS,
+                pin % 8,
+                GpioAlternate::AF0 as u32,
+            );
+
+            // All pins in analog mode means that nothing on the port is in use
+            if read_register(moder_register) == 0xFFFF_FFFF {
+                clear_bit(AHB4ENR, get_ahb4enr_clock_field(self.register));
             }
         }
     }
@@ -232,10 +326,18 @@ impl Gpio {
         set(self.register, self.pin);
     }
 
+    /// Read the sensed level of the pin from the IDR register. For an output this is the actual
+    /// level on the line, which for an open drain output can be pulled low by another device
     pub fn get(&self) -> bool {
         get(self.register, self.pin)
     }
 
+    /// Read the commanded output state of the pin from the ODR register. Unlike `get`, this is
+    /// the value last written with `set`, `clear` or `toggle`, regardless of the level on the line
+    pub fn is_set(&self) -> bool {
+        is_set(self.register, self.pin)
+    }
+
     pub fn clear(&self) {
         clear(self.register, self.pin);
     }
@@ -271,78 +373,108 @@ fn get(register: GpioRegister, pin: GpioPin) -> bool {
     unsafe { get_bit(idr.0, idr.1) == 1 }
 }
 
-fn get_odr(register: GpioRegister, pin: GpioPin) -> (*mut u32, u8) {
-    use registers::{gpioa, gpiob, gpioc, gpiod, gpioe, gpioh, gpioi, gpioj, gpiok};
-
-    let odr_register = match register {
-        GpioRegister::GpioA => gpioa::ODR,
-        GpioRegister::GpioB => gpiob::ODR,
-        GpioRegister::GpioC => gpioc::ODR,
-        GpioRegister::GpioD => gpiod::ODR,
-        GpioRegister::GpioE => gpioe::ODR,
-        GpioRegister::GpioH => gpioh::ODR,
-        GpioRegister::GpioI => gpioi::ODR,
-        GpioRegister::GpioJ => gpioj::ODR,
-        GpioRegister::GpioK => gpiok::ODR,
-    };
-
-    let odr_field = match pin {
-        GpioPin::P0 => gpioa::odr::OD0,
-        GpioPin::P1 => gpioa::odr::OD1,
-        GpioPin::P2 => gpioa::odr::OD2,
-        GpioPin::P3 => gpioa::odr::OD3,
-        GpioPin::P4 => gpioa::odr::OD4,
-        GpioPin::P5 => gpioa::odr::OD5,
-        GpioPin::P6 => gpioa::odr::OD6,
-        GpioPin::P7 => gpioa::odr::OD7,
-        GpioPin::P8 => gpioa::odr::OD8,
-        GpioPin::P9 => gpioa::odr::OD9,
-        GpioPin::P10 => gpioa::odr::OD10,
-        GpioPin::P11 => gpioa::odr::OD11,
-        GpioPin::P12 => gpioa::odr::OD12,
-        GpioPin::P13 => gpioa::odr::OD13,
-        GpioPin::P14 => gpioa::odr::OD14,
-        GpioPin::P15 => gpioa::odr::OD15,
-    };
-
-    (odr_register, odr_field)
+const fn get_ahb4enr_clock_field(register: GpioRegister) -> u8 {
+    use registers::rcc::ahb4enr;
+
+    match register {
+        GpioRegister::GpioA => ahb4enr::GPIOAEN,
+        GpioRegister::GpioB => ahb4enr::GPIOBEN,
+        GpioRegister::GpioC => ahb4enr::GPIOCEN,
+        GpioRegister::GpioD => ahb4enr::GPIODEN,
+        GpioRegister::GpioE => ahb4enr::GPIOEEN,
+        GpioRegister::GpioH => ahb4enr::GPIOHEN,
+        GpioRegister::GpioI => ahb4enr::GPIOIEN,
+        GpioRegister::GpioJ => ahb4enr::GPIOJEN,
+        GpioRegister::GpioK => ahb4enr::GPIOKEN,
+    }
+}
+
+/// Print the registers of a port, see `register_tools::dump`. The port clock has to be enabled,
+/// otherwise all registers read as zero
+pub fn dump_gpio(register: GpioRegister, writer: &mut impl fmt::Write) -> fmt::Result {
+    let block = get_gpio_block(register);
+
+    unsafe {
+        dump(
+            &[
+                ("MODER", &raw const (*block).moder),
+                ("OTYPER", &raw const (*block).otyper),
+                ("OSPEEDR", &raw const (*block).ospeedr),
+                ("PUPDR", &raw const (*block).pupdr),
+                ("IDR", &raw const (*block).idr),
+                ("ODR", &raw const (*block).odr),
+                ("LCKR", &raw const (*block).lckr),
+                ("AFRL", &raw const (*block).afrl),
+                ("AFRH", &raw const (*block).afrh),
+            ],
+            writer,
+        )
+    }
+}
+
+/// The register block of a port
+const fn get_gpio_block(register: GpioRegister) -> *mut GpioBlock {
+    use registers::blocks::{GPIOA, GPIOB, GPIOC, GPIOD, GPIOE, GPIOH, GPIOI, GPIOJ, GPIOK};
+
+    match register {
+        GpioRegister::GpioA => GPIOA,
+        GpioRegister::GpioB => GPIOB,
+        GpioRegister::GpioC => GPIOC,
+        GpioRegister::GpioD => GPIOD,
+        GpioRegister::GpioE => GPIOE,
+        GpioRegister::GpioH => GPIOH,
+        GpioRegister::GpioI => GPIOI,
+        GpioRegister::GpioJ => GPIOJ,
+        GpioRegister::GpioK => GPIOK,
+    }
+}
+
+const fn get_moder_register(register: GpioRegister) -> *mut u32 {
+    unsafe { &raw mut (*get_gpio_block(register)).moder }
+}
+
+const fn get_otyper_register(register: GpioRegister) -> *mut u32 {
+    unsafe { &raw mut (*get_gpio_block(register)).otyper }
+}
+
+const fn get_ospeedr_register(register: GpioRegister) -> *mut u32 {
+    unsafe { &raw mut (*get_gpio_block(register)).ospeedr }
+}
+
+const fn get_pupdr_register(register: GpioRegister) -> *mut u32 {
+    unsafe { &raw mut (*get_gpio_block(register)).pupdr }
+}
+
+/// Get the AFR low register for pin 0-7 or the AFR high register for pin 8-15
+const fn get_afr_register(register: GpioRegister, pin: GpioPin) -> *mut u32 {
+    let block = get_gpio_block(register);
+
+    if (pin as u8) < GpioPin::P8 as u8 {
+        unsafe { &raw mut (*block).afrl }
+    } else {
+        unsafe { &raw mut (*block).afrh }
+    }
+}
+
+fn is_set(register: GpioRegister, pin: GpioPin) -> bool {
+    let odr = get_odr(register, pin);
+    unsafe { get_bit(odr.0, odr.1) == 1 }
+}
+
+/// The ODR register of a port and the bit of a pin in it, which is the pin number
+const fn get_odr(register: GpioRegister, pin: GpioPin) -> (*mut u32, u8) {
+    (
+        unsafe { &raw mut (*get_gpio_block(register)).odr },
+        pin as u8,
+    )
 }
 
+/// The IDR register of a port and the bit of a pin in it, which is the pin number
 const fn get_idr(register: GpioRegister, pin: GpioPin) -> (*mut u32, u8) {
-    use registers::{gpioa, gpiob, gpioc, gpiod, gpioe, gpioh, gpioi, gpioj, gpiok};
-
-    let odr_register = match register {
-        GpioRegister::GpioA => gpioa::IDR,
-        GpioRegister::GpioB => gpiob::IDR,
-        GpioRegister::GpioC => gpioc::IDR,
-        GpioRegister::GpioD => gpiod::IDR,
-        GpioRegister::GpioE => gpioe::IDR,
-        GpioRegister::GpioH => gpioh::IDR,
-        GpioRegister::GpioI => gpioi::IDR,
-        GpioRegister::GpioJ => gpioj::IDR,
-        GpioRegister::GpioK => gpiok::IDR,
-    };
-
-    let odr_field = match pin {
-        GpioPin::P0 => gpioa::idr::ID0,
-        GpioPin::P1 => gpioa::idr::ID1,
-        GpioPin::P2 => gpioa::idr::ID2,
-        GpioPin::P3 => gpioa::idr::ID3,
-        GpioPin::P4 => gpioa::idr::ID4,
-        GpioPin::P5 => gpioa::idr::ID5,
-        GpioPin::P6 => gpioa::idr::ID6,
-        GpioPin::P7 => gpioa::idr::ID7,
-        GpioPin::P8 => gpioa::idr::ID8,
-        GpioPin::P9 => gpioa::idr::ID9,
-        GpioPin::P10 => gpioa::idr::ID10,
-        GpioPin::P11 => gpioa::idr::ID11,
-        GpioPin::P12 => gpioa::idr::ID12,
-        GpioPin::P13 => gpioa::idr::ID13,
-        GpioPin::P14 => gpioa::idr::ID14,
-        GpioPin::P15 => gpioa::idr::ID15,
-    };
-
-    (odr_register, odr_field)
+    (
+        unsafe { &raw mut (*get_gpio_block(register)).idr },
+        pin as u8,
+    )
 }
 
 /// Create a simple output gpio
@@ -353,3 +485,460 @@ pub const fn create_output(register: GpioRegister, pin: GpioPin) -> Gpio {
     led.mode = GpioMode::Output;
     led
 }
+
+/// Create a simple input gpio with the specified pull up/down
+pub const fn create_input(register: GpioRegister, pin: GpioPin, pull: GpioPull) -> Gpio {
+    let mut gpio = Gpio::new();
+    gpio.register = register;
+    gpio.pin = pin;
+    gpio.mode = GpioMode::Input;
+    gpio.pull = pull;
+    gpio
+}
+
+/// Create a gpio routed to an alternate function, such as a usart or timer channel
+pub const fn create_alternate(
+    register: GpioRegister,
+    pin: GpioPin,
+    alternate: GpioAlternate,
+    speed: GpioSpeed,
+) -> Gpio {
+    let mut gpio = Gpio::new();
+    gpio.register = register;
+    gpio.pin = pin;
+    gpio.mode = GpioMode::Alternate;
+    gpio.alternate = alternate;
+    gpio.speed = speed;
+    gpio
+}
+
+/// Create an analog gpio, used for ADC and DAC pins
+pub const fn create_analog(register: GpioRegister, pin: GpioPin) -> Gpio {
+    let mut gpio = Gpio::new();
+    gpio.register = register;
+    gpio.pin = pin;
+    gpio.mode = GpioMode::Analog;
+    gpio
+}
+
+/// Create an open drain output gpio. Note that the line needs an external pull up, or one set
+/// through the `pull` field
+pub const fn create_open_drain(register: GpioRegister, pin: GpioPin) -> Gpio {
+    let mut gpio = Gpio::new();
+    gpio.register = register;
+    gpio.pin = pin;
+    gpio.mode = GpioMode::Output;
+    gpio.output_mode = GpioOutputMode::OpenDrain;
+    gpio
+}
+
+/// A gpio input filtered in software, for buttons and limit switches. The pin is sampled by
+/// calling `tick` at a fixed interval, typically from a cyclical timer interrupt, and a new level
+/// is only accepted once it has been stable for the debounce time. All state is atomic, so the
+/// input can be placed in a `static` and shared between the interrupt and the main loop
+pub struct DebouncedInput {
+    gpio: Gpio,
+    active_low: bool,
+    required_samples: u32,
+    stable_samples: AtomicU32,
+    level: AtomicBool,
+    rising: AtomicBool,
+    falling: AtomicBool,
+}
+
+impl DebouncedInput {
+    /// Create a debounced input that must be stable for `debounce_ms` before a change is
+    /// accepted, when `tick` is called every `tick_interval_ms`
+    pub const fn new(gpio: Gpio, debounce_ms: u32, tick_interval_ms: u32) -> Self {
+        let tick_interval_ms = if tick_interval_ms == 0 {
+            1
+        } else {
+            tick_interval_ms
+        };
+
+        Self {
+            gpio,
+            active_low: false,
+            required_samples: debounce_ms.div_ceil(tick_interval_ms),
+            stable_samples: AtomicU32::new(0),
+            level: AtomicBool::new(false),
+            rising: AtomicBool::new(false),
+            falling: AtomicBool::new(false),
+        }
+    }
+
+    /// Treat a low level as pressed, for buttons connected to ground with a pull up
+    pub const fn active_low(mut self) -> Self {
+        self.active_low = true;
+        self
+    }
+
+    /// Setup the underlying gpio
+    pub fn setup(&self) {
+        self.gpio.setup();
+        self.level.store(self.gpio.get(), Ordering::Relaxed);
+    }
+
+    /// Sample the pin. Should be called periodically with the interval given in `new`
+    pub fn tick(&self) {
+        let sample = self.gpio.get();
+        let level = self.level.load(Ordering::Relaxed);
+
+        if sample == level {
+            self.stable_samples.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let stable_samples = self.stable_samples.load(Ordering::Relaxed) + 1;
+        if stable_samples < self.required_samples {
+            self.stable_samples.store(stable_samples, Ordering::Relaxed);
+            return;
+        }
+
+        // The new level has been stable long enough to be accepted
+        self.stable_samples.store(0, Ordering::Relaxed);
+        self.level.store(sample, Ordering::Relaxed);
+
+        if sample {
+            self.rising.store(true, Ordering::Release);
+        } else {
+            self.falling.store(true, Ordering::Release);
+        }
+    }
+
+    /// The debounced level of the pin
+    pub fn get(&self) -> bool {
+        self.level.load(Ordering::Relaxed)
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.get() != self.active_low
+    }
+
+    /// Returns true once for every debounced low to high transition
+    pub fn rising_event(&self) -> bool {
+        self.rising.swap(false, Ordering::Acquire)
+    }
+
+    /// Returns true once for every debounced high to low transition
+    pub fn falling_event(&self) -> bool {
+        self.falling.swap(false, Ordering::Acquire)
+    }
+}
+
+/// Setup a group of gpios, such as the pins of an FMC or SDMMC interface. The pins are grouped
+/// per port, and every configuration register of a port is written once with the combined
+/// values of all pins in the group, instead of one read-modify-write per pin and register
+pub fn setup_group(gpios: &[Gpio]) {
+    use registers::rcc::AHB4ENR;
+
+    const REGISTERS: [GpioRegister; 9] = [
+        GpioRegister::GpioA,
+        GpioRegister::GpioB,
+        GpioRegister::GpioC,
+        GpioRegister::GpioD,
+        GpioRegister::GpioE,
+        GpioRegister::GpioH,
+        GpioRegister::GpioI,
+        GpioRegister::GpioJ,
+        GpioRegister::GpioK,
+    ];
+
+    for register in REGISTERS {
+        let mut used = false;
+        let mut moder = (0u32, 0u32);
+        let mut otyper = (0u32, 0u32);
+        let mut ospeedr = (0u32, 0u32);
+        let mut pupdr = (0u32, 0u32);
+        let mut afrl = (0u32, 0u32);
+        let mut afrh = (0u32, 0u32);
+
+        // Accumulate the (mask, value) pair of every register for the pins on this port
+        for gpio in gpios.iter().filter(|gpio| gpio.register == register) {
+            used = true;
+            let pin = gpio.pin as u32;
+
+            moder.0 |= 0b11 << (pin * 2);
+            moder.1 |= (gpio.mode as u32) << (pin * 2);
+
+            otyper.0 |= 0b1 << pin;
+            otyper.1 |= (gpio.output_mode as u32) << pin;
+
+            ospeedr.0 |= 0b11 << (pin * 2);
+            ospeedr.1 |= (gpio.speed as u32) << (pin * 2);
+
+            pupdr.0 |= 0b11 << (pin * 2);
+            pupdr.1 |= (gpio.pull as u32) << (pin * 2);
+
+            if gpio.mode == GpioMode::Alternate {
+                let afr = if gpio.pin < GpioPin::P8 {
+                    &mut afrl
+                } else {
+                    &mut afrh
+                };
+                afr.0 |= 0b1111 << ((pin % 8) * 4);
+                afr.1 |= (gpio.alternate as u32) << ((pin % 8) * 4);
+            }
+        }
+
+        if !used {
+            continue;
+        }
+
+        unsafe {
+            set_bit(AHB4ENR, get_ahb4enr_clock_field(register));
+
+            // The alternate function is selected before the mode, so the pin never outputs
+            // another alternate function
+            for (afr_register, (mask, value)) in [
+                (get_afr_register(register, GpioPin::P0), afrl),
+                (get_afr_register(register, GpioPin::P8), afrh),
+            ] {
+                if mask != 0 {
+                    write_masked(afr_register, mask, value);
+                }
+            }
+
+            write_masked(get_otyper_register(register), otyper.0, otyper.1);
+            write_masked(get_ospeedr_register(register), ospeedr.0, ospeedr.1);
+            write_masked(get_pupdr_register(register), pupdr.0, pupdr.1);
+            write_masked(get_moder_register(register), moder.0, moder.1);
+        }
+    }
+}
+
+/// Replace the bits in `mask` with `value` using a single read and write
+unsafe fn write_masked(register: *mut u32, mask: u32, value: u32) {
+    unsafe { write_register(register, (read_register(register) & !mask) | value) };
+}
+
+/// Signalling style of a `ParallelBus`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ParallelBusStyle {
+    /// Separate active low write and read strobes
+    Intel8080,
+    /// A read/write select on the `wr` pin (high for read) and an active high enable strobe on
+    /// the `rd` pin
+    Motorola6800,
+}
+
+/// A bit-banged parallel bus, for 8080 and 6800 style LCD controllers and other legacy parallel
+/// peripherals. Data pin `n` in `data` carries bit `n` of the value. The strobe timing is given
+/// in core clock cycles and measured with the DWT cycle counter
+pub struct ParallelBus<const N: usize> {
+    data: [Gpio; N],
+    wr: Gpio,
+    rd: Gpio,
+    cs: Gpio,
+    style: ParallelBusStyle,
+    setup_cycles: u32,
+    strobe_cycles: u32,
+    hold_cycles: u32,
+}
+
+impl<const N: usize> ParallelBus<N> {
+    /// Create a bus from the data and strobe pins. Only the register and pin of each gpio is
+    /// used, as the bus configures the pins itself
+    pub const fn new(data: [Gpio; N], wr: Gpio, rd: Gpio, cs: Gpio) -> Self {
+        assert!(
+            N > 0 && N <= 16,
+            "A parallel bus has between 1 and 16 data pins"
+        );
+
+        Self {
+            data,
+            wr,
+            rd,
+            cs,
+            style: ParallelBusStyle::Intel8080,
+            setup_cycles: 0,
+            strobe_cycles: 0,
+            hold_cycles: 0,
+        }
+    }
+
+    pub const fn style(mut self, style: ParallelBusStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Set the number of core clock cycles data is stable before the strobe, the strobe is held
+    /// active, and data is held after the strobe
+    pub const fn timing(mut self, setup_cycles: u32, strobe_cycles: u32, hold_cycles: u32) -> Self {
+        self.setup_cycles = setup_cycles;
+        self.strobe_cycles = strobe_cycles;
+        self.hold_cycles = hold_cycles;
+        self
+    }
+
+    /// Setup all pins with the strobes inactive and the data pins as inputs
+    pub fn setup(&self) {
+        enable_cycle_counter();
+
+        for gpio in [self.wr, self.rd, self.cs] {
+            let gpio = gpio.output().push_pull().very_high_speed();
+            gpio.setup();
+        }
+
+        self.cs.set();
+        self.idle_strobes();
+
+        for gpio in self.data {
+            gpio.input().no_pull().very_high_speed().setup();
+        }
+    }
+
+    pub fn write_byte(&self, value: u8) {
+        self.write(value as u16);
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        self.read() as u8
+    }
+
+    /// Write the lowest `N` bits of `value` to the bus
+    pub fn write(&self, value: u16) {
+        self.set_data_mode(GpioMode::Output);
+
+        for (bit, gpio) in self.data.iter().enumerate() {
+            if value & (1 << bit) != 0 {
+                gpio.set();
+            } else {
+                gpio.clear();
+            }
+        }
+
+        self.cs.clear();
+        match self.style {
+            ParallelBusStyle::Intel8080 => {
+                delay_cycles(self.setup_cycles);
+                self.wr.clear();
+                delay_cycles(self.strobe_cycles);
+                self.wr.set();
+            }
+            ParallelBusStyle::Motorola6800 => {
+                self.wr.clear();
+                delay_cycles(self.setup_cycles);
+                self.rd.set();
+                delay_cycles(self.strobe_cycles);
+                self.rd.clear();
+            }
+        }
+        delay_cycles(self.hold_cycles);
+        self.cs.set();
+    }
+
+    /// Read `N` bits from the bus
+    pub fn read(&self) -> u16 {
+        self.set_data_mode(GpioMode::Input);
+
+        self.cs.clear();
+        match self.style {
+            ParallelBusStyle::Intel8080 => {
+                delay_cycles(self.setup_cycles);
+                self.rd.clear();
+            }
+            ParallelBusStyle::Motorola6800 => {
+                self.wr.set();
+                delay_cycles(self.setup_cycles);
+                self.rd.set();
+            }
+        }
+        delay_cycles(self.strobe_cycles);
+
+        let mut value = 0;
+        for (bit, gpio) in self.data.iter().enumerate() {
+            if gpio.get() {
+                value |= 1 << bit;
+            }
+        }
+
+        // Release the strobe
+        self.idle_strobes();
+        delay_cycles(self.hold_cycles);
+        self.cs.set();
+
+        value
+    }
+
+    fn idle_strobes(&self) {
+        match self.style {
+            ParallelBusStyle::Intel8080 => {
+                self.wr.set();
+                self.rd.set();
+            }
+            ParallelBusStyle::Motorola6800 => {
+                self.wr.set();
+                self.rd.clear();
+            }
+        }
+    }
+
+    fn set_data_mode(&self, mode: GpioMode) {
+        for gpio in self.data {
+            unsafe {
+                write_array_field(
+                    get_moder_register(gpio.register),
+                    PIN_FIELDS_2,
+                    gpio.pin as u8,
+                    mode as u32,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock-registers"))]
+mod tests {
+    use super::*;
+    use crate::register_tools::mock::{get_register, reset_registers, set_register};
+    use registers::{gpioa, gpiob, rcc};
+
+    #[test]
+    fn setup_writes_the_two_bit_pin_fields() {
+        reset_registers();
+
+        Gpio::on(GpioRegister::GpioA, GpioPin::P5)
+            .output()
+            .pull_up()
+            .speed(GpioSpeed::HighSpeed)
+            .setup();
+
+        assert_eq!(get_register(gpioa::MODER), 0b01 << 10);
+        assert_eq!(get_register(gpioa::OSPEEDR), 0b10 << 10);
+        assert_eq!(get_register(gpioa::PUPDR), 0b10 << 10);
+        assert_eq!(get_register(gpioa::OTYPER), 0);
+    }
+
+    #[test]
+    fn setup_writes_the_alternate_function_of_upper_pins_to_afrh() {
+        reset_registers();
+
+        Gpio::on(GpioRegister::GpioB, GpioPin::P10)
+            .alternate(GpioAlternate::AF7)
+            .open_drain()
+            .setup();
+
+        assert_eq!(get_register(gpiob::MODER), 0b10 << 20);
+        assert_eq!(get_register(gpiob::OTYPER), 1 << 10);
+        assert_eq!(get_register(gpiob::AFRL), 0);
+        assert_eq!(get_register(gpiob::AFRH), 0b0111 << 8);
+    }
+
+    #[test]
+    fn cleanup_gates_the_port_clock_when_all_pins_are_analog() {
+        reset_registers();
+        set_register(gpioa::MODER, !(0b11 << 10));
+
+        let gpio = Gpio::on(GpioRegister::GpioA, GpioPin::P5).output();
+        gpio.setup();
+        assert_eq!(
+            get_register(rcc::AHB4ENR) & (1 << rcc::ahb4enr::GPIOAEN),
+            1 << rcc::ahb4enr::GPIOAEN
+        );
+
+        gpio.cleanup();
+        assert_eq!(get_register(gpioa::MODER), 0xFFFF_FFFF);
+        assert_eq!(get_register(rcc::AHB4ENR) & (1 << rcc::ahb4enr::GPIOAEN), 0);
+    }
+}
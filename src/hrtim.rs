@@ -0,0 +1,366 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    gpio::Gpio,
+    pwm::BreakPolarity,
+    register_tools::{clear_bit, get_bit, read_register, set_bit, write_bits, write_register},
+    registers,
+    timers::TimerError,
+};
+
+/// Timing units A to E of the high-resolution timer. Each unit has its own counter and drives
+/// two outputs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HrtimTimer {
+    TimerA,
+    TimerB,
+    TimerC,
+    TimerD,
+    TimerE,
+}
+
+/// Output 1 is reset by compare 1 and output 2 by compare 2, so both have their own duty cycle
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HrtimOutput {
+    Output1,
+    Output2,
+}
+
+/// Fault inputs, which turn off the outputs of the timing units they're enabled for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HrtimFault {
+    Fault1,
+    Fault2,
+    Fault3,
+    Fault4,
+    Fault5,
+}
+
+/// Shortest and longest period the timing units accept, in ticks
+const HRTIM_MIN_PERIOD: u32 = 0x0003;
+const HRTIM_MAX_PERIOD: u32 = 0xFFFD;
+
+/// Largest dead-time value for the rising and falling edges
+const HRTIM_MAX_DEAD_TIME: u32 = 0x1FF;
+
+/// Output PWM at `frequency` on an output of a timing unit, starting with a duty cycle of zero.
+/// The counter runs at `clock_frequency` divided by the smallest power of two that fits the
+/// period, which gives a resolution of 2.5 ns with a 400 MHz HRTIM clock. Both outputs of a unit
+/// share the frequency, so setting up the other output resets it
+pub fn setup_hrtim_pwm(
+    timer: &HrtimTimer,
+    output: HrtimOutput,
+    gpio: &Gpio,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{hrtim_common, hrtim_master, hrtim_tima};
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    if frequency == 0 {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    // Use the smallest prescaler that fits the period, to get the finest resolution
+    let ticks = clock_frequency / frequency;
+    let prescaler = (0..8)
+        .find(|prescaler| ticks >> prescaler <= HRTIM_MAX_PERIOD)
+        .ok_or(TimerError::InvalidFrequency(frequency))?;
+    let period = ticks >> prescaler;
+    if period < HRTIM_MIN_PERIOD {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let timer_control_register = get_timer_register(timer, hrtim_tima::TIMACR);
+
+    enable_hrtim_clock();
+    gpio.setup();
+
+    unsafe {
+        // Stop the counter while it's reconfigured
+        clear_bit(hrtim_master::MCR, hrtim_master::mcr::TACEN + *timer as u8);
+
+        write_bits(
+            timer_control_register,
+            hrtim_tima::timacr::CK_PSCX,
+            prescaler,
+            0b111,
+        );
+
+        // Count continuously, and load the buffered period and compare values at each rollover
+        set_bit(timer_control_register, hrtim_tima::timacr::CONT);
+        set_bit(timer_control_register, hrtim_tima::timacr::PREEN);
+        set_bit(timer_control_register, hrtim_tima::timacr::TXRSTU);
+
+        write_register(get_timer_register(timer, hrtim_tima::PERAR), period);
+    }
+
+    set_hrtim_duty(timer, output, 0);
+
+    unsafe {
+        // Load the buffered registers right away
+        set_bit(hrtim_common::CR2, hrtim_common::cr2::TASWU + *timer as u8);
+
+        // Enable the output with active high polarity
+        clear_bit(
+            get_timer_register(timer, hrtim_tima::OUTAR),
+            hrtim_tima::outar::POL1 + output as u8 * 16,
+        );
+        write_register(
+            hrtim_common::OENR,
+            1 << get_output_enable_field(timer, output),
+        );
+
+        // Start the counter
+        set_bit(hrtim_master::MCR, hrtim_master::mcr::TACEN + *timer as u8);
+    }
+
+    Ok(())
+}
+
+/// Output PWM on output 1 of a timing unit, and its inverted complementary on output 2 with a
+/// dead-time inserted by `set_hrtim_dead_time`, e.g. for the switches of a half bridge
+pub fn setup_hrtim_complementary_pwm(
+    timer: &HrtimTimer,
+    gpio: &Gpio,
+    gpio_n: &Gpio,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{hrtim_common, hrtim_tima};
+
+    setup_hrtim_pwm(
+        timer,
+        HrtimOutput::Output1,
+        gpio,
+        frequency,
+        clock_frequency,
+    )?;
+
+    gpio_n.setup();
+
+    unsafe {
+        // Let the dead-time generator derive output 2 from output 1
+        set_bit(
+            get_timer_register(timer, hrtim_tima::OUTAR),
+            hrtim_tima::outar::DTEN,
+        );
+        write_register(
+            hrtim_common::OENR,
+            1 << get_output_enable_field(timer, HrtimOutput::Output2),
+        );
+    }
+
+    Ok(())
+}
+
+/// Number of ticks in a period of a timing unit. A duty cycle of this value keeps the output
+/// active for the whole period
+pub fn get_hrtim_max_duty(timer: &HrtimTimer) -> u32 {
+    use registers::hrtim_tima;
+    unsafe { read_register(get_timer_register(timer, hrtim_tima::PERAR)) }
+}
+
+/// Set the duty cycle of an output in ticks, see `get_hrtim_max_duty`. The output is set at the
+/// start of the period and reset at the compare value, which applies at the next period
+pub fn set_hrtim_duty(timer: &HrtimTimer, output: HrtimOutput, duty: u32) {
+    use registers::hrtim_tima;
+
+    let period = get_hrtim_max_duty(timer);
+
+    // The set and reset registers of output 2 follow those of output 1
+    let set_register =
+        get_timer_register(timer, hrtim_tima::SETA1R).wrapping_add(output as usize * 2);
+    let reset_register =
+        get_timer_register(timer, hrtim_tima::RSTA1R).wrapping_add(output as usize * 2);
+    let compare_register =
+        get_timer_register(timer, hrtim_tima::CMP1AR).wrapping_add(output as usize * 2);
+
+    unsafe {
+        if duty == 0 {
+            // Never set the output
+            write_register(set_register, 0);
+            write_register(reset_register, 1 << hrtim_tima::seta1r::PER);
+        } else if duty >= period {
+            // Never reset the output
+            write_register(set_register, 1 << hrtim_tima::seta1r::PER);
+            write_register(reset_register, 0);
+        } else {
+            write_register(compare_register, duty.max(HRTIM_MIN_PERIOD));
+            write_register(set_register, 1 << hrtim_tima::seta1r::PER);
+            write_register(
+                reset_register,
+                1 << (hrtim_tima::seta1r::CMP1 + output as u8),
+            );
+        }
+    }
+}
+
+/// Set the dead-time before the rising edges of output 1 and 2 in complementary mode. The
+/// dead-time clock is the HRTIM clock divided by the smallest power of two that reaches both
+/// dead-times in 9 bits. Returns `TimerError::InvalidDeadTime` if it can't be reached
+pub fn set_hrtim_dead_time(
+    timer: &HrtimTimer,
+    rising_ns: u32,
+    falling_ns: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::hrtim_tima;
+
+    let to_ticks = |ns: u32| (ns as u64 * clock_frequency as u64 / 1_000_000_000) as u32;
+    let (rising, falling) = (to_ticks(rising_ns), to_ticks(falling_ns));
+
+    let prescaler = (0..8)
+        .find(|prescaler| (rising.max(falling) >> prescaler) <= HRTIM_MAX_DEAD_TIME)
+        .ok_or(TimerError::InvalidDeadTime(rising_ns.max(falling_ns)))?;
+
+    // Both dead-times are positive, so the sign bits are left cleared
+    let dead_time = (falling >> prescaler) << hrtim_tima::dtar::DTFX
+        | prescaler << hrtim_tima::dtar::DTPRSC
+        | (rising >> prescaler) << hrtim_tima::dtar::DTRX;
+
+    unsafe {
+        write_register(get_timer_register(timer, hrtim_tima::DTAR), dead_time);
+    }
+
+    Ok(())
+}
+
+/// Configure a fault input on `gpio` and let it turn off both outputs of a timing unit. `filter`
+/// is the 4 bit digital filter value, where 0 disables the filter. The outputs stay off until
+/// `enable_hrtim_outputs` is called
+pub fn setup_hrtim_fault(
+    timer: &HrtimTimer,
+    fault: HrtimFault,
+    gpio: &Gpio,
+    polarity: BreakPolarity,
+    filter: u8,
+) -> Result<(), TimerError> {
+    use registers::{hrtim_common, hrtim_tima};
+
+    if filter > 0b1111 {
+        return Err(TimerError::InvalidFilter(filter));
+    }
+
+    // Fault 1 to 4 share the first input register with a byte each, fault 5 has the second
+    let (fault_input_register, offset) = match fault {
+        HrtimFault::Fault5 => (hrtim_common::FLTINR2, 0),
+        _ => (hrtim_common::FLTINR1, fault as u8 * 8),
+    };
+
+    enable_hrtim_clock();
+    gpio.setup();
+
+    unsafe {
+        // Use the input pin as source, with the filter and polarity
+        write_bits(
+            fault_input_register,
+            hrtim_common::fltinr1::FLT1F + offset,
+            filter as u32,
+            0b1111,
+        );
+        clear_bit(
+            fault_input_register,
+            hrtim_common::fltinr1::FLT1SRC + offset,
+        );
+        write_bits(
+            fault_input_register,
+            hrtim_common::fltinr1::FLT1P + offset,
+            (polarity == BreakPolarity::ActiveHigh) as u32,
+            0b1,
+        );
+        set_bit(fault_input_register, hrtim_common::fltinr1::FLT1E + offset);
+
+        // Drive both outputs to their inactive level on a fault
+        let output_register = get_timer_register(timer, hrtim_tima::OUTAR);
+        write_bits(output_register, hrtim_tima::outar::FAULT1, 0b10, 0b11);
+        write_bits(output_register, hrtim_tima::outar::FAULT2, 0b10, 0b11);
+
+        set_bit(
+            get_timer_register(timer, hrtim_tima::FLTAR),
+            hrtim_tima::fltar::FLT1EN + fault as u8,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn is_hrtim_fault_active(fault: HrtimFault) -> bool {
+    use registers::hrtim_common;
+    unsafe { get_bit(hrtim_common::ISR, hrtim_common::isr::FLT1 + fault as u8) == 1 }
+}
+
+pub fn clear_hrtim_fault(fault: HrtimFault) {
+    use registers::hrtim_common;
+
+    // The flags are cleared by writing a one, so no read-modify-write is done
+    unsafe {
+        write_register(
+            hrtim_common::ICR,
+            1 << (hrtim_common::isr::FLT1 + fault as u8),
+        );
+    }
+}
+
+/// Turn the outputs of a timing unit back on, e.g. after a fault
+pub fn enable_hrtim_outputs(timer: &HrtimTimer) {
+    use registers::hrtim_common;
+
+    // Writing a zero has no effect, so no read-modify-write is done
+    unsafe {
+        write_register(
+            hrtim_common::OENR,
+            0b11 << get_output_enable_field(timer, HrtimOutput::Output1),
+        );
+    }
+}
+
+pub fn disable_hrtim_outputs(timer: &HrtimTimer) {
+    use registers::hrtim_common;
+
+    unsafe {
+        write_register(
+            hrtim_common::DISR,
+            0b11 << get_output_enable_field(timer, HrtimOutput::Output1),
+        );
+    }
+}
+
+/// Stop a timing unit, disable its outputs and faults and release the pin of an output
+pub fn cleanup_hrtim(timer: &HrtimTimer, gpio: &Gpio) {
+    use registers::{hrtim_master, hrtim_tima};
+
+    disable_hrtim_outputs(timer);
+
+    unsafe {
+        clear_bit(hrtim_master::MCR, hrtim_master::mcr::TACEN + *timer as u8);
+        write_register(get_timer_register(timer, hrtim_tima::FLTAR), 0);
+        clear_bit(
+            get_timer_register(timer, hrtim_tima::OUTAR),
+            hrtim_tima::outar::DTEN,
+        );
+    }
+
+    gpio.cleanup();
+}
+
+fn enable_hrtim_clock() {
+    use registers::rcc::{APB2ENR, apb2enr};
+
+    unsafe {
+        set_bit(APB2ENR, apb2enr::HRTIMEN);
+    }
+}
+
+/// Register of a timing unit, given the same register of timing unit A. The units are laid out in
+/// blocks of 0x80 bytes
+fn get_timer_register(timer: &HrtimTimer, timer_a_register: *mut u32) -> *mut u32 {
+    timer_a_register.wrapping_add(*timer as usize * 0x20)
+}
+
+/// Field of an output in the output enable and disable registers
+fn get_output_enable_field(timer: &HrtimTimer, output: HrtimOutput) -> u8 {
+    *timer as u8 * 2 + output as u8
+}
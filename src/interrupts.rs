@@ -1,14 +1,228 @@
-use crate::register_tools::{self, write_bits};
+#[cfg(target_arch = "arm")]
+use core::arch::asm;
+
+use crate::register_tools::{self, get_bits_in_array, write_bits, write_register};
 use crate::registers::nvic::*;
 
 const NVIC_ISER_REGISTERS: [*mut u32; 5] = [ISER0, ISER1, ISER2, ISER3, ISER4];
 
+const NVIC_ICER_REGISTERS: [*mut u32; 5] = [ICER0, ICER1, ICER2, ICER3, ICER4];
+
+const NVIC_ISPR_REGISTERS: [*mut u32; 5] = [ISPR0, ISPR1, ISPR2, ISPR3, ISPR4];
+
+const NVIC_ICPR_REGISTERS: [*mut u32; 5] = [ICPR0, ICPR1, ICPR2, ICPR3, ICPR4];
+
+const NVIC_IABR_REGISTERS: [*mut u32; 5] = [IABR0, IABR1, IABR2, IABR3, IABR4];
+
 const NVIC_IPR_REGISTERS: [*mut u32; 40] = [
     IPR0, IPR1, IPR2, IPR3, IPR4, IPR5, IPR6, IPR7, IPR8, IPR9, IPR10, IPR11, IPR12, IPR13, IPR14,
     IPR15, IPR16, IPR17, IPR18, IPR19, IPR20, IPR21, IPR22, IPR23, IPR24, IPR25, IPR26, IPR27,
     IPR28, IPR29, IPR30, IPR31, IPR32, IPR33, IPR34, IPR35, IPR36, IPR37, IPR38, IPR39,
 ];
 
+/// Interrupt vectors of the STM32H743, numbered by their position in the vector table. Reserved
+/// positions and the vectors only present on other parts of the family are left out. See the
+/// NVIC vector table in the reference manual
+#[repr(u16)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Irq {
+    Wwdg1 = 0,
+    PvdPvm = 1,
+    RtcTampStampCssLse = 2,
+    RtcWkup = 3,
+    Flash = 4,
+    Rcc = 5,
+    Exti0 = 6,
+    Exti1 = 7,
+    Exti2 = 8,
+    Exti3 = 9,
+    Exti4 = 10,
+    Dma1Stream0 = 11,
+    Dma1Stream1 = 12,
+    Dma1Stream2 = 13,
+    Dma1Stream3 = 14,
+    Dma1Stream4 = 15,
+    Dma1Stream5 = 16,
+    Dma1Stream6 = 17,
+    Adc1And2 = 18,
+    Fdcan1It0 = 19,
+    Fdcan2It0 = 20,
+    Fdcan1It1 = 21,
+    Fdcan2It1 = 22,
+    Exti5To9 = 23,
+    Tim1Brk = 24,
+    Tim1Up = 25,
+    Tim1TrgCom = 26,
+    Tim1Cc = 27,
+    Tim2 = 28,
+    Tim3 = 29,
+    Tim4 = 30,
+    I2c1Ev = 31,
+    I2c1Er = 32,
+    I2c2Ev = 33,
+    I2c2Er = 34,
+    Spi1 = 35,
+    Spi2 = 36,
+    Usart1 = 37,
+    Usart2 = 38,
+    Usart3 = 39,
+    Exti10To15 = 40,
+    RtcAlarm = 41,
+    Tim8BrkTim12 = 43,
+    Tim8UpTim13 = 44,
+    Tim8TrgComTim14 = 45,
+    Tim8Cc = 46,
+    Dma1Stream7 = 47,
+    Fmc = 48,
+    Sdmmc1 = 49,
+    Tim5 = 50,
+    Spi3 = 51,
+    Uart4 = 52,
+    Uart5 = 53,
+    Tim6Dac = 54,
+    Tim7 = 55,
+    Dma2Stream0 = 56,
+    Dma2Stream1 = 57,
+    Dma2Stream2 = 58,
+    Dma2Stream3 = 59,
+    Dma2Stream4 = 60,
+    Eth = 61,
+    EthWkup = 62,
+    FdcanCal = 63,
+    Dma2Stream5 = 68,
+    Dma2Stream6 = 69,
+    Dma2Stream7 = 70,
+    Usart6 = 71,
+    I2c3Ev = 72,
+    I2c3Er = 73,
+    OtgHsEp1Out = 74,
+    OtgHsEp1In = 75,
+    OtgHsWkup = 76,
+    OtgHs = 77,
+    Dcmi = 78,
+    /// HASH and RNG global interrupt, missing from the register description
+    HashRng = 80,
+    Fpu = 81,
+    Uart7 = 82,
+    Uart8 = 83,
+    Spi4 = 84,
+    Spi5 = 85,
+    Spi6 = 86,
+    Sai1 = 87,
+    Ltdc = 88,
+    LtdcEr = 89,
+    Dma2d = 90,
+    Sai2 = 91,
+    Quadspi = 92,
+    Lptim1 = 93,
+    Cec = 94,
+    I2c4Ev = 95,
+    I2c4Er = 96,
+    Spdif = 97,
+    OtgFsEp1Out = 98,
+    OtgFsEp1In = 99,
+    OtgFsWkup = 100,
+    OtgFs = 101,
+    Dmamux1Ov = 102,
+    Hrtim1Mst = 103,
+    Hrtim1Tima = 104,
+    Hrtim1Timb = 105,
+    Hrtim1Timc = 106,
+    Hrtim1Timd = 107,
+    Hrtim1Time = 108,
+    Hrtim1Flt = 109,
+    Dfsdm1Flt0 = 110,
+    Dfsdm1Flt1 = 111,
+    Dfsdm1Flt2 = 112,
+    Dfsdm1Flt3 = 113,
+    Sai3 = 114,
+    Swpmi1 = 115,
+    Tim15 = 116,
+    Tim16 = 117,
+    Tim17 = 118,
+    MdiosWkup = 119,
+    Mdios = 120,
+    Jpeg = 121,
+    Mdma = 122,
+    Sdmmc2 = 124,
+    Hsem0 = 125,
+    Adc3 = 127,
+    Dmamux2Ovr = 128,
+    BdmaCh1 = 129,
+    BdmaCh2 = 130,
+    BdmaCh3 = 131,
+    BdmaCh4 = 132,
+    BdmaCh5 = 133,
+    BdmaCh6 = 134,
+    BdmaCh7 = 135,
+    BdmaCh8 = 136,
+    Comp = 137,
+    Lptim2 = 138,
+    Lptim3 = 139,
+    Lptim4 = 140,
+    Lptim5 = 141,
+    Lpuart = 142,
+    Wwdg1Rst = 143,
+    Crs = 144,
+    Sai4 = 146,
+    WakeupPin = 149,
+}
+
+impl Irq {
+    /// Position of the interrupt in the vector table, as used by the NVIC registers
+    pub const fn number(self) -> u16 {
+        self as u16
+    }
+}
+
+/// Number of priority bits implemented by the NVIC. They are the upper bits of each 8 bit
+/// priority field
+pub const PRIORITY_BITS: u8 = 4;
+
+/// Split of the priority bits between preemption priority and sub-priority. An interrupt only
+/// preempts another one with a higher preemption priority, while the sub-priority decides which
+/// of several pending interrupts with the same preemption priority runs first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PriorityGrouping {
+    /// 16 preemption levels and no sub-priority, which is the reset state
+    Preempt16Sub1 = 0b011,
+    Preempt8Sub2 = 0b100,
+    Preempt4Sub4 = 0b101,
+    Preempt2Sub8 = 0b110,
+    Preempt1Sub16 = 0b111,
+}
+
+impl PriorityGrouping {
+    /// Number of priority bits used for the sub-priority
+    pub const fn sub_priority_bits(self) -> u8 {
+        self as u8 - 0b011
+    }
+
+    /// Number of priority bits used for the preemption priority
+    pub const fn preempt_priority_bits(self) -> u8 {
+        PRIORITY_BITS - self.sub_priority_bits()
+    }
+}
+
+/// Cortex-M7 system exceptions with a configurable priority, numbered by their position in the
+/// vector table
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SystemHandler {
+    MemManage = 4,
+    BusFault = 5,
+    UsageFault = 6,
+    SVCall = 11,
+    DebugMonitor = 12,
+    PendSV = 14,
+    SysTick = 15,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InterruptError {
+    InvalidPreemptPriority(u8),
+    InvalidSubPriority(u8),
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub enum IRQLevel {
@@ -30,29 +244,362 @@ pub enum IRQLevel {
     Level15 = 0x0F,
 }
 
+/// Enable an interrupt in the NVIC
+pub fn enable_interrupt(irq: Irq) {
+    unsafe {
+        register_tools::enable_interrupt(irq as u32, &NVIC_ISER_REGISTERS);
+    }
+}
+
+/// Disable an interrupt in the NVIC
+pub fn disable_interrupt(irq: Irq) {
+    // Zeros written to the ISER are ignored, so the interrupt is disabled through the ICER
+    write_interrupt_bit(irq, &NVIC_ICER_REGISTERS);
+}
+
+pub fn is_interrupt_enabled(irq: Irq) -> bool {
+    unsafe { get_bits_in_array(irq as u32, 0b1, &NVIC_ISER_REGISTERS) != 0 }
+}
+
+/// Set the irq level of an interrupt. Note that lower numbers have higher priorities. The level
+/// is written as is, so with a priority grouping other than `Preempt16Sub1` its lower bits end
+/// up as sub-priority
+pub fn set_irq_level(irq: Irq, level: IRQLevel) {
+    write_irq_priority(irq as u32, level as u8);
+}
+
+/// The priority of an interrupt as an irq level, which includes the sub-priority bits when a
+/// priority grouping other than `Preempt16Sub1` is used
+pub fn get_irq_level(irq: Irq) -> IRQLevel {
+    to_irq_level(read_irq_priority(irq as u32))
+}
+
+/// Set the priority of a system exception, such as the SysTick or PendSV, relative to the
+/// peripheral interrupts. Note that lower numbers have higher priorities
+pub fn set_system_handler_priority(handler: SystemHandler, level: IRQLevel) {
+    let (register, field) = get_shpr_field(handler);
+    let level = ((level as u8) << (8 - PRIORITY_BITS)) as u32;
+
+    unsafe { write_bits(register, field, level, 0xFF) };
+}
+
+pub fn get_system_handler_priority(handler: SystemHandler) -> IRQLevel {
+    let (register, field) = get_shpr_field(handler);
+    let value = unsafe { register_tools::read_register(register) };
+
+    to_irq_level(((value >> field) as u8) >> (8 - PRIORITY_BITS))
+}
+
+/// Select how the priority bits are split between preemption priority and sub-priority. The
+/// priority fields aren't rewritten, so set the grouping before assigning priorities
+pub fn set_priority_grouping(grouping: PriorityGrouping) {
+    use crate::registers::scb::{AIRCR, aircr};
+
+    // Writes to the AIRCR are ignored unless the key is written to the upper half
+    const VECTKEY: u32 = 0x05FA;
+
+    unsafe {
+        register_tools::modify(AIRCR, |value| {
+            (value & !((0xFFFF << aircr::VECTKEYSTAT) | (0b111 << aircr::PRIGROUP)))
+                | (VECTKEY << aircr::VECTKEYSTAT)
+                | ((grouping as u32) << aircr::PRIGROUP)
+        });
+    }
+}
+
+pub fn get_priority_grouping() -> PriorityGrouping {
+    use crate::registers::scb::{AIRCR, aircr};
+
+    match (unsafe { register_tools::read_register(AIRCR) } >> aircr::PRIGROUP) & 0b111 {
+        0b100 => PriorityGrouping::Preempt8Sub2,
+        0b101 => PriorityGrouping::Preempt4Sub4,
+        0b110 => PriorityGrouping::Preempt2Sub8,
+        0b111 => PriorityGrouping::Preempt1Sub16,
+        // With four priority bits, groupings 0 to 3 all leave the sub-priority empty
+        _ => PriorityGrouping::Preempt16Sub1,
+    }
+}
+
+/// Set the preemption priority and sub-priority of an interrupt, encoded according to the
+/// current priority grouping. Lower numbers have higher priorities
+pub fn set_irq_priority(irq: Irq, preempt: u8, sub: u8) -> Result<(), InterruptError> {
+    let grouping = get_priority_grouping();
+    let sub_bits = grouping.sub_priority_bits();
+
+    if preempt >= 1 << grouping.preempt_priority_bits() {
+        return Err(InterruptError::InvalidPreemptPriority(preempt));
+    }
+    if sub >= 1 << sub_bits {
+        return Err(InterruptError::InvalidSubPriority(sub));
+    }
+
+    write_irq_priority(irq as u32, (preempt << sub_bits) | sub);
+    Ok(())
+}
+
+/// Mark an interrupt as pending. If it's enabled, its handler runs as soon as its priority
+/// allows, which can be used to defer work from a higher priority handler
+pub fn set_pending(irq: Irq) {
+    write_interrupt_bit(irq, &NVIC_ISPR_REGISTERS);
+}
+
+/// Remove the pending state of an interrupt, so that its handler won't run for the request that
+/// is currently pending
+pub fn clear_pending(irq: Irq) {
+    write_interrupt_bit(irq, &NVIC_ICPR_REGISTERS);
+}
+
+/// Whether an interrupt is waiting to be serviced
+pub fn is_pending(irq: Irq) -> bool {
+    unsafe { get_bits_in_array(irq as u32, 0b1, &NVIC_ISPR_REGISTERS) != 0 }
+}
+
+/// Whether the handler of an interrupt is running, or has been preempted by a higher priority
+/// interrupt
+pub fn is_active(irq: Irq) -> bool {
+    unsafe { get_bits_in_array(irq as u32, 0b1, &NVIC_IABR_REGISTERS) != 0 }
+}
+
+/// Pend an interrupt by writing its number to the software trigger register. This has the same
+/// effect as `set_pending`, but is a single write that doesn't need the bit position of the
+/// interrupt. Note that unprivileged code can only use it when USERSETMPEND is set in the SCB
+/// configuration control register
+pub fn trigger_interrupt(irq: Irq) {
+    use crate::registers::nvic_stir::{STIR, stir};
+
+    // The software trigger register sits apart from the rest of the NVIC at 0xE000EF00, so it
+    // has its own nvic_stir block
+    unsafe {
+        write_register(STIR, (irq as u32) << stir::INTID);
+    }
+}
+
+/// Sleep until an interrupt is pending. Interrupts masked by PRIMASK still wake the core, so
+/// `free` can be used around the check and the sleep to avoid missing a wakeup
+pub fn wait_for_interrupt() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("wfi");
+    }
+}
+
+/// Sleep until an event is signalled, unless the event register is already set, in which case
+/// it's cleared and this returns immediately. Events come from `send_event`, from interrupts
+/// becoming pending and, with SEVONPEND set, from pending interrupts that are disabled
+pub fn wait_for_event() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("wfe");
+    }
+}
+
+/// Signal an event, setting the event register and waking a core waiting in `wait_for_event`
+pub fn send_event() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("sev");
+    }
+}
+
+/// Return to sleep after the last interrupt handler has finished instead of returning to thread
+/// mode, for applications that run entirely from interrupts
+pub fn set_sleep_on_exit(enabled: bool) {
+    use crate::registers::scb::{SCR, scr};
+
+    unsafe {
+        if enabled {
+            register_tools::set_bit(SCR, scr::SLEEPONEXIT);
+        } else {
+            register_tools::clear_bit(SCR, scr::SLEEPONEXIT);
+        }
+    }
+}
+
+/// Run `f` with all configurable interrupts masked through PRIMASK. The previous mask is
+/// restored afterwards, so calls can be nested and used from interrupt handlers
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    let was_enabled = disable_all();
+    let result = f();
+    restore_all(was_enabled);
+    result
+}
+
+/// Run `f` with interrupts at `level` and all lower priorities masked through BASEPRI, while
+/// interrupts with a higher priority keep running. The mask is only ever raised, so using this
+/// from a context that already masks more doesn't unmask anything. `Level0` masks all
+/// configurable interrupts, as a BASEPRI of zero disables the masking
+pub fn with_priority_mask<R>(level: IRQLevel, f: impl FnOnce() -> R) -> R {
+    if level == IRQLevel::Level0 {
+        return free(f);
+    }
+
+    let previous = raise_basepri((level as u32) << (8 - PRIORITY_BITS));
+    let result = f();
+    set_basepri(previous);
+    result
+}
+
+/// Raise BASEPRI to `basepri` unless it already masks more, returning the previous value
+#[cfg(target_arch = "arm")]
+fn raise_basepri(basepri: u32) -> u32 {
+    let previous: u32;
+
+    // BASEPRI_MAX only updates the mask if the new value masks more than the current one
+    unsafe {
+        asm!(
+            "mrs {}, BASEPRI",
+            "msr BASEPRI_MAX, {}",
+            out(reg) previous,
+            in(reg) basepri,
+        );
+    }
+
+    previous
+}
+
+#[cfg(target_arch = "arm")]
+fn set_basepri(basepri: u32) {
+    unsafe {
+        asm!("msr BASEPRI, {}", in(reg) basepri);
+    }
+}
+
+/// Mask all configurable interrupts, returning whether they were unmasked before
+#[cfg(target_arch = "arm")]
+fn disable_all() -> bool {
+    let primask: u32;
+
+    // The asm blocks aren't marked as `nomem`, so memory accesses aren't moved across them
+    unsafe {
+        asm!("mrs {}, PRIMASK", "cpsid i", out(reg) primask);
+    }
+
+    primask & 1 == 0
+}
+
+/// Unmask the interrupts again if they were unmasked before `disable_all`
+#[cfg(target_arch = "arm")]
+fn restore_all(was_enabled: bool) {
+    if was_enabled {
+        unsafe {
+            asm!("cpsie i");
+        }
+    }
+}
+
+// There are no interrupts to mask when running on the host with the `mock-registers` feature
+#[cfg(not(target_arch = "arm"))]
+fn raise_basepri(_basepri: u32) -> u32 {
+    0
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn set_basepri(_basepri: u32) {}
+
+#[cfg(not(target_arch = "arm"))]
+fn disable_all() -> bool {
+    false
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn restore_all(_was_enabled: bool) {}
+
+/// Implementation of the `critical-section` crate for a single core, built on the same PRIMASK
+/// masking as `free`
+#[cfg(feature = "critical-section")]
+mod critical_section_impl {
+    use critical_section::RawRestoreState;
+
+    struct SingleCoreCriticalSection;
+    critical_section::set_impl!(SingleCoreCriticalSection);
+
+    unsafe impl critical_section::Impl for SingleCoreCriticalSection {
+        unsafe fn acquire() -> RawRestoreState {
+            super::disable_all()
+        }
+
+        unsafe fn release(was_enabled: RawRestoreState) {
+            super::restore_all(was_enabled);
+        }
+    }
+}
+
 /// Enable an interrupt based on an interrupt ID from the registers::irq list
-pub fn enable_interrupt(interrupt: u32) {
+#[deprecated(note = "use `enable_interrupt` with an `Irq`")]
+pub fn enable_interrupt_id(interrupt: u32) {
     unsafe {
         register_tools::enable_interrupt(interrupt, &NVIC_ISER_REGISTERS);
     }
 }
 
 /// Disable an interrupt based on an interrupt ID from the registers::irq list
-pub fn disable_interrupt(interrupt: u32) {
-    unsafe {
-        register_tools::disable_interrupt(interrupt, &NVIC_ISER_REGISTERS);
-    }
+#[deprecated(note = "use `disable_interrupt` with an `Irq`")]
+pub fn disable_interrupt_id(interrupt: u32) {
+    let index = interrupt as usize / 32;
+    unsafe { write_register(NVIC_ICER_REGISTERS[index], 1 << (interrupt % 32)) };
 }
 
-/// Set an irq level for an interrupt ID from the registers::irq list. Note that lower numbers have
-/// higher priorities
-pub fn set_irq_level(irq_id: u32, level: IRQLevel) {
+/// Set an irq level for an interrupt ID from the registers::irq list
+#[deprecated(note = "use `set_irq_level` with an `Irq`")]
+pub fn set_irq_level_id(irq_id: u32, level: IRQLevel) {
+    write_irq_priority(irq_id, level as u8);
+}
+
+/// Write a one to the bit of an interrupt in a set or clear register. Zeros are ignored by
+/// these registers, so no read-modify-write is done
+fn write_interrupt_bit(irq: Irq, registers: &[*mut u32]) {
+    let id = irq as usize;
+    unsafe { write_register(registers[id / 32], 1 << (id % 32)) };
+}
+
+/// Write a priority of `PRIORITY_BITS` bits to the priority field of an interrupt
+fn write_irq_priority(irq_id: u32, priority: u8) {
     let index = (irq_id as usize) / 4;
-    let field = (irq_id % 4) as u8 * 4;
+    let field = (irq_id % 4) as u8 * 8;
+
+    // The priority is the highest bits of the 8 bit field
+    let priority = (priority << (8 - PRIORITY_BITS)) as u32;
 
-    // The interrupt level is the highest 4 bits in the 8 bit field
-    let level = level as u8;
-    let level = (level << 4) as u32;
+    unsafe { write_bits(NVIC_IPR_REGISTERS[index], field, priority, 0xFF) };
+}
 
-    unsafe { write_bits(NVIC_IPR_REGISTERS[index], field, level, 0xFF) };
+/// Read the `PRIORITY_BITS` bit priority of an interrupt
+fn read_irq_priority(irq_id: u32) -> u8 {
+    let index = (irq_id as usize) / 4;
+    let field = (irq_id % 4) as u8 * 8;
+
+    let value = unsafe { register_tools::read_register(NVIC_IPR_REGISTERS[index]) };
+    ((value >> field) as u8) >> (8 - PRIORITY_BITS)
+}
+
+/// System handler priority register and field of a system exception. The 8 bit fields in SHPR1
+/// to SHPR3 start with exception 4, the MemManage fault
+fn get_shpr_field(handler: SystemHandler) -> (*mut u32, u8) {
+    use crate::registers::scb::{SHPR1, SHPR2, SHPR3};
+
+    let offset = handler as usize - 4;
+    let register = [SHPR1, SHPR2, SHPR3][offset / 4];
+
+    (register, (offset % 4) as u8 * 8)
+}
+
+fn to_irq_level(priority: u8) -> IRQLevel {
+    match priority {
+        0x0 => IRQLevel::Level0,
+        0x1 => IRQLevel::Level1,
+        0x2 => IRQLevel::Level2,
+        0x3 => IRQLevel::Level3,
+        0x4 => IRQLevel::Level4,
+        0x5 => IRQLevel::Level5,
+        0x6 => IRQLevel::Level6,
+        0x7 => IRQLevel::Level7,
+        0x8 => IRQLevel::Level8,
+        0x9 => IRQLevel::Level9,
+        0xA => IRQLevel::Level10,
+        0xB => IRQLevel::Level11,
+        0xC => IRQLevel::Level12,
+        0xD => IRQLevel::Level13,
+        0xE => IRQLevel::Level14,
+        _ => IRQLevel::Level15,
+    }
 }
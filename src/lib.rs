@@ -5,3 +5,35 @@ pub mod gpio;
 pub mod usart;
 pub mod timers;
 pub mod interrupts;
+pub mod exti;
+pub mod pinmap;
+pub mod syscfg;
+pub mod ring_buffer;
+pub mod dma;
+pub mod rcc;
+pub mod pwm;
+pub mod capture;
+pub mod basic_timers;
+pub mod lptim;
+pub mod monotonic;
+pub mod delay;
+pub mod timer_sync;
+pub mod output_compare;
+pub mod event_counter;
+pub mod timer_dma;
+pub mod hrtim;
+pub mod commutation;
+pub mod systick;
+pub mod dwt;
+pub mod scheduler;
+pub mod rtic_monotonic;
+#[cfg(feature = "embassy")]
+pub mod embassy_driver;
+pub mod rtc;
+pub mod wwdg;
+pub mod servo;
+pub mod vector_table;
+pub mod faults;
+pub mod cortex;
+pub mod clocks;
+pub mod crs;
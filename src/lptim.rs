@@ -0,0 +1,341 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    rcc::{LSE_FREQUENCY, LSI_FREQUENCY, get_pclk1_frequency, get_pclk4_frequency},
+    register_tools::{clear_bit, get_bit, read_register, set_bit, write_bits, write_register},
+    registers,
+    timers::TimerError,
+};
+
+/// Low-power timers. LPTIM1 is clocked from APB1, while LPTIM2 to LPTIM5 sit in the D3 domain on
+/// APB4. All of them have a 16 bit counter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LpTimer {
+    Lptim1,
+    Lptim2,
+    Lptim3,
+    Lptim4,
+    Lptim5,
+}
+
+/// Kernel clock of a low-power timer. Only the LSE and LSI keep running in Stop mode, and the
+/// oscillator has to be started before the timer is setup. Note that LPTIM3 to LPTIM5 share
+/// their clock selection
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LpTimerClock {
+    /// The APB clock of the timer
+    Pclk = 0b000,
+    Lse = 0b011,
+    Lsi = 0b100,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LpTimerMode {
+    /// Count a single period and stop
+    OneShot,
+    /// Restart the period each time it ends
+    Continuous,
+}
+
+/// Start a low-power timer that fires its interrupt after `period_us` microseconds, once or every
+/// period depending on `mode`. The prescaler is the smallest power of two that fits the period
+/// in the counter
+pub fn setup_lptimer(
+    timer: &LpTimer,
+    clock: LpTimerClock,
+    mode: LpTimerMode,
+    period_us: u32,
+) -> Result<(), TimerError> {
+    use registers::lptim1;
+
+    let clock_frequency =
+        get_lptimer_clock_frequency(timer, clock).ok_or(TimerError::UnknownClock)?;
+
+    let ticks = clock_frequency as u64 * period_us as u64 / 1_000_000;
+    if ticks < 2 {
+        return Err(TimerError::InvalidDelay(period_us));
+    }
+
+    // The prescaler divides by 1 to 128 in powers of two
+    let prescaler = (0..8)
+        .find(|prescaler| (ticks - 1) >> prescaler <= 0xFFFF)
+        .ok_or(TimerError::InvalidDelay(period_us))?;
+    let auto_reload = ((ticks >> prescaler) - 1) as u32;
+
+    let cr_control_register = get_cr_control_register(timer);
+    let cfgr_configuration_register = get_cfgr_configuration_register(timer);
+
+    select_lptimer_clock(timer, clock);
+    enable_lptimer_clock(timer);
+
+    unsafe {
+        // The configuration and interrupt enable registers can only be written while the timer
+        // is disabled
+        clear_bit(cr_control_register, lptim1::cr::ENABLE);
+
+        // Count the internal kernel clock
+        write_register(cfgr_configuration_register, 0);
+        write_bits(
+            cfgr_configuration_register,
+            lptim1::cfgr::PRESC,
+            prescaler as u32,
+            0b111,
+        );
+
+        // Enable the auto reload match interrupt
+        write_register(get_ier_interrupt_register(timer), 1 << lptim1::ier::ARRMIE);
+
+        // The auto reload register can only be written while the timer is enabled, and the write
+        // is done once ARROK is set
+        set_bit(cr_control_register, lptim1::cr::ENABLE);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+        while get_bit(get_isr_status_register(timer), lptim1::isr::ARROK) == 0 {}
+
+        write_register(
+            get_icr_interrupt_clear_register(timer),
+            1 << lptim1::icr::ARROKCF | 1 << lptim1::icr::ARRMCF,
+        );
+
+        enable_interrupt(get_nvic_interrupt_id(timer));
+
+        // Start counting
+        match mode {
+            LpTimerMode::OneShot => set_bit(cr_control_register, lptim1::cr::SNGSTRT),
+            LpTimerMode::Continuous => set_bit(cr_control_register, lptim1::cr::CNTSTRT),
+        }
+    }
+
+    Ok(())
+}
+
+/// Stop a low-power timer, and disable its interrupt and Stop mode wakeup
+pub fn cleanup_lptimer(timer: &LpTimer) {
+    use registers::lptim1;
+
+    disable_interrupt(get_nvic_interrupt_id(timer));
+    disable_lptimer_wakeup(timer);
+
+    unsafe {
+        clear_bit(get_cr_control_register(timer), lptim1::cr::ENABLE);
+        write_register(get_ier_interrupt_register(timer), 0);
+    }
+}
+
+/// Let the auto reload match of a low-power timer wake the core from Stop mode. The timer has to
+/// be clocked from the LSE or LSI for it to keep counting in Stop mode
+pub fn enable_lptimer_wakeup(timer: &LpTimer) {
+    use registers::{exti::CPUIMR2, rcc::D3AMR};
+
+    unsafe {
+        // Unmask the wakeup line in the EXTI for the CPU
+        set_bit(CPUIMR2, get_exti_wakeup_line(timer) - 32);
+
+        // Keep the clock of the timers in the D3 domain running when D1 and D2 are stopped
+        if let Some(autonomous_field) = get_d3amr_autonomous_field(timer) {
+            set_bit(D3AMR, autonomous_field);
+        }
+    }
+}
+
+pub fn disable_lptimer_wakeup(timer: &LpTimer) {
+    use registers::{exti::CPUIMR2, rcc::D3AMR};
+
+    unsafe {
+        clear_bit(CPUIMR2, get_exti_wakeup_line(timer) - 32);
+
+        if let Some(autonomous_field) = get_d3amr_autonomous_field(timer) {
+            clear_bit(D3AMR, autonomous_field);
+        }
+    }
+}
+
+/// Current value of the counter. It runs from an asynchronous clock, so it's read until two
+/// consecutive reads match
+pub fn get_lptimer_count(timer: &LpTimer) -> u16 {
+    let cnt_counter_register = get_cnt_counter_register(timer);
+
+    loop {
+        let (first, second) = unsafe {
+            (
+                read_register(cnt_counter_register),
+                read_register(cnt_counter_register),
+            )
+        };
+
+        if first == second {
+            return first as u16;
+        }
+    }
+}
+
+pub fn clear_lptimer_interrupt_flag(timer: &LpTimer) {
+    use registers::lptim1;
+
+    // The flags are cleared by writing a one, so no read-modify-write is done
+    unsafe {
+        write_register(
+            get_icr_interrupt_clear_register(timer),
+            1 << lptim1::icr::ARRMCF,
+        );
+    }
+}
+
+/// Frequency the counter of a low-power timer is clocked with, before its prescaler
+fn get_lptimer_clock_frequency(timer: &LpTimer, clock: LpTimerClock) -> Option<u32> {
+    match clock {
+        LpTimerClock::Pclk => match timer {
+            LpTimer::Lptim1 => get_pclk1_frequency(),
+            _ => get_pclk4_frequency(),
+        },
+        LpTimerClock::Lse => Some(LSE_FREQUENCY),
+        LpTimerClock::Lsi => Some(LSI_FREQUENCY),
+    }
+}
+
+fn select_lptimer_clock(timer: &LpTimer, clock: LpTimerClock) {
+    use registers::rcc::{D2CCIP2R, D3CCIPR, d2ccip2r, d3ccipr};
+
+    let (register, field) = match timer {
+        LpTimer::Lptim1 => (D2CCIP2R, d2ccip2r::LPTIM1SRC),
+        LpTimer::Lptim2 => (D3CCIPR, d3ccipr::LPTIM2SRC),
+        _ => (D3CCIPR, d3ccipr::LPTIM345SRC),
+    };
+
+    unsafe {
+        write_bits(register, field, clock as u32, 0b111);
+    }
+}
+
+fn enable_lptimer_clock(timer: &LpTimer) {
+    use registers::rcc::{APB1LENR, APB4ENR, apb1lenr, apb4enr};
+
+    let (clock_enable_register, clock_enable_field) = match timer {
+        LpTimer::Lptim1 => (APB1LENR, apb1lenr::LPTIM1EN),
+        LpTimer::Lptim2 => (APB4ENR, apb4enr::LPTIM2EN),
+        LpTimer::Lptim3 => (APB4ENR, apb4enr::LPTIM3EN),
+        LpTimer::Lptim4 => (APB4ENR, apb4enr::LPTIM4EN),
+        LpTimer::Lptim5 => (APB4ENR, apb4enr::LPTIM5EN),
+    };
+
+    unsafe {
+        set_bit(clock_enable_register, clock_enable_field);
+    }
+}
+
+/// Wakeup line of the timer in the EXTI, see the EXTI event input mapping table
+fn get_exti_wakeup_line(timer: &LpTimer) -> u8 {
+    match timer {
+        LpTimer::Lptim1 => 47,
+        LpTimer::Lptim2 => 48,
+        LpTimer::Lptim3 => 50,
+        LpTimer::Lptim4 => 52,
+        LpTimer::Lptim5 => 53,
+    }
+}
+
+/// LPTIM1 is in the D2 domain and has no autonomous mode
+fn get_d3amr_autonomous_field(timer: &LpTimer) -> Option<u8> {
+    use registers::rcc::d3amr;
+
+    match timer {
+        LpTimer::Lptim1 => None,
+        LpTimer::Lptim2 => Some(d3amr::LPTIM2AMEN),
+        LpTimer::Lptim3 => Some(d3amr::LPTIM3AMEN),
+        LpTimer::Lptim4 => Some(d3amr::LPTIM4AMEN),
+        LpTimer::Lptim5 => Some(d3amr::LPTIM5AMEN),
+    }
+}
+
+fn get_isr_status_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::ISR,
+        LpTimer::Lptim2 => lptim2::ISR,
+        LpTimer::Lptim3 => lptim3::ISR,
+        LpTimer::Lptim4 => lptim4::ISR,
+        LpTimer::Lptim5 => lptim5::ISR,
+    }
+}
+
+fn get_icr_interrupt_clear_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::ICR,
+        LpTimer::Lptim2 => lptim2::ICR,
+        LpTimer::Lptim3 => lptim3::ICR,
+        LpTimer::Lptim4 => lptim4::ICR,
+        LpTimer::Lptim5 => lptim5::ICR,
+    }
+}
+
+fn get_ier_interrupt_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::IER,
+        LpTimer::Lptim2 => lptim2::IER,
+        LpTimer::Lptim3 => lptim3::IER,
+        LpTimer::Lptim4 => lptim4::IER,
+        LpTimer::Lptim5 => lptim5::IER,
+    }
+}
+
+fn get_cfgr_configuration_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::CFGR,
+        LpTimer::Lptim2 => lptim2::CFGR,
+        LpTimer::Lptim3 => lptim3::CFGR,
+        LpTimer::Lptim4 => lptim4::CFGR,
+        LpTimer::Lptim5 => lptim5::CFGR,
+    }
+}
+
+fn get_cr_control_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::CR,
+        LpTimer::Lptim2 => lptim2::CR,
+        LpTimer::Lptim3 => lptim3::CR,
+        LpTimer::Lptim4 => lptim4::CR,
+        LpTimer::Lptim5 => lptim5::CR,
+    }
+}
+
+fn get_arr_auto_reload_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::ARR,
+        LpTimer::Lptim2 => lptim2::ARR,
+        LpTimer::Lptim3 => lptim3::ARR,
+        LpTimer::Lptim4 => lptim4::ARR,
+        LpTimer::Lptim5 => lptim5::ARR,
+    }
+}
+
+fn get_cnt_counter_register(timer: &LpTimer) -> *mut u32 {
+    use registers::{lptim1, lptim2, lptim3, lptim4, lptim5};
+
+    match timer {
+        LpTimer::Lptim1 => lptim1::CNT,
+        LpTimer::Lptim2 => lptim2::CNT,
+        LpTimer::Lptim3 => lptim3::CNT,
+        LpTimer::Lptim4 => lptim4::CNT,
+        LpTimer::Lptim5 => lptim5::CNT,
+    }
+}
+
+fn get_nvic_interrupt_id(timer: &LpTimer) -> Irq {
+    match timer {
+        LpTimer::Lptim1 => Irq::Lptim1,
+        LpTimer::Lptim2 => Irq::Lptim2,
+        LpTimer::Lptim3 => Irq::Lptim3,
+        LpTimer::Lptim4 => Irq::Lptim4,
+        LpTimer::Lptim5 => Irq::Lptim5,
+    }
+}
@@ -0,0 +1,158 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+
+use crate::{
+    interrupts::{disable_interrupt, enable_interrupt},
+    register_tools::{ClearByWrite0, clear_bit, get_bit, read_register, set_bit, write_register},
+    registers,
+    timers::{
+        Timer, TimerError, enable_timer_clock, get_arr_auto_reload_register,
+        get_cnt_counter_register, get_cr1_control_register, get_dier_interrupt_register,
+        get_egr_event_generator_register, get_nvic_interrupt_id, get_psc_prescaler_register,
+        get_sr_status_register, get_timer_max_count,
+    },
+};
+
+const TIMERS: [Timer; 6] = [
+    Timer::Tim1,
+    Timer::Tim2,
+    Timer::Tim3,
+    Timer::Tim4,
+    Timer::Tim5,
+    Timer::Tim8,
+];
+
+/// Index of the timer in `TIMERS` used as time base, or `u8::MAX` when none is setup
+static MONOTONIC_TIMER: AtomicU8 = AtomicU8::new(u8::MAX);
+
+/// Number of times the counter has wrapped, which together with the counter forms the time
+static MONOTONIC_OVERFLOWS: AtomicU32 = AtomicU32::new(0);
+
+/// Start a free running timer counting microseconds, extended to 64 bits by counting its
+/// overflows. `handle_monotonic_interrupt` has to be called from the update interrupt handler of
+/// the timer. A 32 bit timer like TIM2 or TIM5 overflows every 71 minutes instead of every 65 ms,
+/// which keeps the interrupt load down
+pub fn setup_monotonic(timer: &Timer, clock_frequency: u32) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if clock_frequency < 1_000_000 || !clock_frequency.is_multiple_of(1_000_000) {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    MONOTONIC_OVERFLOWS.store(0, Ordering::Relaxed);
+    MONOTONIC_TIMER.store(*timer as u8, Ordering::Release);
+
+    enable_timer_clock(timer);
+
+    unsafe {
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+
+        // Tick every microsecond and count through the whole counter range
+        write_register(
+            get_psc_prescaler_register(timer),
+            clock_frequency / 1_000_000 - 1,
+        );
+        write_register(
+            get_arr_auto_reload_register(timer),
+            get_timer_max_count(timer),
+        );
+
+        // Load the pre-scaler and clear the update flag this sets
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+
+        // Enable the update interrupt for the timer
+        set_bit(get_dier_interrupt_register(timer), tim2::dier::UIE);
+        enable_interrupt(get_nvic_interrupt_id(timer));
+
+        // Enable the timer
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Stop the time base. The time restarts from zero if it's setup again
+pub fn cleanup_monotonic() {
+    use registers::tim2;
+
+    let Some(timer) = get_monotonic_timer() else {
+        return;
+    };
+
+    MONOTONIC_TIMER.store(u8::MAX, Ordering::Release);
+
+    unsafe {
+        clear_bit(get_cr1_control_register(&timer), tim2::cr1::CEN);
+        clear_bit(get_dier_interrupt_register(&timer), tim2::dier::UIE);
+    }
+
+    disable_interrupt(get_nvic_interrupt_id(&timer));
+}
+
+/// Count an overflow of the time base. Call this from the update interrupt handler of the timer
+/// passed to `setup_monotonic`
+pub fn handle_monotonic_interrupt() {
+    use registers::tim2;
+
+    let Some(timer) = get_monotonic_timer() else {
+        return;
+    };
+
+    let sr_status_register = get_sr_status_register(&timer);
+
+    unsafe {
+        if get_bit(sr_status_register, tim2::sr::UIF) == 1 {
+            ClearByWrite0::new(sr_status_register).clear(tim2::sr::UIF);
+            MONOTONIC_OVERFLOWS.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Microseconds since `setup_monotonic`, or zero if it hasn't been called. The time never goes
+/// backwards, also when read with the timer interrupt masked or from a higher priority interrupt
+pub fn get_monotonic_us() -> u64 {
+    use registers::tim2;
+
+    let Some(timer) = get_monotonic_timer() else {
+        return 0;
+    };
+
+    let cnt_counter_register = get_cnt_counter_register(&timer);
+    let sr_status_register = get_sr_status_register(&timer);
+    let max_count = get_timer_max_count(&timer);
+
+    loop {
+        let overflows = MONOTONIC_OVERFLOWS.load(Ordering::Acquire);
+        let (count, pending) = unsafe {
+            (
+                read_register(cnt_counter_register),
+                get_bit(sr_status_register, tim2::sr::UIF) == 1,
+            )
+        };
+
+        // Retry if the interrupt counted an overflow in between
+        if overflows != MONOTONIC_OVERFLOWS.load(Ordering::Acquire) {
+            continue;
+        }
+
+        // An overflow that hasn't been handled yet is only included if it happened before the
+        // counter was read, which is when the counter is still low
+        let overflows = overflows as u64 + (pending && count <= max_count / 2) as u64;
+
+        return overflows * (max_count as u64 + 1) + count as u64;
+    }
+}
+
+/// Nanoseconds since `setup_monotonic`, with a resolution of one microsecond
+pub fn get_monotonic_ns() -> u64 {
+    get_monotonic_us() * 1_000
+}
+
+fn get_monotonic_timer() -> Option<Timer> {
+    TIMERS
+        .get(MONOTONIC_TIMER.load(Ordering::Acquire) as usize)
+        .copied()
+}
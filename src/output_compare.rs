@@ -0,0 +1,378 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    gpio::Gpio,
+    register_tools::{
+        ClearByWrite0, clear_bit, get_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    timer_sync::{SlaveMode, TimerTrigger, cleanup_timer_sync, set_timer_slave_mode},
+    timers::{
+        CHANNELS, Timer, TimerChannel, TimerError, compute_timer_period, enable_timer_clock,
+        get_arr_auto_reload_register, get_bdtr_break_dead_time_register,
+        get_ccer_capture_compare_enable_register, get_ccer_enable_field,
+        get_ccmr_capture_compare_mode_register, get_ccr_capture_compare_register,
+        get_cnt_counter_register, get_cr1_control_register, get_egr_event_generator_register,
+        get_psc_prescaler_register, get_sr_status_register,
+    },
+};
+
+/// What the output of a channel does when the counter matches its compare value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputCompareMode {
+    /// The output is left unchanged, which only sets the compare flag
+    Frozen = 0b000,
+    /// The output is set active on a match and stays active
+    Active = 0b001,
+    /// The output is set inactive on a match and stays inactive
+    Inactive = 0b010,
+    /// The output is toggled on each match
+    Toggle = 0b011,
+    /// The output is forced inactive regardless of the counter
+    ForceInactive = 0b100,
+    /// The output is forced active regardless of the counter
+    ForceActive = 0b101,
+}
+
+/// Drive a timer channel pin from compare matches instead of PWM. The timer counts through a
+/// period of `1 / frequency`, and the output reacts each time the counter passes the compare
+/// value, which starts at zero. Set `mode` to `OutputCompareMode::Toggle` for a square wave at
+/// half of `frequency`, or to `Active` or `Inactive` for a single edge at the compare value. All
+/// channels of a timer share the period
+pub fn setup_output_compare(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    mode: OutputCompareMode,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{tim1, tim2};
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    if frequency == 0 {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let (prescaler, auto_reload) =
+        compute_timer_period(timer, (clock_frequency / frequency) as u128)
+            .ok_or(TimerError::InvalidFrequency(frequency))?;
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let ccer_enable_field = get_ccer_enable_field(channel);
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        write_register(get_psc_prescaler_register(timer), prescaler);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+
+        // Start from an inactive output, so that a toggle or a set active gives a clean edge
+        set_output_compare_mode(timer, channel, OutputCompareMode::ForceInactive);
+        write_register(get_ccr_capture_compare_register(timer, channel), 0);
+
+        // Enable the output with active high polarity
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 1);
+        set_bit(ccer_capture_compare_enable_register, ccer_enable_field);
+
+        // The outputs of the advanced timers are gated by the main output enable
+        if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+            set_bit(bdtr_break_dead_time_register, tim1::bdtr::MOE);
+        }
+
+        set_output_compare_mode(timer, channel, mode);
+
+        // Load the pre-scaler without leaving the update flag set
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+
+        // Start the timer
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Output a square wave at `frequency` on a timer channel, by toggling the output twice per
+/// period
+pub fn setup_square_wave(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    setup_output_compare(
+        timer,
+        channel,
+        gpio,
+        OutputCompareMode::Toggle,
+        frequency
+            .checked_mul(2)
+            .ok_or(TimerError::InvalidFrequency(frequency))?,
+        clock_frequency,
+    )
+}
+
+/// Change what the output of a channel does on a compare match. The change is immediate, so the
+/// force modes can be used to set the pin level directly
+pub fn set_output_compare_mode(timer: &Timer, channel: TimerChannel, mode: OutputCompareMode) {
+    use registers::tim2;
+
+    let (ccmr_capture_compare_mode_register, offset) =
+        get_ccmr_capture_compare_mode_register(timer, channel);
+
+    unsafe {
+        // Select output mode for the channel, with the fourth mode bit and preload cleared so
+        // that new compare values apply immediately
+        write_bits(ccmr_capture_compare_mode_register, offset, 0b00, 0b11);
+        write_bits(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1M + offset,
+            mode as u32,
+            0b111,
+        );
+        clear_bit(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1M_3 + offset,
+        );
+        clear_bit(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1PE + offset,
+        );
+    }
+}
+
+/// Set the counter value the output of a channel reacts at, in ticks of the prescaled timer
+/// clock. Values above the auto reload value never match
+pub fn set_output_compare(timer: &Timer, channel: TimerChannel, ticks: u32) {
+    unsafe {
+        write_register(get_ccr_capture_compare_register(timer, channel), ticks);
+    }
+}
+
+/// Arm a single edge `delay_ticks` ticks from now, setting the output active or inactive. The
+/// delay has to be shorter than the timer period
+pub fn schedule_output_compare_edge(
+    timer: &Timer,
+    channel: TimerChannel,
+    active: bool,
+    delay_ticks: u32,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    let period = unsafe { read_register(get_arr_auto_reload_register(timer)) } as u64 + 1;
+    if delay_ticks == 0 || delay_ticks as u64 >= period {
+        return Err(TimerError::InvalidDelay(delay_ticks));
+    }
+
+    let count = unsafe { read_register(get_cnt_counter_register(timer)) } as u64;
+    let compare = ((count + delay_ticks as u64) % period) as u32;
+
+    // Clear a match from before, so that the flag shows when this edge has happened
+    unsafe {
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::CC1IF + channel as u8);
+    }
+
+    set_output_compare(timer, channel, compare);
+    set_output_compare_mode(
+        timer,
+        channel,
+        if active {
+            OutputCompareMode::Active
+        } else {
+            OutputCompareMode::Inactive
+        },
+    );
+
+    Ok(())
+}
+
+/// Whether the counter has matched the compare value of a channel since it was last set by
+/// `schedule_output_compare_edge`
+pub fn is_output_compare_matched(timer: &Timer, channel: TimerChannel) -> bool {
+    use registers::tim2;
+    unsafe {
+        get_bit(
+            get_sr_status_register(timer),
+            tim2::sr::CC1IF + channel as u8,
+        ) == 1
+    }
+}
+
+/// Disable the output of a channel and release its pin. The timer is stopped when no channel
+/// output is left enabled
+pub fn cleanup_output_compare(timer: &Timer, channel: TimerChannel, gpio: &Gpio) {
+    use registers::tim2;
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+
+    set_output_compare_mode(timer, channel, OutputCompareMode::Frozen);
+
+    unsafe {
+        clear_bit(
+            ccer_capture_compare_enable_register,
+            get_ccer_enable_field(channel),
+        );
+
+        if CHANNELS.iter().all(|channel| {
+            get_bit(
+                ccer_capture_compare_enable_register,
+                get_ccer_enable_field(*channel),
+            ) == 0
+        }) {
+            clear_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+        }
+    }
+
+    gpio.cleanup();
+}
+
+/// What starts a pulse in one-pulse mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OnePulseTrigger {
+    /// The pulse starts when `trigger_one_pulse` is called
+    Software,
+    /// The pulse starts on a rising edge of the channel 1 input of the timer
+    Input1,
+    /// The pulse starts on a rising edge of the channel 2 input of the timer
+    Input2,
+}
+
+/// Generate a single pulse of `width_ns` nanoseconds on a timer channel, starting `delay_ns`
+/// nanoseconds after the trigger. The delay is the compare value and the end of the pulse the
+/// auto reload value, after which the timer stops until the next trigger. With an input trigger,
+/// the pin of that input has to be setup separately and can't be the output channel
+pub fn setup_one_pulse(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    trigger: OnePulseTrigger,
+    delay_ns: u64,
+    width_ns: u64,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{tim1, tim2};
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    let to_ticks = |ns: u64| ns as u128 * clock_frequency as u128 / 1_000_000_000;
+    let delay_ticks = to_ticks(delay_ns);
+    let width_ticks = to_ticks(width_ns);
+
+    if width_ticks == 0 {
+        return Err(TimerError::InvalidPeriod(width_ns));
+    }
+
+    let (prescaler, auto_reload) = compute_timer_period(timer, delay_ticks + width_ticks)
+        .ok_or(TimerError::InvalidPeriod(delay_ns + width_ns))?;
+
+    // Start the pulse at least one tick after the trigger, so that the output has a clean edge
+    let compare = ((delay_ticks / (prescaler as u128 + 1)) as u32).clamp(1, auto_reload);
+
+    let trigger_channel = match trigger {
+        OnePulseTrigger::Software => None,
+        OnePulseTrigger::Input1 => Some((TimerChannel::Ch1, TimerTrigger::Ti1Fp1)),
+        OnePulseTrigger::Input2 => Some((TimerChannel::Ch2, TimerTrigger::Ti2Fp2)),
+    };
+
+    if let Some((trigger_channel, _)) = trigger_channel
+        && trigger_channel == channel
+    {
+        return Err(TimerError::Unsupported);
+    }
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let ccer_enable_field = get_ccer_enable_field(channel);
+    let cr1_control_register = get_cr1_control_register(timer);
+    let (ccmr_capture_compare_mode_register, offset) =
+        get_ccmr_capture_compare_mode_register(timer, channel);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+
+        write_register(get_psc_prescaler_register(timer), prescaler);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+        write_register(get_ccr_capture_compare_register(timer, channel), compare);
+
+        // PWM mode 2 keeps the output inactive until the counter reaches the compare value
+        set_output_compare_mode(timer, channel, OutputCompareMode::ForceInactive);
+        write_bits(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1M + offset,
+            0b111,
+            0b111,
+        );
+
+        // Enable the output with active high polarity
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 1);
+        set_bit(ccer_capture_compare_enable_register, ccer_enable_field);
+
+        // The outputs of the advanced timers are gated by the main output enable
+        if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+            set_bit(bdtr_break_dead_time_register, tim1::bdtr::MOE);
+        }
+
+        // Stop the counter at the end of the pulse
+        set_bit(cr1_control_register, tim2::cr1::OPM);
+
+        // Load the pre-scaler without leaving the update flag set
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+    }
+
+    if let Some((trigger_channel, timer_trigger)) = trigger_channel {
+        let (ccmr_capture_compare_mode_register, offset) =
+            get_ccmr_capture_compare_mode_register(timer, trigger_channel);
+
+        unsafe {
+            // Map the trigger channel to its own input, rising edge, without filter
+            write_bits(ccmr_capture_compare_mode_register, offset, 0b01, 0xFF);
+            clear_bit(
+                ccer_capture_compare_enable_register,
+                get_ccer_enable_field(trigger_channel) + 1,
+            );
+        }
+
+        // Let the edge start the counter
+        set_timer_slave_mode(timer, SlaveMode::Trigger, timer_trigger);
+    }
+
+    Ok(())
+}
+
+/// Start a pulse setup with `OnePulseTrigger::Software`. Does nothing if a pulse is already
+/// running
+pub fn trigger_one_pulse(timer: &Timer) {
+    use registers::tim2;
+    unsafe {
+        set_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+    }
+}
+
+/// Whether a pulse, including its delay, is still running
+pub fn is_one_pulse_running(timer: &Timer) -> bool {
+    use registers::tim2;
+    unsafe { get_bit(get_cr1_control_register(timer), tim2::cr1::CEN) == 1 }
+}
+
+/// Disable one-pulse mode and the output of the channel, and release its pin
+pub fn cleanup_one_pulse(timer: &Timer, channel: TimerChannel, gpio: &Gpio) {
+    use registers::tim2;
+
+    cleanup_timer_sync(timer);
+
+    unsafe {
+        clear_bit(get_cr1_control_register(timer), tim2::cr1::OPM);
+    }
+
+    cleanup_output_compare(timer, channel, gpio);
+}
@@ -0,0 +1,1170 @@
+/// Alternate function mapping for peripheral signals, taken from the alternate function tables
+/// in the STM32H743 datasheet (DS12110). Each signal has a table of legal pins together with
+/// the alternate function that routes the signal to that pin, and a function returning a
+/// preconfigured `Gpio` for a pin in the table, e.g. `pinmap::usart3_tx(PD8)`.
+///
+/// See [DS12110 Datasheet](https://www.st.com/resource/en/datasheet/stm32h743vi.pdf)
+use crate::{
+    gpio::{Gpio, GpioAlternate, GpioPin, GpioRegister},
+    timers::{Timer, TimerChannel},
+};
+
+/// Legal pins of a peripheral signal, with the alternate function routing the signal to each pin
+pub type PinTable = &'static [(PortPin, GpioAlternate)];
+
+/// A single pin on a gpio port
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PortPin {
+    pub register: GpioRegister,
+    pub pin: GpioPin,
+}
+
+impl PortPin {
+    pub const fn new(register: GpioRegister, pin: GpioPin) -> Self {
+        Self { register, pin }
+    }
+
+    /// Compare two pins in a const context
+    pub const fn is(&self, other: &PortPin) -> bool {
+        self.register as u8 == other.register as u8 && self.pin as u8 == other.pin as u8
+    }
+
+    /// Create an unconfigured gpio builder for this pin
+    pub const fn gpio(&self) -> Gpio {
+        Gpio::on(self.register, self.pin)
+    }
+
+    /// A unique index for the pin, usable for storing a pin in an atomic
+    pub const fn to_index(&self) -> u8 {
+        self.register as u8 * 16 + self.pin as u8
+    }
+
+    /// Get a pin from an index created with `to_index`
+    pub const fn from_index(index: u8) -> Option<Self> {
+        const REGISTERS: [GpioRegister; 9] = [
+            GpioRegister::GpioA,
+            GpioRegister::GpioB,
+            GpioRegister::GpioC,
+            GpioRegister::GpioD,
+            GpioRegister::GpioE,
+            GpioRegister::GpioH,
+            GpioRegister::GpioI,
+            GpioRegister::GpioJ,
+            GpioRegister::GpioK,
+        ];
+
+        const PINS: [GpioPin; 16] = [
+            GpioPin::P0,
+            GpioPin::P1,
+            GpioPin::P2,
+            GpioPin::P3,
+            GpioPin::P4,
+            GpioPin::P5,
+            GpioPin::P6,
+            GpioPin::P7,
+            GpioPin::P8,
+            GpioPin::P9,
+            GpioPin::P10,
+            GpioPin::P11,
+            GpioPin::P12,
+            GpioPin::P13,
+            GpioPin::P14,
+            GpioPin::P15,
+        ];
+
+        let register = (index / 16) as usize;
+        if register >= REGISTERS.len() {
+            return None;
+        }
+
+        Some(Self::new(REGISTERS[register], PINS[(index % 16) as usize]))
+    }
+}
+
+#[rustfmt::skip]
+mod pins {
+    use super::PortPin;
+    use crate::gpio::{GpioPin, GpioRegister};
+
+    pub const PA0: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P0);
+    pub const PA1: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P1);
+    pub const PA2: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P2);
+    pub const PA3: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P3);
+    pub const PA4: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P4);
+    pub const PA5: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P5);
+    pub const PA6: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P6);
+    pub const PA7: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P7);
+    pub const PA8: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P8);
+    pub const PA9: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P9);
+    pub const PA10: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P10);
+    pub const PA11: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P11);
+    pub const PA12: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P12);
+    pub const PA13: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P13);
+    pub const PA14: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P14);
+    pub const PA15: PortPin = PortPin::new(GpioRegister::GpioA, GpioPin::P15);
+
+    pub const PB0: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P0);
+    pub const PB1: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P1);
+    pub const PB2: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P2);
+    pub const PB3: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P3);
+    pub const PB4: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P4);
+    pub const PB5: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P5);
+    pub const PB6: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P6);
+    pub const PB7: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P7);
+    pub const PB8: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P8);
+    pub const PB9: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P9);
+    pub const PB10: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P10);
+    pub const PB11: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P11);
+    pub const PB12: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P12);
+    pub const PB13: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P13);
+    pub const PB14: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P14);
+    pub const PB15: PortPin = PortPin::new(GpioRegister::GpioB, GpioPin::P15);
+
+    pub const PC0: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P0);
+    pub const PC1: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P1);
+    pub const PC2: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P2);
+    pub const PC3: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P3);
+    pub const PC4: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P4);
+    pub const PC5: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P5);
+    pub const PC6: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P6);
+    pub const PC7: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P7);
+    pub const PC8: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P8);
+    pub const PC9: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P9);
+    pub const PC10: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P10);
+    pub const PC11: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P11);
+    pub const PC12: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P12);
+    pub const PC13: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P13);
+    pub const PC14: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P14);
+    pub const PC15: PortPin = PortPin::new(GpioRegister::GpioC, GpioPin::P15);
+
+    pub const PD0: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P0);
+    pub const PD1: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P1);
+    pub const PD2: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P2);
+    pub const PD3: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P3);
+    pub const PD4: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P4);
+    pub const PD5: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P5);
+    pub const PD6: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P6);
+    pub const PD7: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P7);
+    pub const PD8: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P8);
+    pub const PD9: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P9);
+    pub const PD10: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P10);
+    pub const PD11: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P11);
+    pub const PD12: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P12);
+    pub const PD13: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P13);
+    pub const PD14: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P14);
+    pub const PD15: PortPin = PortPin::new(GpioRegister::GpioD, GpioPin::P15);
+
+    pub const PE0: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P0);
+    pub const PE1: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P1);
+    pub const PE2: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P2);
+    pub const PE3: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P3);
+    pub const PE4: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P4);
+    pub const PE5: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P5);
+    pub const PE6: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P6);
+    pub const PE7: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P7);
+    pub const PE8: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P8);
+    pub const PE9: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P9);
+    pub const PE10: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P10);
+    pub const PE11: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P11);
+    pub const PE12: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P12);
+    pub const PE13: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P13);
+    pub const PE14: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P14);
+    pub const PE15: PortPin = PortPin::new(GpioRegister::GpioE, GpioPin::P15);
+
+    pub const PH0: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P0);
+    pub const PH1: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P1);
+    pub const PH2: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P2);
+    pub const PH3: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P3);
+    pub const PH4: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P4);
+    pub const PH5: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P5);
+    pub const PH6: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P6);
+    pub const PH7: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P7);
+    pub const PH8: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P8);
+    pub const PH9: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P9);
+    pub const PH10: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P10);
+    pub const PH11: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P11);
+    pub const PH12: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P12);
+    pub const PH13: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P13);
+    pub const PH14: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P14);
+    pub const PH15: PortPin = PortPin::new(GpioRegister::GpioH, GpioPin::P15);
+
+    pub const PI0: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P0);
+    pub const PI1: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P1);
+    pub const PI2: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P2);
+    pub const PI3: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P3);
+    pub const PI4: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P4);
+    pub const PI5: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P5);
+    pub const PI6: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P6);
+    pub const PI7: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P7);
+    pub const PI8: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P8);
+    pub const PI9: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P9);
+    pub const PI10: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P10);
+    pub const PI11: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P11);
+    pub const PI12: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P12);
+    pub const PI13: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P13);
+    pub const PI14: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P14);
+    pub const PI15: PortPin = PortPin::new(GpioRegister::GpioI, GpioPin::P15);
+
+    pub const PJ0: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P0);
+    pub const PJ1: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P1);
+    pub const PJ2: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P2);
+    pub const PJ3: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P3);
+    pub const PJ4: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P4);
+    pub const PJ5: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P5);
+    pub const PJ6: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P6);
+    pub const PJ7: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P7);
+    pub const PJ8: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P8);
+    pub const PJ9: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P9);
+    pub const PJ10: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P10);
+    pub const PJ11: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P11);
+    pub const PJ12: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P12);
+    pub const PJ13: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P13);
+    pub const PJ14: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P14);
+    pub const PJ15: PortPin = PortPin::new(GpioRegister::GpioJ, GpioPin::P15);
+
+    pub const PK0: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P0);
+    pub const PK1: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P1);
+    pub const PK2: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P2);
+    pub const PK3: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P3);
+    pub const PK4: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P4);
+    pub const PK5: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P5);
+    pub const PK6: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P6);
+    pub const PK7: PortPin = PortPin::new(GpioRegister::GpioK, GpioPin::P7);
+}
+
+pub use pins::*;
+
+/// Panics if the same pin occurs more than once in `pins`. Used by `pin_table!` to fail the
+/// build when a pin is claimed twice
+pub const fn assert_unique_pins(pins: &[PortPin]) {
+    let mut i = 0;
+    while i < pins.len() {
+        let mut j = i + 1;
+        while j < pins.len() {
+            if pins[i].is(&pins[j]) {
+                panic!("The same pin is claimed more than once in the pin table");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Declare the pins of a board as `Gpio` constants, failing the build if the same pin is claimed
+/// twice. A pin is either routed to a peripheral signal from this module, which also fails the
+/// build if the signal isn't available on the pin, or configured with the `Gpio` builder methods
+///
+/// ```ignore
+/// pin_table! {
+///     PA2 => USART2_TX = usart2_tx,
+///     PA3 => USART2_RX = usart2_rx.pull_up(),
+///     PB0 => LED.output(),
+///     PC13 => BUTTON.input().pull_down(),
+/// }
+/// ```
+#[macro_export]
+macro_rules! pin_table {
+    (@gpio $pin:ident = $signal:ident $(. $method:ident $args:tt)*) => {
+        match $crate::pinmap::$signal($crate::pinmap::$pin) {
+            Some(gpio) => gpio $(. $method $args)*,
+            None => panic!(concat!(
+                stringify!($signal),
+                " is not available on ",
+                stringify!($pin)
+            )),
+        }
+    };
+    (@gpio $pin:ident $(. $method:ident $args:tt)*) => {
+        $crate::pinmap::$pin.gpio() $(. $method $args)*
+    };
+    ($($pin:ident => $name:ident $(= $signal:ident)? $(. $method:ident $args:tt)*),* $(,)?) => {
+        const _: () = $crate::pinmap::assert_unique_pins(&[$($crate::pinmap::$pin),*]);
+
+        $(
+            pub const $name: $crate::gpio::Gpio =
+                $crate::pin_table!(@gpio $pin $(= $signal)? $(. $method $args)*);
+        )*
+    };
+}
+
+/// Find the alternate function for a pin in a signal table, and return the pin configured as an
+/// alternate function. Returns `None` if the signal can't be routed to the pin
+pub const fn lookup(pin: PortPin, table: &[(PortPin, GpioAlternate)]) -> Option<Gpio> {
+    let mut index = 0;
+    while index < table.len() {
+        let (candidate, alternate) = table[index];
+        if candidate.is(&pin) {
+            return Some(pin.gpio().alternate(alternate).high_speed());
+        }
+        index += 1;
+    }
+    None
+}
+
+/// USART1 transmit pins
+pub const USART1_TX_PINS: PinTable = &[
+    (PA9, GpioAlternate::AF7),
+    (PB6, GpioAlternate::AF7),
+    (PB14, GpioAlternate::AF4),
+];
+
+/// USART1 transmit, available on PA9, PB6, PB14
+pub const fn usart1_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART1_TX_PINS)
+}
+
+/// USART1 receive pins
+pub const USART1_RX_PINS: PinTable = &[
+    (PA10, GpioAlternate::AF7),
+    (PB7, GpioAlternate::AF7),
+    (PB15, GpioAlternate::AF4),
+];
+
+/// USART1 receive, available on PA10, PB7, PB15
+pub const fn usart1_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART1_RX_PINS)
+}
+
+/// USART1 clear to send pins
+pub const USART1_CTS_PINS: PinTable = &[(PA11, GpioAlternate::AF7)];
+
+/// USART1 clear to send, available on PA11
+pub const fn usart1_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART1_CTS_PINS)
+}
+
+/// USART1 request to send pins
+pub const USART1_RTS_PINS: PinTable = &[(PA12, GpioAlternate::AF7)];
+
+/// USART1 request to send, available on PA12
+pub const fn usart1_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART1_RTS_PINS)
+}
+
+/// USART1 clock pins
+pub const USART1_CK_PINS: PinTable = &[(PA8, GpioAlternate::AF7)];
+
+/// USART1 clock, available on PA8
+pub const fn usart1_ck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART1_CK_PINS)
+}
+
+/// USART2 transmit pins
+pub const USART2_TX_PINS: PinTable = &[(PA2, GpioAlternate::AF7), (PD5, GpioAlternate::AF7)];
+
+/// USART2 transmit, available on PA2, PD5
+pub const fn usart2_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART2_TX_PINS)
+}
+
+/// USART2 receive pins
+pub const USART2_RX_PINS: PinTable = &[(PA3, GpioAlternate::AF7), (PD6, GpioAlternate::AF7)];
+
+/// USART2 receive, available on PA3, PD6
+pub const fn usart2_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART2_RX_PINS)
+}
+
+/// USART2 clear to send pins
+pub const USART2_CTS_PINS: PinTable = &[(PA0, GpioAlternate::AF7), (PD3, GpioAlternate::AF7)];
+
+/// USART2 clear to send, available on PA0, PD3
+pub const fn usart2_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART2_CTS_PINS)
+}
+
+/// USART2 request to send pins
+pub const USART2_RTS_PINS: PinTable = &[(PA1, GpioAlternate::AF7), (PD4, GpioAlternate::AF7)];
+
+/// USART2 request to send, available on PA1, PD4
+pub const fn usart2_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART2_RTS_PINS)
+}
+
+/// USART2 clock pins
+pub const USART2_CK_PINS: PinTable = &[(PA4, GpioAlternate::AF7), (PD7, GpioAlternate::AF7)];
+
+/// USART2 clock, available on PA4, PD7
+pub const fn usart2_ck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART2_CK_PINS)
+}
+
+/// USART3 transmit pins
+pub const USART3_TX_PINS: PinTable = &[
+    (PB10, GpioAlternate::AF7),
+    (PC10, GpioAlternate::AF7),
+    (PD8, GpioAlternate::AF7),
+];
+
+/// USART3 transmit, available on PB10, PC10, PD8
+pub const fn usart3_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART3_TX_PINS)
+}
+
+/// USART3 receive pins
+pub const USART3_RX_PINS: PinTable = &[
+    (PB11, GpioAlternate::AF7),
+    (PC11, GpioAlternate::AF7),
+    (PD9, GpioAlternate::AF7),
+];
+
+/// USART3 receive, available on PB11, PC11, PD9
+pub const fn usart3_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART3_RX_PINS)
+}
+
+/// USART3 clear to send pins
+pub const USART3_CTS_PINS: PinTable = &[(PB13, GpioAlternate::AF7), (PD11, GpioAlternate::AF7)];
+
+/// USART3 clear to send, available on PB13, PD11
+pub const fn usart3_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART3_CTS_PINS)
+}
+
+/// USART3 request to send pins
+pub const USART3_RTS_PINS: PinTable = &[(PB14, GpioAlternate::AF7), (PD12, GpioAlternate::AF7)];
+
+/// USART3 request to send, available on PB14, PD12
+pub const fn usart3_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART3_RTS_PINS)
+}
+
+/// USART3 clock pins
+pub const USART3_CK_PINS: PinTable = &[
+    (PB12, GpioAlternate::AF7),
+    (PC12, GpioAlternate::AF7),
+    (PD10, GpioAlternate::AF7),
+];
+
+/// USART3 clock, available on PB12, PC12, PD10
+pub const fn usart3_ck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART3_CK_PINS)
+}
+
+/// UART4 transmit pins
+pub const UART4_TX_PINS: PinTable = &[
+    (PA0, GpioAlternate::AF8),
+    (PA12, GpioAlternate::AF6),
+    (PB9, GpioAlternate::AF8),
+    (PC10, GpioAlternate::AF8),
+    (PD1, GpioAlternate::AF8),
+    (PH13, GpioAlternate::AF8),
+];
+
+/// UART4 transmit, available on PA0, PA12, PB9, PC10, PD1, PH13
+pub const fn uart4_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART4_TX_PINS)
+}
+
+/// UART4 receive pins
+pub const UART4_RX_PINS: PinTable = &[
+    (PA1, GpioAlternate::AF8),
+    (PA11, GpioAlternate::AF6),
+    (PB8, GpioAlternate::AF8),
+    (PC11, GpioAlternate::AF8),
+    (PD0, GpioAlternate::AF8),
+    (PH14, GpioAlternate::AF8),
+    (PI9, GpioAlternate::AF8),
+];
+
+/// UART4 receive, available on PA1, PA11, PB8, PC11, PD0, PH14, PI9
+pub const fn uart4_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART4_RX_PINS)
+}
+
+/// UART4 clear to send pins
+pub const UART4_CTS_PINS: PinTable = &[(PB0, GpioAlternate::AF8), (PB15, GpioAlternate::AF8)];
+
+/// UART4 clear to send, available on PB0, PB15
+pub const fn uart4_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART4_CTS_PINS)
+}
+
+/// UART4 request to send pins
+pub const UART4_RTS_PINS: PinTable = &[(PA15, GpioAlternate::AF8), (PB14, GpioAlternate::AF8)];
+
+/// UART4 request to send, available on PA15, PB14
+pub const fn uart4_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART4_RTS_PINS)
+}
+
+/// UART5 transmit pins
+pub const UART5_TX_PINS: PinTable = &[
+    (PB6, GpioAlternate::AF14),
+    (PB13, GpioAlternate::AF14),
+    (PC12, GpioAlternate::AF8),
+];
+
+/// UART5 transmit, available on PB6, PB13, PC12
+pub const fn uart5_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART5_TX_PINS)
+}
+
+/// UART5 receive pins
+pub const UART5_RX_PINS: PinTable = &[
+    (PB5, GpioAlternate::AF14),
+    (PB12, GpioAlternate::AF14),
+    (PD2, GpioAlternate::AF8),
+];
+
+/// UART5 receive, available on PB5, PB12, PD2
+pub const fn uart5_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART5_RX_PINS)
+}
+
+/// UART5 clear to send pins
+pub const UART5_CTS_PINS: PinTable = &[(PC9, GpioAlternate::AF8)];
+
+/// UART5 clear to send, available on PC9
+pub const fn uart5_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART5_CTS_PINS)
+}
+
+/// UART5 request to send pins
+pub const UART5_RTS_PINS: PinTable = &[(PC8, GpioAlternate::AF8)];
+
+/// UART5 request to send, available on PC8
+pub const fn uart5_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART5_RTS_PINS)
+}
+
+/// USART6 transmit pins
+pub const USART6_TX_PINS: PinTable = &[(PC6, GpioAlternate::AF7)];
+
+/// USART6 transmit, available on PC6
+pub const fn usart6_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART6_TX_PINS)
+}
+
+/// USART6 receive pins
+pub const USART6_RX_PINS: PinTable = &[(PC7, GpioAlternate::AF7)];
+
+/// USART6 receive, available on PC7
+pub const fn usart6_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART6_RX_PINS)
+}
+
+/// USART6 clear to send pins. The signal is only available on port G, which isn't supported
+pub const USART6_CTS_PINS: PinTable = &[];
+
+/// USART6 request to send pins. The signal is only available on port G, which isn't supported
+pub const USART6_RTS_PINS: PinTable = &[];
+
+/// USART6 clock pins
+pub const USART6_CK_PINS: PinTable = &[(PC8, GpioAlternate::AF7)];
+
+/// USART6 clock, available on PC8
+pub const fn usart6_ck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, USART6_CK_PINS)
+}
+
+/// UART7 transmit pins
+pub const UART7_TX_PINS: PinTable = &[
+    (PA15, GpioAlternate::AF11),
+    (PB4, GpioAlternate::AF11),
+    (PE8, GpioAlternate::AF7),
+];
+
+/// UART7 transmit, available on PA15, PB4, PE8
+pub const fn uart7_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART7_TX_PINS)
+}
+
+/// UART7 receive pins
+pub const UART7_RX_PINS: PinTable = &[
+    (PA8, GpioAlternate::AF11),
+    (PB3, GpioAlternate::AF11),
+    (PE7, GpioAlternate::AF7),
+];
+
+/// UART7 receive, available on PA8, PB3, PE7
+pub const fn uart7_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART7_RX_PINS)
+}
+
+/// UART7 clear to send pins
+pub const UART7_CTS_PINS: PinTable = &[(PE10, GpioAlternate::AF7)];
+
+/// UART7 clear to send, available on PE10
+pub const fn uart7_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART7_CTS_PINS)
+}
+
+/// UART7 request to send pins
+pub const UART7_RTS_PINS: PinTable = &[(PE9, GpioAlternate::AF7)];
+
+/// UART7 request to send, available on PE9
+pub const fn uart7_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART7_RTS_PINS)
+}
+
+/// UART8 transmit pins
+pub const UART8_TX_PINS: PinTable = &[(PE1, GpioAlternate::AF8), (PJ8, GpioAlternate::AF8)];
+
+/// UART8 transmit, available on PE1, PJ8
+pub const fn uart8_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART8_TX_PINS)
+}
+
+/// UART8 receive pins
+pub const UART8_RX_PINS: PinTable = &[(PE0, GpioAlternate::AF8), (PJ9, GpioAlternate::AF8)];
+
+/// UART8 receive, available on PE0, PJ9
+pub const fn uart8_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART8_RX_PINS)
+}
+
+/// UART8 clear to send pins
+pub const UART8_CTS_PINS: PinTable = &[(PD14, GpioAlternate::AF8)];
+
+/// UART8 clear to send, available on PD14
+pub const fn uart8_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART8_CTS_PINS)
+}
+
+/// UART8 request to send pins
+pub const UART8_RTS_PINS: PinTable = &[(PD15, GpioAlternate::AF8)];
+
+/// UART8 request to send, available on PD15
+pub const fn uart8_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, UART8_RTS_PINS)
+}
+
+/// LPUART1 transmit pins
+pub const LPUART1_TX_PINS: PinTable = &[(PA9, GpioAlternate::AF3), (PB6, GpioAlternate::AF8)];
+
+/// LPUART1 transmit, available on PA9, PB6
+pub const fn lpuart1_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, LPUART1_TX_PINS)
+}
+
+/// LPUART1 receive pins
+pub const LPUART1_RX_PINS: PinTable = &[(PA10, GpioAlternate::AF3), (PB7, GpioAlternate::AF8)];
+
+/// LPUART1 receive, available on PA10, PB7
+pub const fn lpuart1_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, LPUART1_RX_PINS)
+}
+
+/// LPUART1 clear to send pins
+pub const LPUART1_CTS_PINS: PinTable = &[(PA11, GpioAlternate::AF3)];
+
+/// LPUART1 clear to send, available on PA11
+pub const fn lpuart1_cts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, LPUART1_CTS_PINS)
+}
+
+/// LPUART1 request to send pins
+pub const LPUART1_RTS_PINS: PinTable = &[(PA12, GpioAlternate::AF3)];
+
+/// LPUART1 request to send, available on PA12
+pub const fn lpuart1_rts(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, LPUART1_RTS_PINS)
+}
+
+/// SPI1 clock pins
+pub const SPI1_SCK_PINS: PinTable = &[(PA5, GpioAlternate::AF5), (PB3, GpioAlternate::AF5)];
+
+/// SPI1 clock, available on PA5, PB3
+pub const fn spi1_sck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI1_SCK_PINS)
+}
+
+/// SPI1 master in, slave out pins
+pub const SPI1_MISO_PINS: PinTable = &[(PA6, GpioAlternate::AF5), (PB4, GpioAlternate::AF5)];
+
+/// SPI1 master in, slave out, available on PA6, PB4
+pub const fn spi1_miso(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI1_MISO_PINS)
+}
+
+/// SPI1 master out, slave in pins
+pub const SPI1_MOSI_PINS: PinTable = &[
+    (PA7, GpioAlternate::AF5),
+    (PB5, GpioAlternate::AF5),
+    (PD7, GpioAlternate::AF5),
+];
+
+/// SPI1 master out, slave in, available on PA7, PB5, PD7
+pub const fn spi1_mosi(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI1_MOSI_PINS)
+}
+
+/// SPI1 slave select pins
+pub const SPI1_NSS_PINS: PinTable = &[(PA4, GpioAlternate::AF5), (PA15, GpioAlternate::AF5)];
+
+/// SPI1 slave select, available on PA4, PA15
+pub const fn spi1_nss(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI1_NSS_PINS)
+}
+
+/// SPI2 clock pins
+pub const SPI2_SCK_PINS: PinTable = &[
+    (PA9, GpioAlternate::AF5),
+    (PA12, GpioAlternate::AF5),
+    (PB10, GpioAlternate::AF5),
+    (PB13, GpioAlternate::AF5),
+    (PD3, GpioAlternate::AF5),
+    (PI1, GpioAlternate::AF5),
+];
+
+/// SPI2 clock, available on PA9, PA12, PB10, PB13, PD3, PI1
+pub const fn spi2_sck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI2_SCK_PINS)
+}
+
+/// SPI2 master in, slave out pins
+pub const SPI2_MISO_PINS: PinTable = &[
+    (PB14, GpioAlternate::AF5),
+    (PC2, GpioAlternate::AF5),
+    (PI2, GpioAlternate::AF5),
+];
+
+/// SPI2 master in, slave out, available on PB14, PC2, PI2
+pub const fn spi2_miso(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI2_MISO_PINS)
+}
+
+/// SPI2 master out, slave in pins
+pub const SPI2_MOSI_PINS: PinTable = &[
+    (PB15, GpioAlternate::AF5),
+    (PC1, GpioAlternate::AF5),
+    (PC3, GpioAlternate::AF5),
+    (PI3, GpioAlternate::AF5),
+];
+
+/// SPI2 master out, slave in, available on PB15, PC1, PC3, PI3
+pub const fn spi2_mosi(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI2_MOSI_PINS)
+}
+
+/// SPI2 slave select pins
+pub const SPI2_NSS_PINS: PinTable = &[
+    (PA11, GpioAlternate::AF5),
+    (PB4, GpioAlternate::AF7),
+    (PB9, GpioAlternate::AF5),
+    (PB12, GpioAlternate::AF5),
+    (PI0, GpioAlternate::AF5),
+];
+
+/// SPI2 slave select, available on PA11, PB4, PB9, PB12, PI0
+pub const fn spi2_nss(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI2_NSS_PINS)
+}
+
+/// SPI3 clock pins
+pub const SPI3_SCK_PINS: PinTable = &[(PB3, GpioAlternate::AF6), (PC10, GpioAlternate::AF6)];
+
+/// SPI3 clock, available on PB3, PC10
+pub const fn spi3_sck(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI3_SCK_PINS)
+}
+
+/// SPI3 master in, slave out pins
+pub const SPI3_MISO_PINS: PinTable = &[(PB4, GpioAlternate::AF6), (PC11, GpioAlternate::AF6)];
+
+/// SPI3 master in, slave out, available on PB4, PC11
+pub const fn spi3_miso(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI3_MISO_PINS)
+}
+
+/// SPI3 master out, slave in pins
+pub const SPI3_MOSI_PINS: PinTable = &[
+    (PB2, GpioAlternate::AF7),
+    (PB5, GpioAlternate::AF7),
+    (PC12, GpioAlternate::AF6),
+    (PD6, GpioAlternate::AF5),
+];
+
+/// SPI3 master out, slave in, available on PB2, PB5, PC12, PD6
+pub const fn spi3_mosi(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI3_MOSI_PINS)
+}
+
+/// SPI3 slave select pins
+pub const SPI3_NSS_PINS: PinTable = &[(PA4, GpioAlternate::AF6), (PA15, GpioAlternate::AF6)];
+
+/// SPI3 slave select, available on PA4, PA15
+pub const fn spi3_nss(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, SPI3_NSS_PINS)
+}
+
+/// I2C1 clock pins
+pub const I2C1_SCL_PINS: PinTable = &[(PB6, GpioAlternate::AF4), (PB8, GpioAlternate::AF4)];
+
+/// I2C1 clock, available on PB6, PB8
+pub const fn i2c1_scl(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C1_SCL_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C1 data pins
+pub const I2C1_SDA_PINS: PinTable = &[(PB7, GpioAlternate::AF4), (PB9, GpioAlternate::AF4)];
+
+/// I2C1 data, available on PB7, PB9
+pub const fn i2c1_sda(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C1_SDA_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C2 clock pins
+pub const I2C2_SCL_PINS: PinTable = &[(PB10, GpioAlternate::AF4), (PH4, GpioAlternate::AF4)];
+
+/// I2C2 clock, available on PB10, PH4
+pub const fn i2c2_scl(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C2_SCL_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C2 data pins
+pub const I2C2_SDA_PINS: PinTable = &[(PB11, GpioAlternate::AF4), (PH5, GpioAlternate::AF4)];
+
+/// I2C2 data, available on PB11, PH5
+pub const fn i2c2_sda(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C2_SDA_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C3 clock pins
+pub const I2C3_SCL_PINS: PinTable = &[(PA8, GpioAlternate::AF4), (PH7, GpioAlternate::AF4)];
+
+/// I2C3 clock, available on PA8, PH7
+pub const fn i2c3_scl(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C3_SCL_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C3 data pins
+pub const I2C3_SDA_PINS: PinTable = &[(PC9, GpioAlternate::AF4), (PH8, GpioAlternate::AF4)];
+
+/// I2C3 data, available on PC9, PH8
+pub const fn i2c3_sda(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C3_SDA_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C4 clock pins
+pub const I2C4_SCL_PINS: PinTable = &[
+    (PB6, GpioAlternate::AF6),
+    (PB8, GpioAlternate::AF6),
+    (PD12, GpioAlternate::AF4),
+    (PH11, GpioAlternate::AF4),
+];
+
+/// I2C4 clock, available on PB6, PB8, PD12, PH11
+pub const fn i2c4_scl(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C4_SCL_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// I2C4 data pins
+pub const I2C4_SDA_PINS: PinTable = &[
+    (PB7, GpioAlternate::AF6),
+    (PB9, GpioAlternate::AF6),
+    (PD13, GpioAlternate::AF4),
+    (PH12, GpioAlternate::AF4),
+];
+
+/// I2C4 data, available on PB7, PB9, PD13, PH12
+pub const fn i2c4_sda(pin: PortPin) -> Option<Gpio> {
+    match lookup(pin, I2C4_SDA_PINS) {
+        Some(gpio) => Some(gpio.open_drain()),
+        None => None,
+    }
+}
+
+/// FDCAN1 receive pins
+pub const FDCAN1_RX_PINS: PinTable = &[
+    (PA11, GpioAlternate::AF9),
+    (PB8, GpioAlternate::AF9),
+    (PD0, GpioAlternate::AF9),
+    (PH14, GpioAlternate::AF9),
+];
+
+/// FDCAN1 receive, available on PA11, PB8, PD0, PH14
+pub const fn fdcan1_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, FDCAN1_RX_PINS)
+}
+
+/// FDCAN1 transmit pins
+pub const FDCAN1_TX_PINS: PinTable = &[
+    (PA12, GpioAlternate::AF9),
+    (PB9, GpioAlternate::AF9),
+    (PD1, GpioAlternate::AF9),
+    (PH13, GpioAlternate::AF9),
+];
+
+/// FDCAN1 transmit, available on PA12, PB9, PD1, PH13
+pub const fn fdcan1_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, FDCAN1_TX_PINS)
+}
+
+/// FDCAN2 receive pins
+pub const FDCAN2_RX_PINS: PinTable = &[(PB5, GpioAlternate::AF9), (PB12, GpioAlternate::AF9)];
+
+/// FDCAN2 receive, available on PB5, PB12
+pub const fn fdcan2_rx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, FDCAN2_RX_PINS)
+}
+
+/// FDCAN2 transmit pins
+pub const FDCAN2_TX_PINS: PinTable = &[(PB6, GpioAlternate::AF9), (PB13, GpioAlternate::AF9)];
+
+/// FDCAN2 transmit, available on PB6, PB13
+pub const fn fdcan2_tx(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, FDCAN2_TX_PINS)
+}
+
+/// TIM1 channel 1 pins
+pub const TIM1_CH1_PINS: PinTable = &[(PA8, GpioAlternate::AF1), (PE9, GpioAlternate::AF1)];
+
+/// TIM1 channel 1, available on PA8, PE9
+pub const fn tim1_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM1_CH1_PINS)
+}
+
+/// TIM1 channel 2 pins
+pub const TIM1_CH2_PINS: PinTable = &[(PA9, GpioAlternate::AF1), (PE11, GpioAlternate::AF1)];
+
+/// TIM1 channel 2, available on PA9, PE11
+pub const fn tim1_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM1_CH2_PINS)
+}
+
+/// TIM1 channel 3 pins
+pub const TIM1_CH3_PINS: PinTable = &[(PA10, GpioAlternate::AF1), (PE13, GpioAlternate::AF1)];
+
+/// TIM1 channel 3, available on PA10, PE13
+pub const fn tim1_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM1_CH3_PINS)
+}
+
+/// TIM1 channel 4 pins
+pub const TIM1_CH4_PINS: PinTable = &[(PA11, GpioAlternate::AF1), (PE14, GpioAlternate::AF1)];
+
+/// TIM1 channel 4, available on PA11, PE14
+pub const fn tim1_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM1_CH4_PINS)
+}
+
+/// TIM2 channel 1 pins
+pub const TIM2_CH1_PINS: PinTable = &[
+    (PA0, GpioAlternate::AF1),
+    (PA5, GpioAlternate::AF1),
+    (PA15, GpioAlternate::AF1),
+];
+
+/// TIM2 channel 1, available on PA0, PA5, PA15
+pub const fn tim2_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM2_CH1_PINS)
+}
+
+/// TIM2 channel 2 pins
+pub const TIM2_CH2_PINS: PinTable = &[(PA1, GpioAlternate::AF1), (PB3, GpioAlternate::AF1)];
+
+/// TIM2 channel 2, available on PA1, PB3
+pub const fn tim2_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM2_CH2_PINS)
+}
+
+/// TIM2 channel 3 pins
+pub const TIM2_CH3_PINS: PinTable = &[(PA2, GpioAlternate::AF1), (PB10, GpioAlternate::AF1)];
+
+/// TIM2 channel 3, available on PA2, PB10
+pub const fn tim2_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM2_CH3_PINS)
+}
+
+/// TIM2 channel 4 pins
+pub const TIM2_CH4_PINS: PinTable = &[(PA3, GpioAlternate::AF1), (PB11, GpioAlternate::AF1)];
+
+/// TIM2 channel 4, available on PA3, PB11
+pub const fn tim2_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM2_CH4_PINS)
+}
+
+/// TIM3 channel 1 pins
+pub const TIM3_CH1_PINS: PinTable = &[
+    (PA6, GpioAlternate::AF2),
+    (PB4, GpioAlternate::AF2),
+    (PC6, GpioAlternate::AF2),
+];
+
+/// TIM3 channel 1, available on PA6, PB4, PC6
+pub const fn tim3_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM3_CH1_PINS)
+}
+
+/// TIM3 channel 2 pins
+pub const TIM3_CH2_PINS: PinTable = &[
+    (PA7, GpioAlternate::AF2),
+    (PB5, GpioAlternate::AF2),
+    (PC7, GpioAlternate::AF2),
+];
+
+/// TIM3 channel 2, available on PA7, PB5, PC7
+pub const fn tim3_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM3_CH2_PINS)
+}
+
+/// TIM3 channel 3 pins
+pub const TIM3_CH3_PINS: PinTable = &[(PB0, GpioAlternate::AF2), (PC8, GpioAlternate::AF2)];
+
+/// TIM3 channel 3, available on PB0, PC8
+pub const fn tim3_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM3_CH3_PINS)
+}
+
+/// TIM3 channel 4 pins
+pub const TIM3_CH4_PINS: PinTable = &[(PB1, GpioAlternate::AF2), (PC9, GpioAlternate::AF2)];
+
+/// TIM3 channel 4, available on PB1, PC9
+pub const fn tim3_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM3_CH4_PINS)
+}
+
+/// TIM4 channel 1 pins
+pub const TIM4_CH1_PINS: PinTable = &[(PB6, GpioAlternate::AF2), (PD12, GpioAlternate::AF2)];
+
+/// TIM4 channel 1, available on PB6, PD12
+pub const fn tim4_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM4_CH1_PINS)
+}
+
+/// TIM4 channel 2 pins
+pub const TIM4_CH2_PINS: PinTable = &[(PB7, GpioAlternate::AF2), (PD13, GpioAlternate::AF2)];
+
+/// TIM4 channel 2, available on PB7, PD13
+pub const fn tim4_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM4_CH2_PINS)
+}
+
+/// TIM4 channel 3 pins
+pub const TIM4_CH3_PINS: PinTable = &[(PB8, GpioAlternate::AF2), (PD14, GpioAlternate::AF2)];
+
+/// TIM4 channel 3, available on PB8, PD14
+pub const fn tim4_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM4_CH3_PINS)
+}
+
+/// TIM4 channel 4 pins
+pub const TIM4_CH4_PINS: PinTable = &[(PB9, GpioAlternate::AF2), (PD15, GpioAlternate::AF2)];
+
+/// TIM4 channel 4, available on PB9, PD15
+pub const fn tim4_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM4_CH4_PINS)
+}
+
+/// TIM5 channel 1 pins
+pub const TIM5_CH1_PINS: PinTable = &[(PA0, GpioAlternate::AF2), (PH10, GpioAlternate::AF2)];
+
+/// TIM5 channel 1, available on PA0, PH10
+pub const fn tim5_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM5_CH1_PINS)
+}
+
+/// TIM5 channel 2 pins
+pub const TIM5_CH2_PINS: PinTable = &[(PA1, GpioAlternate::AF2), (PH11, GpioAlternate::AF2)];
+
+/// TIM5 channel 2, available on PA1, PH11
+pub const fn tim5_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM5_CH2_PINS)
+}
+
+/// TIM5 channel 3 pins
+pub const TIM5_CH3_PINS: PinTable = &[(PA2, GpioAlternate::AF2), (PH12, GpioAlternate::AF2)];
+
+/// TIM5 channel 3, available on PA2, PH12
+pub const fn tim5_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM5_CH3_PINS)
+}
+
+/// TIM5 channel 4 pins
+pub const TIM5_CH4_PINS: PinTable = &[(PA3, GpioAlternate::AF2), (PI0, GpioAlternate::AF2)];
+
+/// TIM5 channel 4, available on PA3, PI0
+pub const fn tim5_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM5_CH4_PINS)
+}
+
+/// TIM8 channel 1 pins
+pub const TIM8_CH1_PINS: PinTable = &[(PC6, GpioAlternate::AF3), (PI5, GpioAlternate::AF3)];
+
+/// TIM8 channel 1, available on PC6, PI5
+pub const fn tim8_ch1(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM8_CH1_PINS)
+}
+
+/// TIM8 channel 2 pins
+pub const TIM8_CH2_PINS: PinTable = &[(PC7, GpioAlternate::AF3), (PI6, GpioAlternate::AF3)];
+
+/// TIM8 channel 2, available on PC7, PI6
+pub const fn tim8_ch2(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM8_CH2_PINS)
+}
+
+/// TIM8 channel 3 pins
+pub const TIM8_CH3_PINS: PinTable = &[(PC8, GpioAlternate::AF3), (PI7, GpioAlternate::AF3)];
+
+/// TIM8 channel 3, available on PC8, PI7
+pub const fn tim8_ch3(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM8_CH3_PINS)
+}
+
+/// TIM8 channel 4 pins
+pub const TIM8_CH4_PINS: PinTable = &[(PC9, GpioAlternate::AF3), (PI2, GpioAlternate::AF3)];
+
+/// TIM8 channel 4, available on PC9, PI2
+pub const fn tim8_ch4(pin: PortPin) -> Option<Gpio> {
+    lookup(pin, TIM8_CH4_PINS)
+}
+
+/// Legal pins of a timer channel
+pub const fn timer_channel_pins(timer: Timer, channel: TimerChannel) -> PinTable {
+    match (timer, channel) {
+        (Timer::Tim1, TimerChannel::Ch1) => TIM1_CH1_PINS,
+        (Timer::Tim1, TimerChannel::Ch2) => TIM1_CH2_PINS,
+        (Timer::Tim1, TimerChannel::Ch3) => TIM1_CH3_PINS,
+        (Timer::Tim1, TimerChannel::Ch4) => TIM1_CH4_PINS,
+        (Timer::Tim2, TimerChannel::Ch1) => TIM2_CH1_PINS,
+        (Timer::Tim2, TimerChannel::Ch2) => TIM2_CH2_PINS,
+        (Timer::Tim2, TimerChannel::Ch3) => TIM2_CH3_PINS,
+        (Timer::Tim2, TimerChannel::Ch4) => TIM2_CH4_PINS,
+        (Timer::Tim3, TimerChannel::Ch1) => TIM3_CH1_PINS,
+        (Timer::Tim3, TimerChannel::Ch2) => TIM3_CH2_PINS,
+        (Timer::Tim3, TimerChannel::Ch3) => TIM3_CH3_PINS,
+        (Timer::Tim3, TimerChannel::Ch4) => TIM3_CH4_PINS,
+        (Timer::Tim4, TimerChannel::Ch1) => TIM4_CH1_PINS,
+        (Timer::Tim4, TimerChannel::Ch2) => TIM4_CH2_PINS,
+        (Timer::Tim4, TimerChannel::Ch3) => TIM4_CH3_PINS,
+        (Timer::Tim4, TimerChannel::Ch4) => TIM4_CH4_PINS,
+        (Timer::Tim5, TimerChannel::Ch1) => TIM5_CH1_PINS,
+        (Timer::Tim5, TimerChannel::Ch2) => TIM5_CH2_PINS,
+        (Timer::Tim5, TimerChannel::Ch3) => TIM5_CH3_PINS,
+        (Timer::Tim5, TimerChannel::Ch4) => TIM5_CH4_PINS,
+        (Timer::Tim8, TimerChannel::Ch1) => TIM8_CH1_PINS,
+        (Timer::Tim8, TimerChannel::Ch2) => TIM8_CH2_PINS,
+        (Timer::Tim8, TimerChannel::Ch3) => TIM8_CH3_PINS,
+        (Timer::Tim8, TimerChannel::Ch4) => TIM8_CH4_PINS,
+    }
+}
+
+/// A timer channel routed to `pin`, configured as alternate function of the timer. Returns
+/// `None` if the channel isn't available on the pin
+pub const fn timer_channel(timer: Timer, channel: TimerChannel, pin: PortPin) -> Option<Gpio> {
+    lookup(pin, timer_channel_pins(timer, channel))
+}
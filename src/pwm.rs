@@ -0,0 +1,589 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    gpio::Gpio,
+    pinmap::PortPin,
+    register_tools::{
+        ClearByWrite0, clear_bit, get_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers,
+    timers::{
+        CHANNELS, Timer, TimerChannel, TimerError, enable_timer_clock,
+        get_arr_auto_reload_register, get_bdtr_break_dead_time_register,
+        get_ccer_capture_compare_enable_register, get_ccer_enable_field,
+        get_ccmr_capture_compare_mode_register, get_ccr_capture_compare_register,
+        get_cr1_control_register, get_egr_event_generator_register, get_psc_prescaler_register,
+        get_sr_status_register, get_timer_channel_gpio, get_timer_max_count,
+    },
+};
+
+/// Output compare modes used for PWM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PwmMode {
+    /// The output is active while the counter is below the duty cycle
+    Mode1 = 0b110,
+    /// The output is inactive while the counter is below the duty cycle
+    Mode2 = 0b111,
+}
+
+/// Counting mode of a timer. In the center-aligned modes the counter counts up to the auto
+/// reload value and back down, and the mode selects when the compare interrupt flags are set
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PwmAlignment {
+    Edge = 0b00,
+    /// Flags are set while counting down
+    CenterDown = 0b01,
+    /// Flags are set while counting up
+    CenterUp = 0b10,
+    /// Flags are set while counting both up and down
+    CenterBoth = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakPolarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Compute the prescaler and auto reload values for a PWM frequency, using the smallest prescaler
+/// that fits the period in the counter to get the highest duty cycle resolution. A center-aligned
+/// period counts up to the auto reload value and back down, so it only takes half the ticks
+fn compute_pwm_period(
+    timer: &Timer,
+    clock_frequency: u32,
+    frequency: u32,
+) -> Result<(u32, u32), TimerError> {
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    if frequency == 0 {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let center_aligned = is_center_aligned(timer);
+
+    // At least two ticks are needed for the output to toggle
+    let ticks = (clock_frequency / frequency) as u64 >> center_aligned as u32;
+    if ticks < 2 {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let prescaler = (ticks - 1) / (get_timer_max_count(timer) as u64 + 1);
+    if prescaler > 0xFFFF {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let auto_reload = if center_aligned {
+        (ticks / (prescaler + 1)).min(get_timer_max_count(timer) as u64)
+    } else {
+        ticks / (prescaler + 1) - 1
+    };
+
+    Ok((prescaler as u32, auto_reload as u32))
+}
+
+fn is_center_aligned(timer: &Timer) -> bool {
+    use registers::tim2;
+
+    let cr1_control_register = get_cr1_control_register(timer);
+    unsafe {
+        get_bit(cr1_control_register, tim2::cr1::CMS) == 1
+            || get_bit(cr1_control_register, tim2::cr1::CMS + 1) == 1
+    }
+}
+
+/// Output PWM at `frequency` on a timer channel, starting with a duty cycle of zero. `gpio` is
+/// the pin the channel is routed to, configured as alternate function of the timer. All channels
+/// of a timer share the same frequency, so setting up another channel resets it
+pub fn setup_pwm(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    mode: PwmMode,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{tim1, tim2};
+
+    let (prescaler, auto_reload) = compute_pwm_period(timer, clock_frequency, frequency)?;
+    let (ccmr_capture_compare_mode_register, offset) =
+        get_ccmr_capture_compare_mode_register(timer, channel);
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let ccer_enable_field = get_ccer_enable_field(channel);
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    enable_timer_clock(timer);
+    gpio.setup();
+
+    unsafe {
+        write_register(get_psc_prescaler_register(timer), prescaler);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+
+        // Buffer the auto reload register, so that frequency changes apply at the next period
+        set_bit(cr1_control_register, tim2::cr1::ARPE);
+
+        // Select the PWM mode and buffer the duty cycle. The fourth mode bit is kept cleared
+        write_bits(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1M + offset,
+            mode as u32,
+            0b111,
+        );
+        clear_bit(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1M_3 + offset,
+        );
+        set_bit(
+            ccmr_capture_compare_mode_register,
+            tim2::ccmr1_output::OC1PE + offset,
+        );
+
+        write_register(get_ccr_capture_compare_register(timer, channel), 0);
+
+        // Enable the output with active high polarity
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 1);
+        set_bit(ccer_capture_compare_enable_register, ccer_enable_field);
+
+        // The outputs of the advanced timers are gated by the main output enable
+        if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+            set_bit(bdtr_break_dead_time_register, tim1::bdtr::MOE);
+        }
+
+        // Load the pre-scaler and buffered registers, without leaving the update flag set
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+
+        // Start the timer
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
+    Ok(())
+}
+
+/// Output PWM like `setup_pwm`, with the channel routed to `pin`. Returns
+/// `TimerError::InvalidPin` if the channel isn't available on the pin. The configured pin is
+/// returned so that it can be passed to `cleanup_pwm`
+pub fn setup_pwm_on_pin(
+    timer: &Timer,
+    channel: TimerChannel,
+    pin: PortPin,
+    mode: PwmMode,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<Gpio, TimerError> {
+    let gpio = get_timer_channel_gpio(timer, channel, pin)?;
+    setup_pwm(timer, channel, &gpio, mode, frequency, clock_frequency)?;
+
+    Ok(gpio)
+}
+
+/// Output symmetric PWM at `frequency` on a timer channel, with the counter counting up and
+/// down. The pulses of all channels are centered on the same point of the period, which reduces
+/// switching noise in motor drives, and the update event at the valley of the counter can trigger
+/// an ADC conversion through `timer_sync::MasterMode::Update`. The duty cycle resolution is half
+/// of that of edge-aligned PWM at the same frequency
+pub fn setup_center_aligned_pwm(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    mode: PwmMode,
+    alignment: PwmAlignment,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    enable_timer_clock(timer);
+    set_pwm_alignment(timer, alignment);
+
+    setup_pwm(timer, channel, gpio, mode, frequency, clock_frequency)
+}
+
+/// Output PWM on a channel of TIM1 or TIM8 together with its inverted complementary output on
+/// `gpio_n`, e.g. for the high and low side switches of a half bridge. Use `set_pwm_dead_time` to
+/// keep both switches from conducting at the same time. Channel 4 has no complementary output.
+/// Returns `TimerError::Unsupported` on the general-purpose timers. `cleanup_pwm` disables both
+/// outputs, but only releases `gpio`
+pub fn setup_complementary_pwm(
+    timer: &Timer,
+    channel: TimerChannel,
+    gpio: &Gpio,
+    gpio_n: &Gpio,
+    mode: PwmMode,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    if get_bdtr_break_dead_time_register(timer).is_none() || channel == TimerChannel::Ch4 {
+        return Err(TimerError::Unsupported);
+    }
+
+    setup_pwm(timer, channel, gpio, mode, frequency, clock_frequency)?;
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let ccer_enable_field = get_ccer_enable_field(channel);
+
+    gpio_n.setup();
+
+    // Enable the complementary output with active high polarity
+    unsafe {
+        clear_bit(ccer_capture_compare_enable_register, ccer_enable_field + 3);
+        set_bit(ccer_capture_compare_enable_register, ccer_enable_field + 2);
+    }
+
+    Ok(())
+}
+
+/// Encode a dead-time in timer clock ticks into the DTG field, rounded down to the nearest step,
+/// see the TIMx_BDTR register of the advanced-control timers. Returns `None` above 1008 ticks
+fn encode_dead_time(ticks: u64) -> Option<u32> {
+    let ticks = ticks as u32;
+
+    match ticks {
+        0..=127 => Some(ticks),
+        128..=255 => Some(0b1000_0000 | (ticks / 2 - 64)),
+        256..=511 => Some(0b1100_0000 | (ticks / 8 - 32)),
+        512..=1008 => Some(0b1110_0000 | (ticks / 16 - 32)),
+        _ => None,
+    }
+}
+
+/// Compute the CKD and DTG fields for a dead-time, rounded down to what the dead-time generator
+/// can produce
+fn compute_dead_time(dead_time_ns: u32, clock_frequency: u32) -> Result<(u32, u32), TimerError> {
+    let ticks = dead_time_ns as u64 * clock_frequency as u64 / 1_000_000_000;
+
+    // The longest dead-time is 1008 ticks of the dead-time clock divided by 4
+    if ticks > 4 * 1008 {
+        return Err(TimerError::InvalidDeadTime(dead_time_ns));
+    }
+
+    // Use the fastest dead-time clock that can reach the dead-time
+    (0..3)
+        .find_map(|clock_division| {
+            encode_dead_time(ticks >> clock_division).map(|dead_time| (clock_division, dead_time))
+        })
+        .ok_or(TimerError::InvalidDeadTime(dead_time_ns))
+}
+
+/// Insert a dead-time between an output and its complementary output switching on, rounded down
+/// to what the dead-time generator can produce. Dead-times above 1008 timer clock ticks are
+/// reached by dividing the dead-time clock by 2 or 4 with the CKD field, which also slows down
+/// the sampling clock of the input filters. Returns `TimerError::InvalidDeadTime` if it's longer
+/// than 4032 ticks, and `TimerError::Unsupported` on the general-purpose timers
+pub fn set_pwm_dead_time(
+    timer: &Timer,
+    dead_time_ns: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    use registers::{tim1, tim2};
+
+    let bdtr_break_dead_time_register =
+        get_bdtr_break_dead_time_register(timer).ok_or(TimerError::Unsupported)?;
+
+    let (clock_division, dead_time) = compute_dead_time(dead_time_ns, clock_frequency)?;
+
+    unsafe {
+        write_bits(
+            get_cr1_control_register(timer),
+            tim2::cr1::CKD,
+            clock_division,
+            0b11,
+        );
+        write_bits(
+            bdtr_break_dead_time_register,
+            tim1::bdtr::DTG,
+            dead_time,
+            0xFF,
+        );
+    }
+
+    Ok(())
+}
+
+/// Turn off the outputs of TIM1 or TIM8 when the break input on `gpio` becomes active, e.g. from
+/// an overcurrent comparator. The outputs stay off until `enable_pwm_outputs` is called
+pub fn setup_pwm_break(
+    timer: &Timer,
+    gpio: &Gpio,
+    polarity: BreakPolarity,
+) -> Result<(), TimerError> {
+    use registers::tim1;
+
+    let bdtr_break_dead_time_register =
+        get_bdtr_break_dead_time_register(timer).ok_or(TimerError::Unsupported)?;
+
+    gpio.setup();
+
+    unsafe {
+        write_bits(
+            bdtr_break_dead_time_register,
+            tim1::bdtr::BKP,
+            (polarity == BreakPolarity::ActiveHigh) as u32,
+            0b1,
+        );
+        set_bit(bdtr_break_dead_time_register, tim1::bdtr::BKE);
+    }
+
+    Ok(())
+}
+
+/// Filter the break input, so that it has to be active for a number of samples before the outputs
+/// are turned off. `filter` is the 4 bit BKF value, where 0 disables the filter and higher values
+/// require more and slower samples, see the TIMx_BDTR register of the advanced-control timers
+pub fn set_pwm_break_filter(timer: &Timer, filter: u8) -> Result<(), TimerError> {
+    use registers::tim1;
+
+    let bdtr_break_dead_time_register =
+        get_bdtr_break_dead_time_register(timer).ok_or(TimerError::Unsupported)?;
+
+    if filter > 0b1111 {
+        return Err(TimerError::InvalidFilter(filter));
+    }
+
+    unsafe {
+        write_bits(
+            bdtr_break_dead_time_register,
+            tim1::bdtr::BKF,
+            filter as u32,
+            0b1111,
+        );
+    }
+
+    Ok(())
+}
+
+/// Turn the outputs back on automatically at the next update event once the break input is no
+/// longer active, instead of waiting for `enable_pwm_outputs`
+pub fn set_pwm_automatic_output(timer: &Timer, enabled: bool) -> Result<(), TimerError> {
+    use registers::tim1;
+
+    let bdtr_break_dead_time_register =
+        get_bdtr_break_dead_time_register(timer).ok_or(TimerError::Unsupported)?;
+
+    unsafe {
+        write_bits(
+            bdtr_break_dead_time_register,
+            tim1::bdtr::AOE,
+            enabled as u32,
+            0b1,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn cleanup_pwm_break(timer: &Timer, gpio: &Gpio) {
+    use registers::tim1;
+
+    if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+        unsafe {
+            clear_bit(bdtr_break_dead_time_register, tim1::bdtr::BKE);
+            clear_bit(bdtr_break_dead_time_register, tim1::bdtr::AOE);
+            write_bits(bdtr_break_dead_time_register, tim1::bdtr::BKF, 0, 0b1111);
+        }
+        gpio.cleanup();
+    }
+}
+
+/// Set the main output enable of TIM1 or TIM8, e.g. after a break. Does nothing on the
+/// general-purpose timers
+pub fn enable_pwm_outputs(timer: &Timer) {
+    use registers::tim1;
+
+    if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+        unsafe {
+            set_bit(bdtr_break_dead_time_register, tim1::bdtr::MOE);
+        }
+    }
+}
+
+/// Turn off all outputs of TIM1 or TIM8 at once
+pub fn disable_pwm_outputs(timer: &Timer) {
+    use registers::tim1;
+
+    if let Some(bdtr_break_dead_time_register) = get_bdtr_break_dead_time_register(timer) {
+        unsafe {
+            clear_bit(bdtr_break_dead_time_register, tim1::bdtr::MOE);
+        }
+    }
+}
+
+/// Select edge or center-aligned counting. In the center-aligned modes a period takes twice as
+/// many ticks, so the PWM frequency is halved until `set_pwm_frequency` is called again. The
+/// counter is stopped while the mode is changed
+pub fn set_pwm_alignment(timer: &Timer, alignment: PwmAlignment) {
+    use registers::tim2;
+
+    let cr1_control_register = get_cr1_control_register(timer);
+
+    unsafe {
+        let enabled = get_bit(cr1_control_register, tim2::cr1::CEN) == 1;
+        clear_bit(cr1_control_register, tim2::cr1::CEN);
+
+        write_bits(cr1_control_register, tim2::cr1::CMS, alignment as u32, 0b11);
+
+        if enabled {
+            set_bit(cr1_control_register, tim2::cr1::CEN);
+        }
+    }
+}
+
+/// Disable the output of a channel and release its pin. The timer is stopped when no channel is
+/// left enabled
+pub fn cleanup_pwm(timer: &Timer, channel: TimerChannel, gpio: &Gpio) {
+    use registers::tim2;
+
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+
+    unsafe {
+        // Disable the output and any complementary output
+        clear_bit(
+            ccer_capture_compare_enable_register,
+            get_ccer_enable_field(channel),
+        );
+        clear_bit(
+            ccer_capture_compare_enable_register,
+            get_ccer_enable_field(channel) + 2,
+        );
+
+        if CHANNELS.iter().all(|channel| {
+            get_bit(
+                ccer_capture_compare_enable_register,
+                get_ccer_enable_field(*channel),
+            ) == 0
+        }) {
+            clear_bit(get_cr1_control_register(timer), tim2::cr1::CEN);
+        }
+    }
+
+    gpio.cleanup();
+}
+
+/// Change the PWM frequency of a timer. The duty cycles of the enabled channels are scaled to
+/// keep their ratio, and the change applies at the start of the next period
+pub fn set_pwm_frequency(
+    timer: &Timer,
+    frequency: u32,
+    clock_frequency: u32,
+) -> Result<(), TimerError> {
+    let (prescaler, auto_reload) = compute_pwm_period(timer, clock_frequency, frequency)?;
+    let ccer_capture_compare_enable_register = get_ccer_capture_compare_enable_register(timer);
+    let previous_period = get_pwm_max_duty(timer) as u64;
+    let new_period = if is_center_aligned(timer) {
+        auto_reload as u64
+    } else {
+        auto_reload as u64 + 1
+    };
+
+    unsafe {
+        for channel in CHANNELS {
+            if get_bit(
+                ccer_capture_compare_enable_register,
+                get_ccer_enable_field(channel),
+            ) == 0
+            {
+                continue;
+            }
+
+            let ccr_capture_compare_register = get_ccr_capture_compare_register(timer, channel);
+            let duty = read_register(ccr_capture_compare_register) as u64;
+            write_register(
+                ccr_capture_compare_register,
+                (duty * new_period / previous_period.max(1)) as u32,
+            );
+        }
+
+        write_register(get_psc_prescaler_register(timer), prescaler);
+        write_register(get_arr_auto_reload_register(timer), auto_reload);
+    }
+
+    Ok(())
+}
+
+/// Number of timer ticks in a PWM period, or half a period in the center-aligned modes. A duty
+/// cycle of this value or higher keeps the output active for the whole period
+pub fn get_pwm_max_duty(timer: &Timer) -> u32 {
+    let auto_reload = unsafe { read_register(get_arr_auto_reload_register(timer)) };
+
+    if is_center_aligned(timer) {
+        auto_reload
+    } else {
+        auto_reload.saturating_add(1)
+    }
+}
+
+/// Set the duty cycle of a channel in timer ticks, see `get_pwm_max_duty`
+pub fn set_pwm_duty(timer: &Timer, channel: TimerChannel, duty: u32) {
+    unsafe {
+        write_register(get_ccr_capture_compare_register(timer, channel), duty);
+    }
+}
+
+/// Set the duty cycle of a channel in percent, where values above 100 are capped
+pub fn set_pwm_duty_percent(timer: &Timer, channel: TimerChannel, percent: u8) {
+    let duty = get_pwm_max_duty(timer) as u64 * percent.min(100) as u64 / 100;
+    set_pwm_duty(timer, channel, duty as u32);
+}
+
+pub fn get_pwm_duty(timer: &Timer, channel: TimerChannel) -> u32 {
+    unsafe { read_register(get_ccr_capture_compare_register(timer, channel)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dead-time in timer clock ticks produced by a DTG value
+    fn decode_dead_time(dead_time: u32) -> u32 {
+        match dead_time >> 5 {
+            0b000..=0b011 => dead_time,
+            0b100 | 0b101 => (64 + (dead_time & 0x3F)) * 2,
+            0b110 => (32 + (dead_time & 0x1F)) * 8,
+            _ => (32 + (dead_time & 0x1F)) * 16,
+        }
+    }
+
+    #[test]
+    fn encode_dead_time_rounds_down() {
+        for ticks in 0..=1008 {
+            let dead_time = encode_dead_time(ticks).unwrap();
+
+            assert!(dead_time <= 0xFF);
+            assert!(decode_dead_time(dead_time) <= ticks as u32);
+        }
+
+        assert_eq!(encode_dead_time(127), Some(127));
+        assert_eq!(decode_dead_time(encode_dead_time(255).unwrap()), 254);
+        assert_eq!(decode_dead_time(encode_dead_time(511).unwrap()), 504);
+        assert_eq!(decode_dead_time(encode_dead_time(1008).unwrap()), 1008);
+        assert_eq!(encode_dead_time(1009), None);
+    }
+
+    #[test]
+    fn compute_dead_time_rounds_down_and_rejects_long_dead_times() {
+        // At 1 GHz a nanosecond is a tick, which makes the rounding easy to follow
+        let cases = [
+            (0, Ok((0, 0))),
+            (127, Ok((0, 127))),
+            (255, Ok((0, encode_dead_time(255).unwrap()))),
+            (1008, Ok((0, encode_dead_time(1008).unwrap()))),
+            (1009, Ok((1, encode_dead_time(504).unwrap()))),
+            (2017, Ok((1, encode_dead_time(1008).unwrap()))),
+            (2018, Ok((2, encode_dead_time(504).unwrap()))),
+            (4032, Ok((2, encode_dead_time(1008).unwrap()))),
+            (4033, Err(TimerError::InvalidDeadTime(4033))),
+            (u32::MAX, Err(TimerError::InvalidDeadTime(u32::MAX))),
+        ];
+
+        for (dead_time_ns, expected) in cases {
+            assert_eq!(compute_dead_time(dead_time_ns, 1_000_000_000), expected);
+        }
+
+        for ticks in 0..=4032 {
+            let (clock_division, dead_time) = compute_dead_time(ticks, 1_000_000_000).unwrap();
+            assert!(decode_dead_time(dead_time) << clock_division <= ticks);
+        }
+
+        // 100 ns of a 200 MHz timer clock is 20 ticks, and 10.5 ticks rounds down to 10
+        assert_eq!(compute_dead_time(100, 200_000_000), Ok((0, 20)));
+        assert_eq!(compute_dead_time(105, 100_000_000), Ok((0, 10)));
+    }
+}
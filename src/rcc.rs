@@ -0,0 +1,600 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+
+use super::{
+    register_tools::{clear_bit, read_bits, read_register, set_bit},
+    registers,
+};
+
+pub const HSI_FREQUENCY: u32 = 64_000_000;
+pub const CSI_FREQUENCY: u32 = 4_000_000;
+pub const HSI48_FREQUENCY: u32 = 48_000_000;
+pub const LSE_FREQUENCY: u32 = 32_768;
+/// Nominal frequency of the LSI, which varies between parts and with temperature
+pub const LSI_FREQUENCY: u32 = 32_000;
+
+/// Frequency of the external oscillator, which can't be read back from the registers. Zero
+/// means that it hasn't been set
+static HSE_FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+/// Reason of the last reset, stored as the `ResetReason` plus one once the reset flags have been
+/// read and cleared. Zero means that the flags haven't been read yet
+static RESET_REASON: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pll {
+    Pll1,
+    Pll2,
+    Pll3,
+}
+
+/// Output frequencies of a PLL. An output is `None` when its divider is disabled
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PllFrequencies {
+    pub p: Option<u32>,
+    pub q: Option<u32>,
+    pub r: Option<u32>,
+}
+
+/// Peripherals with a reset bit in the RCC AHB and APB reset registers
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Peripheral {
+    // AHB3
+    Mdma,
+    Dma2d,
+    Jpeg,
+    Fmc,
+    Quadspi,
+    Sdmmc1,
+    // AHB1
+    Dma1,
+    Dma2,
+    Adc12,
+    Ethernet,
+    UsbOtgHs1,
+    UsbOtgHs2,
+    // AHB2
+    Dcmi,
+    Cryp,
+    Hash,
+    Rng,
+    Sdmmc2,
+    // AHB4
+    GpioA,
+    GpioB,
+    GpioC,
+    GpioD,
+    GpioE,
+    GpioF,
+    GpioG,
+    GpioH,
+    GpioI,
+    GpioJ,
+    GpioK,
+    Crc,
+    Bdma,
+    Adc3,
+    Hsem,
+    // APB3
+    Ltdc,
+    // APB1
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+    Tim6,
+    Tim7,
+    Tim12,
+    Tim13,
+    Tim14,
+    Lptim1,
+    Spi2,
+    Spi3,
+    Spdifrx,
+    Usart2,
+    Usart3,
+    Uart4,
+    Uart5,
+    I2c1,
+    I2c2,
+    I2c3,
+    Cec,
+    Dac,
+    Uart7,
+    Uart8,
+    Crs,
+    Swpmi,
+    Opamp,
+    Mdios,
+    Fdcan,
+    // APB2
+    Tim1,
+    Tim8,
+    Usart1,
+    Usart6,
+    Spi1,
+    Spi4,
+    Tim15,
+    Tim16,
+    Tim17,
+    Spi5,
+    Sai1,
+    Sai2,
+    Sai3,
+    Dfsdm1,
+    Hrtim,
+    // APB4
+    Syscfg,
+    Lpuart1,
+    Spi6,
+    I2c4,
+    Lptim2,
+    Lptim3,
+    Lptim4,
+    Lptim5,
+    Comp12,
+    Vrefbuf,
+    Sai4,
+}
+
+/// Cause of the last reset, decoded from the flags of RCC_RSR
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResetReason {
+    PowerOn,
+    BrownOut,
+    /// The NRST pin was pulled low
+    Pin,
+    /// A system reset requested by the CPU through SYSRESETREQ
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    /// A Stop or Standby entry that isn't allowed, such as D1 entering DStandby while the option
+    /// bytes prevent it
+    LowPowerError,
+    /// Wakeup of the D1 domain from DStandby
+    D1Standby,
+    /// Wakeup of the D2 domain from DStandby
+    D2Standby,
+    /// A reset of the CPU only, which doesn't reset the peripherals
+    Cpu,
+    Unknown,
+}
+
+/// Tell the crate the frequency of the HSE crystal or external clock, so that clocks derived
+/// from it can be computed
+pub fn set_hse_frequency(frequency: u32) {
+    HSE_FREQUENCY.store(frequency, Ordering::Relaxed);
+}
+
+/// The HSE frequency given to `set_hse_frequency`
+pub fn get_hse_frequency() -> Option<u32> {
+    match HSE_FREQUENCY.load(Ordering::Relaxed) {
+        0 => None,
+        frequency => Some(frequency),
+    }
+}
+
+/// Frequency of the HSI after its divider
+pub fn get_hsi_frequency() -> u32 {
+    use registers::rcc::{CR, cr};
+    HSI_FREQUENCY >> unsafe { read_bits(CR, cr::HSIDIV, 0b11) }
+}
+
+/// Output frequencies of a PLL, read from its source, dividers and fractional part. Returns
+/// `None` if the PLL is off, or if it runs from the HSE and `set_hse_frequency` hasn't been
+/// called
+pub fn get_pll_frequencies(pll: Pll) -> Option<PllFrequencies> {
+    use registers::rcc::{
+        CR, PLL1DIVR, PLL1FRACR, PLL2DIVR, PLL2FRACR, PLL3DIVR, PLL3FRACR, PLLCFGR, PLLCKSELR, cr,
+        pll1divr, pll1fracr, pllcfgr, pllckselr,
+    };
+
+    let (ready, divm, divr, fracr, fracen, enable) = match pll {
+        Pll::Pll1 => (
+            cr::PLL1RDY,
+            pllckselr::DIVM1,
+            PLL1DIVR,
+            PLL1FRACR,
+            pllcfgr::PLL1FRACEN,
+            pllcfgr::DIVP1EN,
+        ),
+        Pll::Pll2 => (
+            cr::PLL2RDY,
+            pllckselr::DIVM2,
+            PLL2DIVR,
+            PLL2FRACR,
+            pllcfgr::PLL2FRACEN,
+            pllcfgr::DIVP2EN,
+        ),
+        Pll::Pll3 => (
+            cr::PLL3RDY,
+            pllckselr::DIVM3,
+            PLL3DIVR,
+            PLL3FRACR,
+            pllcfgr::PLL3FRACEN,
+            pllcfgr::DIVP3EN,
+        ),
+    };
+
+    unsafe {
+        if read_bits(CR, ready, 0b1) == 0 {
+            return None;
+        }
+
+        // See section 8.5.5 PLL description
+        let source = match read_bits(PLLCKSELR, pllckselr::PLLSRC, 0b11) {
+            0b00 => get_hsi_frequency(),
+            0b01 => CSI_FREQUENCY,
+            0b10 => get_hse_frequency()?,
+            _ => return None,
+        };
+
+        let m = read_bits(PLLCKSELR, divm, 0b11_1111);
+        if m == 0 {
+            return None;
+        }
+
+        let n = read_bits(divr, pll1divr::DIVN1, 0x1FF) + 1;
+        let fraction = if read_bits(PLLCFGR, fracen, 0b1) == 1 {
+            read_bits(fracr, pll1fracr::FRACN1, 0x1FFF)
+        } else {
+            0
+        };
+
+        // VCO = source / M * (N + FRACN / 2^13), computed in 64 bits to keep the fraction
+        let vco = ((source as u64 * ((n as u64) << 13 | fraction as u64)) / m as u64) >> 13;
+
+        let output = |divider_field: u8, enable_field: u8| {
+            if read_bits(PLLCFGR, enable_field, 0b1) == 0 {
+                return None;
+            }
+            Some((vco / (read_bits(divr, divider_field, 0x7F) as u64 + 1)) as u32)
+        };
+
+        Some(PllFrequencies {
+            p: output(pll1divr::DIVP1, enable),
+            q: output(pll1divr::DIVQ1, enable + 1),
+            r: output(pll1divr::DIVR1, enable + 2),
+        })
+    }
+}
+
+/// Frequency of the system clock
+pub fn get_sysclk_frequency() -> Option<u32> {
+    use registers::rcc::{CFGR, cfgr};
+
+    match unsafe { read_bits(CFGR, cfgr::SWS, 0b111) } {
+        0b000 => Some(get_hsi_frequency()),
+        0b001 => Some(CSI_FREQUENCY),
+        0b010 => get_hse_frequency(),
+        _ => get_pll_frequencies(Pll::Pll1)?.p,
+    }
+}
+
+/// Divider of the D1CPRE and HPRE prescalers, where 0xxx means no division and 1000 to 1111
+/// divide by 2, 4, 8, 16, 64, 128, 256 and 512
+fn get_ahb_divider(value: u32) -> u32 {
+    match value {
+        0b1000..=0b1011 => 1 << (value - 0b0111),
+        0b1100..=0b1111 => 1 << (value - 0b0110),
+        _ => 1,
+    }
+}
+
+/// Divider of the APB prescalers, where 0xx means no division and 100 to 111 divide by 2, 4, 8
+/// and 16
+fn get_apb_divider(value: u32) -> u32 {
+    match value {
+        0b100..=0b111 => 1 << (value - 0b011),
+        _ => 1,
+    }
+}
+
+/// Frequency of the CPU and SysTick
+pub fn get_cpu_frequency() -> Option<u32> {
+    use registers::rcc::{D1CFGR, d1cfgr};
+    let divider = get_ahb_divider(unsafe { read_bits(D1CFGR, d1cfgr::D1CPRE, 0b1111) });
+    Some(get_sysclk_frequency()? / divider)
+}
+
+/// Frequency of the AHB buses, which also clock the CPU bus matrix and the APB prescalers
+pub fn get_hclk_frequency() -> Option<u32> {
+    use registers::rcc::{D1CFGR, d1cfgr};
+    let divider = get_ahb_divider(unsafe { read_bits(D1CFGR, d1cfgr::HPRE, 0b1111) });
+    Some(get_cpu_frequency()? / divider)
+}
+
+/// Frequency of APB3, which clocks the LTDC and WWDG1
+pub fn get_pclk3_frequency() -> Option<u32> {
+    use registers::rcc::{D1CFGR, d1cfgr};
+    let divider = get_apb_divider(unsafe { read_bits(D1CFGR, d1cfgr::D1PPRE, 0b111) });
+    Some(get_hclk_frequency()? / divider)
+}
+
+/// Frequency of APB1, which clocks USART2/3, UART4/5/7/8 and TIM2 to TIM7
+pub fn get_pclk1_frequency() -> Option<u32> {
+    use registers::rcc::{D2CFGR, d2cfgr};
+    let divider = get_apb_divider(unsafe { read_bits(D2CFGR, d2cfgr::D2PPRE1, 0b111) });
+    Some(get_hclk_frequency()? / divider)
+}
+
+/// Frequency of APB2, which clocks USART1/6 and TIM1/8
+pub fn get_pclk2_frequency() -> Option<u32> {
+    use registers::rcc::{D2CFGR, d2cfgr};
+    let divider = get_apb_divider(unsafe { read_bits(D2CFGR, d2cfgr::D2PPRE2, 0b111) });
+    Some(get_hclk_frequency()? / divider)
+}
+
+/// Kernel clock of the timers on an APB bus. With TIMPRE cleared the timers run at the AHB
+/// frequency when the APB isn't divided and at twice the APB frequency otherwise. With TIMPRE set
+/// they run at the AHB frequency up to an APB divider of 4 and at four times the APB frequency
+/// otherwise. See section 8.5.10 Kernel clock selection
+fn get_timer_frequency(ppre_field: u8) -> Option<u32> {
+    use registers::rcc::{CFGR, D2CFGR, cfgr};
+
+    let (timpre, divider) = unsafe {
+        (
+            read_bits(CFGR, cfgr::TIMPRE, 0b1),
+            get_apb_divider(read_bits(D2CFGR, ppre_field, 0b111)),
+        )
+    };
+
+    let hclk = get_hclk_frequency()?;
+    let multiplier = if timpre == 0 { 2 } else { 4 };
+    Some((hclk / divider * multiplier).min(hclk))
+}
+
+/// Kernel clock of the timers on APB1, which are TIM2 to TIM7 and TIM12 to TIM14
+pub fn get_apb1_timer_frequency() -> Option<u32> {
+    use registers::rcc::d2cfgr;
+    get_timer_frequency(d2cfgr::D2PPRE1)
+}
+
+/// Kernel clock of the timers on APB2, which are TIM1, TIM8 and TIM15 to TIM17
+pub fn get_apb2_timer_frequency() -> Option<u32> {
+    use registers::rcc::d2cfgr;
+    get_timer_frequency(d2cfgr::D2PPRE2)
+}
+
+/// Frequency of APB4, which clocks LPUART1 and the SYSCFG
+pub fn get_pclk4_frequency() -> Option<u32> {
+    use registers::rcc::{D3CFGR, d3cfgr};
+    let divider = get_apb_divider(unsafe { read_bits(D3CFGR, d3cfgr::D3PPRE, 0b111) });
+    Some(get_hclk_frequency()? / divider)
+}
+
+/// Reset a peripheral by pulsing its reset bit, which returns all its registers to their reset
+/// values. The peripheral clock isn't changed, so a driver can reset a peripheral before setting
+/// it up to not depend on the state left behind by a bootloader or an earlier setup
+pub fn reset_peripheral(peripheral: Peripheral) {
+    let (register, field) = get_reset_bit(peripheral);
+
+    // See section 8.7 RCC register description, the peripheral is held in reset while the bit
+    // is set
+    unsafe {
+        set_bit(register, field);
+        clear_bit(register, field);
+    }
+}
+
+/// Keep the clock of a peripheral running while the CPU is in Sleep mode, or gate it to reduce
+/// the Sleep current. All clocks are kept running after reset. A peripheral that wakes the CPU
+/// with an interrupt, such as a timer or usart, has to keep its clock. The HSEM has no sleep
+/// clock gate and is left running
+pub fn enable_clock_in_sleep(peripheral: Peripheral, enable: bool) {
+    let Some((register, field)) = get_sleep_clock_bit(peripheral) else {
+        return;
+    };
+
+    unsafe {
+        if enable {
+            set_bit(register, field);
+        } else {
+            clear_bit(register, field);
+        }
+    }
+}
+
+pub fn is_clock_enabled_in_sleep(peripheral: Peripheral) -> bool {
+    match get_sleep_clock_bit(peripheral) {
+        Some((register, field)) => unsafe { read_bits(register, field, 0b1) == 1 },
+        None => true,
+    }
+}
+
+/// Register and field of a peripheral in the RCC sleep clock enable registers, which have the
+/// same bit layout as the reset registers, see the RCC_AHBxLPENR and RCC_APBxLPENR register
+/// descriptions
+fn get_sleep_clock_bit(peripheral: Peripheral) -> Option<(*mut u32, u8)> {
+    use registers::rcc::{
+        AHB1LPENR, AHB1RSTR, AHB2LPENR, AHB2RSTR, AHB3LPENR, AHB3RSTR, AHB4LPENR, AHB4RSTR,
+        APB1HLPENR, APB1HRSTR, APB1LLPENR, APB1LRSTR, APB2LPENR, APB2RSTR, APB3LPENR, APB3RSTR,
+        APB4LPENR, APB4RSTR,
+    };
+
+    const REGISTERS: [(*mut u32, *mut u32); 9] = [
+        (AHB3RSTR, AHB3LPENR),
+        (AHB1RSTR, AHB1LPENR),
+        (AHB2RSTR, AHB2LPENR),
+        (AHB4RSTR, AHB4LPENR),
+        (APB3RSTR, APB3LPENR),
+        (APB1LRSTR, APB1LLPENR),
+        (APB1HRSTR, APB1HLPENR),
+        (APB2RSTR, APB2LPENR),
+        (APB4RSTR, APB4LPENR),
+    ];
+
+    if peripheral == Peripheral::Hsem {
+        return None;
+    }
+
+    let (reset_register, field) = get_reset_bit(peripheral);
+    REGISTERS
+        .iter()
+        .find(|(register, _)| *register == reset_register)
+        .map(|&(_, register)| (register, field))
+}
+
+/// Reset register and bit of a peripheral
+fn get_reset_bit(peripheral: Peripheral) -> (*mut u32, u8) {
+    use registers::rcc::{
+        AHB1RSTR, AHB2RSTR, AHB3RSTR, AHB4RSTR, APB1HRSTR, APB1LRSTR, APB2RSTR, APB3RSTR, APB4RSTR,
+        ahb1rstr, ahb2rstr, ahb3rstr, ahb4rstr, apb1hrstr, apb1lrstr, apb2rstr, apb3rstr, apb4rstr,
+    };
+
+    match peripheral {
+        Peripheral::Mdma => (AHB3RSTR, ahb3rstr::MDMARST),
+        Peripheral::Dma2d => (AHB3RSTR, ahb3rstr::DMA2DRST),
+        Peripheral::Jpeg => (AHB3RSTR, ahb3rstr::JPGDECRST),
+        Peripheral::Fmc => (AHB3RSTR, ahb3rstr::FMCRST),
+        Peripheral::Quadspi => (AHB3RSTR, ahb3rstr::QSPIRST),
+        Peripheral::Sdmmc1 => (AHB3RSTR, ahb3rstr::SDMMC1RST),
+        Peripheral::Dma1 => (AHB1RSTR, ahb1rstr::DMA1RST),
+        Peripheral::Dma2 => (AHB1RSTR, ahb1rstr::DMA2RST),
+        Peripheral::Adc12 => (AHB1RSTR, ahb1rstr::ADC12RST),
+        Peripheral::Ethernet => (AHB1RSTR, ahb1rstr::ETH1MACRST),
+        Peripheral::UsbOtgHs1 => (AHB1RSTR, ahb1rstr::USB1OTGRST),
+        Peripheral::UsbOtgHs2 => (AHB1RSTR, ahb1rstr::USB2OTGRST),
+        Peripheral::Dcmi => (AHB2RSTR, ahb2rstr::CAMITFRST),
+        Peripheral::Cryp => (AHB2RSTR, ahb2rstr::CRYPTRST),
+        Peripheral::Hash => (AHB2RSTR, ahb2rstr::HASHRST),
+        Peripheral::Rng => (AHB2RSTR, ahb2rstr::RNGRST),
+        Peripheral::Sdmmc2 => (AHB2RSTR, ahb2rstr::SDMMC2RST),
+        Peripheral::GpioA => (AHB4RSTR, ahb4rstr::GPIOARST),
+        Peripheral::GpioB => (AHB4RSTR, ahb4rstr::GPIOBRST),
+        Peripheral::GpioC => (AHB4RSTR, ahb4rstr::GPIOCRST),
+        Peripheral::GpioD => (AHB4RSTR, ahb4rstr::GPIODRST),
+        Peripheral::GpioE => (AHB4RSTR, ahb4rstr::GPIOERST),
+        Peripheral::GpioF => (AHB4RSTR, ahb4rstr::GPIOFRST),
+        Peripheral::GpioG => (AHB4RSTR, ahb4rstr::GPIOGRST),
+        Peripheral::GpioH => (AHB4RSTR, ahb4rstr::GPIOHRST),
+        Peripheral::GpioI => (AHB4RSTR, ahb4rstr::GPIOIRST),
+        Peripheral::GpioJ => (AHB4RSTR, ahb4rstr::GPIOJRST),
+        Peripheral::GpioK => (AHB4RSTR, ahb4rstr::GPIOKRST),
+        Peripheral::Crc => (AHB4RSTR, ahb4rstr::CRCRST),
+        Peripheral::Bdma => (AHB4RSTR, ahb4rstr::BDMARST),
+        Peripheral::Adc3 => (AHB4RSTR, ahb4rstr::ADC3RST),
+        Peripheral::Hsem => (AHB4RSTR, ahb4rstr::HSEMRST),
+        Peripheral::Ltdc => (APB3RSTR, apb3rstr::LTDCRST),
+        Peripheral::Tim2 => (APB1LRSTR, apb1lrstr::TIM2RST),
+        Peripheral::Tim3 => (APB1LRSTR, apb1lrstr::TIM3RST),
+        Peripheral::Tim4 => (APB1LRSTR, apb1lrstr::TIM4RST),
+        Peripheral::Tim5 => (APB1LRSTR, apb1lrstr::TIM5RST),
+        Peripheral::Tim6 => (APB1LRSTR, apb1lrstr::TIM6RST),
+        Peripheral::Tim7 => (APB1LRSTR, apb1lrstr::TIM7RST),
+        Peripheral::Tim12 => (APB1LRSTR, apb1lrstr::TIM12RST),
+        Peripheral::Tim13 => (APB1LRSTR, apb1lrstr::TIM13RST),
+        Peripheral::Tim14 => (APB1LRSTR, apb1lrstr::TIM14RST),
+        Peripheral::Lptim1 => (APB1LRSTR, apb1lrstr::LPTIM1RST),
+        Peripheral::Spi2 => (APB1LRSTR, apb1lrstr::SPI2RST),
+        Peripheral::Spi3 => (APB1LRSTR, apb1lrstr::SPI3RST),
+        Peripheral::Spdifrx => (APB1LRSTR, apb1lrstr::SPDIFRXRST),
+        Peripheral::Usart2 => (APB1LRSTR, apb1lrstr::USART2RST),
+        Peripheral::Usart3 => (APB1LRSTR, apb1lrstr::USART3RST),
+        Peripheral::Uart4 => (APB1LRSTR, apb1lrstr::UART4RST),
+        Peripheral::Uart5 => (APB1LRSTR, apb1lrstr::UART5RST),
+        Peripheral::I2c1 => (APB1LRSTR, apb1lrstr::I2C1RST),
+        Peripheral::I2c2 => (APB1LRSTR, apb1lrstr::I2C2RST),
+        Peripheral::I2c3 => (APB1LRSTR, apb1lrstr::I2C3RST),
+        Peripheral::Cec => (APB1LRSTR, apb1lrstr::CECRST),
+        Peripheral::Dac => (APB1LRSTR, apb1lrstr::DAC12RST),
+        Peripheral::Uart7 => (APB1LRSTR, apb1lrstr::USART7RST),
+        Peripheral::Uart8 => (APB1LRSTR, apb1lrstr::USART8RST),
+        Peripheral::Crs => (APB1HRSTR, apb1hrstr::CRSRST),
+        Peripheral::Swpmi => (APB1HRSTR, apb1hrstr::SWPRST),
+        Peripheral::Opamp => (APB1HRSTR, apb1hrstr::OPAMPRST),
+        Peripheral::Mdios => (APB1HRSTR, apb1hrstr::MDIOSRST),
+        Peripheral::Fdcan => (APB1HRSTR, apb1hrstr::FDCANRST),
+        Peripheral::Tim1 => (APB2RSTR, apb2rstr::TIM1RST),
+        Peripheral::Tim8 => (APB2RSTR, apb2rstr::TIM8RST),
+        Peripheral::Usart1 => (APB2RSTR, apb2rstr::USART1RST),
+        Peripheral::Usart6 => (APB2RSTR, apb2rstr::USART6RST),
+        Peripheral::Spi1 => (APB2RSTR, apb2rstr::SPI1RST),
+        Peripheral::Spi4 => (APB2RSTR, apb2rstr::SPI4RST),
+        Peripheral::Tim15 => (APB2RSTR, apb2rstr::TIM15RST),
+        Peripheral::Tim16 => (APB2RSTR, apb2rstr::TIM16RST),
+        Peripheral::Tim17 => (APB2RSTR, apb2rstr::TIM17RST),
+        Peripheral::Spi5 => (APB2RSTR, apb2rstr::SPI5RST),
+        Peripheral::Sai1 => (APB2RSTR, apb2rstr::SAI1RST),
+        Peripheral::Sai2 => (APB2RSTR, apb2rstr::SAI2RST),
+        Peripheral::Sai3 => (APB2RSTR, apb2rstr::SAI3RST),
+        Peripheral::Dfsdm1 => (APB2RSTR, apb2rstr::DFSDM1RST),
+        Peripheral::Hrtim => (APB2RSTR, apb2rstr::HRTIMRST),
+        Peripheral::Syscfg => (APB4RSTR, apb4rstr::SYSCFGRST),
+        Peripheral::Lpuart1 => (APB4RSTR, apb4rstr::LPUART1RST),
+        Peripheral::Spi6 => (APB4RSTR, apb4rstr::SPI6RST),
+        Peripheral::I2c4 => (APB4RSTR, apb4rstr::I2C4RST),
+        Peripheral::Lptim2 => (APB4RSTR, apb4rstr::LPTIM2RST),
+        Peripheral::Lptim3 => (APB4RSTR, apb4rstr::LPTIM3RST),
+        Peripheral::Lptim4 => (APB4RSTR, apb4rstr::LPTIM4RST),
+        Peripheral::Lptim5 => (APB4RSTR, apb4rstr::LPTIM5RST),
+        Peripheral::Comp12 => (APB4RSTR, apb4rstr::COMP12RST),
+        Peripheral::Vrefbuf => (APB4RSTR, apb4rstr::VREFRST),
+        Peripheral::Sai4 => (APB4RSTR, apb4rstr::SAI4RST),
+    }
+}
+
+/// The cause of the last reset. The reset flags are read and cleared on the first call, so that
+/// the next reset reports only its own cause, and the same reason is returned on later calls. A
+/// pin, brown-out, watchdog or software reset also sets the pin flag, so the flags are checked in
+/// order of the most specific cause, see section 8.4.4 Reset source identification
+pub fn reset_reason() -> ResetReason {
+    use registers::rcc::{RSR, rsr};
+
+    const REASONS: [ResetReason; 11] = [
+        ResetReason::PowerOn,
+        ResetReason::BrownOut,
+        ResetReason::Pin,
+        ResetReason::Software,
+        ResetReason::IndependentWatchdog,
+        ResetReason::WindowWatchdog,
+        ResetReason::LowPowerError,
+        ResetReason::D1Standby,
+        ResetReason::D2Standby,
+        ResetReason::Cpu,
+        ResetReason::Unknown,
+    ];
+
+    match RESET_REASON.load(Ordering::Relaxed) {
+        0 => {}
+        reason => return REASONS[reason as usize - 1],
+    }
+
+    let flags = unsafe { read_register(RSR) };
+    let is_set = |field: u8| flags >> field & 1 == 1;
+
+    let reason = if is_set(rsr::PORRSTF) {
+        ResetReason::PowerOn
+    } else if is_set(rsr::BORRSTF) {
+        ResetReason::BrownOut
+    } else if is_set(rsr::IWDG1RSTF) {
+        ResetReason::IndependentWatchdog
+    } else if is_set(rsr::WWDG1RSTF) {
+        ResetReason::WindowWatchdog
+    } else if is_set(rsr::SFTRSTF) {
+        ResetReason::Software
+    } else if is_set(rsr::LPWRRSTF) {
+        ResetReason::LowPowerError
+    } else if is_set(rsr::PINRSTF) {
+        ResetReason::Pin
+    } else if is_set(rsr::D1RSTF) {
+        ResetReason::D1Standby
+    } else if is_set(rsr::D2RSTF) {
+        ResetReason::D2Standby
+    } else if is_set(rsr::CPURSTF) {
+        ResetReason::Cpu
+    } else {
+        ResetReason::Unknown
+    };
+
+    // The flags stay cleared while RMVF is set, so it's cleared again for the next reset
+    unsafe {
+        set_bit(RSR, rsr::RMVF);
+        clear_bit(RSR, rsr::RMVF);
+    }
+
+    RESET_REASON.store(reason as u8 + 1, Ordering::Relaxed);
+
+    reason
+}
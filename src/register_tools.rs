@@ -1,25 +1,80 @@
-use core::assert;
+#[cfg(not(feature = "mock-registers"))]
 use core::ptr::{read_volatile, write_volatile};
+use core::{assert, fmt};
+
+#[cfg(feature = "mock-registers")]
+use mock::{read_volatile, write_volatile};
 
 /// Write a byte into a register
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn write_register(register: *mut u32, value: u32) {
     unsafe { write_volatile(register, value) };
 }
 
 /// Read a byte from a register
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn read_register(register: *const u32) -> u32 {
     unsafe { read_volatile(register) }
 }
 
+/// Write the lowest byte of a register with a single byte access. Some peripherals, such as the
+/// SPI data register with 8 bit frames, behave differently depending on the access size
+///
+/// # Safety
+/// `register` must be a valid pointer to a memory mapped register that supports byte access
+#[inline(always)]
+pub unsafe fn write_register_u8(register: *mut u32, value: u8) {
+    unsafe { write_volatile(register as *mut u8, value) };
+}
+
+/// Read the lowest byte of a register with a single byte access
+///
+/// # Safety
+/// `register` must be a valid pointer to a memory mapped register that supports byte access
+#[inline(always)]
+pub unsafe fn read_register_u8(register: *const u32) -> u8 {
+    unsafe { read_volatile(register as *const u8) }
+}
+
+/// Write the lower half word of a register with a single half word access
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register that supports half
+/// word access
+#[inline(always)]
+pub unsafe fn write_register_u16(register: *mut u32, value: u16) {
+    unsafe { write_volatile(register as *mut u16, value) };
+}
+
+/// Read the lower half word of a register with a single half word access
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register that supports half
+/// word access
+#[inline(always)]
+pub unsafe fn read_register_u16(register: *const u32) -> u16 {
+    unsafe { read_volatile(register as *const u16) }
+}
+
 /// Set a bit in a register using `register | (1 << field)`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn set_bit(register: *mut u32, field: u8) {
     unsafe { write_volatile(register, read_volatile(register) | (1 << field)) };
 }
 
 /// Clear a bit in a register using `register & !(1 << field)`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn clear_bit(register: *mut u32, field: u8) {
     unsafe { write_volatile(register, read_volatile(register) & !(1 << field)) };
@@ -27,12 +82,28 @@ pub unsafe fn clear_bit(register: *mut u32, field: u8) {
 
 /// Reads a single bit from a register at `field` position.
 /// Equivalent to `(register >> field) & 1`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn get_bit(register: *const u32, field: u8) -> u32 {
     (unsafe { read_volatile(register) } >> field) & 0b1
 }
 
+/// Reads `mask` bits from `field` position, shifted down to bit 0.
+/// Equivalent to `(register >> field) & mask`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn read_bits(register: *const u32, field: u8, mask: u32) -> u32 {
+    (unsafe { read_volatile(register) } >> field) & mask
+}
+
 /// Toggle a bit in a register using `register ^ (1 << field)`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn toggle_bit(register: *mut u32, field: u8) {
     unsafe { write_volatile(register, read_volatile(register) ^ (1 << field)) };
@@ -40,6 +111,9 @@ pub unsafe fn toggle_bit(register: *mut u32, field: u8) {
 
 /// Writes a list of bits to a register by masking out the bit positions and then inserts the value.
 /// Equivalent to `(register & !(mask << field)) | (value << field)`
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
 #[inline(always)]
 pub unsafe fn write_bits(register: *mut u32, field: u8, value: u32, mask: u32) {
     unsafe {
@@ -50,6 +124,203 @@ pub unsafe fn write_bits(register: *mut u32, field: u8, value: u32, mask: u32) {
     };
 }
 
+/// Read a register once, pass the value through `f` and write the result back once. Use this
+/// instead of several `set_bit` and `write_bits` calls when changing multiple fields of the same
+/// register
+///
+/// ```ignore
+/// modify(CR1, |value| (value & !(0b11 << cr1::CMS)) | (1 << cr1::ARPE));
+/// ```
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn modify(register: *mut u32, f: impl FnOnce(u32) -> u32) {
+    unsafe { write_volatile(register, f(read_volatile(register))) };
+}
+
+/// A register that is only written, such as the GPIO BSRR, where every one written triggers an
+/// action and zeros have no effect. A read-modify-write would repeat the actions of the bits that
+/// are read back, so the register is written with the new value only
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WriteOnly(*mut u32);
+
+impl WriteOnly {
+    /// # Safety
+    /// `register` must be a valid, aligned pointer to a memory mapped register
+    pub const unsafe fn new(register: *mut u32) -> Self {
+        Self(register)
+    }
+
+    pub fn write(self, value: u32) {
+        unsafe { write_volatile(self.0, value) };
+    }
+
+    /// Write a one to a single bit, leaving the other bits at zero
+    pub fn write_bit(self, field: u8) {
+        self.write(1 << field);
+    }
+}
+
+/// A status register where flags are cleared by writing a one and writing a zero has no effect
+/// (rc_w1), such as the EXTI pending registers. Clearing a flag with a read-modify-write would
+/// also clear every other flag that was set when the register was read
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClearByWrite1(*mut u32);
+
+impl ClearByWrite1 {
+    /// # Safety
+    /// `register` must be a valid, aligned pointer to a memory mapped register where writing a
+    /// zero to any bit has no effect
+    pub const unsafe fn new(register: *mut u32) -> Self {
+        Self(register)
+    }
+
+    pub fn read(self) -> u32 {
+        unsafe { read_volatile(self.0) }
+    }
+
+    pub fn is_set(self, field: u8) -> bool {
+        self.read() & (1 << field) != 0
+    }
+
+    pub fn clear(self, field: u8) {
+        self.clear_mask(1 << field);
+    }
+
+    /// Clear every flag set in `mask`, leaving the others
+    pub fn clear_mask(self, mask: u32) {
+        unsafe { write_volatile(self.0, mask) };
+    }
+}
+
+/// A status register where flags are cleared by writing a zero and writing a one has no effect
+/// (rc_w0), such as the timer SR. Clearing a flag with a read-modify-write would also clear every
+/// flag that was set between the read and the write
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClearByWrite0(*mut u32);
+
+impl ClearByWrite0 {
+    /// # Safety
+    /// `register` must be a valid, aligned pointer to a memory mapped register where writing a
+    /// one to any bit has no effect
+    pub const unsafe fn new(register: *mut u32) -> Self {
+        Self(register)
+    }
+
+    pub fn read(self) -> u32 {
+        unsafe { read_volatile(self.0) }
+    }
+
+    pub fn is_set(self, field: u8) -> bool {
+        self.read() & (1 << field) != 0
+    }
+
+    pub fn clear(self, field: u8) {
+        self.clear_mask(1 << field);
+    }
+
+    /// Clear every flag set in `mask`, leaving the others
+    pub fn clear_mask(self, mask: u32) {
+        unsafe { write_volatile(self.0, !mask) };
+    }
+}
+
+/// A field of `WIDTH` bits starting at bit `OFFSET` of a register. The mask is derived from the
+/// width, and fields that don't fit in a 32 bit register fail to compile where they're used
+///
+/// ```ignore
+/// const PSC: Field<0, 16> = Field;
+/// write_field(tim2::PSC, PSC, prescaler);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Field<const OFFSET: u8, const WIDTH: u8>;
+
+impl<const OFFSET: u8, const WIDTH: u8> Field<OFFSET, WIDTH> {
+    /// Mask of the field, before it's shifted into place
+    pub const MASK: u32 = {
+        assert!(WIDTH > 0 && OFFSET as u32 + WIDTH as u32 <= 32);
+        u32::MAX >> (32 - WIDTH as u32)
+    };
+}
+
+/// `COUNT` consecutive fields of `WIDTH` bits starting at bit 0, such as the per pin fields of
+/// the gpio registers, where field `n` starts at bit `n * WIDTH`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FieldArray<const WIDTH: u8, const COUNT: u8>;
+
+impl<const WIDTH: u8, const COUNT: u8> FieldArray<WIDTH, COUNT> {
+    /// Mask of a single field, before it's shifted into place
+    pub const MASK: u32 = {
+        assert!(WIDTH > 0 && WIDTH as u32 * COUNT as u32 <= 32);
+        u32::MAX >> (32 - WIDTH as u32)
+    };
+}
+
+/// Read a field of a register
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn read_field<const OFFSET: u8, const WIDTH: u8>(
+    register: *const u32,
+    _field: Field<OFFSET, WIDTH>,
+) -> u32 {
+    (unsafe { read_volatile(register) } >> OFFSET) & Field::<OFFSET, WIDTH>::MASK
+}
+
+/// Write a field of a register, leaving the other bits untouched. Panics if `value` is wider
+/// than the field
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn write_field<const OFFSET: u8, const WIDTH: u8>(
+    register: *mut u32,
+    _field: Field<OFFSET, WIDTH>,
+    value: u32,
+) {
+    let mask = Field::<OFFSET, WIDTH>::MASK;
+    assert!(value <= mask, "value doesn't fit in the register field");
+
+    unsafe { write_bits(register, OFFSET, value, mask) };
+}
+
+/// Read field `index` of a field array
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn read_array_field<const WIDTH: u8, const COUNT: u8>(
+    register: *const u32,
+    _fields: FieldArray<WIDTH, COUNT>,
+    index: u8,
+) -> u32 {
+    assert!(index < COUNT);
+    (unsafe { read_volatile(register) } >> (index * WIDTH)) & FieldArray::<WIDTH, COUNT>::MASK
+}
+
+/// Write field `index` of a field array, leaving the other bits untouched. Panics if `value` is
+/// wider than a field or `index` is out of range
+///
+/// # Safety
+/// `register` must be a valid, aligned pointer to a memory mapped register
+#[inline(always)]
+pub unsafe fn write_array_field<const WIDTH: u8, const COUNT: u8>(
+    register: *mut u32,
+    _fields: FieldArray<WIDTH, COUNT>,
+    index: u8,
+    value: u32,
+) {
+    let mask = FieldArray::<WIDTH, COUNT>::MASK;
+    assert!(index < COUNT);
+    assert!(value <= mask, "value doesn't fit in the register field");
+
+    unsafe { write_bits(register, index * WIDTH, value, mask) };
+}
+
+/// # Safety
+/// Every pointer in `registers` must be a valid, aligned pointer to a memory mapped register
 pub unsafe fn set_bit_in_array(id: u32, registers: &[*mut u32]) {
     let register_index = id as usize / 32;
     let field = (id % 32) as u8;
@@ -58,6 +329,8 @@ pub unsafe fn set_bit_in_array(id: u32, registers: &[*mut u32]) {
     unsafe { set_bit(registers[register_index], field) };
 }
 
+/// # Safety
+/// Every pointer in `registers` must be a valid, aligned pointer to a memory mapped register
 pub unsafe fn clear_bit_in_array(id: u32, registers: &[*mut u32]) {
     let register_index = id as usize / 32;
     let field = (id % 32) as u8;
@@ -66,6 +339,8 @@ pub unsafe fn clear_bit_in_array(id: u32, registers: &[*mut u32]) {
     unsafe { clear_bit(registers[register_index], field) };
 }
 
+/// # Safety
+/// Every pointer in `registers` must be a valid, aligned pointer to a memory mapped register
 pub unsafe fn write_bits_in_array(id: u32, value: u32, mask: u32, registers: &[*mut u32]) {
     let register_index = id as usize / 32;
     let field = (id % 32) as u8;
@@ -74,21 +349,275 @@ pub unsafe fn write_bits_in_array(id: u32, value: u32, mask: u32, registers: &[*
     unsafe { write_bits(registers[register_index], field, value, mask) };
 }
 
+/// # Safety
+/// Every pointer in `registers` must be a valid, aligned pointer to a memory mapped register
 pub unsafe fn get_bits_in_array(id: u32, mask: u32, registers: &[*mut u32]) -> u32 {
     let register_index = id as usize / 32;
     let field = (id % 32) as u8;
 
     assert!(register_index < registers.len());
-    let value = unsafe { read_volatile(registers[register_index]) };
-    value & (mask << field)
+    unsafe { read_bits(registers[register_index], field, mask) }
 }
 
 /// Enable an interrupt in a list of interrupt registers
+///
+/// # Safety
+/// Every pointer in `interrupt_registers` must be a valid, aligned pointer to a memory mapped
+/// register
 pub unsafe fn enable_interrupt(id: u32, interrupt_registers: &[*mut u32]) {
     unsafe { set_bit_in_array(id, interrupt_registers) };
 }
 
 /// Disable an interrupt in a list of interrupt registers
+///
+/// # Safety
+/// Every pointer in `interrupt_registers` must be a valid, aligned pointer to a memory mapped
+/// register
 pub unsafe fn disable_interrupt(id: u32, interrupt_registers: &[*mut u32]) {
     unsafe { clear_bit_in_array(id, interrupt_registers) };
 }
+
+/// Print a table of register names, addresses and values, one register per line, e.g. to a
+/// `UsartWriter` when debugging a peripheral setup
+///
+/// # Safety
+/// Every pointer in `registers` must be a valid, aligned pointer to a memory mapped register.
+/// Reading some registers has side effects, such as a data register popping a received word,
+/// so these should be left out
+pub unsafe fn dump(registers: &[(&str, *const u32)], writer: &mut impl fmt::Write) -> fmt::Result {
+    let width = registers
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (name, register) in registers {
+        let value = unsafe { read_register(*register) };
+        write!(
+            writer,
+            "{name:<width$} {:#010x} {value:#010x}\r\n",
+            *register as usize
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Register backend for host tests, enabled with the `mock-registers` feature. Register
+/// addresses resolve to words in a RAM map instead of the hardware, so that the bit patterns a
+/// driver writes can be asserted with `cargo test` on the host. Each test thread has its own
+/// registers, which all start at zero
+///
+/// ```ignore
+/// mock::reset_registers();
+/// gpio.setup();
+/// assert_eq!(mock::get_register(gpioa::MODER), 0b01 << 10);
+/// ```
+#[cfg(feature = "mock-registers")]
+pub mod mock {
+    extern crate std;
+
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    std::thread_local! {
+        static REGISTERS: RefCell<BTreeMap<usize, u32>> = const { RefCell::new(BTreeMap::new()) };
+    }
+
+    fn read_word(address: usize) -> u32 {
+        REGISTERS.with(|registers| registers.borrow().get(&address).copied().unwrap_or(0))
+    }
+
+    fn write_word(address: usize, value: u32) {
+        REGISTERS.with(|registers| registers.borrow_mut().insert(address, value));
+    }
+
+    /// Access sizes supported by the mock. Byte and half word accesses read and write their lane
+    /// of the word they're in
+    pub(crate) trait Access: Copy {
+        fn read(address: usize) -> Self;
+        fn write(address: usize, value: Self);
+    }
+
+    impl Access for u32 {
+        fn read(address: usize) -> Self {
+            read_word(address)
+        }
+
+        fn write(address: usize, value: Self) {
+            write_word(address, value);
+        }
+    }
+
+    impl Access for u16 {
+        fn read(address: usize) -> Self {
+            (read_word(address & !0b11) >> ((address & 0b10) * 8)) as u16
+        }
+
+        fn write(address: usize, value: Self) {
+            let shift = (address & 0b10) * 8;
+            let word = read_word(address & !0b11) & !(0xFFFF << shift);
+            write_word(address & !0b11, word | ((value as u32) << shift));
+        }
+    }
+
+    impl Access for u8 {
+        fn read(address: usize) -> Self {
+            (read_word(address & !0b11) >> ((address & 0b11) * 8)) as u8
+        }
+
+        fn write(address: usize, value: Self) {
+            let shift = (address & 0b11) * 8;
+            let word = read_word(address & !0b11) & !(0xFF << shift);
+            write_word(address & !0b11, word | ((value as u32) << shift));
+        }
+    }
+
+    /// Stand-in for `core::ptr::read_volatile` used by the register tools
+    ///
+    /// # Safety
+    /// Kept unsafe to match the signature of `core::ptr::read_volatile`
+    #[inline(always)]
+    pub(crate) unsafe fn read_volatile<T: Access>(register: *const T) -> T {
+        T::read(register as usize)
+    }
+
+    /// Stand-in for `core::ptr::write_volatile` used by the register tools
+    ///
+    /// # Safety
+    /// Kept unsafe to match the signature of `core::ptr::write_volatile`
+    #[inline(always)]
+    pub(crate) unsafe fn write_volatile<T: Access>(register: *mut T, value: T) {
+        T::write(register as usize, value);
+    }
+
+    /// Set a register without going through a driver, such as a status flag the hardware would
+    /// set
+    pub fn set_register(register: *const u32, value: u32) {
+        write_word(register as usize, value);
+    }
+
+    pub fn get_register(register: *const u32) -> u32 {
+        read_word(register as usize)
+    }
+
+    /// Set all registers of the current thread back to zero
+    pub fn reset_registers() {
+        REGISTERS.with(|registers| registers.borrow_mut().clear());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_bits_returns_the_shifted_down_field() {
+        let mut register: u32 = 0;
+        let register = &mut register as *mut u32;
+
+        unsafe {
+            write_register(register, 0b1011_0110 << 8);
+            assert_eq!(read_bits(register, 8, 0b1111), 0b0110);
+            assert_eq!(read_bits(register, 12, 0b1111), 0b1011);
+            assert_eq!(read_bits(register, 9, 0b11), 0b11);
+            assert_eq!(read_bits(register, 0, 0xFF), 0);
+        }
+    }
+
+    #[test]
+    fn read_bits_reads_back_write_bits() {
+        let mut register: u32 = 0;
+        let register = &mut register as *mut u32;
+
+        unsafe {
+            write_register(register, 0xFFFF_FFFF);
+            write_bits(register, 4, 0b010, 0b111);
+            assert_eq!(read_bits(register, 4, 0b111), 0b010);
+            assert_eq!(read_register(register), 0xFFFF_FFAF);
+        }
+    }
+
+    #[test]
+    fn get_bits_in_array_returns_the_shifted_down_field() {
+        let mut first: u32 = 0;
+        let mut second: u32 = 0;
+        let registers = [&mut first as *mut u32, &mut second as *mut u32];
+
+        unsafe {
+            write_register(registers[1], 0b101 << 3);
+            assert_eq!(get_bits_in_array(32 + 3, 0b111, &registers), 0b101);
+            assert_eq!(get_bits_in_array(32 + 5, 0b1, &registers), 1);
+            assert_eq!(get_bits_in_array(32 + 4, 0b1, &registers), 0);
+            assert_eq!(get_bits_in_array(3, 0b111, &registers), 0);
+        }
+    }
+
+    #[test]
+    fn bit_array_helpers_select_the_register_by_id() {
+        let mut first: u32 = 0;
+        let mut second: u32 = 0;
+        let registers = [&mut first as *mut u32, &mut second as *mut u32];
+
+        unsafe {
+            set_bit_in_array(33, &registers);
+            set_bit_in_array(31, &registers);
+            assert_eq!(read_register(registers[0]), 1 << 31);
+            assert_eq!(read_register(registers[1]), 1 << 1);
+
+            clear_bit_in_array(33, &registers);
+            assert_eq!(read_register(registers[1]), 0);
+        }
+    }
+
+    #[test]
+    fn dump_prints_one_aligned_line_per_register() {
+        extern crate std;
+        use std::string::String;
+
+        let mut first: u32 = 0;
+        let mut second: u32 = 0;
+        let first = &mut first as *mut u32;
+        let second = &mut second as *mut u32;
+
+        let mut output = String::new();
+        unsafe {
+            write_register(first, 0x1234);
+            write_register(second, 0xFFFF_0000);
+            dump(&[("CR1", first), ("OSPEEDR", second)], &mut output).unwrap();
+        }
+
+        let lines: std::vec::Vec<&str> = output.split_terminator("\r\n").collect();
+        assert_eq!(
+            lines,
+            [
+                std::format!("CR1     {:#010x} 0x00001234", first as usize),
+                std::format!("OSPEEDR {:#010x} 0xffff0000", second as usize),
+            ]
+        );
+    }
+
+    #[test]
+    fn clear_by_write_registers_write_only_the_cleared_flags() {
+        let mut register: u32 = 0;
+        let register = &mut register as *mut u32;
+
+        unsafe {
+            ClearByWrite0::new(register).clear(3);
+            assert_eq!(read_register(register), !(1 << 3));
+
+            ClearByWrite1::new(register).clear_mask(0b101);
+            assert_eq!(read_register(register), 0b101);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_bits_in_array_rejects_ids_past_the_registers() {
+        let mut register: u32 = 0;
+        let registers = [&mut register as *mut u32];
+
+        unsafe {
+            get_bits_in_array(32, 0b1, &registers);
+        }
+    }
+}
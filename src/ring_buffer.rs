@@ -0,0 +1,80 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A lock free single producer, single consumer byte queue that can be placed in a `static`.
+/// One side, such as an interrupt handler, may only push while the other side only pops. One
+/// slot is kept empty to tell a full buffer from an empty one, so it holds `N - 1` bytes
+pub struct RingBuffer<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// The producer only writes the slot at `head` before publishing it, and the consumer only reads
+// the slot at `tail` before releasing it, so the two sides never access the same slot
+unsafe impl<const N: usize> Sync for RingBuffer<N> {}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        assert!(N > 1, "A ring buffer needs at least two slots");
+
+        Self {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a byte to the buffer. Returns false if the buffer is full
+    pub fn push(&self, value: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % N;
+
+        if next == self.tail.load(Ordering::Acquire) {
+            return false;
+        }
+
+        unsafe { (*self.buffer.get())[head] = value };
+        self.head.store(next, Ordering::Release);
+        true
+    }
+
+    /// Remove the oldest byte from the buffer
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { (*self.buffer.get())[tail] };
+        self.tail.store((tail + 1) % N, Ordering::Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (head + N - tail) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N - 1
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
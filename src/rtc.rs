@@ -0,0 +1,819 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+pub use crate::clocks::enable_backup_domain_access;
+use crate::{
+    clocks::{LseDrive, enable_lse, enable_lsi, set_rtc_clock},
+    exti::{ExtiEdge, ExtiLine, cleanup_exti_line, clear_exti_line_pending, setup_exti_line},
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    rcc::{LSE_FREQUENCY, LSI_FREQUENCY},
+    register_tools::{clear_bit, get_bit, read_register, set_bit, write_bits, write_register},
+    registers,
+};
+
+/// Number of 32 bit backup registers
+pub const RTC_BACKUP_REGISTERS: usize = 32;
+
+/// Frequency of the selected RTC clock, or zero if the RTC hasn't been setup
+static RTC_CLOCK_FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+/// Registered callbacks for alarm A, alarm B, the wakeup timer, the timestamp and tamper 1 to 3,
+/// stored as function pointers. A zero means that no callback is registered
+static RTC_CALLBACKS: [AtomicUsize; 7] = [const { AtomicUsize::new(0) }; 7];
+
+const RTC_WAKEUP_CALLBACK: usize = 2;
+const RTC_TIMESTAMP_CALLBACK: usize = 3;
+const RTC_TAMPER_CALLBACKS: usize = 4;
+
+/// Clock of the RTC, selected with RTCSEL in RCC_BDCR
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcClock {
+    /// The 32.768 kHz external crystal, which keeps running in Standby and VBAT mode
+    Lse = 0b01,
+    /// The internal low-speed oscillator, which is less accurate
+    Lsi = 0b10,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcAlarm {
+    AlarmA,
+    AlarmB,
+}
+
+/// Tamper inputs, where tamper 1 is on PC13, tamper 2 on PI8 and tamper 3 on PC1. The RTC takes
+/// over the pin when the input is enabled, so no alternate function has to be setup
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcTamper {
+    Tamper1,
+    Tamper2,
+    Tamper3,
+}
+
+/// Edge of the timestamp or tamper input that triggers an event
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcEdge {
+    Rising,
+    Falling,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum RtcError {
+    /// A field of the date or time is out of range
+    InvalidDateTime,
+    /// The backup domain already runs the RTC from another clock, which can only be changed by
+    /// resetting the backup domain
+    ClockInUse,
+    /// The wakeup period can't be generated by the wakeup timer
+    InvalidWakeupPeriod(u32),
+    /// The RTC hasn't been setup
+    NotInitialized,
+}
+
+/// Calendar date and time. The RTC counts years from 2000 to 2099
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RtcDateTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    /// 1 for Monday to 7 for Sunday
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Fields an alarm matches against. A field set to `None` is masked, so that the alarm triggers
+/// every second when all fields are `None`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RtcAlarmTime {
+    /// Day of the month, 1 to 31
+    pub day: Option<u8>,
+    pub hour: Option<u8>,
+    pub minute: Option<u8>,
+    pub second: Option<u8>,
+}
+
+/// Calendar time of a timestamp event. The year isn't recorded by the RTC
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RtcTimestamp {
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Set when another timestamp event happened before this one was read, which was lost
+    pub overflow: bool,
+}
+
+fn to_bcd(value: u8) -> u32 {
+    (((value / 10) << 4) | (value % 10)) as u32
+}
+
+fn from_bcd(value: u32) -> u8 {
+    ((value >> 4 & 0xF) * 10 + (value & 0xF)) as u8
+}
+
+/// Start the RTC from `clock`. The calendar keeps running through resets, so if the RTC is
+/// already running from the same clock it's left untouched. The prescalers are set so that the
+/// calendar counts in seconds
+pub fn setup_rtc(clock: RtcClock) -> Result<(), RtcError> {
+    use registers::rcc::{APB4ENR, apb4enr};
+
+    // Start the oscillator and wait until it's stable
+    match clock {
+        RtcClock::Lse => enable_lse(LseDrive::Low, false),
+        RtcClock::Lsi => enable_lsi(),
+    }
+
+    set_rtc_clock(clock).map_err(|_| RtcError::ClockInUse)?;
+
+    unsafe {
+        set_bit(APB4ENR, apb4enr::RTCAPBEN);
+    }
+
+    let frequency = match clock {
+        RtcClock::Lse => LSE_FREQUENCY,
+        RtcClock::Lsi => LSI_FREQUENCY,
+    };
+    RTC_CLOCK_FREQUENCY.store(frequency, Ordering::Relaxed);
+
+    // Divide down to 1 Hz with the largest asynchronous prescaler, which uses the least power
+    let prescaler = (frequency / 128 - 1) | 127 << 16;
+    if unsafe { read_register(registers::rtc::RTC_PRER) } != prescaler {
+        with_rtc_init_mode(|| unsafe {
+            write_register(registers::rtc::RTC_PRER, prescaler);
+        });
+    }
+
+    Ok(())
+}
+
+/// Disable writes to the RTC registers again, which are unlocked with a key sequence
+fn lock_rtc() {
+    use registers::rtc::RTC_WPR;
+    unsafe {
+        write_register(RTC_WPR, 0xFF);
+    }
+}
+
+fn unlock_rtc() {
+    use registers::rtc::RTC_WPR;
+
+    // See the RTC register write protection section of the reference manual
+    unsafe {
+        write_register(RTC_WPR, 0xCA);
+        write_register(RTC_WPR, 0x53);
+    }
+}
+
+/// Write the INIT bit of the ISR. The event flags in the register are cleared by writing zero
+/// (rc_w0), so a read-modify-write would clear a flag set between the read and the write. They're
+/// written as one instead to leave them
+fn write_rtc_init(init: bool) {
+    use registers::rtc::{RTC_ISR, rtc_isr};
+    unsafe {
+        write_register(
+            RTC_ISR,
+            !(1 << rtc_isr::INIT) | (init as u32) << rtc_isr::INIT,
+        );
+    }
+}
+
+/// Clear the flags set in `mask` in the ISR, leaving the other flags and the INIT bit
+fn clear_rtc_flags(mask: u32) {
+    use registers::rtc::{RTC_ISR, rtc_isr};
+    unsafe {
+        let init = read_register(RTC_ISR) & 1 << rtc_isr::INIT;
+        write_register(RTC_ISR, !(mask | 1 << rtc_isr::INIT) | init);
+    }
+}
+
+/// Run `f` with the calendar stopped in initialization mode, needed to write the calendar and
+/// prescaler registers
+fn with_rtc_init_mode(f: impl FnOnce()) {
+    use registers::rtc::{RTC_ISR, rtc_isr};
+
+    unlock_rtc();
+
+    write_rtc_init(true);
+    while unsafe { get_bit(RTC_ISR, rtc_isr::INITF) } == 0 {}
+
+    f();
+
+    write_rtc_init(false);
+
+    lock_rtc();
+}
+
+/// Set the calendar. The seconds start counting from zero on exit
+pub fn set_rtc_date_time(date_time: &RtcDateTime) -> Result<(), RtcError> {
+    use registers::rtc::{RTC_DR, RTC_TR, rtc_dr, rtc_tr};
+
+    if RTC_CLOCK_FREQUENCY.load(Ordering::Relaxed) == 0 {
+        return Err(RtcError::NotInitialized);
+    }
+
+    if !(2000..=2099).contains(&date_time.year)
+        || !(1..=12).contains(&date_time.month)
+        || !(1..=31).contains(&date_time.day)
+        || !(1..=7).contains(&date_time.weekday)
+        || date_time.hour > 23
+        || date_time.minute > 59
+        || date_time.second > 59
+    {
+        return Err(RtcError::InvalidDateTime);
+    }
+
+    let time = to_bcd(date_time.hour) << rtc_tr::HU
+        | to_bcd(date_time.minute) << rtc_tr::MNU
+        | to_bcd(date_time.second) << rtc_tr::SU;
+    let date = to_bcd((date_time.year - 2000) as u8) << rtc_dr::YU
+        | (date_time.weekday as u32) << rtc_dr::WDU
+        | to_bcd(date_time.month) << rtc_dr::MU
+        | to_bcd(date_time.day) << rtc_dr::DU;
+
+    with_rtc_init_mode(|| unsafe {
+        write_register(RTC_TR, time);
+        write_register(RTC_DR, date);
+    });
+
+    Ok(())
+}
+
+/// Read the calendar
+pub fn get_rtc_date_time() -> Result<RtcDateTime, RtcError> {
+    use registers::rtc::{RTC_DR, RTC_ISR, RTC_TR, rtc_dr, rtc_isr, rtc_tr};
+
+    if RTC_CLOCK_FREQUENCY.load(Ordering::Relaxed) == 0 {
+        return Err(RtcError::NotInitialized);
+    }
+
+    // The shadow registers aren't updated in Stop and Standby mode, so the synchronization flag
+    // is cleared and waited for to not read a stale calendar after a wakeup
+    unlock_rtc();
+    clear_rtc_flags(1 << rtc_isr::RSF);
+    lock_rtc();
+
+    unsafe {
+        // Reading the time register locks the date register until it's read, so the two are
+        // consistent
+        while get_bit(RTC_ISR, rtc_isr::RSF) == 0 {}
+
+        let time = read_register(RTC_TR);
+        let date = read_register(RTC_DR);
+
+        Ok(RtcDateTime {
+            year: 2000 + from_bcd(date >> rtc_dr::YU) as u16,
+            month: from_bcd(date >> rtc_dr::MU & 0x1F),
+            day: from_bcd(date >> rtc_dr::DU & 0x3F),
+            weekday: (date >> rtc_dr::WDU & 0b111) as u8,
+            hour: from_bcd(time >> rtc_tr::HU & 0x3F),
+            minute: from_bcd(time >> rtc_tr::MNU & 0x7F),
+            second: from_bcd(time >> rtc_tr::SU & 0x7F),
+        })
+    }
+}
+
+/// Control register bits of an alarm: the enable, interrupt enable and write allowed flags
+fn get_alarm_fields(alarm: RtcAlarm) -> (*mut u32, u8, u8, u8, u8) {
+    use registers::rtc::{RTC_ALRMAR, RTC_ALRMBR, rtc_cr, rtc_isr};
+
+    match alarm {
+        RtcAlarm::AlarmA => (
+            RTC_ALRMAR,
+            rtc_cr::ALRAE,
+            rtc_cr::ALRAIE,
+            rtc_isr::ALRAWF,
+            rtc_isr::ALRAF,
+        ),
+        RtcAlarm::AlarmB => (
+            RTC_ALRMBR,
+            rtc_cr::ALRBE,
+            rtc_cr::ALRBIE,
+            rtc_isr::ALRBWF,
+            rtc_isr::ALRBF,
+        ),
+    }
+}
+
+/// Trigger an alarm when the calendar matches `time`, with an interrupt on EXTI line 17. The
+/// interrupt also wakes the core from Stop mode
+pub fn setup_rtc_alarm(alarm: RtcAlarm, time: &RtcAlarmTime) -> Result<(), RtcError> {
+    use registers::rtc::{RTC_CR, RTC_ISR, rtc_alrmar};
+
+    let (alrmr_alarm_register, enable, interrupt_enable, write_allowed, flag) =
+        get_alarm_fields(alarm);
+
+    let field = |value: Option<u8>, max: u8, shift: u8, mask: u8| match value {
+        Some(value) if value <= max => Ok(to_bcd(value) << shift),
+        Some(_) => Err(RtcError::InvalidDateTime),
+        None => Ok(1 << mask),
+    };
+
+    if time.day == Some(0) {
+        return Err(RtcError::InvalidDateTime);
+    }
+
+    let value = field(time.day, 31, rtc_alrmar::DU, rtc_alrmar::MSK4)?
+        | field(time.hour, 23, rtc_alrmar::HU, rtc_alrmar::MSK3)?
+        | field(time.minute, 59, rtc_alrmar::MNU, rtc_alrmar::MSK2)?
+        | field(time.second, 59, rtc_alrmar::SU, rtc_alrmar::MSK1)?;
+
+    unlock_rtc();
+
+    unsafe {
+        // The alarm can only be written while it's disabled
+        clear_bit(RTC_CR, enable);
+        while get_bit(RTC_ISR, write_allowed) == 0 {}
+
+        write_register(alrmr_alarm_register, value);
+        clear_rtc_flags(1 << flag);
+
+        set_bit(RTC_CR, interrupt_enable);
+        set_bit(RTC_CR, enable);
+    }
+
+    lock_rtc();
+
+    // Route the alarm to the CPU through its EXTI line. The line is configurable, so selecting
+    // the rising edge can't fail
+    let _ = setup_exti_line(ExtiLine::RtcAlarm, ExtiEdge::Rising);
+
+    enable_interrupt(Irq::RtcAlarm);
+
+    Ok(())
+}
+
+/// Disable an alarm and remove its callback. The EXTI line is masked when both alarms are off
+pub fn cleanup_rtc_alarm(alarm: RtcAlarm) {
+    use registers::rtc::{RTC_CR, rtc_cr};
+
+    let (_, enable, interrupt_enable, _, _) = get_alarm_fields(alarm);
+
+    unlock_rtc();
+
+    unsafe {
+        clear_bit(RTC_CR, interrupt_enable);
+        clear_bit(RTC_CR, enable);
+    }
+
+    lock_rtc();
+
+    unregister_rtc_alarm_callback(alarm);
+
+    if unsafe { get_bit(RTC_CR, rtc_cr::ALRAE) == 0 && get_bit(RTC_CR, rtc_cr::ALRBE) == 0 } {
+        cleanup_exti_line(ExtiLine::RtcAlarm);
+        disable_interrupt(Irq::RtcAlarm);
+    }
+}
+
+pub fn is_rtc_alarm_triggered(alarm: RtcAlarm) -> bool {
+    use registers::rtc::RTC_ISR;
+
+    let (_, _, _, _, flag) = get_alarm_fields(alarm);
+    unsafe { get_bit(RTC_ISR, flag) == 1 }
+}
+
+/// Register a callback that is run from the RTC alarm interrupt handler when the alarm triggers
+pub fn register_rtc_alarm_callback(alarm: RtcAlarm, callback: fn()) {
+    RTC_CALLBACKS[alarm as usize].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_rtc_alarm_callback(alarm: RtcAlarm) {
+    RTC_CALLBACKS[alarm as usize].store(0, Ordering::Release);
+}
+
+/// Start the periodic wakeup timer with an interrupt every `period_ms` milliseconds on EXTI line
+/// 19. Periods up to 32 seconds (with the LSE) are counted with a millisecond resolution, and
+/// longer periods up to 36 hours in whole seconds
+pub fn setup_rtc_wakeup(period_ms: u32) -> Result<(), RtcError> {
+    use registers::rtc::{RTC_CR, RTC_ISR, RTC_WUTR, rtc_cr, rtc_isr};
+
+    let frequency = RTC_CLOCK_FREQUENCY.load(Ordering::Relaxed);
+    if frequency == 0 {
+        return Err(RtcError::NotInitialized);
+    }
+
+    // Count the RTC clock divided by 16 for short periods, and the 1 Hz calendar clock for long
+    // periods, where the extra setting adds 2^16 to the counter. A zero period ends up in the
+    // error case
+    let ticks = (period_ms as u64 * (frequency / 16) as u64).div_ceil(1_000);
+    let (clock_select, reload) = match ticks {
+        1..=0x1_0000 => (0b000, ticks as u32 - 1),
+        _ => match period_ms.div_ceil(1_000) {
+            seconds @ 1..=0x1_0000 => (0b100, seconds - 1),
+            seconds @ 0x1_0001..=0x2_0000 => (0b110, seconds - 0x1_0001),
+            _ => return Err(RtcError::InvalidWakeupPeriod(period_ms)),
+        },
+    };
+
+    unlock_rtc();
+
+    unsafe {
+        // The wakeup timer can only be written while it's disabled
+        clear_bit(RTC_CR, rtc_cr::WUTE);
+        while get_bit(RTC_ISR, rtc_isr::WUTWF) == 0 {}
+
+        write_register(RTC_WUTR, reload);
+        write_bits(RTC_CR, rtc_cr::WUCKSEL, clock_select, 0b111);
+        clear_rtc_flags(1 << rtc_isr::WUTF);
+
+        set_bit(RTC_CR, rtc_cr::WUTIE);
+        set_bit(RTC_CR, rtc_cr::WUTE);
+    }
+
+    lock_rtc();
+
+    // Route the wakeup timer to the CPU through its EXTI line
+    let _ = setup_exti_line(ExtiLine::RtcWakeup, ExtiEdge::Rising);
+
+    enable_interrupt(Irq::RtcWkup);
+
+    Ok(())
+}
+
+/// Stop the wakeup timer and remove its callback
+pub fn cleanup_rtc_wakeup() {
+    use registers::rtc::{RTC_CR, rtc_cr};
+
+    unlock_rtc();
+
+    unsafe {
+        clear_bit(RTC_CR, rtc_cr::WUTIE);
+        clear_bit(RTC_CR, rtc_cr::WUTE);
+    }
+
+    lock_rtc();
+
+    cleanup_exti_line(ExtiLine::RtcWakeup);
+
+    unregister_rtc_wakeup_callback();
+    disable_interrupt(Irq::RtcWkup);
+}
+
+/// Register a callback that is run from the RTC wakeup interrupt handler on every period
+pub fn register_rtc_wakeup_callback(callback: fn()) {
+    RTC_CALLBACKS[RTC_WAKEUP_CALLBACK].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_rtc_wakeup_callback() {
+    RTC_CALLBACKS[RTC_WAKEUP_CALLBACK].store(0, Ordering::Release);
+}
+
+fn run_rtc_callback(index: usize) {
+    let callback = RTC_CALLBACKS[index].load(Ordering::Acquire);
+    if callback != 0 {
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+}
+
+/// Clear and dispatch the triggered alarms. This is the body of the RTC alarm interrupt handler
+/// and can be called from a user defined handler as well
+pub fn handle_rtc_alarm_interrupt() {
+    use registers::rtc::RTC_ISR;
+
+    clear_exti_line_pending(ExtiLine::RtcAlarm);
+
+    for alarm in [RtcAlarm::AlarmA, RtcAlarm::AlarmB] {
+        let (_, _, _, _, flag) = get_alarm_fields(alarm);
+
+        if unsafe { get_bit(RTC_ISR, flag) } == 0 {
+            continue;
+        }
+
+        clear_rtc_flags(1 << flag);
+
+        run_rtc_callback(alarm as usize);
+    }
+}
+
+/// Clear and dispatch a wakeup timer event. This is the body of the RTC wakeup interrupt
+/// handler and can be called from a user defined handler as well
+pub fn handle_rtc_wakeup_interrupt() {
+    use registers::rtc::{RTC_ISR, rtc_isr};
+
+    clear_exti_line_pending(ExtiLine::RtcWakeup);
+
+    if unsafe { get_bit(RTC_ISR, rtc_isr::WUTF) } == 0 {
+        return;
+    }
+
+    clear_rtc_flags(1 << rtc_isr::WUTF);
+
+    run_rtc_callback(RTC_WAKEUP_CALLBACK);
+}
+
+/// Record the calendar time on an edge of the RTC_TS input on PC13, with an interrupt on EXTI
+/// line 18. The time is read with `get_rtc_timestamp`
+pub fn setup_rtc_timestamp(edge: RtcEdge) {
+    use registers::rtc::{RTC_CR, rtc_cr, rtc_isr};
+
+    unlock_rtc();
+
+    unsafe {
+        // The edge can only be changed while the timestamp is disabled
+        clear_bit(RTC_CR, rtc_cr::TSE);
+
+        match edge {
+            RtcEdge::Rising => clear_bit(RTC_CR, rtc_cr::TSEDGE),
+            RtcEdge::Falling => set_bit(RTC_CR, rtc_cr::TSEDGE),
+        }
+
+        clear_rtc_flags(1 << rtc_isr::TSF | 1 << rtc_isr::TSOVF);
+
+        set_bit(RTC_CR, rtc_cr::TSIE);
+        set_bit(RTC_CR, rtc_cr::TSE);
+    }
+
+    lock_rtc();
+
+    enable_rtc_tamper_exti_line();
+    enable_interrupt(Irq::RtcTampStampCssLse);
+}
+
+/// Stop recording timestamps and remove the timestamp callback
+pub fn cleanup_rtc_timestamp() {
+    use registers::rtc::{RTC_CR, rtc_cr};
+
+    unlock_rtc();
+
+    unsafe {
+        clear_bit(RTC_CR, rtc_cr::TSIE);
+        clear_bit(RTC_CR, rtc_cr::TSE);
+    }
+
+    lock_rtc();
+
+    unregister_rtc_timestamp_callback();
+    disable_rtc_tamper_exti_line();
+}
+
+/// The recorded timestamp, or `None` if no timestamp event has happened since the last read.
+/// Reading it allows the next event to be recorded
+pub fn get_rtc_timestamp() -> Option<RtcTimestamp> {
+    use registers::rtc::{RTC_ISR, RTC_TSDR, RTC_TSTR, rtc_isr, rtc_tsdr, rtc_tstr};
+
+    unsafe {
+        if get_bit(RTC_ISR, rtc_isr::TSF) == 0 {
+            return None;
+        }
+
+        let time = read_register(RTC_TSTR);
+        let date = read_register(RTC_TSDR);
+        let overflow = get_bit(RTC_ISR, rtc_isr::TSOVF) == 1;
+
+        // The overflow flag is cleared after the timestamp flag, so a new event in between
+        // isn't reported as an overflow of this one
+        clear_rtc_flags(1 << rtc_isr::TSF);
+        clear_rtc_flags(1 << rtc_isr::TSOVF);
+
+        Some(RtcTimestamp {
+            month: from_bcd(date >> rtc_tsdr::MU & 0x1F),
+            day: from_bcd(date >> rtc_tsdr::DU & 0x3F),
+            weekday: (date >> rtc_tsdr::WDU & 0b111) as u8,
+            hour: from_bcd(time >> rtc_tstr::HU & 0x3F),
+            minute: from_bcd(time >> rtc_tstr::MNU & 0x7F),
+            second: from_bcd(time >> rtc_tstr::SU & 0x7F),
+            overflow,
+        })
+    }
+}
+
+/// Register a callback that is run from the tamper and timestamp interrupt handler on a
+/// timestamp event. The timestamp can be read with `get_rtc_timestamp` from the callback
+pub fn register_rtc_timestamp_callback(callback: fn()) {
+    RTC_CALLBACKS[RTC_TIMESTAMP_CALLBACK].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_rtc_timestamp_callback() {
+    RTC_CALLBACKS[RTC_TIMESTAMP_CALLBACK].store(0, Ordering::Release);
+}
+
+/// Enable, trigger edge, interrupt enable and no-erase bits of a tamper input in RTC_TAMPCR, and
+/// its flag in RTC_ISR
+fn get_tamper_fields(tamper: RtcTamper) -> (u8, u8, u8, u8, u8) {
+    use registers::rtc::{rtc_isr, rtc_tampcr};
+
+    match tamper {
+        RtcTamper::Tamper1 => (
+            rtc_tampcr::TAMP1E,
+            rtc_tampcr::TAMP1TRG,
+            rtc_tampcr::TAMP1IE,
+            rtc_tampcr::TAMP1NOERASE,
+            rtc_isr::TAMP1F,
+        ),
+        RtcTamper::Tamper2 => (
+            rtc_tampcr::TAMP2E,
+            rtc_tampcr::TAMP2TRG,
+            rtc_tampcr::TAMP2IE,
+            rtc_tampcr::TAMP2NOERASE,
+            rtc_isr::TAMP2F,
+        ),
+        RtcTamper::Tamper3 => (
+            rtc_tampcr::TAMP3E,
+            rtc_tampcr::TAMP3TRG,
+            rtc_tampcr::TAMP3IE,
+            rtc_tampcr::TAMP3NOERASE,
+            rtc_isr::TAMP3F,
+        ),
+    }
+}
+
+/// Detect an edge on a tamper input, with an interrupt on EXTI line 18. When `erase_backup` is
+/// set the backup registers are erased by hardware on the event, for secrets that must not
+/// survive tampering. The input is sampled without a filter, so it also works in VBAT mode
+pub fn setup_rtc_tamper(tamper: RtcTamper, edge: RtcEdge, erase_backup: bool) {
+    use registers::rtc::{RTC_TAMPCR, rtc_tampcr};
+
+    let (enable, trigger, interrupt_enable, no_erase, flag) = get_tamper_fields(tamper);
+
+    unsafe {
+        // The trigger can only be changed while the input is disabled. Edge detection is used
+        // when the filter is zero
+        clear_bit(RTC_TAMPCR, enable);
+        write_bits(RTC_TAMPCR, rtc_tampcr::TAMPFLT, 0b00, 0b11);
+
+        match edge {
+            RtcEdge::Rising => clear_bit(RTC_TAMPCR, trigger),
+            RtcEdge::Falling => set_bit(RTC_TAMPCR, trigger),
+        }
+
+        if erase_backup {
+            clear_bit(RTC_TAMPCR, no_erase);
+        } else {
+            set_bit(RTC_TAMPCR, no_erase);
+        }
+
+        clear_rtc_flags(1 << flag);
+
+        set_bit(RTC_TAMPCR, interrupt_enable);
+        set_bit(RTC_TAMPCR, enable);
+    }
+
+    enable_rtc_tamper_exti_line();
+    enable_interrupt(Irq::RtcTampStampCssLse);
+}
+
+/// Disable a tamper input and remove its callback
+pub fn cleanup_rtc_tamper(tamper: RtcTamper) {
+    use registers::rtc::RTC_TAMPCR;
+
+    let (enable, _, interrupt_enable, _, _) = get_tamper_fields(tamper);
+
+    unsafe {
+        clear_bit(RTC_TAMPCR, interrupt_enable);
+        clear_bit(RTC_TAMPCR, enable);
+    }
+
+    unregister_rtc_tamper_callback(tamper);
+    disable_rtc_tamper_exti_line();
+}
+
+/// Also record a timestamp on tamper events, readable with `get_rtc_timestamp`
+pub fn set_rtc_tamper_timestamp(enable: bool) {
+    use registers::rtc::{RTC_TAMPCR, rtc_tampcr};
+
+    unsafe {
+        if enable {
+            set_bit(RTC_TAMPCR, rtc_tampcr::TAMPTS);
+        } else {
+            clear_bit(RTC_TAMPCR, rtc_tampcr::TAMPTS);
+        }
+    }
+}
+
+pub fn is_rtc_tamper_triggered(tamper: RtcTamper) -> bool {
+    use registers::rtc::RTC_ISR;
+
+    let (_, _, _, _, flag) = get_tamper_fields(tamper);
+    unsafe { get_bit(RTC_ISR, flag) == 1 }
+}
+
+/// Clear a tamper event. The backup registers can't be written while the flag is set
+pub fn clear_rtc_tamper(tamper: RtcTamper) {
+    let (_, _, _, _, flag) = get_tamper_fields(tamper);
+    clear_rtc_flags(1 << flag);
+}
+
+/// Register a callback that is run from the tamper and timestamp interrupt handler when the
+/// tamper input triggers
+pub fn register_rtc_tamper_callback(tamper: RtcTamper, callback: fn()) {
+    RTC_CALLBACKS[RTC_TAMPER_CALLBACKS + tamper as usize]
+        .store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_rtc_tamper_callback(tamper: RtcTamper) {
+    RTC_CALLBACKS[RTC_TAMPER_CALLBACKS + tamper as usize].store(0, Ordering::Release);
+}
+
+fn enable_rtc_tamper_exti_line() {
+    let _ = setup_exti_line(ExtiLine::RtcTamperTimestampCssLse, ExtiEdge::Rising);
+}
+
+/// Mask EXTI line 18 and its interrupt once neither the timestamp, a tamper input nor the LSE
+/// clock security system is in use
+fn disable_rtc_tamper_exti_line() {
+    use registers::{
+        rcc::{BDCR, bdcr},
+        rtc::{RTC_CR, RTC_TAMPCR, rtc_cr, rtc_tampcr},
+    };
+
+    let (timestamp_enabled, tampers_enabled, css_enabled) = unsafe {
+        (
+            get_bit(RTC_CR, rtc_cr::TSE),
+            read_register(RTC_TAMPCR)
+                & (1 << rtc_tampcr::TAMP1E | 1 << rtc_tampcr::TAMP2E | 1 << rtc_tampcr::TAMP3E),
+            get_bit(BDCR, bdcr::LSECSSON),
+        )
+    };
+
+    if timestamp_enabled == 0 && tampers_enabled == 0 && css_enabled == 0 {
+        cleanup_exti_line(ExtiLine::RtcTamperTimestampCssLse);
+        disable_interrupt(Irq::RtcTampStampCssLse);
+    }
+}
+
+/// Read one of the 32 backup registers, which keep their value in Standby and VBAT mode
+pub fn read_rtc_backup_register(index: usize) -> Option<u32> {
+    use registers::rtc::RTC_BKP0R;
+
+    if index >= RTC_BACKUP_REGISTERS {
+        return None;
+    }
+
+    Some(unsafe { read_register(RTC_BKP0R.wrapping_add(index)) })
+}
+
+/// Write one of the 32 backup registers. Backup domain access has to be enabled, which
+/// `setup_rtc` does. Returns false if the index is out of range
+pub fn write_rtc_backup_register(index: usize, value: u32) -> bool {
+    use registers::rtc::RTC_BKP0R;
+
+    if index >= RTC_BACKUP_REGISTERS {
+        return false;
+    }
+
+    unsafe {
+        write_register(RTC_BKP0R.wrapping_add(index), value);
+    }
+
+    true
+}
+
+/// Clear and dispatch the timestamp and tamper events. This is the body of the RTC tamper and
+/// timestamp interrupt handler and can be called from a user defined handler as well
+pub fn handle_rtc_tamper_interrupt() {
+    use registers::rtc::{RTC_ISR, rtc_isr};
+
+    clear_exti_line_pending(ExtiLine::RtcTamperTimestampCssLse);
+
+    // The timestamp flag is left for `get_rtc_timestamp` in the callback, and cleared after it
+    if unsafe { get_bit(RTC_ISR, rtc_isr::TSF) } == 1 {
+        run_rtc_callback(RTC_TIMESTAMP_CALLBACK);
+
+        clear_rtc_flags(1 << rtc_isr::TSF);
+        clear_rtc_flags(1 << rtc_isr::TSOVF);
+    }
+
+    for tamper in [RtcTamper::Tamper1, RtcTamper::Tamper2, RtcTamper::Tamper3] {
+        if !is_rtc_tamper_triggered(tamper) {
+            continue;
+        }
+
+        clear_rtc_tamper(tamper);
+        run_rtc_callback(RTC_TAMPER_CALLBACKS + tamper as usize);
+    }
+}
+
+/// RTC interrupt handlers, exported with the vector names used by `cortex-m-rt` when the
+/// `rtc-handlers` feature is enabled. Leave it disabled if the application defines these
+/// handlers itself
+#[cfg(feature = "rtc-handlers")]
+#[allow(non_snake_case)]
+mod handlers {
+    use super::{
+        handle_rtc_alarm_interrupt, handle_rtc_tamper_interrupt, handle_rtc_wakeup_interrupt,
+    };
+    use crate::clocks::handle_css_interrupt;
+
+    #[unsafe(no_mangle)]
+    extern "C" fn RTC_TAMP_STAMP_CSS_LSE() {
+        handle_rtc_tamper_interrupt();
+        handle_css_interrupt();
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn RTC_ALARM() {
+        handle_rtc_alarm_interrupt();
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn RTC_WKUP() {
+        handle_rtc_wakeup_interrupt();
+    }
+}
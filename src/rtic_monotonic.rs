@@ -0,0 +1,208 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU32, Ordering, compiler_fence};
+
+use crate::{
+    interrupts::{Irq, disable_interrupt, enable_interrupt, set_pending},
+    register_tools::{ClearByWrite0, clear_bit, read_register, set_bit, write_register},
+    registers,
+    timers::{Timer, TimerError, enable_timer_clock},
+};
+
+/// Frequency of the monotonic ticks
+pub const TICK_FREQUENCY: u32 = 1_000_000;
+
+/// Number of half periods of the TIM2 counter, counted at the overflow and at the midpoint of the
+/// counter. Together with the counter it forms a 64 bit time
+static HALF_PERIODS: AtomicU32 = AtomicU32::new(0);
+
+/// Counter value where the midpoint compare on channel 2 fires
+const HALF_PERIOD: u32 = 1 << 31;
+
+/// A time driver for RTIC 2 backed by the 32 bit TIM2, counting microsecond ticks extended to 64
+/// bits. Channel 1 is the compare used to schedule the next wakeup, and channel 2 fires at the
+/// midpoint of the counter so that the overflow can't be missed.
+///
+/// With the `rtic` feature it implements `rtic_time::Monotonic`, started with
+/// `setup_rtic_monotonic`. The TIM2 interrupt handler has to call `handle_rtic_interrupt`, so
+/// don't enable the `timer-handlers` feature together with it
+pub struct Tim2Monotonic;
+
+impl Tim2Monotonic {
+    /// Start TIM2 counting from zero at `TICK_FREQUENCY`. The timer clock has to be a multiple of
+    /// it
+    pub fn start(clock_frequency: u32) -> Result<(), TimerError> {
+        use registers::tim2;
+
+        if clock_frequency < TICK_FREQUENCY || !clock_frequency.is_multiple_of(TICK_FREQUENCY) {
+            return Err(TimerError::InvalidClockSpeed(clock_frequency));
+        }
+
+        HALF_PERIODS.store(0, Ordering::Relaxed);
+
+        enable_timer_clock(&Timer::Tim2);
+
+        unsafe {
+            clear_bit(tim2::CR1, tim2::cr1::CEN);
+
+            write_register(tim2::PSC, clock_frequency / TICK_FREQUENCY - 1);
+            write_register(tim2::ARR, u32::MAX);
+            write_register(tim2::CCR1, 0);
+            write_register(tim2::CCR2, HALF_PERIOD);
+
+            // Load the pre-scaler and clear the flags this sets
+            set_bit(tim2::EGR, tim2::egr::UG);
+            write_register(tim2::SR, 0);
+
+            // Interrupt on overflow and at the midpoint. The compare interrupt of channel 1 is
+            // enabled here as well, its flag is only acted on by the timer queue
+            write_register(
+                tim2::DIER,
+                1 << tim2::dier::UIE | 1 << tim2::dier::CC1IE | 1 << tim2::dier::CC2IE,
+            );
+
+            write_register(tim2::CNT, 0);
+            set_bit(tim2::CR1, tim2::cr1::CEN);
+        }
+
+        enable_interrupt(Irq::Tim2);
+
+        Ok(())
+    }
+
+    /// Current time in ticks since `start`
+    pub fn now() -> u64 {
+        use registers::tim2;
+
+        // The half period count has to be read before the counter. If an overflow or midpoint is
+        // pending, the parity of the count tells which half the counter is expected in
+        let half_periods = HALF_PERIODS.load(Ordering::Relaxed);
+        compiler_fence(Ordering::Acquire);
+        let count = unsafe { read_register(tim2::CNT) };
+
+        ((half_periods as u64) << 31) + (count ^ ((half_periods & 1) << 31)) as u64
+    }
+
+    /// Schedule the compare interrupt at `instant`. An instant more than a counter period away
+    /// is left to the midpoint and overflow interrupts, which make the timer queue check again
+    pub fn set_compare(instant: u64) {
+        use registers::tim2;
+
+        let compare = match instant.checked_sub(Self::now()) {
+            Some(ticks) if ticks <= u32::MAX as u64 => instant as u32,
+            _ => 0,
+        };
+
+        unsafe {
+            write_register(tim2::CCR1, compare);
+        }
+    }
+
+    pub fn clear_compare_flag() {
+        use registers::tim2;
+
+        unsafe {
+            ClearByWrite0::new(tim2::SR).clear(tim2::sr::CC1IF);
+        }
+    }
+
+    /// Make the TIM2 interrupt pending, so that the timer queue runs from it
+    pub fn pend_interrupt() {
+        set_pending(Irq::Tim2);
+    }
+
+    /// Count the overflow and midpoint events of the counter. Call this from the TIM2
+    /// interrupt handler
+    pub fn on_interrupt() {
+        use registers::tim2;
+
+        let status = unsafe { read_register(tim2::SR) };
+
+        for flag in [tim2::sr::UIF, tim2::sr::CC2IF] {
+            if status & (1 << flag) != 0 {
+                unsafe {
+                    ClearByWrite0::new(tim2::SR).clear(flag);
+                }
+                HALF_PERIODS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Stop TIM2 and its interrupt
+    pub fn stop() {
+        use registers::tim2;
+
+        unsafe {
+            clear_bit(tim2::CR1, tim2::cr1::CEN);
+            write_register(tim2::DIER, 0);
+        }
+
+        disable_interrupt(Irq::Tim2);
+    }
+}
+
+/// Start TIM2 as the time base of the RTIC timer queue. The timer clock has to be a multiple of
+/// `TICK_FREQUENCY`, and the TIM2 interrupt handler has to call `handle_rtic_interrupt`
+#[cfg(feature = "rtic")]
+pub fn setup_rtic_monotonic(clock_frequency: u32) -> Result<(), TimerError> {
+    Tim2Monotonic::start(clock_frequency)?;
+    rtic_impl::TIMER_QUEUE.initialize(Tim2Monotonic);
+
+    Ok(())
+}
+
+/// Count the counter overflows and wake the tasks that are due. Call this from the TIM2
+/// interrupt handler
+#[cfg(feature = "rtic")]
+pub fn handle_rtic_interrupt() {
+    // Safety: this is only called from the TIM2 interrupt handler
+    unsafe {
+        rtic_impl::TIMER_QUEUE.on_monotonic_interrupt();
+    }
+}
+
+#[cfg(feature = "rtic")]
+mod rtic_impl {
+    use rtic_time::{
+        monotonic::TimerQueueBasedMonotonic,
+        timer_queue::{TimerQueue, TimerQueueBackend},
+    };
+
+    use super::{TICK_FREQUENCY, Tim2Monotonic};
+
+    pub(super) static TIMER_QUEUE: TimerQueue<Tim2Monotonic> = TimerQueue::new();
+
+    // The inherent functions take precedence, so these only forward to them
+    impl TimerQueueBackend for Tim2Monotonic {
+        type Ticks = u64;
+
+        fn now() -> u64 {
+            Tim2Monotonic::now()
+        }
+
+        fn set_compare(instant: u64) {
+            Tim2Monotonic::set_compare(instant);
+        }
+
+        fn clear_compare_flag() {
+            Tim2Monotonic::clear_compare_flag();
+        }
+
+        fn pend_interrupt() {
+            Tim2Monotonic::pend_interrupt();
+        }
+
+        fn on_interrupt() {
+            Tim2Monotonic::on_interrupt();
+        }
+
+        fn timer_queue() -> &'static TimerQueue<Self> {
+            &TIMER_QUEUE
+        }
+    }
+
+    impl TimerQueueBasedMonotonic for Tim2Monotonic {
+        type Backend = Self;
+        type Instant = fugit::Instant<u64, 1, TICK_FREQUENCY>;
+        type Duration = fugit::Duration<u64, 1, TICK_FREQUENCY>;
+    }
+}
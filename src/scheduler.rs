@@ -0,0 +1,180 @@
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::timers::{
+    Timer, TimerError, cleanup_timer, register_timer_callback, setup_cyclical_timer_period,
+};
+
+/// Number of software timers that can be scheduled at the same time
+pub const SCHEDULER_CAPACITY: usize = 16;
+
+/// Marks a slot that is being filled in by `schedule`, so that `run_scheduler` skips it
+const SLOT_RESERVED: usize = usize::MAX;
+
+/// Callback of each slot, stored as a function pointer. A zero means that the slot is free
+static TASK_CALLBACKS: [AtomicUsize; SCHEDULER_CAPACITY] =
+    [const { AtomicUsize::new(0) }; SCHEDULER_CAPACITY];
+
+/// Tick the task is due at next
+static TASK_DEADLINES: [AtomicU32; SCHEDULER_CAPACITY] =
+    [const { AtomicU32::new(0) }; SCHEDULER_CAPACITY];
+
+/// Period of a repeating task in ticks, or zero for a task that runs once
+static TASK_INTERVALS: [AtomicU32; SCHEDULER_CAPACITY] =
+    [const { AtomicU32::new(0) }; SCHEDULER_CAPACITY];
+
+/// Incremented every time a slot is freed, so that a stale `TaskId` can't cancel a new task
+/// placed in the same slot
+static TASK_GENERATIONS: [AtomicU32; SCHEDULER_CAPACITY] =
+    [const { AtomicU32::new(0) }; SCHEDULER_CAPACITY];
+
+/// Milliseconds counted by `handle_scheduler_tick`, wrapping after about 49 days
+static SCHEDULER_NOW_MS: AtomicU32 = AtomicU32::new(0);
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum SchedulerError {
+    /// All `SCHEDULER_CAPACITY` slots are in use
+    Full,
+    /// A repeating task needs an interval of at least one millisecond
+    InvalidInterval(u32),
+}
+
+/// Handle to a scheduled task, used to cancel it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaskId {
+    index: usize,
+    generation: u32,
+}
+
+/// Drive the scheduler from a timer with an update interrupt every millisecond. The timer
+/// interrupt handler has to call `handle_timer_interrupt`, which the `timer-handlers` feature
+/// provides. Tasks are run by calling `run_scheduler` from the main loop
+pub fn setup_scheduler(timer: &Timer, clock_frequency: u32) -> Result<(), TimerError> {
+    register_timer_callback(timer, handle_scheduler_tick);
+    setup_cyclical_timer_period(timer, clock_frequency, 1_000_000)?;
+
+    Ok(())
+}
+
+/// Stop the timer driving the scheduler. Scheduled tasks are kept, but no longer become due
+pub fn cleanup_scheduler(timer: &Timer) {
+    cleanup_timer(timer);
+}
+
+/// Advance the scheduler by one millisecond. This is registered as the timer callback by
+/// `setup_scheduler`, but can be called from any other millisecond tick, like the SysTick
+pub fn handle_scheduler_tick() {
+    SCHEDULER_NOW_MS.fetch_add(1, Ordering::Release);
+}
+
+/// Milliseconds since the scheduler was started
+pub fn get_scheduler_ms() -> u32 {
+    SCHEDULER_NOW_MS.load(Ordering::Acquire)
+}
+
+/// Run `callback` every `interval_ms` milliseconds, with the first run one interval from now
+pub fn schedule_every(interval_ms: u32, callback: fn()) -> Result<TaskId, SchedulerError> {
+    if interval_ms == 0 {
+        return Err(SchedulerError::InvalidInterval(interval_ms));
+    }
+
+    schedule(interval_ms, interval_ms, callback)
+}
+
+/// Run `callback` once, `delay_ms` milliseconds from now. The slot is freed before the callback
+/// runs, so the callback can schedule itself again
+pub fn schedule_once(delay_ms: u32, callback: fn()) -> Result<TaskId, SchedulerError> {
+    schedule(delay_ms, 0, callback)
+}
+
+fn schedule(delay_ms: u32, interval_ms: u32, callback: fn()) -> Result<TaskId, SchedulerError> {
+    for index in 0..SCHEDULER_CAPACITY {
+        // Reserve the slot before filling it in, so that it can't be taken or run half written
+        if TASK_CALLBACKS[index]
+            .compare_exchange(0, SLOT_RESERVED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            continue;
+        }
+
+        TASK_DEADLINES[index].store(get_scheduler_ms().wrapping_add(delay_ms), Ordering::Relaxed);
+        TASK_INTERVALS[index].store(interval_ms, Ordering::Relaxed);
+        let generation = TASK_GENERATIONS[index].load(Ordering::Relaxed);
+
+        TASK_CALLBACKS[index].store(callback as usize, Ordering::Release);
+
+        return Ok(TaskId { index, generation });
+    }
+
+    Err(SchedulerError::Full)
+}
+
+/// Cancel a scheduled task. Returns false if the task has already run or been cancelled
+pub fn cancel(id: TaskId) -> bool {
+    let callback = TASK_CALLBACKS[id.index].load(Ordering::Acquire);
+    if callback == 0
+        || callback == SLOT_RESERVED
+        || TASK_GENERATIONS[id.index].load(Ordering::Acquire) != id.generation
+    {
+        return false;
+    }
+
+    free_slot(id.index, callback)
+}
+
+/// Free a slot if it still holds `callback`, and invalidate the ids pointing to it
+fn free_slot(index: usize, callback: usize) -> bool {
+    if TASK_CALLBACKS[index]
+        .compare_exchange(
+            callback,
+            SLOT_RESERVED,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        return false;
+    }
+
+    TASK_GENERATIONS[index].fetch_add(1, Ordering::Relaxed);
+    TASK_CALLBACKS[index].store(0, Ordering::Release);
+
+    true
+}
+
+/// Run all tasks that are due. Call this from the main loop; the tasks run in the caller's
+/// context, so they can take as long as they need without blocking interrupts
+pub fn run_scheduler() {
+    let now = get_scheduler_ms();
+
+    for index in 0..SCHEDULER_CAPACITY {
+        let callback = TASK_CALLBACKS[index].load(Ordering::Acquire);
+        if callback == 0 || callback == SLOT_RESERVED {
+            continue;
+        }
+
+        // Compare through a signed difference, so that the deadline works across the wrap of
+        // the millisecond counter
+        let deadline = TASK_DEADLINES[index].load(Ordering::Relaxed);
+        if (now.wrapping_sub(deadline) as i32) < 0 {
+            continue;
+        }
+
+        let interval = TASK_INTERVALS[index].load(Ordering::Relaxed);
+        if interval == 0 {
+            if !free_slot(index, callback) {
+                continue;
+            }
+        } else {
+            // Keep the period free of drift, but skip missed runs instead of catching up on them
+            let mut next = deadline.wrapping_add(interval);
+            if (now.wrapping_sub(next) as i32) >= 0 {
+                next = now.wrapping_add(interval);
+            }
+            TASK_DEADLINES[index].store(next, Ordering::Relaxed);
+        }
+
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    gpio::Gpio,
+    pwm::{PwmMode, cleanup_pwm, get_pwm_max_duty, set_pwm_duty, setup_pwm},
+    timers::{Timer, TimerChannel, TimerError},
+};
+
+/// Frequency of the servo control signal
+pub const SERVO_FREQUENCY: u32 = 50;
+
+/// Period of the servo control signal in microseconds
+const SERVO_PERIOD_US: u32 = 1_000_000 / SERVO_FREQUENCY;
+
+/// A hobby servo driven by a 50 Hz PWM signal on a timer channel, where the pulse width sets the
+/// position. Most servos move over 180 degrees with pulses from 1 to 2 ms, but many accept a
+/// wider range which can be set with `set_pulse_range`. Other channels of the same timer can
+/// drive more servos, as they share the 50 Hz period. The channel is released when the servo is
+/// dropped
+pub struct Servo {
+    timer: Timer,
+    channel: TimerChannel,
+    gpio: Gpio,
+    min_pulse_us: u32,
+    max_pulse_us: u32,
+    pulse_us: u32,
+}
+
+impl Servo {
+    pub const DEFAULT_MIN_PULSE_US: u32 = 1_000;
+    pub const DEFAULT_MAX_PULSE_US: u32 = 2_000;
+
+    /// Angle the pulse range is mapped to
+    pub const MAX_ANGLE: u32 = 180;
+
+    /// Start the control signal on a timer channel with the servo centered. `gpio` is the pin
+    /// the channel is routed to, configured as alternate function of the timer
+    pub fn setup(
+        timer: Timer,
+        channel: TimerChannel,
+        gpio: &Gpio,
+        clock_frequency: u32,
+    ) -> Result<Self, TimerError> {
+        setup_pwm(
+            &timer,
+            channel,
+            gpio,
+            PwmMode::Mode1,
+            SERVO_FREQUENCY,
+            clock_frequency,
+        )?;
+
+        let mut servo = Self {
+            timer,
+            channel,
+            gpio: *gpio,
+            min_pulse_us: Self::DEFAULT_MIN_PULSE_US,
+            max_pulse_us: Self::DEFAULT_MAX_PULSE_US,
+            pulse_us: 0,
+        };
+        servo.set_angle(Self::MAX_ANGLE / 2);
+
+        Ok(servo)
+    }
+
+    /// Calibrate the pulse widths of the end positions of the servo. The current position is
+    /// kept within the new range
+    pub fn set_pulse_range(
+        &mut self,
+        min_pulse_us: u32,
+        max_pulse_us: u32,
+    ) -> Result<(), TimerError> {
+        if min_pulse_us >= max_pulse_us || max_pulse_us >= SERVO_PERIOD_US {
+            return Err(TimerError::InvalidPulseWidth(max_pulse_us));
+        }
+
+        self.min_pulse_us = min_pulse_us;
+        self.max_pulse_us = max_pulse_us;
+        self.set_pulse_us(self.pulse_us);
+
+        Ok(())
+    }
+
+    /// Move to `degrees`, from 0 at the minimum pulse width to `MAX_ANGLE` at the maximum.
+    /// Angles above `MAX_ANGLE` are clamped
+    pub fn set_angle(&mut self, degrees: u32) {
+        let degrees = degrees.min(Self::MAX_ANGLE);
+        let range = self.max_pulse_us - self.min_pulse_us;
+
+        self.set_pulse_us(self.min_pulse_us + range * degrees / Self::MAX_ANGLE);
+    }
+
+    /// Set the pulse width directly, clamped to the calibrated range
+    pub fn set_pulse_us(&mut self, pulse_us: u32) {
+        self.pulse_us = pulse_us.clamp(self.min_pulse_us, self.max_pulse_us);
+
+        let period_ticks = get_pwm_max_duty(&self.timer) as u64;
+        let duty = self.pulse_us as u64 * period_ticks / SERVO_PERIOD_US as u64;
+        set_pwm_duty(&self.timer, self.channel, duty as u32);
+    }
+
+    pub fn pulse_us(&self) -> u32 {
+        self.pulse_us
+    }
+
+    /// Current position, rounded down to whole degrees
+    pub fn angle(&self) -> u32 {
+        let range = self.max_pulse_us - self.min_pulse_us;
+        (self.pulse_us - self.min_pulse_us) * Self::MAX_ANGLE / range
+    }
+}
+
+impl Drop for Servo {
+    fn drop(&mut self) {
+        cleanup_pwm(&self.timer, self.channel, &self.gpio);
+    }
+}
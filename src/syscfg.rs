@@ -0,0 +1,50 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    register_tools::{clear_bit, get_bit, set_bit},
+    registers,
+};
+
+/// Enable the I/O compensation cell and block until it reports ready. The cell adjusts the slew
+/// rate of the I/O drivers to process, voltage and temperature, and should be enabled when pins
+/// are driven at `GpioSpeed::HighSpeed` or `GpioSpeed::VeryHighSpeed` with a supply above 2.5 V,
+/// such as for SDMMC, FMC or QUADSPI.
+///
+/// The cell is clocked by the CSI oscillator, which is enabled if it isn't running already
+pub fn enable_io_compensation() {
+    use registers::{
+        rcc::{APB4ENR, CR, apb4enr, cr},
+        syscfg::{CCCSR, cccsr},
+    };
+
+    unsafe {
+        // Enable the syscfg clock
+        set_bit(APB4ENR, apb4enr::SYSCFGEN);
+
+        // Enable the CSI oscillator and wait for it to stabilize
+        set_bit(CR, cr::CSION);
+        while get_bit(CR, cr::CSIRDY) == 0 {}
+
+        // Use the values computed by the cell rather than the CCCR register
+        clear_bit(CCCSR, cccsr::CS);
+
+        // Enable the compensation cell and wait for it to be ready
+        set_bit(CCCSR, cccsr::EN);
+        while get_bit(CCCSR, cccsr::READY) == 0 {}
+    }
+}
+
+/// Disable the I/O compensation cell. The CSI oscillator is left running, as it might be used by
+/// other peripherals
+pub fn disable_io_compensation() {
+    use registers::syscfg::{CCCSR, cccsr};
+
+    unsafe {
+        clear_bit(CCCSR, cccsr::EN);
+    }
+}
+
+pub fn is_io_compensation_ready() -> bool {
+    use registers::syscfg::{CCCSR, cccsr};
+
+    unsafe { get_bit(CCCSR, cccsr::READY) == 1 }
+}
@@ -0,0 +1,202 @@
+/// See the [ARMv7-M Architecture Reference Manual](https://developer.arm.com/documentation/ddi0403/latest/)
+/// for the SysTick timer
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::{
+    register_tools::{clear_bit, get_bit, read_register, set_bit, write_register},
+    registers,
+    timers::TimerError,
+};
+
+/// Number of ticks since `setup_systick`, split in a low and a high word since there are no 64
+/// bit atomics. Only the SysTick handler writes them
+static SYSTICK_TICKS_LOW: AtomicU32 = AtomicU32::new(0);
+static SYSTICK_TICKS_HIGH: AtomicU32 = AtomicU32::new(0);
+
+static SYSTICK_TICK_FREQUENCY: AtomicU32 = AtomicU32::new(0);
+static SYSTICK_CORE_FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+/// Registered tick callback, stored as a function pointer. A zero means that no callback is
+/// registered
+static SYSTICK_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Largest reload value of the 24 bit SysTick counter
+const SYSTICK_MAX_RELOAD: u32 = 0xFF_FFFF;
+
+/// Start the SysTick timer with an interrupt at `tick_frequency`, counting the uptime from zero.
+/// The timer is clocked from the core clock. `handle_systick` has to be called from the SysTick
+/// exception handler, which the `systick-handler` feature provides
+pub fn setup_systick(core_frequency: u32, tick_frequency: u32) -> Result<(), TimerError> {
+    use registers::stk;
+
+    if core_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(core_frequency));
+    }
+
+    if tick_frequency == 0 || tick_frequency > core_frequency {
+        return Err(TimerError::InvalidFrequency(tick_frequency));
+    }
+
+    let reload = core_frequency / tick_frequency - 1;
+    if reload == 0 || reload > SYSTICK_MAX_RELOAD {
+        return Err(TimerError::InvalidFrequency(tick_frequency));
+    }
+
+    unsafe {
+        clear_bit(stk::CSR, stk::csr::ENABLE);
+    }
+
+    SYSTICK_TICKS_LOW.store(0, Ordering::Relaxed);
+    SYSTICK_TICKS_HIGH.store(0, Ordering::Relaxed);
+    SYSTICK_TICK_FREQUENCY.store(tick_frequency, Ordering::Relaxed);
+    SYSTICK_CORE_FREQUENCY.store(core_frequency, Ordering::Relaxed);
+
+    unsafe {
+        write_register(stk::RVR, reload);
+        write_register(stk::CVR, 0);
+
+        // Use the core clock, enable the interrupt and start counting
+        write_register(
+            stk::CSR,
+            1 << stk::csr::CLKSOURCE | 1 << stk::csr::TICKINT | 1 << stk::csr::ENABLE,
+        );
+    }
+
+    Ok(())
+}
+
+/// Stop the SysTick timer. The uptime stops counting
+pub fn cleanup_systick() {
+    use registers::stk;
+
+    unsafe {
+        write_register(stk::CSR, 0);
+    }
+
+    unregister_systick_callback();
+}
+
+/// Register a callback that is run from the SysTick handler on every tick
+pub fn register_systick_callback(callback: fn()) {
+    SYSTICK_CALLBACK.store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_systick_callback() {
+    SYSTICK_CALLBACK.store(0, Ordering::Release);
+}
+
+/// Count a tick and run the registered callback. This is the body of the SysTick handler and
+/// can be called from a user defined handler as well
+pub fn handle_systick() {
+    let low = SYSTICK_TICKS_LOW.load(Ordering::Relaxed).wrapping_add(1);
+
+    // Carry into the high word before the low word wraps to zero, so a reader never sees the
+    // time go backwards
+    if low == 0 {
+        SYSTICK_TICKS_HIGH.fetch_add(1, Ordering::Release);
+    }
+    SYSTICK_TICKS_LOW.store(low, Ordering::Release);
+
+    let callback = SYSTICK_CALLBACK.load(Ordering::Acquire);
+    if callback != 0 {
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+}
+
+/// Number of ticks since `setup_systick`
+pub fn get_systick_ticks() -> u64 {
+    loop {
+        let high = SYSTICK_TICKS_HIGH.load(Ordering::Acquire);
+        let low = SYSTICK_TICKS_LOW.load(Ordering::Acquire);
+
+        if high == SYSTICK_TICKS_HIGH.load(Ordering::Acquire) {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// Milliseconds since `setup_systick`, with the resolution of one tick
+pub fn millis() -> u64 {
+    match SYSTICK_TICK_FREQUENCY.load(Ordering::Relaxed) {
+        0 => 0,
+        tick_frequency => get_systick_ticks() * 1_000 / tick_frequency as u64,
+    }
+}
+
+/// Microseconds since `setup_systick`, interpolated within a tick from the SysTick counter
+pub fn micros() -> u64 {
+    use registers::{scb, stk};
+
+    let tick_frequency = SYSTICK_TICK_FREQUENCY.load(Ordering::Relaxed) as u64;
+    let core_frequency = SYSTICK_CORE_FREQUENCY.load(Ordering::Relaxed) as u64;
+    if tick_frequency == 0 {
+        return 0;
+    }
+
+    let (ticks, elapsed) = loop {
+        let ticks = get_systick_ticks();
+        let (reload, current, pending) = unsafe {
+            (
+                read_register(stk::RVR),
+                read_register(stk::CVR),
+                get_bit(scb::ICSR, scb::icsr::PENDSTSET) == 1,
+            )
+        };
+
+        // Retry if the handler counted a tick in between
+        if ticks != get_systick_ticks() {
+            continue;
+        }
+
+        // A tick that hasn't been handled yet, e.g. when called with interrupts masked, is only
+        // included if it happened before the counter was read, which is when the counter has
+        // just been reloaded
+        let ticks = ticks + (pending && current > reload / 2) as u64;
+
+        break (ticks, (reload - current) as u64);
+    };
+
+    ticks * 1_000_000 / tick_frequency + elapsed * 1_000_000 / core_frequency
+}
+
+/// Set up the SysTick timer at 1 kHz, so that a tick is a millisecond
+pub fn setup_systick_1khz(core_frequency: u32) -> Result<(), TimerError> {
+    setup_systick(core_frequency, 1_000)
+}
+
+/// Whether the SysTick timer is running
+pub fn is_systick_enabled() -> bool {
+    use registers::stk;
+    unsafe { get_bit(stk::CSR, stk::csr::ENABLE) == 1 }
+}
+
+/// Keep the SysTick exception from firing while the counter keeps running
+pub fn disable_systick_interrupt() {
+    use registers::stk;
+    unsafe {
+        clear_bit(stk::CSR, stk::csr::TICKINT);
+    }
+}
+
+pub fn enable_systick_interrupt() {
+    use registers::stk;
+    unsafe {
+        set_bit(stk::CSR, stk::csr::TICKINT);
+    }
+}
+
+/// SysTick exception handler, exported with the name used by `cortex-m-rt` when the
+/// `systick-handler` feature is enabled. Leave it disabled if the application defines the
+/// handler itself
+#[cfg(feature = "systick-handler")]
+#[allow(non_snake_case)]
+mod handlers {
+    use super::handle_systick;
+
+    #[unsafe(no_mangle)]
+    extern "C" fn SysTick() {
+        handle_systick();
+    }
+}
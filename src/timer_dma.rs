@@ -0,0 +1,127 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    dma::{DmaDirection, DmaSize, DmaStream, DmaTransfer, disable_dma_stream, setup_dma_stream},
+    register_tools::{clear_bit, set_bit, write_bits, write_register},
+    registers,
+    timers::{Timer, TimerChannel, TimerError, get_dier_interrupt_register},
+};
+
+/// Offset of CCR1 from CR1 in words, which is the DMA base address of channel 1
+const CCR1_BURST_ADDRESS: u32 = 13;
+
+/// Stream compare values from memory into `burst_length` consecutive channels starting at
+/// `first_channel`, one burst of values on each update event of the timer. `buffer` holds the
+/// bursts back to back, e.g. `[ch1, ch2, ch1, ch2, ...]` for two channels, and is played once or
+/// repeated when `circular` is set. The timer has to be setup for PWM or output compare on the
+/// channels beforehand. With a single channel this gives arbitrary PWM waveforms, such as the
+/// bit stream of a WS2812 LED strip. The buffer must be placed in memory the DMA can reach, see
+/// `DmaStream`
+pub fn setup_timer_dma_burst(
+    timer: &Timer,
+    stream: DmaStream,
+    first_channel: TimerChannel,
+    burst_length: u8,
+    buffer: &'static [u32],
+    circular: bool,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if burst_length == 0
+        || first_channel as u8 + burst_length > 4
+        || !buffer.len().is_multiple_of(burst_length as usize)
+        || buffer.len() > u16::MAX as usize
+    {
+        return Err(TimerError::InvalidBurstLength(burst_length));
+    }
+
+    let dcr_dma_control_register = get_dcr_dma_control_register(timer);
+
+    unsafe {
+        // Select the first register of the burst and the number of registers written per burst
+        write_bits(
+            dcr_dma_control_register,
+            tim2::dcr::DBA,
+            CCR1_BURST_ADDRESS + first_channel as u32,
+            0b1_1111,
+        );
+        write_bits(
+            dcr_dma_control_register,
+            tim2::dcr::DBL,
+            burst_length as u32 - 1,
+            0b1_1111,
+        );
+    }
+
+    setup_dma_stream(
+        stream,
+        &DmaTransfer {
+            request: get_dma_update_request(timer),
+            peripheral_address: get_dmar_dma_address_register(timer) as u32,
+            memory_address: buffer.as_ptr() as u32,
+            length: buffer.len() as u16,
+            direction: DmaDirection::MemoryToPeripheral,
+            size: DmaSize::Word,
+            circular,
+        },
+    );
+
+    unsafe {
+        // Let the update event request the DMA
+        set_bit(get_dier_interrupt_register(timer), tim2::dier::UDE);
+    }
+
+    Ok(())
+}
+
+/// Stop streaming compare values. The channels keep the last written values
+pub fn cleanup_timer_dma_burst(timer: &Timer, stream: DmaStream) {
+    use registers::tim2;
+
+    unsafe {
+        clear_bit(get_dier_interrupt_register(timer), tim2::dier::UDE);
+    }
+
+    disable_dma_stream(stream);
+
+    unsafe {
+        write_register(get_dcr_dma_control_register(timer), 0);
+    }
+}
+
+/// DMAMUX1 request line of the update event of each timer, see section 17.3.2
+fn get_dma_update_request(timer: &Timer) -> u8 {
+    match timer {
+        Timer::Tim1 => 15,
+        Timer::Tim2 => 22,
+        Timer::Tim3 => 27,
+        Timer::Tim4 => 32,
+        Timer::Tim5 => 59,
+        Timer::Tim8 => 51,
+    }
+}
+
+fn get_dcr_dma_control_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::DCR,
+        Timer::Tim2 => tim2::DCR,
+        Timer::Tim3 => tim3::DCR,
+        Timer::Tim4 => tim4::DCR,
+        Timer::Tim5 => tim5::DCR,
+        Timer::Tim8 => tim8::DCR,
+    }
+}
+
+fn get_dmar_dma_address_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::DMAR,
+        Timer::Tim2 => tim2::DMAR,
+        Timer::Tim3 => tim3::DMAR,
+        Timer::Tim4 => tim4::DMAR,
+        Timer::Tim5 => tim5::DMAR,
+        Timer::Tim8 => tim8::DMAR,
+    }
+}
@@ -0,0 +1,198 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use crate::{
+    register_tools::{clear_bit, write_bits},
+    registers,
+    timers::{Timer, TimerError, get_cr2_control_register, get_smcr_slave_mode_control_register},
+};
+
+/// Event a master timer outputs on its trigger output TRGO, used by slave timers, the ADCs and
+/// the DAC
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MasterMode {
+    /// The UG bit of the event generator register
+    Reset = 0b000,
+    /// The counter enable signal
+    Enable = 0b001,
+    /// Each update event, such as a counter overflow
+    Update = 0b010,
+    /// A pulse on each capture or compare match of channel 1
+    ComparePulse = 0b011,
+    Oc1Ref = 0b100,
+    Oc2Ref = 0b101,
+    Oc3Ref = 0b110,
+    Oc4Ref = 0b111,
+}
+
+/// How a slave timer reacts to its trigger input
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlaveMode {
+    /// The timer is clocked by its internal clock and ignores the trigger
+    Disabled = 0b0000,
+    /// Restart the counter on a rising edge of the trigger
+    Reset = 0b0100,
+    /// Count only while the trigger is high
+    Gated = 0b0101,
+    /// Start the counter on a rising edge of the trigger
+    Trigger = 0b0110,
+    /// Count rising edges of the trigger instead of the internal clock
+    ExternalClock = 0b0111,
+    /// Restart and start the counter on a rising edge of the trigger
+    ResetTrigger = 0b1000,
+}
+
+/// Trigger input of a slave timer. The internal triggers connect to other timers, see
+/// `get_internal_trigger`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimerTrigger {
+    Itr0 = 0b00000,
+    Itr1 = 0b00001,
+    Itr2 = 0b00010,
+    Itr3 = 0b00011,
+    /// Both edges of channel 1 input
+    Ti1Edge = 0b00100,
+    /// Filtered channel 1 input
+    Ti1Fp1 = 0b00101,
+    /// Filtered channel 2 input
+    Ti2Fp2 = 0b00110,
+    /// Filtered external trigger input
+    Etrf = 0b00111,
+}
+
+/// Select the event a timer outputs on TRGO
+pub fn set_timer_master_mode(timer: &Timer, mode: MasterMode) {
+    use registers::tim2;
+
+    unsafe {
+        write_bits(
+            get_cr2_control_register(timer),
+            tim2::cr2::MMS,
+            mode as u32,
+            0b111,
+        );
+    }
+}
+
+/// Select the trigger input of a timer and how it reacts to it. The timer still has to be
+/// enabled, except in `SlaveMode::Trigger` where the trigger enables it
+pub fn set_timer_slave_mode(timer: &Timer, mode: SlaveMode, trigger: TimerTrigger) {
+    use registers::tim2;
+
+    let smcr_slave_mode_control_register = get_smcr_slave_mode_control_register(timer);
+
+    unsafe {
+        // Disable the slave mode while the trigger is changed, to avoid false edges
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::SMS,
+            0b000,
+            0b111,
+        );
+        clear_bit(smcr_slave_mode_control_register, tim2::smcr::SMS_3);
+
+        // The trigger selection and slave mode are split in a low and a high part
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::TS,
+            trigger as u32,
+            0b111,
+        );
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::TS_4_3,
+            trigger as u32 >> 3,
+            0b11,
+        );
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::SMS_3,
+            mode as u32 >> 3,
+            0b1,
+        );
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::SMS,
+            mode as u32,
+            0b111,
+        );
+    }
+}
+
+/// Internal trigger of `slave` connected to the TRGO of `master`, see the TIMx internal trigger
+/// connection tables. Returns `None` if the timers aren't connected
+pub fn get_internal_trigger(slave: &Timer, master: &Timer) -> Option<TimerTrigger> {
+    use TimerTrigger::{Itr0, Itr1, Itr2, Itr3};
+
+    match (slave, master) {
+        (Timer::Tim1, Timer::Tim2) => Some(Itr1),
+        (Timer::Tim1, Timer::Tim3) => Some(Itr2),
+        (Timer::Tim1, Timer::Tim4) => Some(Itr3),
+        (Timer::Tim2, Timer::Tim1) => Some(Itr0),
+        (Timer::Tim2, Timer::Tim8) => Some(Itr1),
+        (Timer::Tim2, Timer::Tim3) => Some(Itr2),
+        (Timer::Tim2, Timer::Tim4) => Some(Itr3),
+        (Timer::Tim3, Timer::Tim1) => Some(Itr0),
+        (Timer::Tim3, Timer::Tim2) => Some(Itr1),
+        (Timer::Tim3, Timer::Tim4) => Some(Itr3),
+        (Timer::Tim4, Timer::Tim1) => Some(Itr0),
+        (Timer::Tim4, Timer::Tim2) => Some(Itr1),
+        (Timer::Tim4, Timer::Tim3) => Some(Itr2),
+        (Timer::Tim4, Timer::Tim8) => Some(Itr3),
+        (Timer::Tim5, Timer::Tim1) => Some(Itr0),
+        (Timer::Tim5, Timer::Tim8) => Some(Itr1),
+        (Timer::Tim5, Timer::Tim3) => Some(Itr2),
+        (Timer::Tim5, Timer::Tim4) => Some(Itr3),
+        (Timer::Tim8, Timer::Tim1) => Some(Itr0),
+        (Timer::Tim8, Timer::Tim2) => Some(Itr1),
+        (Timer::Tim8, Timer::Tim4) => Some(Itr2),
+        (Timer::Tim8, Timer::Tim5) => Some(Itr3),
+        _ => None,
+    }
+}
+
+/// Drive `slave` from the TRGO of `master`. For example, `MasterMode::Update` with
+/// `SlaveMode::ExternalClock` makes the slave count the overflows of the master, chaining the 32
+/// bit TIM2 and the 16 bit TIM3 into a 48 bit counter
+pub fn connect_timers(
+    master: &Timer,
+    master_mode: MasterMode,
+    slave: &Timer,
+    slave_mode: SlaveMode,
+) -> Result<(), TimerError> {
+    let trigger = get_internal_trigger(slave, master).ok_or(TimerError::Unsupported)?;
+
+    set_timer_master_mode(master, master_mode);
+    set_timer_slave_mode(slave, slave_mode, trigger);
+
+    Ok(())
+}
+
+/// Reset the master and slave mode of a timer
+pub fn cleanup_timer_sync(timer: &Timer) {
+    use registers::tim2;
+
+    let smcr_slave_mode_control_register = get_smcr_slave_mode_control_register(timer);
+
+    set_timer_master_mode(timer, MasterMode::Reset);
+
+    unsafe {
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::SMS,
+            0b000,
+            0b111,
+        );
+        clear_bit(smcr_slave_mode_control_register, tim2::smcr::SMS_3);
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::TS,
+            0b000,
+            0b111,
+        );
+        write_bits(
+            smcr_slave_mode_control_register,
+            tim2::smcr::TS_4_3,
+            0b00,
+            0b11,
+        );
+    }
+}
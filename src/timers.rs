@@ -1,75 +1,179 @@
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering};
+
 use crate::{
-    interrupts::{disable_interrupt, enable_interrupt},
-    register_tools::{clear_bit, read_register, set_bit, write_register},
+    gpio::Gpio,
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    pinmap::{PortPin, timer_channel},
+    rcc,
+    register_tools::{
+        ClearByWrite0, clear_bit, get_bit, modify, read_register, set_bit, write_register,
+    },
     registers,
 };
 
-enum Timer {
+/// General-purpose timers TIM2 to TIM5 and the advanced timers TIM1 and TIM8. TIM2 and TIM5 have
+/// 32 bit counters, the others 16 bit counters
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Timer {
+    Tim1,
     Tim2,
     Tim3,
     Tim4,
     Tim5,
+    Tim8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimerChannel {
+    Ch1,
+    Ch2,
+    Ch3,
+    Ch4,
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum TimerError {
     InvalidClockSpeed(u32),
     InvalidInterval(u16),
+    InvalidDelay(u32),
+    InvalidFrequency(u32),
+    /// The period in nanoseconds is too short or too long for the timer
+    InvalidPeriod(u64),
+    /// The dead-time in nanoseconds can't be generated from the timer clock
+    InvalidDeadTime(u32),
+    /// The digital filter value is above the 4 bits of the filter field
+    InvalidFilter(u8),
+    /// The feature isn't available on this timer or channel
+    Unsupported,
+    /// The timer is already owned by a handle
+    InUse,
+    /// The DMA burst doesn't fit in the channels of the timer, or the buffer isn't a whole
+    /// number of bursts
+    InvalidBurstLength(u8),
+    /// The timer channel can't be routed to the pin
+    InvalidPin,
+    /// The pulse width in microseconds doesn't fit in the period
+    InvalidPulseWidth(u32),
+    /// The frequency of the timer clock isn't known, such as when it's derived from the HSE
+    UnknownClock,
 }
 
+/// Route a timer channel to `pin`, returning the pin configured as alternate function of the
+/// timer. The legal pins of each channel are listed in `pinmap::timer_channel_pins`
+pub fn get_timer_channel_gpio(
+    timer: &Timer,
+    channel: TimerChannel,
+    pin: PortPin,
+) -> Result<Gpio, TimerError> {
+    timer_channel(*timer, channel, pin).ok_or(TimerError::InvalidPin)
+}
+
+/// Registered update callbacks per timer, indexed by `Timer`, stored as function pointers. A zero
+/// means that no callback is registered for the timer
+static TIMER_CALLBACKS: [AtomicUsize; 6] = [const { AtomicUsize::new(0) }; 6];
+
+/// Frequency the counter of each timer ticks with after its prescaler, indexed by `Timer`. Zero
+/// means that the timer hasn't been setup as a cyclical or one-shot timer
+static TIMER_TICK_FREQUENCIES: [AtomicU32; 6] = [const { AtomicU32::new(0) }; 6];
+
 fn setup_cyclical_timer(
     timer: &Timer,
     clock_frequency: u32,
     interval_ms: u16,
 ) -> Result<(), TimerError> {
-    use registers::{
-        rcc::{APB1LENR, apb1lenr},
-        tim2, tim3, tim4, tim5,
-    };
+    if interval_ms == 0 {
+        return Err(TimerError::InvalidInterval(interval_ms));
+    }
 
+    setup_cyclical_timer_period(timer, clock_frequency, interval_ms as u64 * 1_000_000)?;
+
+    Ok(())
+}
+
+/// Start a timer with an update interrupt every `period_ns` nanoseconds. The period is rounded to
+/// the nearest the timer clock can generate, which is returned in nanoseconds. TIM2 and TIM5 can
+/// use their 32 bit counters for long periods with a fine resolution
+pub fn setup_cyclical_timer_period(
+    timer: &Timer,
+    clock_frequency: u32,
+    period_ns: u64,
+) -> Result<u64, TimerError> {
     if clock_frequency == 0 {
         return Err(TimerError::InvalidClockSpeed(clock_frequency));
     }
 
-    if interval_ms == 0 {
-        return Err(TimerError::InvalidInterval(interval_ms));
+    let ticks = get_period_ticks(clock_frequency, period_ns);
+    start_cyclical_timer(timer, clock_frequency, ticks).ok_or(TimerError::InvalidPeriod(period_ns))
+}
+
+/// Start a timer with an update interrupt at `frequency`. Returns the achieved period in
+/// nanoseconds, like `setup_cyclical_timer_period`
+pub fn setup_cyclical_timer_frequency(
+    timer: &Timer,
+    clock_frequency: u32,
+    frequency: u32,
+) -> Result<u64, TimerError> {
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
     }
 
-    // Trigger a clock tick every microsecond
-    // 1 tick = 1 us
-    let prescaler: u32 = (clock_frequency / 1_000_000) - 1;
+    if frequency == 0 {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
 
-    // Adjust the auto reload register to count up every microsecond to the desired interval in
-    // milliseconds
-    let auto_reload: u32 = (interval_ms as u32 * 1_000) - 1;
+    let ticks = (clock_frequency as u128 + frequency as u128 / 2) / frequency as u128;
+    start_cyclical_timer(timer, clock_frequency, ticks)
+        .ok_or(TimerError::InvalidFrequency(frequency))
+}
 
-    unsafe {
-        let apb1lenr_clock_field = match timer {
-            Timer::Tim2 => apb1lenr::TIM2EN,
-            Timer::Tim3 => apb1lenr::TIM3EN,
-            Timer::Tim4 => apb1lenr::TIM4EN,
-            Timer::Tim5 => apb1lenr::TIM5EN,
-        };
+/// A period in nanoseconds in ticks of the timer clock, rounded to the nearest tick
+fn get_period_ticks(clock_frequency: u32, period_ns: u64) -> u128 {
+    (period_ns as u128 * clock_frequency as u128 + 500_000_000) / 1_000_000_000
+}
+
+/// Compute the prescaler and auto reload values for a period in timer clock ticks. The smallest
+/// prescaler that fits the period in the counter gives the finest resolution, and the auto
+/// reload value is rounded to the nearest tick of the prescaled clock
+pub(crate) fn compute_timer_period(timer: &Timer, ticks: u128) -> Option<(u32, u32)> {
+    // The counter doesn't run with an auto reload value of zero
+    if ticks < 2 {
+        return None;
+    }
+
+    let prescaler = (ticks - 1) / (get_timer_max_count(timer) as u128 + 1);
+    if prescaler > 0xFFFF {
+        return None;
+    }
+
+    let divider = prescaler + 1;
+    let auto_reload = ((ticks + divider / 2) / divider).max(2) - 1;
 
+    Some((
+        prescaler as u32,
+        auto_reload.min(get_timer_max_count(timer) as u128) as u32,
+    ))
+}
+
+/// Start a timer with a period of `ticks` timer clock ticks, and return the achieved period in
+/// nanoseconds
+fn start_cyclical_timer(timer: &Timer, clock_frequency: u32, ticks: u128) -> Option<u64> {
+    use registers::tim2;
+
+    let (prescaler, auto_reload) = compute_timer_period(timer, ticks)?;
+
+    TIMER_TICK_FREQUENCIES[*timer as usize]
+        .store(clock_frequency / (prescaler + 1), Ordering::Relaxed);
+
+    unsafe {
         // Enable the clock for the specified timer
-        set_bit(APB1LENR, apb1lenr_clock_field);
+        enable_timer_clock(timer);
 
-        let psc_prescaler_register = match timer {
-            Timer::Tim2 => tim2::PSC,
-            Timer::Tim3 => tim3::PSC,
-            Timer::Tim4 => tim4::PSC,
-            Timer::Tim5 => tim5::PSC,
-        };
+        let psc_prescaler_register = get_psc_prescaler_register(timer);
 
         // Write the pre-scaler into the pre-scaler register
         write_register(psc_prescaler_register, prescaler);
 
-        let arr_auto_load_register = match timer {
-            Timer::Tim2 => tim2::ARR,
-            Timer::Tim3 => tim3::ARR,
-            Timer::Tim4 => tim4::ARR,
-            Timer::Tim5 => tim5::ARR,
-        };
+        let arr_auto_load_register = get_arr_auto_reload_register(timer);
 
         // Write the auto reload register
         write_register(arr_auto_load_register, auto_reload);
@@ -95,17 +199,197 @@ fn setup_cyclical_timer(
         set_bit(cr1_control_register, tim2::cr1::CEN);
     }
 
+    let period_ticks = (prescaler as u64 + 1) * (auto_reload as u64 + 1);
+    Some((period_ticks as u128 * 1_000_000_000 / clock_frequency as u128) as u64)
+}
+
+/// Bitmask of the timers currently owned by a `CyclicalTimer` handle, indexed by `Timer`
+static TIMER_HANDLES: AtomicU8 = AtomicU8::new(0);
+
+/// Owned handle to a timer running with a periodic update interrupt. Only one handle can exist
+/// for each timer at a time, and the timer is cleaned up when the handle is dropped
+#[derive(Debug)]
+pub struct CyclicalTimer {
+    timer: Timer,
+    clock_frequency: u32,
+    period_ns: u64,
+}
+
+impl CyclicalTimer {
+    /// Take ownership of a timer and start it with an update interrupt every `period_ns`
+    /// nanoseconds. See `setup_cyclical_timer_period` for how the period is rounded
+    pub fn setup(timer: Timer, clock_frequency: u32, period_ns: u64) -> Result<Self, TimerError> {
+        let mask = 1 << timer as u8;
+        if TIMER_HANDLES.fetch_or(mask, Ordering::AcqRel) & mask != 0 {
+            return Err(TimerError::InUse);
+        }
+
+        match setup_cyclical_timer_period(&timer, clock_frequency, period_ns) {
+            Ok(period_ns) => Ok(Self {
+                timer,
+                clock_frequency,
+                period_ns,
+            }),
+            Err(error) => {
+                TIMER_HANDLES.fetch_and(!mask, Ordering::AcqRel);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn timer(&self) -> Timer {
+        self.timer
+    }
+
+    /// The achieved period in nanoseconds
+    pub fn period_ns(&self) -> u64 {
+        self.period_ns
+    }
+
+    /// Stop the counter, keeping its value
+    pub fn pause(&mut self) {
+        use registers::tim2;
+        unsafe {
+            clear_bit(get_cr1_control_register(&self.timer), tim2::cr1::CEN);
+        }
+    }
+
+    /// Continue counting from where the timer was paused
+    pub fn resume(&mut self) {
+        use registers::tim2;
+        unsafe {
+            set_bit(get_cr1_control_register(&self.timer), tim2::cr1::CEN);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        use registers::tim2;
+        unsafe { get_bit(get_cr1_control_register(&self.timer), tim2::cr1::CEN) == 1 }
+    }
+
+    /// Change the period while the timer is running. The counter restarts from zero, and the
+    /// achieved period in nanoseconds is returned
+    pub fn set_period(&mut self, period_ns: u64) -> Result<u64, TimerError> {
+        use registers::tim2;
+
+        let ticks = get_period_ticks(self.clock_frequency, period_ns);
+        let (prescaler, auto_reload) =
+            compute_timer_period(&self.timer, ticks).ok_or(TimerError::InvalidPeriod(period_ns))?;
+
+        let cr1_control_register = get_cr1_control_register(&self.timer);
+
+        TIMER_TICK_FREQUENCIES[self.timer as usize]
+            .store(self.clock_frequency / (prescaler + 1), Ordering::Relaxed);
+
+        unsafe {
+            write_register(get_psc_prescaler_register(&self.timer), prescaler);
+            write_register(get_arr_auto_reload_register(&self.timer), auto_reload);
+
+            // Load the new values without firing the update interrupt
+            set_bit(cr1_control_register, tim2::cr1::URS);
+            set_bit(get_egr_event_generator_register(&self.timer), tim2::egr::UG);
+            clear_bit(cr1_control_register, tim2::cr1::URS);
+        }
+
+        let period_ticks = (prescaler as u64 + 1) * (auto_reload as u64 + 1);
+        self.period_ns =
+            (period_ticks as u128 * 1_000_000_000 / self.clock_frequency as u128) as u64;
+
+        Ok(self.period_ns)
+    }
+
+    /// Restart the current period from zero
+    pub fn reset_count(&mut self) {
+        unsafe {
+            write_register(get_cnt_counter_register(&self.timer), 0);
+        }
+    }
+
+    /// Time into the current period in microseconds
+    pub fn now_us(&self) -> u64 {
+        get_now_us(&self.timer)
+    }
+
+    /// Register a callback that is run on each period, see `register_timer_callback`
+    pub fn on_tick(&mut self, callback: fn()) {
+        register_timer_callback(&self.timer, callback);
+    }
+}
+
+impl Drop for CyclicalTimer {
+    /// Stop the timer, remove its callback and release ownership
+    fn drop(&mut self) {
+        cleanup_timer(&self.timer);
+        TIMER_HANDLES.fetch_and(!(1 << self.timer as u8), Ordering::AcqRel);
+    }
+}
+
+fn setup_oneshot_timer(
+    timer: &Timer,
+    clock_frequency: u32,
+    delay_us: u32,
+) -> Result<(), TimerError> {
+    use registers::tim2;
+
+    if clock_frequency == 0 {
+        return Err(TimerError::InvalidClockSpeed(clock_frequency));
+    }
+
+    // Count up to the delay, after which the update event stops the counter
+    let ticks = get_period_ticks(clock_frequency, delay_us as u64 * 1_000);
+    let (prescaler, auto_reload) =
+        compute_timer_period(timer, ticks).ok_or(TimerError::InvalidDelay(delay_us))?;
+
+    TIMER_TICK_FREQUENCIES[*timer as usize]
+        .store(clock_frequency / (prescaler + 1), Ordering::Relaxed);
+
+    unsafe {
+        // Enable the clock for the specified timer
+        enable_timer_clock(timer);
+
+        let psc_prescaler_register = get_psc_prescaler_register(timer);
+
+        // Write the pre-scaler into the pre-scaler register
+        write_register(psc_prescaler_register, prescaler);
+
+        let arr_auto_load_register = get_arr_auto_reload_register(timer);
+
+        // Write the auto reload register
+        write_register(arr_auto_load_register, auto_reload);
+
+        let cr1_control_register = get_cr1_control_register(timer);
+
+        // Stop the counter at the next update event
+        set_bit(cr1_control_register, tim2::cr1::OPM);
+
+        // Load the pre-scaler and auto reload register, and clear the update flag the generated
+        // update sets, so that the interrupt only fires when the delay has passed
+        set_bit(get_egr_event_generator_register(timer), tim2::egr::UG);
+        ClearByWrite0::new(get_sr_status_register(timer)).clear(tim2::sr::UIF);
+
+        // Enable the update interrupt for the timer
+        set_bit(get_dier_interrupt_register(timer), tim2::dier::UIE);
+
+        // Enable the timer interrupt in the NVIC
+        enable_interrupt(get_nvic_interrupt_id(timer));
+
+        // Start the timer
+        set_bit(cr1_control_register, tim2::cr1::CEN);
+    }
+
     Ok(())
 }
 
-fn cleanup_timer(timer: &Timer) {
+pub(crate) fn cleanup_timer(timer: &Timer) {
     use registers::tim2;
 
     unsafe {
         let cr1_control_register = get_cr1_control_register(timer);
 
-        // Disable the timer
-        clear_bit(cr1_control_register, tim2::cr1::CEN);
+        // Disable the timer and one-pulse mode
+        modify(cr1_control_register, |value| {
+            value & !((1 << tim2::cr1::CEN) | (1 << tim2::cr1::OPM))
+        });
 
         let dier_interrupt_register = get_dier_interrupt_register(timer);
 
@@ -122,89 +406,450 @@ fn cleanup_timer(timer: &Timer) {
         // Disable the nvic interrupt
         disable_interrupt(nvic_interrupt_id);
     }
+
+    unregister_timer_callback(timer);
 }
 
+/// Time since the counter of a timer last wrapped, computed from the tick frequency it was setup
+/// with
 fn get_now_us(timer: &Timer) -> u64 {
-    use registers::{tim2, tim3, tim4, tim5};
+    get_now_ticks(timer, 1_000_000)
+}
+
+fn get_now_ns(timer: &Timer) -> u64 {
+    get_now_ticks(timer, 1_000_000_000)
+}
+
+/// Counter value of a timer converted to ticks of `frequency`
+fn get_now_ticks(timer: &Timer, frequency: u64) -> u64 {
+    let tick_frequency = TIMER_TICK_FREQUENCIES[*timer as usize].load(Ordering::Relaxed);
+    if tick_frequency == 0 {
+        return 0;
+    }
 
     // Get the address of the CNT register for the selected timer
-    let cnt_register = match timer {
+    let cnt_register = get_cnt_counter_register(timer);
+
+    // Read the current counter value
+    let count = unsafe { read_register(cnt_register) as u64 };
+
+    count * frequency / tick_frequency as u64
+}
+
+/// Register a callback that is run from the timer interrupt handler on each update event
+pub fn register_timer_callback(timer: &Timer, callback: fn()) {
+    TIMER_CALLBACKS[*timer as usize].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_timer_callback(timer: &Timer) {
+    TIMER_CALLBACKS[*timer as usize].store(0, Ordering::Release);
+}
+
+/// Clear a pending update event of a timer and dispatch it to the registered callback. This is
+/// the body of the timer interrupt handlers and can be called from user defined handlers as well
+pub fn handle_timer_interrupt(timer: &Timer) {
+    use registers::tim2;
+
+    let sr_status_register = get_sr_status_register(timer);
+
+    unsafe {
+        if get_bit(sr_status_register, tim2::sr::UIF) == 0 {
+            return;
+        }
+
+        ClearByWrite0::new(sr_status_register).clear(tim2::sr::UIF);
+    }
+
+    let callback = TIMER_CALLBACKS[*timer as usize].load(Ordering::Acquire);
+    if callback != 0 {
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+}
+
+/// Kernel clock of a timer, read back from the RCC. This is the `clock_frequency` the setup
+/// functions expect, which is twice the APB frequency when the APB is divided. Returns `None` if
+/// the clock is derived from the HSE and its frequency isn't known
+pub fn get_timer_kernel_clock(timer: &Timer) -> Option<u32> {
+    match timer {
+        Timer::Tim1 | Timer::Tim8 => rcc::get_apb2_timer_frequency(),
+        _ => rcc::get_apb1_timer_frequency(),
+    }
+}
+
+/// Enable the clock of a timer on its APB bus
+pub(crate) fn enable_timer_clock(timer: &Timer) {
+    use registers::rcc::{APB1LENR, APB2ENR, apb1lenr, apb2enr};
+
+    let (clock_enable_register, clock_enable_field) = match timer {
+        Timer::Tim1 => (APB2ENR, apb2enr::TIM1EN),
+        Timer::Tim2 => (APB1LENR, apb1lenr::TIM2EN),
+        Timer::Tim3 => (APB1LENR, apb1lenr::TIM3EN),
+        Timer::Tim4 => (APB1LENR, apb1lenr::TIM4EN),
+        Timer::Tim5 => (APB1LENR, apb1lenr::TIM5EN),
+        Timer::Tim8 => (APB2ENR, apb2enr::TIM8EN),
+    };
+
+    unsafe {
+        set_bit(clock_enable_register, clock_enable_field);
+    }
+}
+
+/// Highest value the counter of a timer can reach
+pub(crate) fn get_timer_max_count(timer: &Timer) -> u32 {
+    match timer {
+        Timer::Tim1 | Timer::Tim3 | Timer::Tim4 | Timer::Tim8 => 0xFFFF,
+        Timer::Tim2 | Timer::Tim5 => 0xFFFF_FFFF,
+    }
+}
+
+pub(crate) fn get_psc_prescaler_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::PSC,
+        Timer::Tim2 => tim2::PSC,
+        Timer::Tim3 => tim3::PSC,
+        Timer::Tim4 => tim4::PSC,
+        Timer::Tim5 => tim5::PSC,
+        Timer::Tim8 => tim8::PSC,
+    }
+}
+
+pub(crate) fn get_arr_auto_reload_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::ARR,
+        Timer::Tim2 => tim2::ARR,
+        Timer::Tim3 => tim3::ARR,
+        Timer::Tim4 => tim4::ARR,
+        Timer::Tim5 => tim5::ARR,
+        Timer::Tim8 => tim8::ARR,
+    }
+}
+
+pub(crate) fn get_cnt_counter_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CNT,
         Timer::Tim2 => tim2::CNT,
         Timer::Tim3 => tim3::CNT,
         Timer::Tim4 => tim4::CNT,
         Timer::Tim5 => tim5::CNT,
-    };
+        Timer::Tim8 => tim8::CNT,
+    }
+}
 
-    // Read the current counter value (microseconds)
-    let current_us = unsafe { read_register(cnt_register) as u64 };
+/// Capture/compare mode register of channel 1 and 2
+pub(crate) fn get_ccmr1_capture_compare_mode_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
 
-    // Convert µs → ns
-    current_us * 1_000
+    match timer {
+        Timer::Tim1 => tim1::CCMR1_OUTPUT,
+        Timer::Tim2 => tim2::CCMR1_OUTPUT,
+        Timer::Tim3 => tim3::CCMR1_OUTPUT,
+        Timer::Tim4 => tim4::CCMR1_OUTPUT,
+        Timer::Tim5 => tim5::CCMR1_OUTPUT,
+        Timer::Tim8 => tim8::CCMR1_OUTPUT,
+    }
 }
 
-fn get_now_ns(timer: &Timer) -> u64 {
-    get_now_us(timer) * 1_000
+/// Capture/compare mode register of channel 3 and 4
+pub(crate) fn get_ccmr2_capture_compare_mode_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CCMR2_OUTPUT,
+        Timer::Tim2 => tim2::CCMR2_OUTPUT,
+        Timer::Tim3 => tim3::CCMR2_OUTPUT,
+        Timer::Tim4 => tim4::CCMR2_OUTPUT,
+        Timer::Tim5 => tim5::CCMR2_OUTPUT,
+        Timer::Tim8 => tim8::CCMR2_OUTPUT,
+    }
+}
+
+pub(crate) const CHANNELS: [TimerChannel; 4] = [
+    TimerChannel::Ch1,
+    TimerChannel::Ch2,
+    TimerChannel::Ch3,
+    TimerChannel::Ch4,
+];
+
+/// Capture/compare mode register of a channel, and the offset of the channel fields in it.
+/// Channel 2 and 4 use the second byte of the register
+pub(crate) fn get_ccmr_capture_compare_mode_register(
+    timer: &Timer,
+    channel: TimerChannel,
+) -> (*mut u32, u8) {
+    match channel {
+        TimerChannel::Ch1 => (get_ccmr1_capture_compare_mode_register(timer), 0),
+        TimerChannel::Ch2 => (get_ccmr1_capture_compare_mode_register(timer), 8),
+        TimerChannel::Ch3 => (get_ccmr2_capture_compare_mode_register(timer), 0),
+        TimerChannel::Ch4 => (get_ccmr2_capture_compare_mode_register(timer), 8),
+    }
+}
+
+pub(crate) fn get_ccr_capture_compare_register(timer: &Timer, channel: TimerChannel) -> *mut u32 {
+    // The capture/compare registers are four bytes apart
+    get_ccr1_capture_compare_register(timer).wrapping_add(channel as usize)
+}
+
+/// Enable bit of a channel in CCER. The polarity bit follows it
+pub(crate) fn get_ccer_enable_field(channel: TimerChannel) -> u8 {
+    channel as u8 * 4
+}
+
+pub(crate) fn get_ccer_capture_compare_enable_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CCER,
+        Timer::Tim2 => tim2::CCER,
+        Timer::Tim3 => tim3::CCER,
+        Timer::Tim4 => tim4::CCER,
+        Timer::Tim5 => tim5::CCER,
+        Timer::Tim8 => tim8::CCER,
+    }
+}
+
+/// Capture/compare register of channel 1. The registers of channel 2 to 4 follow it
+pub(crate) fn get_ccr1_capture_compare_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CCR1,
+        Timer::Tim2 => tim2::CCR1,
+        Timer::Tim3 => tim3::CCR1,
+        Timer::Tim4 => tim4::CCR1,
+        Timer::Tim5 => tim5::CCR1,
+        Timer::Tim8 => tim8::CCR1,
+    }
 }
 
-fn get_cr1_control_register(timer: &Timer) -> *mut u32 {
-    use registers::{tim2, tim3, tim4, tim5};
+pub(crate) fn get_smcr_slave_mode_control_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
 
     match timer {
+        Timer::Tim1 => tim1::SMCR,
+        Timer::Tim2 => tim2::SMCR,
+        Timer::Tim3 => tim3::SMCR,
+        Timer::Tim4 => tim4::SMCR,
+        Timer::Tim5 => tim5::SMCR,
+        Timer::Tim8 => tim8::SMCR,
+    }
+}
+
+/// Only the advanced timers have a break and dead-time register
+pub(crate) fn get_bdtr_break_dead_time_register(timer: &Timer) -> Option<*mut u32> {
+    use registers::{tim1, tim8};
+
+    match timer {
+        Timer::Tim1 => Some(tim1::BDTR),
+        Timer::Tim8 => Some(tim8::BDTR),
+        _ => None,
+    }
+}
+
+pub(crate) fn get_cr1_control_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CR1,
         Timer::Tim2 => tim2::CR1,
         Timer::Tim3 => tim3::CR1,
         Timer::Tim4 => tim4::CR1,
         Timer::Tim5 => tim5::CR1,
+        Timer::Tim8 => tim8::CR1,
+    }
+}
+
+pub(crate) fn get_cr2_control_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::CR2,
+        Timer::Tim2 => tim2::CR2,
+        Timer::Tim3 => tim3::CR2,
+        Timer::Tim4 => tim4::CR2,
+        Timer::Tim5 => tim5::CR2,
+        Timer::Tim8 => tim8::CR2,
     }
 }
 
-fn get_dier_interrupt_register(timer: &Timer) -> *mut u32 {
-    use registers::{tim2, tim3, tim4, tim5};
+pub(crate) fn get_dier_interrupt_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
 
     match timer {
+        Timer::Tim1 => tim1::DIER,
         Timer::Tim2 => tim2::DIER,
         Timer::Tim3 => tim3::DIER,
         Timer::Tim4 => tim4::DIER,
         Timer::Tim5 => tim5::DIER,
+        Timer::Tim8 => tim8::DIER,
+    }
+}
+
+pub(crate) fn get_sr_status_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
+
+    match timer {
+        Timer::Tim1 => tim1::SR,
+        Timer::Tim2 => tim2::SR,
+        Timer::Tim3 => tim3::SR,
+        Timer::Tim4 => tim4::SR,
+        Timer::Tim5 => tim5::SR,
+        Timer::Tim8 => tim8::SR,
     }
 }
 
-fn get_egr_event_generator_register(timer: &Timer) -> *mut u32 {
-    use registers::{tim2, tim3, tim4, tim5};
+pub(crate) fn get_egr_event_generator_register(timer: &Timer) -> *mut u32 {
+    use registers::{tim1, tim2, tim3, tim4, tim5, tim8};
 
     match timer {
+        Timer::Tim1 => tim1::EGR,
         Timer::Tim2 => tim2::EGR,
         Timer::Tim3 => tim3::EGR,
         Timer::Tim4 => tim4::EGR,
         Timer::Tim5 => tim5::EGR,
+        Timer::Tim8 => tim8::EGR,
     }
 }
 
-fn get_nvic_interrupt_id(timer: &Timer) -> u32 {
-    use registers::irq;
+/// TIM1 and TIM8 have a separate capture/compare interrupt, while the general-purpose timers
+/// share one interrupt for all events
+pub(crate) fn get_nvic_capture_compare_interrupt_id(timer: &Timer) -> Irq {
     match timer {
-        Timer::Tim2 => irq::TIM2_IRQ,
-        Timer::Tim3 => irq::TIM3_IRQ,
-        Timer::Tim4 => irq::TIM4_IRQ,
-        Timer::Tim5 => irq::TIM5_IRQ,
+        Timer::Tim1 => Irq::Tim1Cc,
+        Timer::Tim8 => Irq::Tim8Cc,
+        _ => get_nvic_interrupt_id(timer),
     }
 }
 
+pub(crate) fn get_nvic_interrupt_id(timer: &Timer) -> Irq {
+    match timer {
+        Timer::Tim1 => Irq::Tim1Up,
+        Timer::Tim2 => Irq::Tim2,
+        Timer::Tim3 => Irq::Tim3,
+        Timer::Tim4 => Irq::Tim4,
+        Timer::Tim5 => Irq::Tim5,
+        Timer::Tim8 => Irq::Tim8UpTim13,
+    }
+}
+
+#[deprecated(note = "use `setup_cyclical_timer2_from_rcc`")]
 pub fn setup_cyclical_timer2(clock_frequency: u32, interval_ms: u16) -> Result<(), TimerError> {
     setup_cyclical_timer(&Timer::Tim2, clock_frequency, interval_ms)
 }
 
+#[deprecated(note = "use `setup_cyclical_timer3_from_rcc`")]
 pub fn setup_cyclical_timer3(clock_frequency: u32, interval_ms: u16) -> Result<(), TimerError> {
     setup_cyclical_timer(&Timer::Tim3, clock_frequency, interval_ms)
 }
 
+#[deprecated(note = "use `setup_cyclical_timer4_from_rcc`")]
 pub fn setup_cyclical_timer4(clock_frequency: u32, interval_ms: u16) -> Result<(), TimerError> {
     setup_cyclical_timer(&Timer::Tim4, clock_frequency, interval_ms)
 }
 
+#[deprecated(note = "use `setup_cyclical_timer5_from_rcc`")]
 pub fn setup_cyclical_timer5(clock_frequency: u32, interval_ms: u16) -> Result<(), TimerError> {
     setup_cyclical_timer(&Timer::Tim5, clock_frequency, interval_ms)
 }
 
+/// Fire the update interrupt of timer 2 once, `delay_us` microseconds from now. The timer stops by
+/// itself afterwards
+#[deprecated(note = "use `setup_oneshot_timer2_from_rcc`")]
+pub fn setup_oneshot_timer2(clock_frequency: u32, delay_us: u32) -> Result<(), TimerError> {
+    setup_oneshot_timer(&Timer::Tim2, clock_frequency, delay_us)
+}
+
+/// Like `setup_oneshot_timer2`, but limited to 2^32 timer clock ticks by the 16 bit counter
+#[deprecated(note = "use `setup_oneshot_timer3_from_rcc`")]
+pub fn setup_oneshot_timer3(clock_frequency: u32, delay_us: u32) -> Result<(), TimerError> {
+    setup_oneshot_timer(&Timer::Tim3, clock_frequency, delay_us)
+}
+
+/// Like `setup_oneshot_timer2`, but limited to 2^32 timer clock ticks by the 16 bit counter
+#[deprecated(note = "use `setup_oneshot_timer4_from_rcc`")]
+pub fn setup_oneshot_timer4(clock_frequency: u32, delay_us: u32) -> Result<(), TimerError> {
+    setup_oneshot_timer(&Timer::Tim4, clock_frequency, delay_us)
+}
+
+#[deprecated(note = "use `setup_oneshot_timer5_from_rcc`")]
+pub fn setup_oneshot_timer5(clock_frequency: u32, delay_us: u32) -> Result<(), TimerError> {
+    setup_oneshot_timer(&Timer::Tim5, clock_frequency, delay_us)
+}
+
+/// Start timer 2 with an update interrupt every `interval_ms` milliseconds. The timer clock is
+/// read back from the RCC, see `get_timer_kernel_clock`
+pub fn setup_cyclical_timer2_from_rcc(interval_ms: u16) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim2).ok_or(TimerError::UnknownClock)?;
+    setup_cyclical_timer(&Timer::Tim2, clock_frequency, interval_ms)
+}
+
+pub fn setup_cyclical_timer3_from_rcc(interval_ms: u16) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim3).ok_or(TimerError::UnknownClock)?;
+    setup_cyclical_timer(&Timer::Tim3, clock_frequency, interval_ms)
+}
+
+pub fn setup_cyclical_timer4_from_rcc(interval_ms: u16) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim4).ok_or(TimerError::UnknownClock)?;
+    setup_cyclical_timer(&Timer::Tim4, clock_frequency, interval_ms)
+}
+
+pub fn setup_cyclical_timer5_from_rcc(interval_ms: u16) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim5).ok_or(TimerError::UnknownClock)?;
+    setup_cyclical_timer(&Timer::Tim5, clock_frequency, interval_ms)
+}
+
+/// Like `setup_oneshot_timer2`, but with the timer clock read back from the RCC, see
+/// `get_timer_kernel_clock`
+pub fn setup_oneshot_timer2_from_rcc(delay_us: u32) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim2).ok_or(TimerError::UnknownClock)?;
+    setup_oneshot_timer(&Timer::Tim2, clock_frequency, delay_us)
+}
+
+/// Like `setup_oneshot_timer2_from_rcc`, but limited to 2^32 timer clock ticks by the 16 bit
+/// counter
+pub fn setup_oneshot_timer3_from_rcc(delay_us: u32) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim3).ok_or(TimerError::UnknownClock)?;
+    setup_oneshot_timer(&Timer::Tim3, clock_frequency, delay_us)
+}
+
+/// Like `setup_oneshot_timer2_from_rcc`, but limited to 2^32 timer clock ticks by the 16 bit
+/// counter
+pub fn setup_oneshot_timer4_from_rcc(delay_us: u32) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim4).ok_or(TimerError::UnknownClock)?;
+    setup_oneshot_timer(&Timer::Tim4, clock_frequency, delay_us)
+}
+
+pub fn setup_oneshot_timer5_from_rcc(delay_us: u32) -> Result<(), TimerError> {
+    let clock_frequency = get_timer_kernel_clock(&Timer::Tim5).ok_or(TimerError::UnknownClock)?;
+    setup_oneshot_timer(&Timer::Tim5, clock_frequency, delay_us)
+}
+
+/// Run `callback` on each update event of timer 2, such as every interval of a cyclical timer
+pub fn on_timer2_tick(callback: fn()) {
+    register_timer_callback(&Timer::Tim2, callback);
+}
+
+pub fn on_timer3_tick(callback: fn()) {
+    register_timer_callback(&Timer::Tim3, callback);
+}
+
+pub fn on_timer4_tick(callback: fn()) {
+    register_timer_callback(&Timer::Tim4, callback);
+}
+
+pub fn on_timer5_tick(callback: fn()) {
+    register_timer_callback(&Timer::Tim5, callback);
+}
+
+/// Stop a timer started with the free setup functions. A `CyclicalTimer` handle does this when
+/// it's dropped
 pub fn cleanup_timer2() {
     cleanup_timer(&Timer::Tim2);
 }
@@ -256,27 +901,143 @@ pub fn get_timer5_now_ns() -> u64 {
 pub fn clear_timer2_interrupt_flag() {
     use registers::tim2::{SR, sr::UIF};
     unsafe {
-        clear_bit(SR, UIF);
+        ClearByWrite0::new(SR).clear(UIF);
     }
 }
 
 pub fn clear_timer3_interrupt_flag() {
     use registers::tim3::{SR, sr::UIF};
     unsafe {
-        clear_bit(SR, UIF);
+        ClearByWrite0::new(SR).clear(UIF);
     }
 }
 
 pub fn clear_timer4_interrupt_flag() {
     use registers::tim4::{SR, sr::UIF};
     unsafe {
-        clear_bit(SR, UIF);
+        ClearByWrite0::new(SR).clear(UIF);
     }
 }
 
 pub fn clear_timer5_interrupt_flag() {
     use registers::tim5::{SR, sr::UIF};
     unsafe {
-        clear_bit(SR, UIF);
+        ClearByWrite0::new(SR).clear(UIF);
+    }
+}
+
+/// Timer interrupt handlers for TIM2 to TIM5, exported with the vector names used by
+/// `cortex-m-rt` when the `timer-handlers` feature is enabled. Leave it disabled if the
+/// application defines these handlers itself
+#[cfg(feature = "timer-handlers")]
+#[allow(non_snake_case)]
+mod handlers {
+    use super::{Timer, handle_timer_interrupt};
+
+    #[unsafe(no_mangle)]
+    extern "C" fn TIM2() {
+        handle_timer_interrupt(&Timer::Tim2);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn TIM3() {
+        handle_timer_interrupt(&Timer::Tim3);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn TIM4() {
+        handle_timer_interrupt(&Timer::Tim4);
+    }
+
+    #[unsafe(no_mangle)]
+    extern "C" fn TIM5() {
+        handle_timer_interrupt(&Timer::Tim5);
+    }
+}
+
+#[cfg(all(test, feature = "mock-registers"))]
+mod tests {
+    use super::*;
+    use crate::register_tools::mock::{get_register, reset_registers};
+    use registers::{nvic, tim3};
+
+    #[test]
+    fn cyclical_timer_writes_the_period_and_starts_the_timer() {
+        reset_registers();
+
+        let period_ns = setup_cyclical_timer_period(&Timer::Tim3, 1_000_000, 1_000_000).unwrap();
+
+        let prescaler = get_register(tim3::PSC);
+        let auto_reload = get_register(tim3::ARR);
+        assert_eq!((prescaler + 1) * (auto_reload + 1), 1_000);
+        assert_eq!(period_ns, 1_000_000);
+
+        assert_eq!(
+            get_register(tim3::CR1) & (1 << tim3::cr1::CEN),
+            1 << tim3::cr1::CEN
+        );
+        assert_eq!(
+            get_register(tim3::DIER) & (1 << tim3::dier::UIE),
+            1 << tim3::dier::UIE
+        );
+        assert_eq!(get_register(nvic::ISER0), 1 << Irq::Tim3.number());
+    }
+
+    #[test]
+    fn compute_timer_period_picks_the_smallest_prescaler() {
+        let cases = [
+            // The counter needs an auto reload value of at least one
+            (Timer::Tim3, 0, None),
+            (Timer::Tim3, 1, None),
+            (Timer::Tim3, 2, Some((0, 1))),
+            (Timer::Tim3, 1_000, Some((0, 999))),
+            (Timer::Tim3, 0x1_0000, Some((0, 0xFFFF))),
+            (Timer::Tim3, 0x1_0001, Some((1, 0x8000))),
+            // Half ticks of the prescaled clock are rounded to the nearest
+            (Timer::Tim3, 100_000, Some((1, 49_999))),
+            (Timer::Tim3, 100_001, Some((1, 50_000))),
+            (Timer::Tim3, 1 << 32, Some((0xFFFF, 0xFFFF))),
+            (Timer::Tim3, (1 << 32) + 1, None),
+            (Timer::Tim2, 1 << 32, Some((0, 0xFFFF_FFFF))),
+            (Timer::Tim2, (1 << 32) + 1, Some((1, 0x8000_0000))),
+            (Timer::Tim2, 1 << 48, Some((0xFFFF, 0xFFFF_FFFF))),
+            (Timer::Tim2, (1 << 48) + 1, None),
+        ];
+
+        for (timer, ticks, expected) in cases {
+            assert_eq!(
+                compute_timer_period(&timer, ticks),
+                expected,
+                "{ticks} ticks"
+            );
+        }
+    }
+
+    #[test]
+    fn oneshot_timer_counts_the_delay_in_timer_clock_ticks() {
+        reset_registers();
+
+        // 1.5 MHz isn't a whole number of microsecond ticks
+        setup_oneshot_timer(&Timer::Tim3, 1_500_000, 1_000).unwrap();
+        assert_eq!(get_register(tim3::PSC), 0);
+        assert_eq!(get_register(tim3::ARR), 1_499);
+
+        // A delay longer than the 16 bit counter uses the prescaler
+        setup_oneshot_timer(&Timer::Tim3, 200_000_000, 1_000_000).unwrap();
+        let prescaler = get_register(tim3::PSC);
+        let auto_reload = get_register(tim3::ARR);
+        assert!(prescaler > 0);
+        assert!(((prescaler + 1) * (auto_reload + 1)).abs_diff(200_000_000) <= prescaler / 2 + 1);
+    }
+
+    #[test]
+    fn cyclical_timer_rejects_a_zero_clock() {
+        reset_registers();
+
+        assert_eq!(
+            setup_cyclical_timer_period(&Timer::Tim3, 0, 1_000_000),
+            Err(TimerError::InvalidClockSpeed(0))
+        );
+        assert_eq!(get_register(tim3::CR1), 0);
     }
 }
@@ -1,140 +1,931 @@
-use super::register_tools::{clear_bit, get_bit, set_bit, write_bits, write_register};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicU8, AtomicU16, AtomicUsize, Ordering},
+};
 
+use super::{
+    dma::{
+        DmaDirection, DmaSize, DmaStream, DmaTransfer, disable_dma_stream,
+        get_dma_remaining_transfers, setup_dma_stream,
+    },
+    gpio::{Gpio, GpioAlternate, GpioPin, GpioRegister, GpioSpeed, create_alternate},
+    interrupts::{Irq, disable_interrupt, enable_interrupt},
+    pinmap::{self, PinTable, PortPin},
+    rcc::{self, Pll},
+    register_tools::{
+        clear_bit, dump, get_bit, read_register, set_bit, write_bits, write_register,
+    },
+    registers::blocks::UsartBlock,
+    ring_buffer::RingBuffer,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum USART {
+    USART1,
     USART2,
     USART3,
+    UART4,
+    UART5,
+    USART6,
+    UART7,
+    UART8,
+    LPUART1,
 }
 
-fn get_cr_usart_control_register(usart: &USART) -> *mut u32 {
-    use super::registers::{usart2, usart3};
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartError {
+    /// The pin can't be routed to the usart signal
+    InvalidPin,
+    /// A frame was received before the previous one was read, so data has been lost
+    Overrun,
+    /// The stop bit of a frame wasn't detected, usually caused by a baud rate mismatch or a
+    /// break on the line
+    Framing,
+    /// Noise was detected on the line while receiving a frame
+    Noise,
+    /// The parity bit of a frame didn't match its data
+    Parity,
+    /// The usart hasn't been setup
+    NotInitialized,
+    /// The baud rate can't be generated from the kernel clock within the allowed error
+    InvalidBaudRate,
+    /// The kernel clock frequency can't be determined, see `get_usart_kernel_clock`
+    UnknownClock,
+    /// The mode isn't supported by this usart
+    Unsupported,
+    /// The usart is already owned by a `Usart` handle
+    InUse,
+    /// The self test didn't receive the transmitted pattern
+    SelfTestFailed,
+}
+
+/// Number of data bits in a frame, not counting the parity bit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartDataBits {
+    Seven,
+    Eight,
+    Nine,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartParity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartStopBits {
+    One = 0b00,
+    Half = 0b01,
+    Two = 0b10,
+    OneAndHalf = 0b11,
+}
+
+/// Number of samples taken per bit. Oversampling by 8 doubles the highest reachable baud rate,
+/// at the cost of a lower tolerance to clock deviation. LPUART1 has no oversampling and ignores
+/// this setting
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartOversampling {
+    By16,
+    By8,
+}
+
+/// Fill level of a transmit or receive FIFO that sets its threshold flag. For the transmit FIFO
+/// the level counts empty slots, for the receive FIFO received bytes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartFifoThreshold {
+    OneEighth = 0b000,
+    OneQuarter = 0b001,
+    Half = 0b010,
+    ThreeQuarters = 0b011,
+    SevenEighths = 0b100,
+    Full = 0b101,
+}
+
+/// Frame format of a usart. Defaults to 8N1, oversampling by 16 and at most 2 % baud rate error.
+/// The hardware counts the parity bit as part of the word length, which is at most 9 bits, so
+/// nine data bits can't be combined with parity
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UsartConfig {
+    pub data_bits: UsartDataBits,
+    pub parity: UsartParity,
+    pub stop_bits: UsartStopBits,
+    pub oversampling: UsartOversampling,
+    /// Largest allowed deviation of the generated baud rate from the requested one, in percent
+    pub max_baud_error: u8,
+    /// Enable the 16 byte transmit and receive FIFOs
+    pub fifo: bool,
+    pub tx_fifo_threshold: UsartFifoThreshold,
+    pub rx_fifo_threshold: UsartFifoThreshold,
+}
+
+impl UsartConfig {
+    pub const fn new() -> Self {
+        Self {
+            data_bits: UsartDataBits::Eight,
+            parity: UsartParity::None,
+            stop_bits: UsartStopBits::One,
+            oversampling: UsartOversampling::By16,
+            max_baud_error: 2,
+            fifo: false,
+            tx_fifo_threshold: UsartFifoThreshold::Half,
+            rx_fifo_threshold: UsartFifoThreshold::Half,
+        }
+    }
+
+    pub const fn data_bits(mut self, data_bits: UsartDataBits) -> Self {
+        self.data_bits = data_bits;
+        assert!(
+            self.is_valid(),
+            "Nine data bits can't be combined with parity"
+        );
+        self
+    }
+
+    pub const fn parity(mut self, parity: UsartParity) -> Self {
+        self.parity = parity;
+        assert!(
+            self.is_valid(),
+            "Nine data bits can't be combined with parity"
+        );
+        self
+    }
+
+    pub const fn stop_bits(mut self, stop_bits: UsartStopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    pub const fn oversampling(mut self, oversampling: UsartOversampling) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    pub const fn max_baud_error(mut self, percent: u8) -> Self {
+        self.max_baud_error = percent;
+        self
+    }
+
+    /// Enable the FIFOs with the given thresholds. With the FIFOs enabled, the transmit interrupt
+    /// of `BufferedUsart` fires when the transmit FIFO has `tx_threshold` empty slots, and fills
+    /// it in one go
+    pub const fn fifo(
+        mut self,
+        tx_threshold: UsartFifoThreshold,
+        rx_threshold: UsartFifoThreshold,
+    ) -> Self {
+        self.fifo = true;
+        self.tx_fifo_threshold = tx_threshold;
+        self.rx_fifo_threshold = rx_threshold;
+        self
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        !matches!(self.data_bits, UsartDataBits::Nine) || matches!(self.parity, UsartParity::None)
+    }
+
+    /// The M1:M0 word length field, including the parity bit
+    const fn word_length(&self) -> u32 {
+        let parity_bits = match self.parity {
+            UsartParity::None => 0,
+            _ => 1,
+        };
+
+        let data_bits = match self.data_bits {
+            UsartDataBits::Seven => 7,
+            UsartDataBits::Eight => 8,
+            UsartDataBits::Nine => 9,
+        };
+
+        match data_bits + parity_bits {
+            7 => 0b10,
+            8 => 0b00,
+            _ => 0b01,
+        }
+    }
+}
+
+impl Default for UsartConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Smartcard (ISO 7816) settings, defaulting to a guard time of 16 bit times, the kernel clock
+/// divided by 20 on the clock pin, and NACK on parity errors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UsartSmartcardConfig {
+    guard_time: u8,
+    clock_prescaler: u8,
+    nack: bool,
+}
+
+impl UsartSmartcardConfig {
+    pub const fn new() -> Self {
+        Self {
+            guard_time: 16,
+            clock_prescaler: 10,
+            nack: true,
+        }
+    }
+
+    /// Number of bit times the transmission complete flag is delayed after each frame
+    pub const fn guard_time(mut self, guard_time: u8) -> Self {
+        self.guard_time = guard_time;
+        self
+    }
+
+    /// Divide the kernel clock by `2 * prescaler` to get the card clock, where `prescaler` is 1
+    /// to 31
+    pub const fn clock_prescaler(mut self, prescaler: u8) -> Self {
+        self.clock_prescaler = prescaler;
+        self
+    }
+
+    /// Signal parity errors to the card with a NACK, so that it resends the frame
+    pub const fn nack(mut self, nack: bool) -> Self {
+        self.nack = nack;
+        self
+    }
+}
+
+impl Default for UsartSmartcardConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The register block of a usart. LPUART1 shares the block, but has no guard time and receiver
+/// timeout, so `gtpr` and `rtor` are reserved on it
+fn get_usart_block(usart: &USART) -> *mut UsartBlock {
+    use super::registers::blocks::{
+        LPUART1, UART4, UART5, UART7, UART8, USART1, USART2, USART3, USART6,
+    };
 
     match usart {
-        USART::USART2 => usart2::CR1,
-        USART::USART3 => usart3::CR1,
+        USART::USART1 => USART1,
+        USART::USART2 => USART2,
+        USART::USART3 => USART3,
+        USART::UART4 => UART4,
+        USART::UART5 => UART5,
+        USART::USART6 => USART6,
+        USART::UART7 => UART7,
+        USART::UART8 => UART8,
+        USART::LPUART1 => LPUART1,
     }
 }
 
-fn get_apb1lenr_usart_clock_enable_field(usart: &USART) -> u8 {
-    use super::registers::rcc;
+fn get_cr_usart_control_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).cr1 }
+}
+
+fn get_cr2_usart_control_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).cr2 }
+}
+
+fn get_cr3_usart_control_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).cr3 }
+}
+
+fn get_brr_usart_baud_rate_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).brr }
+}
+
+fn get_isr_usart_interrupt_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).isr }
+}
+
+fn get_icr_usart_interrupt_clear_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).icr }
+}
+
+/// LPUART1 has no guard time and prescaler register
+fn get_gtpr_usart_guard_time_register(usart: &USART) -> Option<*mut u32> {
+    match usart {
+        USART::LPUART1 => None,
+        _ => Some(unsafe { &raw mut (*get_usart_block(usart)).gtpr }),
+    }
+}
 
+/// LPUART1 has no receiver timeout
+fn get_rtor_usart_receiver_timeout_register(usart: &USART) -> Option<*mut u32> {
     match usart {
-        USART::USART2 => rcc::apb1lenr::USART2EN,
-        USART::USART3 => rcc::apb1lenr::USART3EN,
+        USART::LPUART1 => None,
+        _ => Some(unsafe { &raw mut (*get_usart_block(usart)).rtor }),
     }
 }
 
-fn setup_usart(clock_speed: u32, baud_rate: u32, usart: &USART) {
-    use super::{
-        gpio::{Gpio, GpioAlternate, GpioMode, GpioPin, GpioRegister, GpioSpeed},
-        registers::{rcc, usart2, usart3},
-    };
+fn get_rqr_usart_request_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).rqr }
+}
+
+fn get_tdr_usart_data_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).tdr }
+}
+
+fn get_rdr_usart_data_register(usart: &USART) -> *mut u32 {
+    unsafe { &raw mut (*get_usart_block(usart)).rdr }
+}
 
+/// Mask for the data bits in the RDR register. When parity is enabled the most significant bit
+/// of the received word is the parity bit, which is masked out
+fn get_rdr_data_mask(usart: &USART) -> u32 {
+    use super::registers::usart2;
     let cr_usart_control_register = get_cr_usart_control_register(usart);
-    let apb1lenr_usart_clock_enable_field = get_apb1lenr_usart_clock_enable_field(usart);
-    let ahb4enr_gpio_clock_enable_field = match usart {
-        USART::USART2 => rcc::ahb4enr::GPIOAEN,
-        USART::USART3 => rcc::ahb4enr::GPIODEN,
+
+    let (m0, m1, parity) = unsafe {
+        (
+            get_bit(cr_usart_control_register, usart2::cr1::M0),
+            get_bit(cr_usart_control_register, usart2::cr1::M1),
+            get_bit(cr_usart_control_register, usart2::cr1::PCE),
+        )
     };
 
-    let mut usart_tx_gpio = match usart {
-        USART::USART2 => {
-            let mut gpio = Gpio::new();
-            gpio.register = GpioRegister::GpioA;
-            gpio.pin = GpioPin::P2;
-            gpio
-        }
-        USART::USART3 => {
-            let mut gpio = Gpio::new();
-            gpio.register = GpioRegister::GpioD;
-            gpio.pin = GpioPin::P8;
-            gpio
-        }
+    let word_length = match (m1, m0) {
+        (1, _) => 7,
+        (0, 1) => 9,
+        _ => 8,
     };
 
-    let mut usart_rx_gpio = match usart {
-        USART::USART2 => {
-            let mut gpio = Gpio::new();
-            gpio.register = GpioRegister::GpioA;
-            gpio.pin = GpioPin::P3;
-            gpio
-        }
-        USART::USART3 => {
-            let mut gpio = Gpio::new();
-            gpio.register = GpioRegister::GpioD;
-            gpio.pin = GpioPin::P9;
-            gpio
+    (1 << (word_length - parity)) - 1
+}
+
+fn get_nvic_interrupt_id(usart: &USART) -> Irq {
+    match usart {
+        USART::USART1 => Irq::Usart1,
+        USART::USART2 => Irq::Usart2,
+        USART::USART3 => Irq::Usart3,
+        USART::UART4 => Irq::Uart4,
+        USART::UART5 => Irq::Uart5,
+        USART::USART6 => Irq::Usart6,
+        USART::UART7 => Irq::Uart7,
+        USART::UART8 => Irq::Uart8,
+        USART::LPUART1 => Irq::Lpuart,
+    }
+}
+
+/// DMAMUX1 request line for the receiver of each usart, see section 17.3.2. LPUART1 is served by
+/// the BDMA through DMAMUX2, which isn't supported
+fn get_dma_rx_request(usart: &USART) -> u8 {
+    match usart {
+        USART::USART1 => 41,
+        USART::USART2 => 43,
+        USART::USART3 => 45,
+        USART::UART4 => 63,
+        USART::UART5 => 65,
+        USART::USART6 => 71,
+        USART::UART7 => 79,
+        USART::UART8 => 81,
+        USART::LPUART1 => panic!("LPUART1 can't be used with DMA1 or DMA2"),
+    }
+}
+
+/// USART1 and USART6 are clocked from APB2, LPUART1 from APB4 and the rest from APB1
+fn get_usart_clock_enable(usart: &USART) -> (*mut u32, u8) {
+    use super::registers::rcc::{APB1LENR, APB2ENR, APB4ENR, apb1lenr, apb2enr, apb4enr};
+
+    match usart {
+        USART::USART1 => (APB2ENR, apb2enr::USART1EN),
+        USART::USART2 => (APB1LENR, apb1lenr::USART2EN),
+        USART::USART3 => (APB1LENR, apb1lenr::USART3EN),
+        USART::UART4 => (APB1LENR, apb1lenr::UART4EN),
+        USART::UART5 => (APB1LENR, apb1lenr::UART5EN),
+        USART::USART6 => (APB2ENR, apb2enr::USART6EN),
+        USART::UART7 => (APB1LENR, apb1lenr::USART7EN),
+        USART::UART8 => (APB1LENR, apb1lenr::USART8EN),
+        USART::LPUART1 => (APB4ENR, apb4enr::LPUART1EN),
+    }
+}
+
+/// Pins selected for each usart, indexed by the `USART` discriminant. The transmit pin index is
+/// stored in the low byte and the receive pin index in the high byte, see `PortPin::to_index`.
+/// `NO_PINS` means that the usart hasn't been setup, in which case the default pins are used
+static USART_PINS: [AtomicU16; 9] = [const { AtomicU16::new(NO_PINS) }; 9];
+const NO_PINS: u16 = 0xFFFF;
+
+/// Receive pin index stored for a usart in half-duplex mode, which only uses the transmit pin
+const NO_RX_PIN: u8 = 0xFE;
+
+/// Flow control pins selected for each usart, with the clear to send pin index in the low byte
+/// and the request to send or driver enable pin index in the high byte. A byte of `0xFF` means
+/// the signal isn't used
+static USART_FLOW_PINS: [AtomicU16; 9] = [const { AtomicU16::new(NO_PINS) }; 9];
+
+/// Clock pin of each usart in smartcard mode, see `PortPin::to_index`. 0xFF means that no pin is
+/// used
+static USART_CK_PINS: [AtomicU8; 9] = [const { AtomicU8::new(0xFF) }; 9];
+
+/// Receiver timeout callbacks of each usart, stored as function pointers. A zero means that no
+/// callback is registered
+static USART_TIMEOUT_CALLBACKS: [AtomicUsize; 9] = [const { AtomicUsize::new(0) }; 9];
+
+/// Legal transmit and receive pins of each usart, from the alternate function tables
+fn get_usart_pin_tables(usart: &USART) -> (PinTable, PinTable) {
+    match usart {
+        USART::USART1 => (pinmap::USART1_TX_PINS, pinmap::USART1_RX_PINS),
+        USART::USART2 => (pinmap::USART2_TX_PINS, pinmap::USART2_RX_PINS),
+        USART::USART3 => (pinmap::USART3_TX_PINS, pinmap::USART3_RX_PINS),
+        USART::UART4 => (pinmap::UART4_TX_PINS, pinmap::UART4_RX_PINS),
+        USART::UART5 => (pinmap::UART5_TX_PINS, pinmap::UART5_RX_PINS),
+        USART::USART6 => (pinmap::USART6_TX_PINS, pinmap::USART6_RX_PINS),
+        USART::UART7 => (pinmap::UART7_TX_PINS, pinmap::UART7_RX_PINS),
+        USART::UART8 => (pinmap::UART8_TX_PINS, pinmap::UART8_RX_PINS),
+        USART::LPUART1 => (pinmap::LPUART1_TX_PINS, pinmap::LPUART1_RX_PINS),
+    }
+}
+
+/// Legal clear to send and request to send pins of each usart
+fn get_usart_flow_pin_tables(usart: &USART) -> (PinTable, PinTable) {
+    match usart {
+        USART::USART1 => (pinmap::USART1_CTS_PINS, pinmap::USART1_RTS_PINS),
+        USART::USART2 => (pinmap::USART2_CTS_PINS, pinmap::USART2_RTS_PINS),
+        USART::USART3 => (pinmap::USART3_CTS_PINS, pinmap::USART3_RTS_PINS),
+        USART::UART4 => (pinmap::UART4_CTS_PINS, pinmap::UART4_RTS_PINS),
+        USART::UART5 => (pinmap::UART5_CTS_PINS, pinmap::UART5_RTS_PINS),
+        USART::USART6 => (pinmap::USART6_CTS_PINS, pinmap::USART6_RTS_PINS),
+        USART::UART7 => (pinmap::UART7_CTS_PINS, pinmap::UART7_RTS_PINS),
+        USART::UART8 => (pinmap::UART8_CTS_PINS, pinmap::UART8_RTS_PINS),
+        USART::LPUART1 => (pinmap::LPUART1_CTS_PINS, pinmap::LPUART1_RTS_PINS),
+    }
+}
+
+/// Only the synchronous usarts have a clock pin
+fn get_usart_ck_pin_table(usart: &USART) -> Option<PinTable> {
+    match usart {
+        USART::USART1 => Some(pinmap::USART1_CK_PINS),
+        USART::USART2 => Some(pinmap::USART2_CK_PINS),
+        USART::USART3 => Some(pinmap::USART3_CK_PINS),
+        USART::USART6 => Some(pinmap::USART6_CK_PINS),
+        _ => None,
+    }
+}
+
+/// Get the pins a usart was setup with, or the default pins if it hasn't been setup. There's no
+/// receive pin in half-duplex mode
+fn get_usart_gpios(usart: &USART) -> (Gpio, Option<Gpio>) {
+    let pins = USART_PINS[*usart as usize].load(Ordering::Relaxed);
+    let (tx_table, rx_table) = get_usart_pin_tables(usart);
+
+    let tx = PortPin::from_index(pins as u8).and_then(|pin| pinmap::lookup(pin, tx_table));
+    if (pins >> 8) as u8 == NO_RX_PIN {
+        return (tx.unwrap_or_else(|| get_usart_tx_gpio(usart)), None);
+    }
+
+    let rx = PortPin::from_index((pins >> 8) as u8).and_then(|pin| pinmap::lookup(pin, rx_table));
+
+    (
+        tx.unwrap_or_else(|| get_usart_tx_gpio(usart)),
+        Some(rx.unwrap_or_else(|| get_usart_rx_gpio(usart))),
+    )
+}
+
+/// Default transmit pin of each usart
+fn get_usart_tx_gpio(usart: &USART) -> Gpio {
+    match usart {
+        USART::USART1 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P9,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART2 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P2,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART3 => create_alternate(
+            GpioRegister::GpioD,
+            GpioPin::P8,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART4 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P0,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART5 => create_alternate(
+            GpioRegister::GpioC,
+            GpioPin::P12,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART6 => create_alternate(
+            GpioRegister::GpioC,
+            GpioPin::P6,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART7 => create_alternate(
+            GpioRegister::GpioE,
+            GpioPin::P8,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART8 => create_alternate(
+            GpioRegister::GpioE,
+            GpioPin::P1,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::LPUART1 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P9,
+            GpioAlternate::AF3,
+            GpioSpeed::HighSpeed,
+        ),
+    }
+}
+
+/// Default receive pin of each usart
+fn get_usart_rx_gpio(usart: &USART) -> Gpio {
+    match usart {
+        USART::USART1 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P10,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART2 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P3,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART3 => create_alternate(
+            GpioRegister::GpioD,
+            GpioPin::P9,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART4 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P1,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART5 => create_alternate(
+            GpioRegister::GpioD,
+            GpioPin::P2,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::USART6 => create_alternate(
+            GpioRegister::GpioC,
+            GpioPin::P7,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART7 => create_alternate(
+            GpioRegister::GpioE,
+            GpioPin::P7,
+            GpioAlternate::AF7,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::UART8 => create_alternate(
+            GpioRegister::GpioE,
+            GpioPin::P0,
+            GpioAlternate::AF8,
+            GpioSpeed::HighSpeed,
+        ),
+        USART::LPUART1 => create_alternate(
+            GpioRegister::GpioA,
+            GpioPin::P10,
+            GpioAlternate::AF3,
+            GpioSpeed::HighSpeed,
+        ),
+    }
+}
+
+/// Kernel clock source of a usart. USART1 and USART6 share one selection, as do USART2/3,
+/// UART4/5 and UART7/8. `Pclk` is APB2 for USART1/6, APB4 for LPUART1 and APB1 for the rest
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartClockSource {
+    Pclk = 0b000,
+    Pll2Q = 0b001,
+    Pll3Q = 0b010,
+    Hsi = 0b011,
+    Csi = 0b100,
+    Lse = 0b101,
+}
+
+/// Kernel clock selection register and field of a usart, see section 8.7.21 RCC domain 2 kernel
+/// clock configuration register (RCC_D2CCIP2R)
+fn get_usart_clock_source_field(usart: &USART) -> (*mut u32, u8) {
+    use super::registers::rcc::{D2CCIP2R, D3CCIPR, d2ccip2r, d3ccipr};
+
+    match usart {
+        USART::USART1 | USART::USART6 => (D2CCIP2R, d2ccip2r::USART16SRC),
+        USART::LPUART1 => (D3CCIPR, d3ccipr::LPUART1SRC),
+        _ => (D2CCIP2R, d2ccip2r::USART234578SRC),
+    }
+}
+
+/// Select the kernel clock of a usart, which also changes it for the other usarts in the same
+/// group. Must be done before the usart is setup
+pub fn set_usart_clock_source(source: UsartClockSource, usart: &USART) {
+    use super::registers::rcc::{CR, cr};
+
+    let (register, field) = get_usart_clock_source_field(usart);
+
+    unsafe {
+        // Keep the HSI or CSI kernel clock running, also in Stop mode
+        match source {
+            UsartClockSource::Hsi => set_bit(CR, cr::HSIKERON),
+            UsartClockSource::Csi => set_bit(CR, cr::CSIKERON),
+            _ => {}
         }
-    };
 
-    usart_tx_gpio.mode = GpioMode::Alternate;
-    usart_tx_gpio.speed = GpioSpeed::HighSpeed;
-    usart_tx_gpio.alternate = GpioAlternate::AF7;
+        write_bits(register, field, source as u32, 0b111);
+    }
+}
+
+/// Frequency of the kernel clock of a usart, read from the selected source. Returns `None` if
+/// the source is off, or derived from the HSE and `rcc::set_hse_frequency` hasn't been called
+pub fn get_usart_kernel_clock(usart: &USART) -> Option<u32> {
+    let (register, field) = get_usart_clock_source_field(usart);
+
+    match unsafe { (read_register(register) >> field) & 0b111 } {
+        0b000 => match usart {
+            USART::USART1 | USART::USART6 => rcc::get_pclk2_frequency(),
+            USART::LPUART1 => rcc::get_pclk4_frequency(),
+            _ => rcc::get_pclk1_frequency(),
+        },
+        0b001 => rcc::get_pll_frequencies(Pll::Pll2)?.q,
+        0b010 => rcc::get_pll_frequencies(Pll::Pll3)?.q,
+        0b011 => Some(rcc::get_hsi_frequency()),
+        0b100 => Some(rcc::CSI_FREQUENCY),
+        0b101 => Some(rcc::LSE_FREQUENCY),
+        _ => None,
+    }
+}
+
+/// Setup a usart on its default pins. The baud rate is generated from the kernel clock read back
+/// from the RCC, see `get_usart_kernel_clock`. Returns `UsartError::InvalidBaudRate` if the baud
+/// rate can't be generated within the error allowed by `config`
+pub fn setup_usart(baud_rate: u32, config: &UsartConfig, usart: &USART) -> Result<(), UsartError> {
+    configure_usart(
+        baud_rate,
+        config,
+        &get_usart_tx_gpio(usart),
+        Some(&get_usart_rx_gpio(usart)),
+        usart,
+    )?;
+    USART_PINS[*usart as usize].store(NO_PINS, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Setup a usart on the given pins. Returns `UsartError::InvalidPin` if a pin can't be routed to
+/// the transmit or receive signal of the usart, according to the alternate function tables in
+/// `pinmap`
+pub fn setup_usart_with_pins(
+    baud_rate: u32,
+    config: &UsartConfig,
+    tx: PortPin,
+    rx: PortPin,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    let (tx_table, rx_table) = get_usart_pin_tables(usart);
+    let tx_gpio = pinmap::lookup(tx, tx_table).ok_or(UsartError::InvalidPin)?;
+    let rx_gpio = pinmap::lookup(rx, rx_table).ok_or(UsartError::InvalidPin)?;
+
+    configure_usart(baud_rate, config, &tx_gpio, Some(&rx_gpio), usart)?;
+    USART_PINS[*usart as usize].store(
+        tx.to_index() as u16 | (rx.to_index() as u16) << 8,
+        Ordering::Relaxed,
+    );
+
+    Ok(())
+}
+
+/// Setup a usart in single-wire half-duplex mode on the given transmit pin, for one-wire buses
+/// such as Dynamixel servos. The pin is configured as open-drain with the internal pull-up, and
+/// transmits and receives on the same line. The receiver hears everything the usart transmits,
+/// so use `set_usart_direction` to turn it off while sending. Returns `UsartError::InvalidPin`
+/// if the pin can't be routed to the transmit signal of the usart
+pub fn setup_usart_half_duplex(
+    baud_rate: u32,
+    config: &UsartConfig,
+    pin: PortPin,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    let (tx_table, _) = get_usart_pin_tables(usart);
+    let gpio = pinmap::lookup(pin, tx_table)
+        .ok_or(UsartError::InvalidPin)?
+        .open_drain()
+        .pull_up();
+
+    configure_usart(baud_rate, config, &gpio, None, usart)?;
+    USART_PINS[*usart as usize].store(
+        pin.to_index() as u16 | (NO_RX_PIN as u16) << 8,
+        Ordering::Relaxed,
+    );
 
-    usart_rx_gpio.mode = GpioMode::Alternate;
-    usart_rx_gpio.speed = GpioSpeed::HighSpeed;
-    usart_rx_gpio.alternate = GpioAlternate::AF7;
+    Ok(())
+}
+
+/// Compute the BRR value for a baud rate, see section 48.5.7 USART baud rate generation and
+/// 49.4.7 LPUART baud rate generation. The divider is rounded to the nearest integer, and
+/// `UsartError::InvalidBaudRate` is returned if it's out of range or the resulting baud rate is
+/// off by more than `config.max_baud_error` percent
+fn compute_baud_rate_register(
+    clock_speed: u32,
+    baud_rate: u32,
+    config: &UsartConfig,
+    usart: &USART,
+) -> Result<u32, UsartError> {
+    if baud_rate == 0 {
+        return Err(UsartError::InvalidBaudRate);
+    }
+
+    let clock_speed = clock_speed as u64;
+    let baud_rate = baud_rate as u64;
 
-    let brr_usart_baud_rate_register = match usart {
-        USART::USART2 => usart2::BRR,
-        USART::USART3 => usart3::BRR,
+    // Scale of the divider compared to fck / baud
+    let scale = match (usart, config.oversampling) {
+        (USART::LPUART1, _) => 256,
+        (_, UsartOversampling::By16) => 1,
+        (_, UsartOversampling::By8) => 2,
     };
 
+    let divider = (scale * clock_speed + baud_rate / 2) / baud_rate;
+    let (min, max) = match usart {
+        USART::LPUART1 => (0x300, 0xF_FFFF),
+        _ => (16, 0xFFFF),
+    };
+
+    if divider < min || divider > max {
+        return Err(UsartError::InvalidBaudRate);
+    }
+
+    let actual_baud_rate = scale * clock_speed / divider;
+    if actual_baud_rate.abs_diff(baud_rate) * 100 > baud_rate * config.max_baud_error as u64 {
+        return Err(UsartError::InvalidBaudRate);
+    }
+
+    let divider = divider as u32;
+    Ok(match (usart, config.oversampling) {
+        // With oversampling by 8 the lowest four bits of the divider are shifted down by one,
+        // and bit 3 must be kept cleared
+        (USART::LPUART1, _) | (_, UsartOversampling::By16) => divider,
+        (_, UsartOversampling::By8) => (divider & !0xF) | ((divider & 0xF) >> 1),
+    })
+}
+
+fn configure_usart(
+    baud_rate: u32,
+    config: &UsartConfig,
+    usart_tx_gpio: &Gpio,
+    usart_rx_gpio: Option<&Gpio>,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    use super::registers::usart3;
+
+    // Compute the baud rate first, so that nothing is changed if it can't be reached
+    let clock_speed = get_usart_kernel_clock(usart).ok_or(UsartError::UnknownClock)?;
+    let brr = compute_baud_rate_register(clock_speed, baud_rate, config, usart)?;
+
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+    let (usart_clock_enable_register, usart_clock_enable_field) = get_usart_clock_enable(usart);
+
+    let brr_usart_baud_rate_register = get_brr_usart_baud_rate_register(usart);
+
     unsafe {
         // Disable USART before configuring
         clear_bit(cr_usart_control_register, usart3::cr1::UE);
 
         // Enable the usart clock
-        set_bit(rcc::APB1LENR, apb1lenr_usart_clock_enable_field);
-
-        // Enable the gpioa clock
-        set_bit(rcc::AHB4ENR, ahb4enr_gpio_clock_enable_field);
+        set_bit(usart_clock_enable_register, usart_clock_enable_field);
 
-        // Setup gpio pins as alternate functions (usart)
+        // Setup gpio pins as alternate functions (usart). This also enables the gpio clocks
         usart_tx_gpio.setup();
-        usart_rx_gpio.setup();
+        if let Some(usart_rx_gpio) = usart_rx_gpio {
+            usart_rx_gpio.setup();
+        }
+
+        // Set the oversampling and baud rate. OVER8 is reserved on LPUART1
+        if *usart != USART::LPUART1 {
+            write_bits(
+                cr_usart_control_register,
+                usart3::cr1::OVER8,
+                (config.oversampling == UsartOversampling::By8) as u32,
+                0b1,
+            );
+        }
+        write_register(brr_usart_baud_rate_register, brr);
+
+        // Set the word length. M1 and M0 are split in two separate bits
+        let word_length = config.word_length();
+        write_bits(
+            cr_usart_control_register,
+            usart3::cr1::M0,
+            word_length & 0b1,
+            0b1,
+        );
+        write_bits(
+            cr_usart_control_register,
+            usart3::cr1::M1,
+            word_length >> 1,
+            0b1,
+        );
+
+        // Set the parity control and selection
+        match config.parity {
+            UsartParity::None => clear_bit(cr_usart_control_register, usart3::cr1::PCE),
+            UsartParity::Even => {
+                clear_bit(cr_usart_control_register, usart3::cr1::PS);
+                set_bit(cr_usart_control_register, usart3::cr1::PCE);
+            }
+            UsartParity::Odd => {
+                set_bit(cr_usart_control_register, usart3::cr1::PS);
+                set_bit(cr_usart_control_register, usart3::cr1::PCE);
+            }
+        }
 
-        // From section 48.5.7 USART baud rate generation
-        let usartdiv = clock_speed as f32 / (baud_rate << 4) as f32;
-        let mantissa = usartdiv as u32;
-        let fraction = ((usartdiv - mantissa as f32) * 16.0) as u32;
+        // Set the number of stop bits
+        write_bits(
+            cr2_usart_control_register,
+            usart3::cr2::STOP,
+            config.stop_bits as u32,
+            0b11,
+        );
 
-        // Set the baud rate
+        // Enable the FIFOs and set their thresholds
+        write_bits(
+            cr_usart_control_register,
+            usart3::cr1::FIFOEN,
+            config.fifo as u32,
+            0b1,
+        );
         write_bits(
-            brr_usart_baud_rate_register,
-            usart3::brr::BRR_4_15,
-            mantissa,
-            0xfff,
+            get_cr3_usart_control_register(usart),
+            usart3::cr3::TXFTCFG,
+            config.tx_fifo_threshold as u32,
+            0b111,
         );
         write_bits(
-            brr_usart_baud_rate_register,
-            usart3::brr::BRR_0_3,
-            fraction,
-            0xf,
+            get_cr3_usart_control_register(usart),
+            usart3::cr3::RXFTCFG,
+            config.rx_fifo_threshold as u32,
+            0b111,
         );
 
-        // Enable transmit
+        // Connect the receiver to the transmit pin when there's no receive pin
+        write_bits(
+            get_cr3_usart_control_register(usart),
+            usart3::cr3::HDSEL,
+            usart_rx_gpio.is_none() as u32,
+            0b1,
+        );
+
+        // Enable transmit and receive
         set_bit(cr_usart_control_register, usart3::cr1::TE);
+        set_bit(cr_usart_control_register, usart3::cr1::RE);
 
         // Enable usart3
         set_bit(cr_usart_control_register, usart3::cr1::UE);
     }
+
+    Ok(())
 }
 
 pub fn cleanup_usart(usart: &USART) {
-    use super::registers::{rcc, usart2};
+    use super::registers::usart2;
 
-    let apb1lenr_usart_clock_enable_field = get_apb1lenr_usart_clock_enable_field(usart);
+    let (usart_clock_enable_register, usart_clock_enable_field) = get_usart_clock_enable(usart);
     let cr_usart_control_register = get_cr_usart_control_register(usart);
 
-    unsafe {
-        // Disable the usart clock
-        clear_bit(rcc::APB1LENR, apb1lenr_usart_clock_enable_field);
+    cleanup_usart_flow_control(usart);
+    cleanup_usart_lin(usart);
+    cleanup_usart_mute_mode(usart);
+    cleanup_usart_smartcard(usart);
+    cleanup_usart_irda(usart);
+    cleanup_usart_receiver_timeout(usart);
 
-        // Disable transmit
+    unsafe {
+        // Disable transmit and receive
         clear_bit(cr_usart_control_register, usart2::cr1::TE);
+        clear_bit(cr_usart_control_register, usart2::cr1::RE);
 
-        // Disable usart2
+        // Disable the usart
         clear_bit(cr_usart_control_register, usart2::cr1::UE);
+
+        // Disable the usart clock. This is done last, as the usart registers can't be written
+        // without a clock
+        clear_bit(usart_clock_enable_register, usart_clock_enable_field);
+    }
+
+    // Release the tx and rx pins
+    let (usart_tx_gpio, usart_rx_gpio) = get_usart_gpios(usart);
+    usart_tx_gpio.cleanup();
+    if let Some(usart_rx_gpio) = usart_rx_gpio {
+        usart_rx_gpio.cleanup();
     }
 }
 
@@ -149,13 +940,20 @@ pub fn is_usart_setup(usart: &USART) -> bool {
     }
 }
 
+/// Enable the transmit interrupt. With the FIFO enabled it fires when the transmit FIFO reaches
+/// its threshold, otherwise when the data register is empty
 pub fn enable_usart_tx_interrupt(usart: &USART) {
     use super::registers::usart2;
     let cr_usart_control_register = get_cr_usart_control_register(usart);
 
     unsafe {
-        // Enable the transmit interrupt
-        set_bit(cr_usart_control_register, usart2::cr1::TXEIE);
+        if get_bit(cr_usart_control_register, usart2::cr1::FIFOEN) == 1 {
+            // Enable the transmit FIFO threshold interrupt
+            set_bit(get_cr3_usart_control_register(usart), usart2::cr3::TXFTIE);
+        } else {
+            // Enable the transmit interrupt
+            set_bit(cr_usart_control_register, usart2::cr1::TXEIE);
+        }
     }
 }
 
@@ -164,88 +962,1983 @@ pub fn disable_usart_tx_interrupt(usart: &USART) {
     let cr_usart_control_register = get_cr_usart_control_register(usart);
 
     unsafe {
-        // Disable the transmit interrupt
+        // Disable the transmit interrupts
         clear_bit(cr_usart_control_register, usart2::cr1::TXEIE);
+        clear_bit(get_cr3_usart_control_register(usart), usart2::cr3::TXFTIE);
     }
 }
 
-pub fn write_usart_character(character: char, usart: &USART) {
-    use super::registers::{usart2, usart3};
+/// Run `configure` with the usart disabled, as many control bits can only be written while UE is
+/// cleared. Waits for the last frame to leave the transmitter first, and enables the usart again
+/// afterwards if it was enabled
+fn with_usart_disabled(usart: &USART, configure: impl FnOnce()) {
+    use super::registers::usart2;
 
-    if !is_usart_setup(usart) {
-        return;
-    }
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
 
-    let isr_usart_interrupt_register = match usart {
-        USART::USART2 => usart2::ISR,
-        USART::USART3 => usart3::ISR,
-    };
+    unsafe {
+        while get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::TC) == 0
+            && get_bit(cr_usart_control_register, usart2::cr1::TE) == 1
+        {}
 
-    let tdr_usart_data_register = match usart {
-        USART::USART2 => usart2::TDR,
-        USART::USART3 => usart3::TDR,
-    };
+        let usart_enabled = get_bit(cr_usart_control_register, usart2::cr1::UE) == 1;
+        clear_bit(cr_usart_control_register, usart2::cr1::UE);
 
-    unsafe {
-        // Ensure USART TX buffer is ready
-        while get_bit(isr_usart_interrupt_register, usart2::isr::TXE) == 0 {}
+        configure();
 
-        // Write the character to the USART Data Register
-        write_register(tdr_usart_data_register, character as u32);
+        if usart_enabled {
+            set_bit(cr_usart_control_register, usart2::cr1::UE);
+        }
     }
 }
 
-pub fn write_usart_string(string: &str, usart: &USART) {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartDirection {
+    Transmit,
+    Receive,
+}
+
+/// Switch the direction of a half-duplex usart. `Transmit` turns the receiver off, so that the
+/// usart doesn't receive its own frames. `Receive` waits for the last frame to be sent before
+/// turning the receiver back on, so the line can be handed over right after writing. Returns
+/// `UsartError::NotInitialized` if the usart hasn't been setup
+pub fn set_usart_direction(direction: UsartDirection, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
     if !is_usart_setup(usart) {
-        return;
+        return Err(UsartError::NotInitialized);
     }
 
-    for character in string.chars() {
-        write_usart_character(character, usart);
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+
+    unsafe {
+        match direction {
+            UsartDirection::Transmit => clear_bit(cr_usart_control_register, usart2::cr1::RE),
+            UsartDirection::Receive => {
+                while get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::TC) == 0
+                    && get_bit(cr_usart_control_register, usart2::cr1::TE) == 1
+                {}
+                set_bit(cr_usart_control_register, usart2::cr1::RE);
+            }
+        }
     }
+
+    Ok(())
 }
 
-// USART 2
+/// Enable hardware flow control on a usart that has been setup. With clear to send, the
+/// transmitter waits for the CTS input to go low before sending each frame. With request to
+/// send, RTS is driven low while the receiver has room for a new frame. Either signal can be
+/// left out by passing `None`. Returns `UsartError::InvalidPin` if a pin can't be routed to the
+/// signal, in which case nothing is changed
+pub fn setup_usart_flow_control(
+    cts: Option<PortPin>,
+    rts: Option<PortPin>,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    use super::registers::usart2;
 
-pub fn setup_usart2(clock_speed: u32, baud_rate: u32) {
-    setup_usart(clock_speed, baud_rate, &USART::USART2);
-}
+    let (cts_table, rts_table) = get_usart_flow_pin_tables(usart);
+    let cts_gpio = match cts {
+        Some(pin) => Some(pinmap::lookup(pin, cts_table).ok_or(UsartError::InvalidPin)?),
+        None => None,
+    };
+    let rts_gpio = match rts {
+        Some(pin) => Some(pinmap::lookup(pin, rts_table).ok_or(UsartError::InvalidPin)?),
+        None => None,
+    };
 
-pub fn cleanup_usart2() {
-    cleanup_usart(&USART::USART2);
-}
+    // Release any flow control pins from an earlier call
+    cleanup_usart_flow_control(usart);
 
-pub fn is_usart2_setup() -> bool {
-    is_usart_setup(&USART::USART2)
-}
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
 
-pub fn enable_usart2_tx_interrupt() {
-    enable_usart_tx_interrupt(&USART::USART2);
-}
+    with_usart_disabled(usart, || unsafe {
+        if let Some(gpio) = cts_gpio {
+            gpio.setup();
+            set_bit(cr3_usart_control_register, usart2::cr3::CTSE);
+        }
 
-pub fn disable_usart2_tx_interrupt() {
-    disable_usart_tx_interrupt(&USART::USART2);
-}
+        if let Some(gpio) = rts_gpio {
+            gpio.setup();
+            set_bit(cr3_usart_control_register, usart2::cr3::RTSE);
+        }
+    });
 
-pub fn write_usart2_character(character: char) {
-    write_usart_character(character, &USART::USART2);
-}
+    USART_FLOW_PINS[*usart as usize].store(
+        cts.map_or(0xFF, |pin| pin.to_index()) as u16
+            | (rts.map_or(0xFF, |pin| pin.to_index()) as u16) << 8,
+        Ordering::Relaxed,
+    );
 
-pub fn write_usart2_string(string: &str) {
-    write_usart_string(string, &USART::USART2);
+    Ok(())
 }
 
-// USART 3
+/// Disable hardware flow control or RS-485 driver enable, and release the pins. Also done by
+/// `cleanup_usart`
+pub fn cleanup_usart_flow_control(usart: &USART) {
+    use super::registers::usart2;
 
-pub fn setup_usart3(clock_speed: u32, baud_rate: u32) {
-    setup_usart(clock_speed, baud_rate, &USART::USART3);
-}
+    let pins = USART_FLOW_PINS[*usart as usize].swap(NO_PINS, Ordering::Relaxed);
+    if pins == NO_PINS {
+        return;
+    }
 
-pub fn cleanup_usart3() {
-    cleanup_usart(&USART::USART3);
-}
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
 
-pub fn is_usart3_setup() -> bool {
+    with_usart_disabled(usart, || unsafe {
+        clear_bit(cr3_usart_control_register, usart2::cr3::CTSE);
+        clear_bit(cr3_usart_control_register, usart2::cr3::RTSE);
+        clear_bit(cr3_usart_control_register, usart2::cr3::DEM);
+    });
+
+    let (cts_table, rts_table) = get_usart_flow_pin_tables(usart);
+    if let Some(gpio) =
+        PortPin::from_index(pins as u8).and_then(|pin| pinmap::lookup(pin, cts_table))
+    {
+        gpio.cleanup();
+    }
+    if let Some(gpio) =
+        PortPin::from_index((pins >> 8) as u8).and_then(|pin| pinmap::lookup(pin, rts_table))
+    {
+        gpio.cleanup();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartDePolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Drive the enable input of an RS-485 transceiver from a usart that has been setup. The driver
+/// enable signal is output on a request to send pin and asserted around every transmitted frame.
+/// `assertion_time` and `deassertion_time` are the delays between the DE edge and the start of
+/// the first or the end of the last stop bit, in sample times of 1/16 or 1/8 bit depending on the
+/// oversampling, and are limited to 31. Returns `UsartError::InvalidPin` if the pin can't be
+/// routed to the request to send signal
+pub fn setup_usart_driver_enable(
+    de: PortPin,
+    polarity: UsartDePolarity,
+    assertion_time: u8,
+    deassertion_time: u8,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    let (_, rts_table) = get_usart_flow_pin_tables(usart);
+    let de_gpio = pinmap::lookup(de, rts_table).ok_or(UsartError::InvalidPin)?;
+
+    // Release any flow control pins, as DE and RTS share the same pin
+    cleanup_usart_flow_control(usart);
+
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        de_gpio.setup();
+
+        write_bits(
+            cr_usart_control_register,
+            usart2::cr1::DEAT0,
+            assertion_time.min(31) as u32,
+            0b1_1111,
+        );
+        write_bits(
+            cr_usart_control_register,
+            usart2::cr1::DEDT0,
+            deassertion_time.min(31) as u32,
+            0b1_1111,
+        );
+        write_bits(
+            cr3_usart_control_register,
+            usart2::cr3::DEP,
+            (polarity == UsartDePolarity::ActiveLow) as u32,
+            0b1,
+        );
+        set_bit(cr3_usart_control_register, usart2::cr3::DEM);
+    });
+
+    USART_FLOW_PINS[*usart as usize].store(0xFF | (de.to_index() as u16) << 8, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Setup a usart in smartcard (ISO 7816) mode, with the card data on `io` and the card clock on
+/// `ck`. The frame format is fixed to 8 data bits, even parity and 1.5 stop bits, and `io` is
+/// configured as open-drain with the internal pull-up. The default ISO 7816 rate is the card
+/// clock divided by 372. Returns `UsartError::Unsupported` on the UARTs and LPUART1
+pub fn setup_usart_smartcard(
+    baud_rate: u32,
+    smartcard: &UsartSmartcardConfig,
+    io: PortPin,
+    ck: PortPin,
+    usart: &USART,
+) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    let gtpr_usart_guard_time_register =
+        get_gtpr_usart_guard_time_register(usart).ok_or(UsartError::Unsupported)?;
+    let ck_table = get_usart_ck_pin_table(usart).ok_or(UsartError::Unsupported)?;
+    let ck_gpio = pinmap::lookup(ck, ck_table).ok_or(UsartError::InvalidPin)?;
+
+    let config = UsartConfig::new()
+        .data_bits(UsartDataBits::Eight)
+        .parity(UsartParity::Even)
+        .stop_bits(UsartStopBits::OneAndHalf);
+
+    // The card transmits and receives on the same open-drain line, like in half-duplex mode
+    setup_usart_half_duplex(baud_rate, &config, io, usart)?;
+
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    ck_gpio.setup();
+
+    with_usart_disabled(usart, || unsafe {
+        write_bits(
+            gtpr_usart_guard_time_register,
+            usart2::gtpr::GT,
+            smartcard.guard_time as u32,
+            0xFF,
+        );
+        write_bits(
+            gtpr_usart_guard_time_register,
+            usart2::gtpr::PSC,
+            smartcard.clock_prescaler.clamp(1, 31) as u32,
+            0xFF,
+        );
+
+        // The receiver is connected to the transmit line internally, and HDSEL must be cleared
+        clear_bit(cr3_usart_control_register, usart2::cr3::HDSEL);
+        write_bits(
+            cr3_usart_control_register,
+            usart2::cr3::NACK,
+            smartcard.nack as u32,
+            0b1,
+        );
+        set_bit(cr3_usart_control_register, usart2::cr3::SCEN);
+
+        // Output the card clock
+        set_bit(cr2_usart_control_register, usart2::cr2::CLKEN);
+    });
+
+    USART_CK_PINS[*usart as usize].store(ck.to_index(), Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Leave smartcard mode and release the clock pin. Also done by `cleanup_usart`
+pub fn cleanup_usart_smartcard(usart: &USART) {
+    use super::registers::usart2;
+
+    let pin = USART_CK_PINS[*usart as usize].swap(0xFF, Ordering::Relaxed);
+    let (Some(ck_table), Some(pin)) = (get_usart_ck_pin_table(usart), PortPin::from_index(pin))
+    else {
+        return;
+    };
+
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        clear_bit(cr3_usart_control_register, usart2::cr3::SCEN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::NACK);
+        clear_bit(cr2_usart_control_register, usart2::cr2::CLKEN);
+    });
+
+    if let Some(gpio) = pinmap::lookup(pin, ck_table) {
+        gpio.cleanup();
+    }
+}
+
+/// Nominal low-power IrDA pulse clock
+const IRDA_LOW_POWER_FREQUENCY: u32 = 1_843_200;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartIrdaMode {
+    /// Pulses of 3/16 bit time
+    Normal,
+    /// Pulses of three periods of a 1.8432 MHz clock divided from the kernel clock, independent
+    /// of the baud rate
+    LowPower,
+}
+
+/// Put a usart that has been setup with one stop bit into IrDA SIR mode, to drive an infrared
+/// transceiver from its transmit and receive pins. The SIR baud rate is limited to 115200. IrDA
+/// isn't available on LPUART1, in which case `UsartError::Unsupported` is returned
+pub fn setup_usart_irda(mode: UsartIrdaMode, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    let gtpr_usart_guard_time_register =
+        get_gtpr_usart_guard_time_register(usart).ok_or(UsartError::Unsupported)?;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    // The prescaler must be one in normal mode, and divides the kernel clock down to the pulse
+    // clock in low-power mode
+    let prescaler = match mode {
+        UsartIrdaMode::Normal => 1,
+        UsartIrdaMode::LowPower => {
+            let clock = get_usart_kernel_clock(usart).ok_or(UsartError::UnknownClock)?;
+            (clock + IRDA_LOW_POWER_FREQUENCY / 2) / IRDA_LOW_POWER_FREQUENCY
+        }
+    };
+
+    if !(1..=0xFF).contains(&prescaler) {
+        return Err(UsartError::InvalidBaudRate);
+    }
+
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        // IrDA requires one stop bit, no clock output and none of the LIN, smartcard and
+        // half-duplex modes
+        write_bits(cr2_usart_control_register, usart2::cr2::STOP, 0b00, 0b11);
+        clear_bit(cr2_usart_control_register, usart2::cr2::CLKEN);
+        clear_bit(cr2_usart_control_register, usart2::cr2::LINEN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::SCEN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::HDSEL);
+
+        write_bits(
+            gtpr_usart_guard_time_register,
+            usart2::gtpr::PSC,
+            prescaler,
+            0xFF,
+        );
+        write_bits(
+            cr3_usart_control_register,
+            usart2::cr3::IRLP,
+            (mode == UsartIrdaMode::LowPower) as u32,
+            0b1,
+        );
+        set_bit(cr3_usart_control_register, usart2::cr3::IREN);
+    });
+
+    Ok(())
+}
+
+/// Leave IrDA mode. Also done by `cleanup_usart`
+pub fn cleanup_usart_irda(usart: &USART) {
+    use super::registers::usart2;
+
+    if *usart == USART::LPUART1 {
+        return;
+    }
+
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        clear_bit(cr3_usart_control_register, usart2::cr3::IREN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::IRLP);
+    });
+}
+
+/// Length of a break that the receiver detects in LIN mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinBreakLength {
+    TenBits,
+    ElevenBits,
+}
+
+/// Put a usart that has been setup with 8N1 framing into LIN mode. In LIN mode `send_usart_break`
+/// sends a 13 bit break and the receiver detects breaks of `break_length`, which sets a flag that
+/// is read with `take_usart_break`. LIN isn't available on LPUART1, in which case
+/// `UsartError::Unsupported` is returned
+pub fn setup_usart_lin(break_length: LinBreakLength, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if *usart == USART::LPUART1 {
+        return Err(UsartError::Unsupported);
+    }
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        // LIN mode requires one stop bit, no clock output and none of the smartcard, half-duplex
+        // and IrDA modes, see USART LIN (local interconnection network) mode in section 48.5
+        write_bits(cr2_usart_control_register, usart2::cr2::STOP, 0b00, 0b11);
+        clear_bit(cr2_usart_control_register, usart2::cr2::CLKEN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::SCEN);
+        clear_bit(cr3_usart_control_register, usart2::cr3::HDSEL);
+        clear_bit(cr3_usart_control_register, usart2::cr3::IREN);
+
+        write_bits(
+            cr2_usart_control_register,
+            usart2::cr2::LBDL,
+            (break_length == LinBreakLength::ElevenBits) as u32,
+            0b1,
+        );
+        set_bit(cr2_usart_control_register, usart2::cr2::LINEN);
+    });
+
+    Ok(())
+}
+
+/// Leave LIN mode and disable the break detection interrupt
+pub fn cleanup_usart_lin(usart: &USART) {
+    use super::registers::usart2;
+
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        clear_bit(cr2_usart_control_register, usart2::cr2::LBDIE);
+        clear_bit(cr2_usart_control_register, usart2::cr2::LINEN);
+    });
+}
+
+/// How a muted receiver wakes up again, see USART multiprocessor communication in section 48.5
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UsartMuteWakeup {
+    /// Wake up when the line has been idle for a frame
+    IdleLine,
+    /// Wake up on an address mark frame carrying this 4-bit address
+    Address4(u8),
+    /// Wake up on an address mark frame carrying this 7-bit address. Requires 8 or 9 data bits
+    Address7(u8),
+}
+
+/// Enable mute mode for multidrop buses. While muted, the receiver ignores all frames until the
+/// wakeup condition is met, so a node only sees the frames addressed to it. An address mark is
+/// a frame with its most significant data bit set, e.g. bit 8 with 9 data bits. Use
+/// `enter_usart_mute_mode` to mute the receiver
+pub fn setup_usart_mute_mode(wakeup: UsartMuteWakeup, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+    let cr2_usart_control_register = get_cr2_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        match wakeup {
+            UsartMuteWakeup::IdleLine => {
+                clear_bit(cr_usart_control_register, usart2::cr1::WAKE);
+            }
+            UsartMuteWakeup::Address4(address) => {
+                write_bits(
+                    cr2_usart_control_register,
+                    usart2::cr2::ADD0_3,
+                    address as u32,
+                    0b1111,
+                );
+                clear_bit(cr2_usart_control_register, usart2::cr2::ADDM7);
+                set_bit(cr_usart_control_register, usart2::cr1::WAKE);
+            }
+            UsartMuteWakeup::Address7(address) => {
+                write_bits(
+                    cr2_usart_control_register,
+                    usart2::cr2::ADD0_3,
+                    address as u32,
+                    0b111_1111,
+                );
+                set_bit(cr2_usart_control_register, usart2::cr2::ADDM7);
+                set_bit(cr_usart_control_register, usart2::cr1::WAKE);
+            }
+        }
+
+        set_bit(cr_usart_control_register, usart2::cr1::MME);
+    });
+
+    Ok(())
+}
+
+pub fn cleanup_usart_mute_mode(usart: &USART) {
+    use super::registers::usart2;
+
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+
+    with_usart_disabled(usart, || unsafe {
+        clear_bit(cr_usart_control_register, usart2::cr1::MME);
+        clear_bit(cr_usart_control_register, usart2::cr1::WAKE);
+    });
+}
+
+/// Mute the receiver until the wakeup condition given to `setup_usart_mute_mode` is met
+pub fn enter_usart_mute_mode(usart: &USART) {
+    use super::registers::usart2;
+
+    unsafe {
+        write_register(
+            get_rqr_usart_request_register(usart),
+            1 << usart2::rqr::MMRQ,
+        );
+    }
+}
+
+pub fn is_usart_muted(usart: &USART) -> bool {
+    use super::registers::usart2;
+    unsafe { get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::RWU) == 1 }
+}
+
+/// Request a break, which is sent after the frame currently being transmitted. Outside of LIN mode
+/// the break lasts one frame plus the stop bits. A frame written right after the request is sent
+/// once the break is done, separated from it by the stop bits, which is the mark after break of
+/// e.g. DMX512
+pub fn send_usart_break(usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    // The request register is write only, so no read-modify-write is done
+    unsafe {
+        write_register(
+            get_rqr_usart_request_register(usart),
+            1 << usart2::rqr::SBKRQ,
+        );
+    }
+
+    Ok(())
+}
+
+/// Check if a requested break is still waiting to be sent
+pub fn is_usart_break_pending(usart: &USART) -> bool {
+    use super::registers::usart2;
+    unsafe { get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::SBKF) == 1 }
+}
+
+/// Generate a usart interrupt when a LIN break is detected. The interrupt handler should call
+/// `take_usart_break` to clear the flag
+pub fn enable_usart_break_interrupt(usart: &USART) {
+    use super::registers::usart2;
+
+    unsafe {
+        set_bit(get_cr2_usart_control_register(usart), usart2::cr2::LBDIE);
+    }
+
+    enable_interrupt(get_nvic_interrupt_id(usart));
+}
+
+pub fn disable_usart_break_interrupt(usart: &USART) {
+    use super::registers::usart2;
+
+    unsafe {
+        clear_bit(get_cr2_usart_control_register(usart), usart2::cr2::LBDIE);
+    }
+}
+
+/// Check if a break has been detected since the last call, and clear the flag. Breaks are only
+/// detected in LIN mode, see `setup_usart_lin`
+pub fn take_usart_break(usart: &USART) -> bool {
+    use super::registers::usart2;
+
+    unsafe {
+        if get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::LBDF) == 0 {
+            return false;
+        }
+
+        write_register(
+            get_icr_usart_interrupt_clear_register(usart),
+            1 << usart2::icr::LBDCF,
+        );
+    }
+
+    true
+}
+
+/// Consider a received frame complete after `bit_times` bit durations of silence on the receive
+/// line, such as the 3.5 character gap that ends a Modbus RTU frame. The timeout interrupt is
+/// enabled, and should be handled with `handle_usart_receiver_timeout`. The timeout is limited to
+/// 2^24 - 1 bit times. Returns `UsartError::Unsupported` on LPUART1, which has no receiver
+/// timeout
+pub fn setup_usart_receiver_timeout(bit_times: u32, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    let rtor_usart_receiver_timeout_register =
+        get_rtor_usart_receiver_timeout_register(usart).ok_or(UsartError::Unsupported)?;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    unsafe {
+        write_bits(
+            rtor_usart_receiver_timeout_register,
+            usart2::rtor::RTO,
+            bit_times.min(0xFF_FFFF),
+            0xFF_FFFF,
+        );
+
+        write_register(
+            get_icr_usart_interrupt_clear_register(usart),
+            1 << usart2::icr::RTOCF,
+        );
+        set_bit(get_cr2_usart_control_register(usart), usart2::cr2::RTOEN);
+        set_bit(get_cr_usart_control_register(usart), usart2::cr1::RTOIE);
+    }
+
+    enable_interrupt(get_nvic_interrupt_id(usart));
+
+    Ok(())
+}
+
+pub fn cleanup_usart_receiver_timeout(usart: &USART) {
+    use super::registers::usart2;
+
+    unsafe {
+        clear_bit(get_cr_usart_control_register(usart), usart2::cr1::RTOIE);
+        clear_bit(get_cr2_usart_control_register(usart), usart2::cr2::RTOEN);
+    }
+
+    unregister_usart_receiver_timeout_callback(usart);
+}
+
+/// Register a callback that is run by `handle_usart_receiver_timeout` when a frame is complete
+pub fn register_usart_receiver_timeout_callback(callback: fn(), usart: &USART) {
+    USART_TIMEOUT_CALLBACKS[*usart as usize].store(callback as usize, Ordering::Release);
+}
+
+pub fn unregister_usart_receiver_timeout_callback(usart: &USART) {
+    USART_TIMEOUT_CALLBACKS[*usart as usize].store(0, Ordering::Release);
+}
+
+/// Check and clear the receiver timeout flag, and run the registered callback if it was set.
+/// Call this from the usart interrupt handler after the received data has been read. Returns
+/// true if the receiver timed out
+pub fn handle_usart_receiver_timeout(usart: &USART) -> bool {
+    use super::registers::usart2;
+
+    unsafe {
+        if get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::RTOF) == 0 {
+            return false;
+        }
+
+        write_register(
+            get_icr_usart_interrupt_clear_register(usart),
+            1 << usart2::icr::RTOCF,
+        );
+    }
+
+    let callback = USART_TIMEOUT_CALLBACKS[*usart as usize].load(Ordering::Acquire);
+    if callback != 0 {
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+
+    true
+}
+
+/// Check the receive error flags of a usart and clear them. Returns the first error found, in
+/// the order parity, framing, noise and overrun. Can be called from the interrupt handler or
+/// polled
+pub fn check_usart_errors(usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    let flags = unsafe { read_register(get_isr_usart_interrupt_register(usart)) }
+        & (1 << usart2::isr::PE
+            | 1 << usart2::isr::FE
+            | 1 << usart2::isr::NF
+            | 1 << usart2::isr::ORE);
+
+    if flags == 0 {
+        return Ok(());
+    }
+
+    // The flags are cleared by writing a one to the same bit positions in the clear register
+    unsafe {
+        write_register(get_icr_usart_interrupt_clear_register(usart), flags);
+    }
+
+    if flags & (1 << usart2::isr::PE) != 0 {
+        Err(UsartError::Parity)
+    } else if flags & (1 << usart2::isr::FE) != 0 {
+        Err(UsartError::Framing)
+    } else if flags & (1 << usart2::isr::NF) != 0 {
+        Err(UsartError::Noise)
+    } else {
+        Err(UsartError::Overrun)
+    }
+}
+
+/// Block until the transmitter is ready and write a character. Returns
+/// `UsartError::NotInitialized` if the usart isn't setup
+pub fn write_usart_character(character: char, usart: &USART) -> Result<(), UsartError> {
+    write_usart_u16(character as u32 as u16, usart)
+}
+
+/// Block until the transmitter is ready and write a data word. With 9 data bits all of bit 0 to
+/// 8 are sent, where bit 8 is the address mark in multidrop protocols. Returns
+/// `UsartError::NotInitialized` if the usart isn't setup
+pub fn write_usart_u16(word: u16, usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    let isr_usart_interrupt_register = get_isr_usart_interrupt_register(usart);
+    let tdr_usart_data_register = get_tdr_usart_data_register(usart);
+
+    unsafe {
+        // Ensure USART TX buffer is ready
+        while get_bit(isr_usart_interrupt_register, usart2::isr::TXE) == 0 {}
+
+        // Write the word to the USART Data Register
+        write_register(tdr_usart_data_register, word as u32 & 0x1FF);
+    }
+
+    Ok(())
+}
+
+/// Block until a byte is received. The byte is dropped and an error returned if it was received
+/// with a parity, framing or noise error, or if data was lost to an overrun. Returns
+/// `UsartError::NotInitialized` if the usart isn't setup
+pub fn read_usart_byte(usart: &USART) -> Result<u8, UsartError> {
+    read_usart_u16(usart).map(|value| value as u8)
+}
+
+/// Block until a data word is received, with the parity bit masked out. With 9 data bits bit 8
+/// holds the address mark. Errors are handled as in `read_usart_byte`
+pub fn read_usart_u16(usart: &USART) -> Result<u16, UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    let isr_usart_interrupt_register = get_isr_usart_interrupt_register(usart);
+    let rdr_usart_data_register = get_rdr_usart_data_register(usart);
+
+    unsafe {
+        // Wait for the USART RX buffer to be filled. An overrun blocks further reception until
+        // the flag is cleared, so it's reported right away
+        while get_bit(isr_usart_interrupt_register, usart2::isr::RXNE) == 0 {
+            if get_bit(isr_usart_interrupt_register, usart2::isr::ORE) == 1 {
+                return check_usart_errors(usart).map(|_| 0);
+            }
+        }
+
+        // Reading the USART Data Register clears the RXNE flag
+        let value = (read_register(rdr_usart_data_register) & get_rdr_data_mask(usart)) as u16;
+
+        check_usart_errors(usart).map(|_| value)
+    }
+}
+
+/// Block until a character is received
+pub fn read_usart_character(usart: &USART) -> Result<char, UsartError> {
+    read_usart_byte(usart).map(|value| value as char)
+}
+
+/// Block until `buffer` is filled with received bytes. Stops at the first receive error, in
+/// which case the content of `buffer` is incomplete
+pub fn read_usart_bytes(buffer: &mut [u8], usart: &USART) -> Result<(), UsartError> {
+    for byte in buffer.iter_mut() {
+        *byte = read_usart_byte(usart)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_usart_string(string: &str, usart: &USART) -> Result<(), UsartError> {
+    for character in string.chars() {
+        write_usart_character(character, usart)?;
+    }
+
+    Ok(())
+}
+
+/// Write a character if the transmitter is ready, otherwise return `nb::Error::WouldBlock`
+/// right away. Lets the usart be polled from a cooperative scheduler without spinning
+pub fn try_write_usart_character(character: char, usart: &USART) -> nb::Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(nb::Error::Other(UsartError::NotInitialized));
+    }
+
+    unsafe {
+        if get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::TXE) == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        write_register(get_tdr_usart_data_register(usart), character as u32 & 0x1FF);
+    }
+
+    Ok(())
+}
+
+/// Read a byte if one has been received, otherwise return `nb::Error::WouldBlock` right away.
+/// Errors are handled as in `read_usart_byte`
+pub fn try_read_usart_byte(usart: &USART) -> nb::Result<u8, UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(nb::Error::Other(UsartError::NotInitialized));
+    }
+
+    let isr_usart_interrupt_register = get_isr_usart_interrupt_register(usart);
+
+    unsafe {
+        if get_bit(isr_usart_interrupt_register, usart2::isr::RXNE) == 0 {
+            // An overrun blocks further reception, so it's reported even without data
+            if get_bit(isr_usart_interrupt_register, usart2::isr::ORE) == 1 {
+                check_usart_errors(usart)?;
+            }
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let value =
+            (read_register(get_rdr_usart_data_register(usart)) & get_rdr_data_mask(usart)) as u8;
+
+        check_usart_errors(usart)?;
+        Ok(value)
+    }
+}
+
+pub fn try_read_usart_character(usart: &USART) -> nb::Result<char, UsartError> {
+    try_read_usart_byte(usart).map(|value| value as char)
+}
+
+/// Print the registers of a usart, see `register_tools::dump`. The receive and transmit data
+/// registers are left out, as reading RDR pops a received word. The usart clock has to be
+/// enabled, otherwise all registers read as zero
+pub fn dump_usart(usart: &USART, writer: &mut impl fmt::Write) -> fmt::Result {
+    let block = get_usart_block(usart);
+
+    unsafe {
+        dump(
+            &[
+                ("CR1", &raw const (*block).cr1),
+                ("CR2", &raw const (*block).cr2),
+                ("CR3", &raw const (*block).cr3),
+                ("BRR", &raw const (*block).brr),
+                ("GTPR", &raw const (*block).gtpr),
+                ("RTOR", &raw const (*block).rtor),
+                ("ISR", &raw const (*block).isr),
+                ("PRESC", &raw const (*block).presc),
+            ],
+            writer,
+        )
+    }
+}
+
+/// Pattern sent by `self_test_usart`, toggling every bit and covering all zeros and all ones
+const SELF_TEST_PATTERN: [u8; 4] = [0x55, 0xAA, 0x00, 0xFF];
+
+/// Check that a usart can transmit and receive, e.g. in a power-on self test. The transmitter is
+/// looped back to the receiver internally by temporarily selecting half-duplex mode, so no
+/// external wiring is needed, but the pattern is also driven onto the transmit pin. Returns
+/// `UsartError::SelfTestFailed` if the pattern isn't received back in time, or the receive error
+/// if one occurs. Use 8 or 9 data bits, as the pattern doesn't fit in 7
+pub fn self_test_usart(usart: &USART) -> Result<(), UsartError> {
+    use super::registers::usart2;
+
+    if !is_usart_setup(usart) {
+        return Err(UsartError::NotInitialized);
+    }
+
+    let cr_usart_control_register = get_cr_usart_control_register(usart);
+    let cr3_usart_control_register = get_cr3_usart_control_register(usart);
+
+    let (half_duplex, receiver_enabled) = unsafe {
+        (
+            get_bit(cr3_usart_control_register, usart2::cr3::HDSEL) == 1,
+            get_bit(cr_usart_control_register, usart2::cr1::RE) == 1,
+        )
+    };
+
+    with_usart_disabled(usart, || unsafe {
+        set_bit(cr3_usart_control_register, usart2::cr3::HDSEL);
+        set_bit(cr_usart_control_register, usart2::cr1::RE);
+    });
+
+    // Drop anything received before the test
+    while !matches!(try_read_usart_byte(usart), Err(nb::Error::WouldBlock)) {}
+
+    // A bit takes at most BRR kernel clock cycles, so a frame of at most 12 bits takes 12 * BRR
+    // cycles. Polling sixteen times that leaves a wide margin even if the loop runs slower than
+    // the kernel clock
+    let timeout = unsafe { read_register(get_brr_usart_baud_rate_register(usart)) } * 12 * 16;
+
+    let result = SELF_TEST_PATTERN.iter().try_for_each(|&expected| {
+        write_usart_character(expected as char, usart)?;
+
+        for _ in 0..timeout {
+            match try_read_usart_byte(usart) {
+                Ok(value) if value == expected => return Ok(()),
+                Ok(_) => return Err(UsartError::SelfTestFailed),
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(error)) => return Err(error),
+            }
+        }
+
+        Err(UsartError::SelfTestFailed)
+    });
+
+    // Restore the previous mode
+    with_usart_disabled(usart, || unsafe {
+        write_bits(
+            cr3_usart_control_register,
+            usart2::cr3::HDSEL,
+            half_duplex as u32,
+            0b1,
+        );
+        write_bits(
+            cr_usart_control_register,
+            usart2::cr1::RE,
+            receiver_enabled as u32,
+            0b1,
+        );
+    });
+
+    result
+}
+
+/// Blocking writer implementing `core::fmt::Write`, so that formatted text can be written
+/// directly to a usart
+///
+/// ```ignore
+/// let mut usart2 = UsartWriter::new(USART::USART2);
+/// writeln!(usart2, "temp={}", t).ok();
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UsartWriter {
+    usart: USART,
+}
+
+impl UsartWriter {
+    pub const fn new(usart: USART) -> Self {
+        Self { usart }
+    }
+}
+
+impl fmt::Write for UsartWriter {
+    /// Write the string byte by byte. Fails if the usart isn't setup
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for byte in string.bytes() {
+            write_usart_character(byte as char, &self.usart).map_err(|_| fmt::Error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write formatted text to a usart, e.g. `uprint!(USART::USART2, "temp={}", t)`. Nothing is
+/// written if the usart isn't setup
+#[macro_export]
+macro_rules! uprint {
+    ($usart:expr, $($arg:tt)*) => {{
+        let _ = core::fmt::Write::write_fmt(
+            &mut $crate::usart::UsartWriter::new($usart),
+            format_args!($($arg)*),
+        );
+    }};
+}
+
+/// Write formatted text followed by `\r\n` to a usart, e.g.
+/// `uprintln!(USART::USART2, "temp={}", t)`
+#[macro_export]
+macro_rules! uprintln {
+    ($usart:expr) => {
+        $crate::uprint!($usart, "\r\n")
+    };
+    ($usart:expr, $($arg:tt)*) => {{
+        $crate::uprint!($usart, $($arg)*);
+        $crate::uprint!($usart, "\r\n");
+    }};
+}
+
+/// Name of a usart instance when used with the `Usart` handle
+pub type UsartInstance = USART;
+
+/// Bitmask of the usarts currently owned by a `Usart` handle
+static USART_HANDLES: AtomicU16 = AtomicU16::new(0);
+
+/// Owned handle to a setup usart. Only one handle can exist for each usart at a time, so the
+/// handle can be passed around without other code reconfiguring the usart under it
+///
+/// ```ignore
+/// let mut usart = Usart::setup(UsartInstance::USART3, 115_200, &UsartConfig::new())?;
+/// usart.write(b"hello\r\n")?;
+/// ```
+#[derive(Debug)]
+pub struct Usart {
+    usart: UsartInstance,
+    baud_rate: u32,
+    config: UsartConfig,
+}
+
+impl Usart {
+    /// Setup a usart on its default pins and take ownership of it. Returns `UsartError::InUse`
+    /// if a handle to the usart already exists
+    pub fn setup(
+        usart: UsartInstance,
+        baud_rate: u32,
+        config: &UsartConfig,
+    ) -> Result<Self, UsartError> {
+        Self::take(usart, baud_rate, config, |usart| {
+            setup_usart(baud_rate, config, usart)
+        })
+    }
+
+    /// Setup a usart on the given pins and take ownership of it, see `setup_usart_with_pins`
+    pub fn setup_with_pins(
+        usart: UsartInstance,
+        baud_rate: u32,
+        config: &UsartConfig,
+        tx: PortPin,
+        rx: PortPin,
+    ) -> Result<Self, UsartError> {
+        Self::take(usart, baud_rate, config, |usart| {
+            setup_usart_with_pins(baud_rate, config, tx, rx, usart)
+        })
+    }
+
+    /// Mark the usart as owned and run `setup`, releasing it again if the setup fails
+    fn take(
+        usart: UsartInstance,
+        baud_rate: u32,
+        config: &UsartConfig,
+        setup: impl FnOnce(&USART) -> Result<(), UsartError>,
+    ) -> Result<Self, UsartError> {
+        let mask = 1 << usart as u16;
+        if USART_HANDLES.fetch_or(mask, Ordering::AcqRel) & mask != 0 {
+            return Err(UsartError::InUse);
+        }
+
+        if let Err(error) = setup(&usart) {
+            USART_HANDLES.fetch_and(!mask, Ordering::AcqRel);
+            return Err(error);
+        }
+
+        Ok(Self {
+            usart,
+            baud_rate,
+            config: *config,
+        })
+    }
+
+    /// Turn off the usart and release its pins and ownership
+    pub fn cleanup(self) {
+        cleanup_usart(&self.usart);
+        USART_HANDLES.fetch_and(!(1 << self.usart as u16), Ordering::AcqRel);
+    }
+
+    pub fn instance(&self) -> UsartInstance {
+        self.usart
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    pub fn config(&self) -> &UsartConfig {
+        &self.config
+    }
+
+    /// Block until all bytes have been written to the transmitter
+    pub fn write(&mut self, data: &[u8]) -> Result<(), UsartError> {
+        for byte in data {
+            write_usart_character(*byte as char, &self.usart)?;
+        }
+
+        Ok(())
+    }
+
+    /// Block until `buffer` is filled with received bytes, see `read_usart_bytes`
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), UsartError> {
+        read_usart_bytes(buffer, &self.usart)
+    }
+
+    /// Block until a byte is received, see `read_usart_byte`
+    pub fn read_byte(&mut self) -> Result<u8, UsartError> {
+        read_usart_byte(&self.usart)
+    }
+
+    /// Write a character without blocking, see `try_write_usart_character`
+    pub fn try_write_character(&mut self, character: char) -> nb::Result<(), UsartError> {
+        try_write_usart_character(character, &self.usart)
+    }
+
+    /// Read a character without blocking, see `try_read_usart_character`
+    pub fn try_read_character(&mut self) -> nb::Result<char, UsartError> {
+        try_read_usart_character(&self.usart)
+    }
+
+    /// Write a 9-bit data word, see `write_usart_u16`
+    pub fn write_u16(&mut self, word: u16) -> Result<(), UsartError> {
+        write_usart_u16(word, &self.usart)
+    }
+
+    /// Block until a 9-bit data word is received, see `read_usart_u16`
+    pub fn read_u16(&mut self) -> Result<u16, UsartError> {
+        read_usart_u16(&self.usart)
+    }
+
+    pub fn check_errors(&mut self) -> Result<(), UsartError> {
+        check_usart_errors(&self.usart)
+    }
+
+    pub fn enable_tx_interrupt(&mut self) {
+        enable_usart_tx_interrupt(&self.usart);
+    }
+
+    pub fn disable_tx_interrupt(&mut self) {
+        disable_usart_tx_interrupt(&self.usart);
+    }
+
+    pub fn set_direction(&mut self, direction: UsartDirection) -> Result<(), UsartError> {
+        set_usart_direction(direction, &self.usart)
+    }
+
+    pub fn setup_flow_control(
+        &mut self,
+        cts: Option<PortPin>,
+        rts: Option<PortPin>,
+    ) -> Result<(), UsartError> {
+        setup_usart_flow_control(cts, rts, &self.usart)
+    }
+
+    pub fn setup_receiver_timeout(&mut self, bit_times: u32) -> Result<(), UsartError> {
+        setup_usart_receiver_timeout(bit_times, &self.usart)
+    }
+
+    pub fn send_break(&mut self) -> Result<(), UsartError> {
+        send_usart_break(&self.usart)
+    }
+
+    /// Loop the transmitter back to the receiver and check a test pattern, see
+    /// `self_test_usart`
+    pub fn self_test(&mut self) -> Result<(), UsartError> {
+        self_test_usart(&self.usart)
+    }
+
+    /// Check and clear the break detection flag, see `take_usart_break`
+    pub fn take_break(&mut self) -> bool {
+        take_usart_break(&self.usart)
+    }
+}
+
+impl fmt::Write for Usart {
+    /// Write the string byte by byte
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.write(string.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-hal-nb"))]
+fn is_usart_transmission_complete(usart: &USART) -> bool {
+    use super::registers::usart2;
+    unsafe { get_bit(get_isr_usart_interrupt_register(usart), usart2::isr::TC) == 1 }
+}
+
+#[cfg(feature = "embedded-io")]
+mod io {
+    use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write, WriteReady};
+
+    use super::{
+        Usart, UsartError, get_isr_usart_interrupt_register, is_usart_transmission_complete,
+        try_read_usart_byte, try_write_usart_character,
+    };
+    use crate::{register_tools::get_bit, registers::usart2};
+
+    impl embedded_io::Error for UsartError {
+        fn kind(&self) -> ErrorKind {
+            match self {
+                UsartError::Framing | UsartError::Noise | UsartError::Parity => {
+                    ErrorKind::InvalidData
+                }
+                UsartError::InvalidPin | UsartError::InvalidBaudRate | UsartError::Unsupported => {
+                    ErrorKind::InvalidInput
+                }
+                UsartError::NotInitialized => ErrorKind::NotConnected,
+                UsartError::InUse => ErrorKind::AddrInUse,
+                UsartError::Overrun | UsartError::UnknownClock | UsartError::SelfTestFailed => {
+                    ErrorKind::Other
+                }
+            }
+        }
+    }
+
+    impl ErrorType for Usart {
+        type Error = UsartError;
+    }
+
+    impl Read for Usart {
+        /// Block until at least one byte is received, then read the bytes that are available
+        /// without blocking further
+        fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UsartError> {
+            let Some((first, rest)) = buffer.split_first_mut() else {
+                return Ok(0);
+            };
+
+            *first = self.read_byte()?;
+
+            let mut count = 1;
+            for byte in rest {
+                match try_read_usart_byte(&self.usart) {
+                    Ok(value) => *byte = value,
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(error)) => return Err(error),
+                }
+                count += 1;
+            }
+
+            Ok(count)
+        }
+    }
+
+    impl ReadReady for Usart {
+        fn read_ready(&mut self) -> Result<bool, UsartError> {
+            let isr_usart_interrupt_register = get_isr_usart_interrupt_register(&self.usart);
+            Ok(unsafe { get_bit(isr_usart_interrupt_register, usart2::isr::RXNE) } == 1)
+        }
+    }
+
+    impl Write for Usart {
+        /// Block until the first byte is written, then write the bytes the transmitter accepts
+        /// without blocking further
+        fn write(&mut self, data: &[u8]) -> Result<usize, UsartError> {
+            let Some((first, rest)) = data.split_first() else {
+                return Ok(0);
+            };
+
+            Usart::write(self, &[*first])?;
+
+            let mut count = 1;
+            for byte in rest {
+                if try_write_usart_character(*byte as char, &self.usart).is_err() {
+                    break;
+                }
+                count += 1;
+            }
+
+            Ok(count)
+        }
+
+        /// Block until the last frame has left the transmitter
+        fn flush(&mut self) -> Result<(), UsartError> {
+            while !is_usart_transmission_complete(&self.usart) {}
+            Ok(())
+        }
+    }
+
+    impl WriteReady for Usart {
+        fn write_ready(&mut self) -> Result<bool, UsartError> {
+            let isr_usart_interrupt_register = get_isr_usart_interrupt_register(&self.usart);
+            Ok(unsafe { get_bit(isr_usart_interrupt_register, usart2::isr::TXE) } == 1)
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+mod serial {
+    use embedded_hal_nb::serial::{ErrorKind, ErrorType, Read, Write};
+
+    use super::{
+        Usart, UsartError, is_usart_transmission_complete, try_read_usart_byte,
+        try_write_usart_character,
+    };
+
+    impl embedded_hal_nb::serial::Error for UsartError {
+        fn kind(&self) -> ErrorKind {
+            match self {
+                UsartError::Overrun => ErrorKind::Overrun,
+                UsartError::Framing => ErrorKind::FrameFormat,
+                UsartError::Parity => ErrorKind::Parity,
+                UsartError::Noise => ErrorKind::Noise,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    impl ErrorType for Usart {
+        type Error = UsartError;
+    }
+
+    impl Read for Usart {
+        fn read(&mut self) -> nb::Result<u8, UsartError> {
+            try_read_usart_byte(&self.usart)
+        }
+    }
+
+    impl Write for Usart {
+        fn write(&mut self, byte: u8) -> nb::Result<(), UsartError> {
+            try_write_usart_character(byte as char, &self.usart)
+        }
+
+        fn flush(&mut self) -> nb::Result<(), UsartError> {
+            if is_usart_transmission_complete(&self.usart) {
+                Ok(())
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+}
+
+const NO_ERROR: u8 = 0;
+
+/// Store a receive error in an atomic, with `NO_ERROR` meaning that no error has occurred
+fn encode_usart_error(error: UsartError) -> u8 {
+    match error {
+        UsartError::Overrun => 1,
+        UsartError::Framing => 2,
+        UsartError::Noise => 3,
+        UsartError::Parity => 4,
+        _ => NO_ERROR,
+    }
+}
+
+fn decode_usart_error(value: u8) -> Option<UsartError> {
+    match value {
+        1 => Some(UsartError::Overrun),
+        2 => Some(UsartError::Framing),
+        3 => Some(UsartError::Noise),
+        4 => Some(UsartError::Parity),
+        _ => None,
+    }
+}
+
+/// An interrupt driven usart with software ring buffers for transmit and receive. Place it in a
+/// `static` and call `handle_interrupt` from the interrupt handler of the usart, e.g.
+///
+/// ```ignore
+/// static SERIAL: BufferedUsart<256, 256> = BufferedUsart::new(USART::USART2);
+///
+/// #[interrupt]
+/// fn USART2() {
+///     SERIAL.handle_interrupt();
+/// }
+/// ```
+pub struct BufferedUsart<const TX: usize, const RX: usize> {
+    usart: USART,
+    tx: RingBuffer<TX>,
+    rx: RingBuffer<RX>,
+    /// Last receive error seen by the interrupt handler, see `encode_usart_error`
+    error: AtomicU8,
+}
+
+impl<const TX: usize, const RX: usize> BufferedUsart<TX, RX> {
+    pub const fn new(usart: USART) -> Self {
+        Self {
+            usart,
+            tx: RingBuffer::new(),
+            rx: RingBuffer::new(),
+            error: AtomicU8::new(NO_ERROR),
+        }
+    }
+
+    /// Setup the usart with 8N1 framing and enable the receive interrupt
+    pub fn setup(&self, baud_rate: u32) -> Result<(), UsartError> {
+        self.setup_with_config(baud_rate, &UsartConfig::new())
+    }
+
+    /// Setup the usart with the given frame format and enable the receive interrupt
+    pub fn setup_with_config(
+        &self,
+        baud_rate: u32,
+        config: &UsartConfig,
+    ) -> Result<(), UsartError> {
+        use super::registers::usart2;
+
+        setup_usart(baud_rate, config, &self.usart)?;
+
+        unsafe {
+            set_bit(
+                get_cr_usart_control_register(&self.usart),
+                usart2::cr1::RXNEIE,
+            );
+        }
+
+        enable_interrupt(get_nvic_interrupt_id(&self.usart));
+
+        Ok(())
+    }
+
+    pub fn cleanup(&self) {
+        use super::registers::usart2;
+
+        disable_interrupt(get_nvic_interrupt_id(&self.usart));
+
+        unsafe {
+            clear_bit(
+                get_cr_usart_control_register(&self.usart),
+                usart2::cr1::RXNEIE,
+            );
+        }
+
+        disable_usart_tx_interrupt(&self.usart);
+        cleanup_usart(&self.usart);
+    }
+
+    /// Queue bytes for transmission without blocking. Returns the number of bytes queued, which
+    /// is less than `data.len()` if the transmit buffer is full
+    pub fn write(&self, data: &[u8]) -> usize {
+        let mut written = 0;
+        for byte in data {
+            if !self.tx.push(*byte) {
+                break;
+            }
+            written += 1;
+        }
+
+        if written > 0 {
+            // The interrupt handler drains the buffer and disables the interrupt when empty
+            enable_usart_tx_interrupt(&self.usart);
+        }
+
+        written
+    }
+
+    /// Take received bytes from the receive buffer without blocking. Returns the number of bytes
+    /// copied into `buffer`
+    pub fn read(&self, buffer: &mut [u8]) -> usize {
+        let mut read = 0;
+        for byte in buffer.iter_mut() {
+            match self.rx.pop() {
+                Some(value) => *byte = value,
+                None => break,
+            }
+            read += 1;
+        }
+        read
+    }
+
+    /// Number of received bytes waiting to be read
+    pub fn available(&self) -> usize {
+        self.rx.len()
+    }
+
+    /// Take the last receive error detected by the interrupt handler, if any
+    pub fn take_error(&self) -> Option<UsartError> {
+        decode_usart_error(self.error.swap(NO_ERROR, Ordering::Relaxed))
+    }
+
+    /// True when every queued byte has been handed to the usart
+    pub fn is_tx_empty(&self) -> bool {
+        self.tx.is_empty()
+    }
+
+    /// Body of the usart interrupt handler. Moves received bytes into the receive buffer and
+    /// bytes from the transmit buffer into the usart. With the FIFOs enabled, both FIFOs are
+    /// drained or filled in one go. Received bytes are dropped if the receive buffer is full.
+    /// Receiver timeouts are passed on to `handle_usart_receiver_timeout`
+    pub fn handle_interrupt(&self) {
+        use super::registers::usart2;
+
+        let isr_usart_interrupt_register = get_isr_usart_interrupt_register(&self.usart);
+        let cr_usart_control_register = get_cr_usart_control_register(&self.usart);
+        let cr3_usart_control_register = get_cr3_usart_control_register(&self.usart);
+
+        unsafe {
+            // The error flags belong to the oldest received byte, so they're checked once per
+            // byte. RXNE reads as RXFNE with the FIFO enabled
+            loop {
+                let error = check_usart_errors(&self.usart).err();
+                if let Some(error) = error {
+                    self.error
+                        .store(encode_usart_error(error), Ordering::Relaxed);
+                }
+
+                if get_bit(isr_usart_interrupt_register, usart2::isr::RXNE) == 0 {
+                    break;
+                }
+
+                let value = read_register(get_rdr_usart_data_register(&self.usart))
+                    & get_rdr_data_mask(&self.usart);
+
+                // A byte received with a parity, framing or noise error is dropped. An overrun
+                // means that later bytes were lost, but the one in the data register is intact
+                if matches!(error, None | Some(UsartError::Overrun)) {
+                    self.rx.push(value as u8);
+                }
+            }
+
+            // Run the receiver timeout callback once the whole frame is in the receive buffer
+            handle_usart_receiver_timeout(&self.usart);
+
+            let transmit_ready = (get_bit(cr_usart_control_register, usart2::cr1::TXEIE) == 1
+                && get_bit(isr_usart_interrupt_register, usart2::isr::TXE) == 1)
+                || (get_bit(cr3_usart_control_register, usart2::cr3::TXFTIE) == 1
+                    && get_bit(isr_usart_interrupt_register, usart2::isr::TXFT) == 1);
+
+            // TXE reads as TXFNF with the FIFO enabled, so this fills the FIFO
+            while transmit_ready && get_bit(isr_usart_interrupt_register, usart2::isr::TXE) == 1 {
+                match self.tx.pop() {
+                    Some(value) => {
+                        write_register(get_tdr_usart_data_register(&self.usart), value as u32)
+                    }
+                    None => {
+                        disable_usart_tx_interrupt(&self.usart);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Number of received frames that can be queued before `poll_frame` is called. Frames that
+/// don't fit are merged with the next one
+const FRAME_QUEUE_LENGTH: usize = 8;
+
+/// DMA driven receiver that splits the incoming data into frames on idle line detection, for
+/// protocols where frames have no fixed length, such as Modbus RTU or NMEA. The receiver writes
+/// into `buffer` in circular mode, and the usart interrupt records where each frame ends when
+/// the line goes idle. Place it in a `static` in DMA accessible RAM (not the DTCM) and call
+/// `handle_interrupt` from the interrupt handler of the usart, e.g.
+///
+/// ```ignore
+/// #[unsafe(link_section = ".axisram")]
+/// static MODBUS: UsartRx<256> = UsartRx::new(USART::USART2, DmaStream::Dma1Stream0);
+///
+/// #[interrupt]
+/// fn USART2() {
+///     MODBUS.handle_interrupt();
+/// }
+/// ```
+///
+/// The buffer must be large enough to hold the data received between two calls to
+/// `poll_frame`, otherwise the oldest data is overwritten. If the data cache is enabled, the
+/// buffer must be placed in a non-cacheable region
+pub struct UsartRx<const N: usize> {
+    usart: USART,
+    stream: DmaStream,
+    buffer: UnsafeCell<[u8; N]>,
+    /// Position in `buffer` of the first byte not yet returned by `poll_frame`
+    read_index: AtomicUsize,
+    /// Queue of positions in `buffer` where a frame ends, written by the interrupt handler
+    frame_ends: [AtomicUsize; FRAME_QUEUE_LENGTH],
+    frame_head: AtomicUsize,
+    frame_tail: AtomicUsize,
+}
+
+// The buffer is only written by the DMA and only read by `poll_frame`, behind the frame end
+// positions published by the interrupt handler
+unsafe impl<const N: usize> Sync for UsartRx<N> {}
+
+impl<const N: usize> UsartRx<N> {
+    pub const fn new(usart: USART, stream: DmaStream) -> Self {
+        assert!(
+            N > 0 && N <= 0xFFFF,
+            "The DMA can transfer at most 65535 bytes"
+        );
+        assert!(
+            !matches!(usart, USART::LPUART1),
+            "LPUART1 can't be used with DMA1 or DMA2"
+        );
+
+        Self {
+            usart,
+            stream,
+            buffer: UnsafeCell::new([0; N]),
+            read_index: AtomicUsize::new(0),
+            frame_ends: [const { AtomicUsize::new(0) }; FRAME_QUEUE_LENGTH],
+            frame_head: AtomicUsize::new(0),
+            frame_tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Setup the usart with 8N1 framing and start receiving
+    pub fn setup(&'static self, baud_rate: u32) -> Result<(), UsartError> {
+        self.setup_with_config(baud_rate, &UsartConfig::new())
+    }
+
+    /// Setup the usart with the given frame format and start receiving. The receiver needs a
+    /// `'static` reference, as the DMA keeps writing into the buffer until `cleanup` is called
+    pub fn setup_with_config(
+        &'static self,
+        baud_rate: u32,
+        config: &UsartConfig,
+    ) -> Result<(), UsartError> {
+        use super::registers::usart2;
+
+        self.read_index.store(0, Ordering::Relaxed);
+        self.frame_head.store(0, Ordering::Relaxed);
+        self.frame_tail.store(0, Ordering::Relaxed);
+
+        setup_usart(baud_rate, config, &self.usart)?;
+
+        setup_dma_stream(
+            self.stream,
+            &DmaTransfer {
+                request: get_dma_rx_request(&self.usart),
+                peripheral_address: get_rdr_usart_data_register(&self.usart) as u32,
+                memory_address: self.buffer.get() as u32,
+                length: N as u16,
+                direction: DmaDirection::PeripheralToMemory,
+                size: DmaSize::Byte,
+                circular: true,
+            },
+        );
+
+        unsafe {
+            // Let the receiver request the DMA on each received byte
+            set_bit(
+                get_cr3_usart_control_register(&self.usart),
+                usart2::cr3::DMAR,
+            );
+
+            // Clear any idle flag from before the setup and enable the idle line interrupt
+            write_register(
+                get_icr_usart_interrupt_clear_register(&self.usart),
+                1 << usart2::icr::IDLECF,
+            );
+            set_bit(
+                get_cr_usart_control_register(&self.usart),
+                usart2::cr1::IDLEIE,
+            );
+        }
+
+        enable_interrupt(get_nvic_interrupt_id(&self.usart));
+
+        Ok(())
+    }
+
+    pub fn cleanup(&self) {
+        use super::registers::usart2;
+
+        disable_interrupt(get_nvic_interrupt_id(&self.usart));
+
+        unsafe {
+            clear_bit(
+                get_cr_usart_control_register(&self.usart),
+                usart2::cr1::IDLEIE,
+            );
+            clear_bit(
+                get_cr3_usart_control_register(&self.usart),
+                usart2::cr3::DMAR,
+            );
+        }
+
+        disable_dma_stream(self.stream);
+        cleanup_usart(&self.usart);
+    }
+
+    /// Copy the oldest complete frame into `frame`. Returns the length of the frame, or `None` if
+    /// no frame has been received since the last call. Bytes that don't fit in `frame` are
+    /// dropped
+    pub fn poll_frame(&self, frame: &mut [u8]) -> Option<usize> {
+        let tail = self.frame_tail.load(Ordering::Relaxed);
+        if tail == self.frame_head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let end = self.frame_ends[tail].load(Ordering::Relaxed);
+        let mut index = self.read_index.load(Ordering::Relaxed);
+        let mut length = 0;
+
+        while index != end {
+            // The buffer is written by the DMA behind the back of the compiler
+            let value = unsafe { (self.buffer.get() as *const u8).add(index).read_volatile() };
+
+            if length < frame.len() {
+                frame[length] = value;
+                length += 1;
+            }
+
+            index = (index + 1) % N;
+        }
+
+        self.read_index.store(end, Ordering::Relaxed);
+        self.frame_tail
+            .store((tail + 1) % FRAME_QUEUE_LENGTH, Ordering::Release);
+
+        Some(length)
+    }
+
+    /// Body of the usart interrupt handler. Records the end of the current frame when the line
+    /// goes idle
+    pub fn handle_interrupt(&self) {
+        use super::registers::usart2;
+
+        let isr_usart_interrupt_register = get_isr_usart_interrupt_register(&self.usart);
+        let icr_usart_interrupt_clear_register =
+            get_icr_usart_interrupt_clear_register(&self.usart);
+
+        unsafe {
+            // An overrun stops the receiver from requesting the DMA until the flag is cleared
+            if get_bit(isr_usart_interrupt_register, usart2::isr::ORE) == 1 {
+                write_register(icr_usart_interrupt_clear_register, 1 << usart2::icr::ORECF);
+            }
+
+            if get_bit(isr_usart_interrupt_register, usart2::isr::IDLE) == 0 {
+                return;
+            }
+
+            write_register(icr_usart_interrupt_clear_register, 1 << usart2::icr::IDLECF);
+        }
+
+        // The DMA counts down from N and reloads when it wraps, so the write position is the
+        // number of bytes transferred so far
+        let end = (N - get_dma_remaining_transfers(self.stream) as usize) % N;
+
+        let head = self.frame_head.load(Ordering::Relaxed);
+        let next = (head + 1) % FRAME_QUEUE_LENGTH;
+
+        if next == self.frame_tail.load(Ordering::Acquire) {
+            // The queue is full. The data is kept in the buffer and returned as part of the next
+            // frame that fits in the queue
+            return;
+        }
+
+        self.frame_ends[head].store(end, Ordering::Relaxed);
+        self.frame_head.store(next, Ordering::Release);
+    }
+}
+
+// USART 2
+
+pub fn setup_usart2(baud_rate: u32) -> Result<(), UsartError> {
+    setup_usart(baud_rate, &UsartConfig::new(), &USART::USART2)
+}
+
+pub fn setup_usart2_with_config(baud_rate: u32, config: &UsartConfig) -> Result<(), UsartError> {
+    setup_usart(baud_rate, config, &USART::USART2)
+}
+
+pub fn setup_usart2_with_pins(baud_rate: u32, tx: PortPin, rx: PortPin) -> Result<(), UsartError> {
+    setup_usart_with_pins(baud_rate, &UsartConfig::new(), tx, rx, &USART::USART2)
+}
+
+pub fn setup_usart2_flow_control(
+    cts: Option<PortPin>,
+    rts: Option<PortPin>,
+) -> Result<(), UsartError> {
+    setup_usart_flow_control(cts, rts, &USART::USART2)
+}
+
+pub fn setup_usart2_half_duplex(baud_rate: u32, pin: PortPin) -> Result<(), UsartError> {
+    setup_usart_half_duplex(baud_rate, &UsartConfig::new(), pin, &USART::USART2)
+}
+
+pub fn set_usart2_direction(direction: UsartDirection) -> Result<(), UsartError> {
+    set_usart_direction(direction, &USART::USART2)
+}
+
+pub fn setup_usart2_driver_enable(
+    de: PortPin,
+    polarity: UsartDePolarity,
+    assertion_time: u8,
+    deassertion_time: u8,
+) -> Result<(), UsartError> {
+    setup_usart_driver_enable(
+        de,
+        polarity,
+        assertion_time,
+        deassertion_time,
+        &USART::USART2,
+    )
+}
+
+pub fn setup_usart2_smartcard(
+    baud_rate: u32,
+    smartcard: &UsartSmartcardConfig,
+    io: PortPin,
+    ck: PortPin,
+) -> Result<(), UsartError> {
+    setup_usart_smartcard(baud_rate, smartcard, io, ck, &USART::USART2)
+}
+
+pub fn setup_usart2_irda(mode: UsartIrdaMode) -> Result<(), UsartError> {
+    setup_usart_irda(mode, &USART::USART2)
+}
+
+pub fn setup_usart2_lin(break_length: LinBreakLength) -> Result<(), UsartError> {
+    setup_usart_lin(break_length, &USART::USART2)
+}
+
+pub fn send_usart2_break() -> Result<(), UsartError> {
+    send_usart_break(&USART::USART2)
+}
+
+pub fn is_usart2_break_pending() -> bool {
+    is_usart_break_pending(&USART::USART2)
+}
+
+pub fn enable_usart2_break_interrupt() {
+    enable_usart_break_interrupt(&USART::USART2)
+}
+
+pub fn disable_usart2_break_interrupt() {
+    disable_usart_break_interrupt(&USART::USART2)
+}
+
+pub fn take_usart2_break() -> bool {
+    take_usart_break(&USART::USART2)
+}
+
+pub fn setup_usart2_receiver_timeout(bit_times: u32) -> Result<(), UsartError> {
+    setup_usart_receiver_timeout(bit_times, &USART::USART2)
+}
+
+pub fn self_test_usart2() -> Result<(), UsartError> {
+    self_test_usart(&USART::USART2)
+}
+
+pub fn cleanup_usart2() {
+    cleanup_usart(&USART::USART2);
+}
+
+pub fn is_usart2_setup() -> bool {
+    is_usart_setup(&USART::USART2)
+}
+
+pub fn enable_usart2_tx_interrupt() {
+    enable_usart_tx_interrupt(&USART::USART2);
+}
+
+pub fn disable_usart2_tx_interrupt() {
+    disable_usart_tx_interrupt(&USART::USART2);
+}
+
+pub fn write_usart2_character(character: char) -> Result<(), UsartError> {
+    write_usart_character(character, &USART::USART2)
+}
+
+pub fn write_usart2_string(string: &str) -> Result<(), UsartError> {
+    write_usart_string(string, &USART::USART2)
+}
+
+pub fn read_usart2_byte() -> Result<u8, UsartError> {
+    read_usart_byte(&USART::USART2)
+}
+
+pub fn try_write_usart2_character(character: char) -> nb::Result<(), UsartError> {
+    try_write_usart_character(character, &USART::USART2)
+}
+
+pub fn try_read_usart2_character() -> nb::Result<char, UsartError> {
+    try_read_usart_character(&USART::USART2)
+}
+
+pub fn write_usart2_u16(word: u16) -> Result<(), UsartError> {
+    write_usart_u16(word, &USART::USART2)
+}
+
+pub fn read_usart2_u16() -> Result<u16, UsartError> {
+    read_usart_u16(&USART::USART2)
+}
+
+pub fn setup_usart2_mute_mode(wakeup: UsartMuteWakeup) -> Result<(), UsartError> {
+    setup_usart_mute_mode(wakeup, &USART::USART2)
+}
+
+pub fn enter_usart2_mute_mode() {
+    enter_usart_mute_mode(&USART::USART2)
+}
+
+pub fn read_usart2_character() -> Result<char, UsartError> {
+    read_usart_character(&USART::USART2)
+}
+
+pub fn read_usart2_bytes(buffer: &mut [u8]) -> Result<(), UsartError> {
+    read_usart_bytes(buffer, &USART::USART2)
+}
+
+pub fn dump_usart2(writer: &mut impl fmt::Write) -> fmt::Result {
+    dump_usart(&USART::USART2, writer)
+}
+
+// USART 3
+
+pub fn setup_usart3(baud_rate: u32) -> Result<(), UsartError> {
+    setup_usart(baud_rate, &UsartConfig::new(), &USART::USART3)
+}
+
+pub fn setup_usart3_with_config(baud_rate: u32, config: &UsartConfig) -> Result<(), UsartError> {
+    setup_usart(baud_rate, config, &USART::USART3)
+}
+
+pub fn setup_usart3_with_pins(baud_rate: u32, tx: PortPin, rx: PortPin) -> Result<(), UsartError> {
+    setup_usart_with_pins(baud_rate, &UsartConfig::new(), tx, rx, &USART::USART3)
+}
+
+pub fn setup_usart3_flow_control(
+    cts: Option<PortPin>,
+    rts: Option<PortPin>,
+) -> Result<(), UsartError> {
+    setup_usart_flow_control(cts, rts, &USART::USART3)
+}
+
+pub fn setup_usart3_half_duplex(baud_rate: u32, pin: PortPin) -> Result<(), UsartError> {
+    setup_usart_half_duplex(baud_rate, &UsartConfig::new(), pin, &USART::USART3)
+}
+
+pub fn set_usart3_direction(direction: UsartDirection) -> Result<(), UsartError> {
+    set_usart_direction(direction, &USART::USART3)
+}
+
+pub fn setup_usart3_driver_enable(
+    de: PortPin,
+    polarity: UsartDePolarity,
+    assertion_time: u8,
+    deassertion_time: u8,
+) -> Result<(), UsartError> {
+    setup_usart_driver_enable(
+        de,
+        polarity,
+        assertion_time,
+        deassertion_time,
+        &USART::USART3,
+    )
+}
+
+pub fn setup_usart3_smartcard(
+    baud_rate: u32,
+    smartcard: &UsartSmartcardConfig,
+    io: PortPin,
+    ck: PortPin,
+) -> Result<(), UsartError> {
+    setup_usart_smartcard(baud_rate, smartcard, io, ck, &USART::USART3)
+}
+
+pub fn setup_usart3_irda(mode: UsartIrdaMode) -> Result<(), UsartError> {
+    setup_usart_irda(mode, &USART::USART3)
+}
+
+pub fn setup_usart3_lin(break_length: LinBreakLength) -> Result<(), UsartError> {
+    setup_usart_lin(break_length, &USART::USART3)
+}
+
+pub fn send_usart3_break() -> Result<(), UsartError> {
+    send_usart_break(&USART::USART3)
+}
+
+pub fn is_usart3_break_pending() -> bool {
+    is_usart_break_pending(&USART::USART3)
+}
+
+pub fn enable_usart3_break_interrupt() {
+    enable_usart_break_interrupt(&USART::USART3)
+}
+
+pub fn disable_usart3_break_interrupt() {
+    disable_usart_break_interrupt(&USART::USART3)
+}
+
+pub fn take_usart3_break() -> bool {
+    take_usart_break(&USART::USART3)
+}
+
+pub fn setup_usart3_receiver_timeout(bit_times: u32) -> Result<(), UsartError> {
+    setup_usart_receiver_timeout(bit_times, &USART::USART3)
+}
+
+pub fn self_test_usart3() -> Result<(), UsartError> {
+    self_test_usart(&USART::USART3)
+}
+
+pub fn cleanup_usart3() {
+    cleanup_usart(&USART::USART3);
+}
+
+pub fn is_usart3_setup() -> bool {
     is_usart_setup(&USART::USART3)
 }
 
@@ -257,10 +2950,287 @@ pub fn disable_usart3_tx_interrupt() {
     disable_usart_tx_interrupt(&USART::USART3);
 }
 
-pub fn write_usart3_character(character: char) {
-    write_usart_character(character, &USART::USART3);
+pub fn write_usart3_character(character: char) -> Result<(), UsartError> {
+    write_usart_character(character, &USART::USART3)
+}
+
+pub fn write_usart3_string(string: &str) -> Result<(), UsartError> {
+    write_usart_string(string, &USART::USART3)
+}
+
+pub fn read_usart3_byte() -> Result<u8, UsartError> {
+    read_usart_byte(&USART::USART3)
+}
+
+pub fn try_write_usart3_character(character: char) -> nb::Result<(), UsartError> {
+    try_write_usart_character(character, &USART::USART3)
+}
+
+pub fn try_read_usart3_character() -> nb::Result<char, UsartError> {
+    try_read_usart_character(&USART::USART3)
+}
+
+pub fn write_usart3_u16(word: u16) -> Result<(), UsartError> {
+    write_usart_u16(word, &USART::USART3)
+}
+
+pub fn read_usart3_u16() -> Result<u16, UsartError> {
+    read_usart_u16(&USART::USART3)
+}
+
+pub fn setup_usart3_mute_mode(wakeup: UsartMuteWakeup) -> Result<(), UsartError> {
+    setup_usart_mute_mode(wakeup, &USART::USART3)
+}
+
+pub fn enter_usart3_mute_mode() {
+    enter_usart_mute_mode(&USART::USART3)
+}
+
+pub fn read_usart3_character() -> Result<char, UsartError> {
+    read_usart_character(&USART::USART3)
+}
+
+pub fn read_usart3_bytes(buffer: &mut [u8]) -> Result<(), UsartError> {
+    read_usart_bytes(buffer, &USART::USART3)
+}
+
+pub fn dump_usart3(writer: &mut impl fmt::Write) -> fmt::Result {
+    dump_usart(&USART::USART3, writer)
+}
+
+// LPUART 1
+
+/// Kernel clock source of LPUART1. Only the HSI, CSI and LSE sources keep running in Stop mode,
+/// so one of them must be selected to receive wake-up characters
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LpuartClockSource {
+    Pclk4 = 0b000,
+    Pll2Q = 0b001,
+    Pll3Q = 0b010,
+    Hsi = 0b011,
+    Csi = 0b100,
+    Lse = 0b101,
+}
+
+/// Event that wakes the MCU from Stop mode through LPUART1
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LpuartWakeup {
+    AddressMatch = 0b00,
+    StartBit = 0b10,
+    ReceiveNotEmpty = 0b11,
+}
+
+/// LPUART1 receive wake-up line in the EXTI, see section 20.4 EXTI event input mapping
+const EXTI_LPUART1_RX_WAKEUP_LINE: u8 = 34;
+
+pub fn setup_lpuart1(baud_rate: u32) -> Result<(), UsartError> {
+    setup_usart(baud_rate, &UsartConfig::new(), &USART::LPUART1)
+}
+
+pub fn setup_lpuart1_with_config(baud_rate: u32, config: &UsartConfig) -> Result<(), UsartError> {
+    setup_usart(baud_rate, config, &USART::LPUART1)
+}
+
+/// Select the kernel clock of LPUART1. Must be done before `setup_lpuart1`. The baud rate must
+/// be between fck / 4096 and fck / 3, so a 32.768 kHz LSE allows up to 9600 baud
+pub fn set_lpuart1_clock_source(source: LpuartClockSource) {
+    let source = match source {
+        LpuartClockSource::Pclk4 => UsartClockSource::Pclk,
+        LpuartClockSource::Pll2Q => UsartClockSource::Pll2Q,
+        LpuartClockSource::Pll3Q => UsartClockSource::Pll3Q,
+        LpuartClockSource::Hsi => UsartClockSource::Hsi,
+        LpuartClockSource::Csi => UsartClockSource::Csi,
+        LpuartClockSource::Lse => UsartClockSource::Lse,
+    };
+
+    set_usart_clock_source(source, &USART::LPUART1);
+}
+
+/// Allow LPUART1 to wake the MCU from Stop mode on the given event. LPUART1 must be setup and
+/// clocked from the HSI, CSI or LSE. The wake-up is delivered as an LPUART1 interrupt, which
+/// should clear the flag with `clear_lpuart1_wakeup_flag`
+pub fn enable_lpuart1_stop_mode_wakeup(wakeup: LpuartWakeup) {
+    use super::registers::{
+        exti::CPUIMR2,
+        lpuart1::{CR1, CR3, cr1, cr3},
+        rcc::{D3AMR, d3amr},
+    };
+
+    unsafe {
+        // The wake-up selection can only be written while the LPUART is disabled
+        clear_bit(CR1, cr1::UE);
+        write_bits(CR3, cr3::WUS, wakeup as u32, 0b11);
+        set_bit(CR3, cr3::WUFIE);
+        set_bit(CR1, cr1::UE);
+
+        // Keep the LPUART clocked in Stop mode
+        set_bit(CR1, cr1::UESM);
+        set_bit(D3AMR, d3amr::LPUART1AMEN);
+
+        // Unmask the wake-up line so it can wake the CPU
+        set_bit(CPUIMR2, EXTI_LPUART1_RX_WAKEUP_LINE - 32);
+    }
+
+    enable_interrupt(get_nvic_interrupt_id(&USART::LPUART1));
+}
+
+pub fn disable_lpuart1_stop_mode_wakeup() {
+    use super::registers::{
+        exti::CPUIMR2,
+        lpuart1::{CR1, CR3, cr1, cr3},
+        rcc::{D3AMR, d3amr},
+    };
+
+    unsafe {
+        clear_bit(CPUIMR2, EXTI_LPUART1_RX_WAKEUP_LINE - 32);
+        clear_bit(D3AMR, d3amr::LPUART1AMEN);
+        clear_bit(CR1, cr1::UESM);
+        clear_bit(CR3, cr3::WUFIE);
+    }
+}
+
+pub fn clear_lpuart1_wakeup_flag() {
+    use super::registers::lpuart1::{ICR, icr};
+
+    unsafe {
+        write_register(ICR, 1 << icr::WUCF);
+    }
 }
 
-pub fn write_usart3_string(string: &str) {
-    write_usart_string(string, &USART::USART3);
+pub fn cleanup_lpuart1() {
+    cleanup_usart(&USART::LPUART1);
+}
+
+pub fn is_lpuart1_setup() -> bool {
+    is_usart_setup(&USART::LPUART1)
+}
+
+pub fn write_lpuart1_character(character: char) -> Result<(), UsartError> {
+    write_usart_character(character, &USART::LPUART1)
+}
+
+pub fn write_lpuart1_string(string: &str) -> Result<(), UsartError> {
+    write_usart_string(string, &USART::LPUART1)
+}
+
+pub fn read_lpuart1_byte() -> Result<u8, UsartError> {
+    read_usart_byte(&USART::LPUART1)
+}
+
+pub fn read_lpuart1_character() -> Result<char, UsartError> {
+    read_usart_character(&USART::LPUART1)
+}
+
+pub fn read_lpuart1_bytes(buffer: &mut [u8]) -> Result<(), UsartError> {
+    read_usart_bytes(buffer, &USART::LPUART1)
+}
+
+pub fn dump_lpuart1(writer: &mut impl fmt::Write) -> fmt::Result {
+    dump_usart(&USART::LPUART1, writer)
+}
+
+#[cfg(all(test, feature = "mock-registers"))]
+mod tests {
+    use super::*;
+    use crate::register_tools::mock::{get_register, reset_registers};
+    use crate::registers::usart3;
+
+    #[test]
+    fn setup_writes_the_baud_rate_and_enables_the_usart() {
+        reset_registers();
+
+        // With the registers at their zero state, USART3 runs from PCLK1 at the 64 MHz HSI
+        setup_usart(115_200, &UsartConfig::new(), &USART::USART3).unwrap();
+
+        assert_eq!(
+            get_register(usart3::BRR),
+            (64_000_000 + 115_200 / 2) / 115_200
+        );
+
+        let cr1 = get_register(usart3::CR1);
+        for field in [usart3::cr1::TE, usart3::cr1::RE, usart3::cr1::UE] {
+            assert_eq!(cr1 & (1 << field), 1 << field);
+        }
+    }
+
+    #[test]
+    fn compute_baud_rate_register_rounds_and_checks_the_divider() {
+        let by_8 = UsartConfig::new().oversampling(UsartOversampling::By8);
+        let by_16 = UsartConfig::new();
+        let lenient = UsartConfig::new().max_baud_error(3);
+
+        let cases = [
+            (100_000_000, 115_200, &by_16, USART::USART1, Ok(868)),
+            // The divider of 1736 is written with its lowest four bits shifted down by one
+            (100_000_000, 115_200, &by_8, USART::USART1, Ok(0x6C4)),
+            (16_000_000, 1_000_000, &by_16, USART::USART2, Ok(16)),
+            (
+                16_000_000,
+                2_000_000,
+                &by_16,
+                USART::USART2,
+                Err(UsartError::InvalidBaudRate),
+            ),
+            (16_000_000, 2_000_000, &by_8, USART::USART2, Ok(0x10)),
+            (
+                100_000_000,
+                1_200,
+                &by_16,
+                USART::USART3,
+                Err(UsartError::InvalidBaudRate),
+            ),
+            (
+                100_000_000,
+                0,
+                &by_16,
+                USART::USART3,
+                Err(UsartError::InvalidBaudRate),
+            ),
+            // A divider of 17 gives 941176 baud, which is 2.1 % off
+            (
+                16_000_000,
+                921_600,
+                &by_16,
+                USART::USART3,
+                Err(UsartError::InvalidBaudRate),
+            ),
+            (16_000_000, 921_600, &lenient, USART::USART3, Ok(17)),
+            // The LPUART divider is scaled by 256, and ignores the oversampling
+            (64_000_000, 115_200, &by_16, USART::LPUART1, Ok(142_222)),
+            (64_000_000, 115_200, &by_8, USART::LPUART1, Ok(142_222)),
+            (
+                100_000_000,
+                9_600,
+                &by_16,
+                USART::LPUART1,
+                Err(UsartError::InvalidBaudRate),
+            ),
+            (
+                64_000_000,
+                30_000_000,
+                &by_16,
+                USART::LPUART1,
+                Err(UsartError::InvalidBaudRate),
+            ),
+        ];
+
+        for (clock_speed, baud_rate, config, usart, expected) in cases {
+            assert_eq!(
+                compute_baud_rate_register(clock_speed, baud_rate, config, &usart),
+                expected,
+                "{baud_rate} baud from {clock_speed} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn setup_rejects_a_zero_baud_rate() {
+        reset_registers();
+
+        assert_eq!(
+            setup_usart(0, &UsartConfig::new(), &USART::USART3),
+            Err(UsartError::InvalidBaudRate)
+        );
+        assert_eq!(get_register(usart3::CR1), 0);
+    }
 }
@@ -0,0 +1,115 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::{
+    cortex::{dsb, isb},
+    interrupts::{self, Irq, SystemHandler},
+    register_tools::{read_register, write_register},
+    registers,
+};
+
+/// Number of entries in the vector table. The initial stack pointer and the 15 system
+/// exceptions are followed by the 150 interrupts
+pub const VECTOR_COUNT: usize = 16 + 150;
+
+/// A vector table in RAM. The VTOR needs the table aligned to the table size rounded up to a
+/// power of two, which is 1024 bytes for 166 entries. Place a table in a specific memory, such as
+/// the ITCM, with `#[unsafe(link_section = ...)]` on the static holding it
+#[repr(C, align(1024))]
+pub struct VectorTable([AtomicU32; VECTOR_COUNT]);
+
+impl VectorTable {
+    pub const fn new() -> Self {
+        Self([const { AtomicU32::new(0) }; VECTOR_COUNT])
+    }
+}
+
+impl Default for VectorTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VectorTableError {
+    /// The vector table hasn't been moved to RAM, so its handlers can't be changed
+    NotRelocated,
+}
+
+/// Table used by `relocate_vector_table`, placed in the default RAM section
+static VECTOR_TABLE: VectorTable = VectorTable::new();
+
+/// Address of the vector table in RAM that the VTOR points to. A zero means that the vector
+/// table hasn't been relocated
+static ACTIVE_TABLE: AtomicUsize = AtomicUsize::new(0);
+
+/// Copy the active vector table to a table in RAM and point the VTOR to it, so that handlers
+/// can be installed with `set_handler`
+pub fn relocate_vector_table() {
+    relocate_vector_table_to(&VECTOR_TABLE);
+}
+
+/// Copy the active vector table to `table` and point the VTOR to it
+pub fn relocate_vector_table_to(table: &'static VectorTable) {
+    use registers::scb::VTOR;
+
+    let address = table as *const VectorTable as u32;
+
+    // Mask interrupts so that no exception is taken while the table is half copied
+    interrupts::free(|| {
+        let source = unsafe { read_register(VTOR) } as *const u32;
+
+        for (index, entry) in table.0.iter().enumerate() {
+            entry.store(
+                unsafe { source.add(index).read_volatile() },
+                Ordering::Relaxed,
+            );
+        }
+
+        // Make sure the copy is complete before the table is used, and that the new table is
+        // used for the next exception
+        dsb();
+        unsafe {
+            write_register(VTOR, address);
+        }
+        dsb();
+        isb();
+
+        ACTIVE_TABLE.store(address as usize, Ordering::Release);
+    });
+}
+
+pub fn is_vector_table_relocated() -> bool {
+    ACTIVE_TABLE.load(Ordering::Acquire) != 0
+}
+
+/// Install the handler of an interrupt in the relocated vector table
+pub fn set_handler(irq: Irq, handler: extern "C" fn()) -> Result<(), VectorTableError> {
+    write_vector(16 + irq as usize, handler)
+}
+
+/// Install the handler of a system exception in the relocated vector table
+pub fn set_system_handler(
+    handler: SystemHandler,
+    function: extern "C" fn(),
+) -> Result<(), VectorTableError> {
+    write_vector(handler as usize, function)
+}
+
+fn write_vector(index: usize, handler: extern "C" fn()) -> Result<(), VectorTableError> {
+    let table = ACTIVE_TABLE.load(Ordering::Acquire);
+    if table == 0 {
+        return Err(VectorTableError::NotRelocated);
+    }
+
+    // Safety: only addresses of `'static` vector tables are stored in `ACTIVE_TABLE`
+    let table = unsafe { &*(table as *const VectorTable) };
+
+    // The function address already has the thumb bit set
+    table.0[index].store(handler as usize as u32, Ordering::Relaxed);
+
+    // Make sure the entry is written before the interrupt can be taken
+    dsb();
+
+    Ok(())
+}
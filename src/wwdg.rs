@@ -0,0 +1,199 @@
+/// See [RM0433 Reference Manual](https://www.st.com/resource/en/reference_manual/rm0433-stm32h742-stm32h743753-and-stm32h750-value-line-advanced-armbased-32bit-mcus-stmicroelectronics.pdf)
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::{
+    interrupts::{Irq, enable_interrupt},
+    register_tools::{get_bit, set_bit, write_register},
+    registers,
+};
+
+/// Counter value written on every feed, or zero if the watchdog hasn't been setup
+static WWDG_RELOAD: AtomicU8 = AtomicU8::new(0);
+
+/// Registered early wakeup callback, stored as a function pointer. A zero means that no callback
+/// is registered
+static WWDG_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// The watchdog resets the system when the counter drops below this value
+const WWDG_COUNTER_MIN: u32 = 0x40;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum WwdgError {
+    InvalidClockSpeed(u32),
+    /// The longest service time in microseconds can't be reached with the APB3 clock
+    InvalidTimeout(u32),
+    /// The shortest service time in microseconds isn't below the longest
+    InvalidWindow(u32),
+}
+
+/// Start the window watchdog, which resets the system unless `feed_wwdg` is called between
+/// `min_service_us` and `max_service_us` after the previous feed. The times are rounded to the
+/// watchdog tick, with the maximum rounded down and the minimum up so the window only shrinks.
+/// Once started, the watchdog can only be stopped by a reset
+pub fn setup_wwdg(
+    pclk3_frequency: u32,
+    min_service_us: u32,
+    max_service_us: u32,
+) -> Result<(), WwdgError> {
+    use registers::{
+        rcc::{APB3ENR, apb3enr},
+        wwdg::{self, cfr, cr},
+    };
+
+    let (prescaler, reload, window) =
+        compute_wwdg_config(pclk3_frequency, min_service_us, max_service_us)?;
+
+    WWDG_RELOAD.store(reload as u8, Ordering::Relaxed);
+
+    unsafe {
+        set_bit(APB3ENR, apb3enr::WWDG1EN);
+
+        // The prescaler field is three bits on the STM32H7
+        write_register(wwdg::CFR, prescaler << cfr::WDGTB | window << cfr::W);
+
+        // Start the watchdog
+        write_register(wwdg::CR, 1 << cr::WDGA | reload << cr::T);
+    }
+
+    Ok(())
+}
+
+/// Compute the prescaler, counter reload value and window value of the watchdog for a service
+/// window, see `setup_wwdg`
+fn compute_wwdg_config(
+    pclk3_frequency: u32,
+    min_service_us: u32,
+    max_service_us: u32,
+) -> Result<(u32, u32, u32), WwdgError> {
+    if pclk3_frequency < 4096 {
+        return Err(WwdgError::InvalidClockSpeed(pclk3_frequency));
+    }
+
+    if min_service_us >= max_service_us {
+        return Err(WwdgError::InvalidWindow(min_service_us));
+    }
+
+    // The counter ticks every 4096 * 2^WDGTB APB3 clock cycles, and counts 64 ticks from 0x7F
+    // down to 0x40. Use the fastest tick that reaches the timeout, for the finest window
+    let (prescaler, ticks, tick_frequency) = (0..8)
+        .map(|prescaler| {
+            let tick_frequency = pclk3_frequency as u64 / (4096 << prescaler);
+            let ticks = max_service_us as u64 * tick_frequency / 1_000_000;
+            (prescaler, ticks, tick_frequency)
+        })
+        .find(|(_, ticks, _)| *ticks <= 64)
+        .ok_or(WwdgError::InvalidTimeout(max_service_us))?;
+
+    if ticks == 0 {
+        return Err(WwdgError::InvalidTimeout(max_service_us));
+    }
+
+    let reload = WWDG_COUNTER_MIN - 1 + ticks as u32;
+
+    // Feeding is allowed once the counter is at or below the window value
+    let min_ticks = (min_service_us as u64 * tick_frequency).div_ceil(1_000_000);
+    if min_ticks >= ticks {
+        return Err(WwdgError::InvalidWindow(min_service_us));
+    }
+    let window = reload - min_ticks as u32;
+
+    Ok((prescaler, reload, window))
+}
+
+/// Reload the watchdog counter. Feeding before the window opens resets the system as well
+pub fn feed_wwdg() {
+    use registers::wwdg::{self, cr};
+
+    let reload = WWDG_RELOAD.load(Ordering::Relaxed) as u32;
+    if reload == 0 {
+        return;
+    }
+
+    unsafe {
+        write_register(wwdg::CR, 1 << cr::WDGA | reload << cr::T);
+    }
+}
+
+/// Interrupt when the counter reaches 0x40, one tick before the reset, and run `callback` from
+/// the interrupt handler. This gives a last chance to log state or feed the watchdog. The
+/// interrupt can't be disabled again until a reset
+pub fn setup_wwdg_early_wakeup(callback: fn()) {
+    use registers::wwdg::{self, cfr};
+
+    WWDG_CALLBACK.store(callback as usize, Ordering::Release);
+
+    unsafe {
+        // Clear a stale flag before the interrupt is enabled
+        write_register(wwdg::SR, 0);
+        set_bit(wwdg::CFR, cfr::EWI);
+    }
+
+    enable_interrupt(Irq::Wwdg1);
+}
+
+/// Clear the early wakeup flag and run the registered callback. This is the body of the WWDG
+/// interrupt handler and can be called from a user defined handler as well
+pub fn handle_wwdg_interrupt() {
+    use registers::wwdg::{self, sr};
+
+    unsafe {
+        if get_bit(wwdg::SR, sr::EWIF) == 0 {
+            return;
+        }
+
+        // The flag is cleared by writing a zero
+        write_register(wwdg::SR, 0);
+    }
+
+    let callback = WWDG_CALLBACK.load(Ordering::Acquire);
+    if callback != 0 {
+        // Safety: only valid `fn()` pointers are stored in the callback table
+        let callback: fn() = unsafe { core::mem::transmute::<usize, fn()>(callback) };
+        callback();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_wwdg_config_rounds_the_window_inwards() {
+        // 4.096 MHz gives a watchdog tick of 1 ms without prescaler
+        let cases = [
+            (4_096_000, 10_000, 50_000, Ok((0, 0x3F + 50, 0x3F + 40))),
+            (4_096_000, 0, 64_000, Ok((0, 0x7F, 0x7F))),
+            // The maximum is rounded down and the minimum up
+            (4_096_000, 10_500, 50_900, Ok((0, 0x3F + 50, 0x3F + 39))),
+            // Longer timeouts use a slower tick
+            (4_096_000, 0, 65_000, Ok((1, 0x3F + 32, 0x3F + 32))),
+            (
+                4_096_000,
+                0,
+                10_000_000,
+                Err(WwdgError::InvalidTimeout(10_000_000)),
+            ),
+            (4_096_000, 0, 500, Err(WwdgError::InvalidTimeout(500))),
+            (
+                4_096_000,
+                50_000,
+                50_000,
+                Err(WwdgError::InvalidWindow(50_000)),
+            ),
+            (
+                4_096_000,
+                49_500,
+                50_000,
+                Err(WwdgError::InvalidWindow(49_500)),
+            ),
+            (4_095, 0, 50_000, Err(WwdgError::InvalidClockSpeed(4_095))),
+        ];
+
+        for (pclk3_frequency, min_service_us, max_service_us, expected) in cases {
+            assert_eq!(
+                compute_wwdg_config(pclk3_frequency, min_service_us, max_service_us),
+                expected
+            );
+        }
+    }
+}